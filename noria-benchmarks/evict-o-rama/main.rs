@@ -1,14 +1,17 @@
+#[macro_use]
+extern crate clap;
 extern crate noria;
+extern crate rand;
+extern crate zipf;
 
-use noria::ControllerBuilder;
+use noria::{ControllerBuilder, EvictionPolicy};
+use rand::distributions::Distribution;
+use zipf::ZipfDistribution;
 
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-static NUM_ARTICLES: usize = 10_000;
-
-fn main() {
-    // inline recipe definition
-    let sql = "# base tables
+// inline recipe definition
+static SQL: &str = "# base tables
                CREATE TABLE Article (aid int, title varchar(255), \
                                      url text, PRIMARY KEY(aid));
                CREATE TABLE Vote (aid int, uid int, PRIMARY KEY(aid, uid));
@@ -22,10 +25,11 @@ fn main() {
                                          ON (Article.aid = VoteCount.aid) \
                             WHERE Article.aid = ?;";
 
+fn run(policy: EvictionPolicy, articles: usize, ops: usize, memory: usize, skew: f64) {
     let persistence_params = noria::PersistenceParameters::new(
-        noria::DurabilityMode::Permanent,
+        noria::DurabilityMode::DeleteOnExit,
         Duration::from_millis(1),
-        Some(String::from("evictorama")),
+        Some(format!("evictorama{:?}", policy).to_lowercase()),
         1,
     );
 
@@ -33,14 +37,15 @@ fn main() {
     let mut builder = ControllerBuilder::default();
     builder.log_with(noria::logger_pls());
     builder.set_persistence(persistence_params);
-    builder.set_memory_limit(100 * 1024, Duration::from_millis(1000));
+    builder.set_memory_limit(memory, Duration::from_millis(1000));
+    builder.set_eviction_policy(policy);
 
     // TODO: This should be removed when the `it_works_with_reads_before_writes`
     // test passes again.
     //builder.disable_partial();
 
     let mut blender = builder.build_local().unwrap();
-    blender.install_recipe(sql).unwrap();
+    blender.install_recipe(SQL).unwrap();
 
     // Get mutators and getter.
     let mut article = blender.table("Article").unwrap();
@@ -48,7 +53,7 @@ fn main() {
     let mut awvc = blender.view("ArticleWithVoteCount").unwrap();
 
     println!("Creating articles...");
-    for aid in 1..NUM_ARTICLES {
+    for aid in 1..articles {
         // Make sure the article exists:
         let title = format!("Article {}", aid);
         let url = "http://pdos.csail.mit.edu";
@@ -59,20 +64,96 @@ fn main() {
     }
 
     println!("Reading articles...");
-    for aid in 1..NUM_ARTICLES {
+    for aid in 1..articles {
         awvc.lookup(&[aid.into()], true).unwrap();
     }
 
     println!("Casting votes...");
-    let mut aid = 0;
-    loop {
+    // popular articles should stay cached under a good policy, so reads of them don't replay
+    let zipf = ZipfDistribution::new(articles - 1, skew).unwrap();
+    let mut rng = rand::thread_rng();
+    let start = Instant::now();
+    for _ in 0..ops {
         let uid = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs() as i64;
-        aid = (aid + 1) % NUM_ARTICLES;
-        vote.insert(vec![(aid + 1).into(), uid.into()]).unwrap();
+        let aid = zipf.sample(&mut rng);
+        vote.insert(vec![aid.into(), uid.into()]).unwrap();
 
         awvc.lookup(&[aid.into()], true).unwrap();
     }
+    let took = start.elapsed();
+    let took = took.as_secs() as f64 + f64::from(took.subsec_nanos()) / 1_000_000_000.0;
+    println!(
+        "{:?}: {} vote+read ops in {:.2}s ({:.0} ops/s)",
+        policy,
+        ops,
+        took,
+        ops as f64 / took
+    );
+}
+
+fn main() {
+    use clap::{App, Arg};
+
+    let args = App::new("evict-o-rama")
+        .about("Compares eviction policies under a skewed vote workload")
+        .arg(
+            Arg::with_name("policy")
+                .long("policy")
+                .takes_value(true)
+                .possible_values(&["random", "lru", "lfu", "all"])
+                .default_value("all")
+                .help("Eviction policy to benchmark"),
+        )
+        .arg(
+            Arg::with_name("articles")
+                .long("articles")
+                .takes_value(true)
+                .default_value("10000")
+                .help("Number of articles"),
+        )
+        .arg(
+            Arg::with_name("ops")
+                .long("ops")
+                .takes_value(true)
+                .default_value("100000")
+                .help("Number of votes to cast (each followed by a read)"),
+        )
+        .arg(
+            Arg::with_name("memory")
+                .long("memory")
+                .takes_value(true)
+                .default_value("102400")
+                .help("Memory limit for partially materialized state, in bytes"),
+        )
+        .arg(
+            Arg::with_name("skew")
+                .long("skew")
+                .takes_value(true)
+                .default_value("1.08")
+                .help("Zipf exponent for choosing which article to vote for"),
+        )
+        .get_matches();
+
+    let articles = value_t_or_exit!(args, "articles", usize);
+    let ops = value_t_or_exit!(args, "ops", usize);
+    let memory = value_t_or_exit!(args, "memory", usize);
+    let skew = value_t_or_exit!(args, "skew", f64);
+
+    let policies = match args.value_of("policy").unwrap() {
+        "random" => vec![EvictionPolicy::Random],
+        "lru" => vec![EvictionPolicy::Lru],
+        "lfu" => vec![EvictionPolicy::Lfu],
+        "all" => vec![
+            EvictionPolicy::Random,
+            EvictionPolicy::Lru,
+            EvictionPolicy::Lfu,
+        ],
+        _ => unreachable!(),
+    };
+    for policy in policies {
+        run(policy, articles, ops, memory, skew);
+    }
 }
@@ -10,7 +10,7 @@ mod records;
 pub use self::local::*;
 pub use self::map::*;
 pub use self::records::*;
-pub use noria::DataType;
+pub use noria::{Collation, DataType};
 
 pub trait SizeOf {
     fn deep_size_of(&self) -> u64;
@@ -22,7 +22,10 @@ impl SizeOf for DataType {
         use std::mem::size_of_val;
 
         let inner = match *self {
-            DataType::Text(ref t) => size_of_val(t) as u64 + t.to_bytes().len() as u64,
+            DataType::Text(ref t) | DataType::Json(ref t) => {
+                size_of_val(t) as u64 + t.to_bytes().len() as u64
+            }
+            DataType::ByteArray(ref b) => size_of_val(&**b) as u64 + b.len() as u64,
             _ => 0u64,
         };
 
@@ -66,6 +69,7 @@ mod tests {
         let shrt = DataType::Int(5);
         let long = DataType::BigInt(5);
         let time = DataType::Timestamp(NaiveDateTime::from_timestamp(0, 42_000_000));
+        let bytes = DataType::from(vec![1u8, 2, 3]);
 
         let rec = vec![DataType::Int(5), "asdfasdfasdfasdf".into(), "asdf".into()];
 
@@ -79,6 +83,8 @@ mod tests {
         assert_eq!(size_of_val(&time), 16);
         assert_eq!(size_of_val(&time) as u64, time.size_of());
         assert_eq!(time.deep_size_of(), 16); // DataType + inline NaiveDateTime
+        assert_eq!(size_of_val(&bytes), 16);
+        assert_eq!(bytes.deep_size_of(), 16 + 24 + 3); // DataType + Vec + 3 bytes
 
         assert_eq!(size_of_val(&rec), 24);
         assert_eq!(rec.size_of(), 24 + 3 * 16);
@@ -1,5 +1,5 @@
 use noria::internal::LocalNodeIndex;
-use noria::DataType;
+use noria::{Collation, DataType};
 use petgraph::graph::NodeIndex;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
@@ -166,4 +166,25 @@ impl<'a> KeyType<'a> {
             _ => unimplemented!(),
         }
     }
+
+    /// The values of this key, in column order.
+    pub fn values(&self) -> Vec<&DataType> {
+        match *self {
+            KeyType::Single(k) => vec![k],
+            KeyType::Double((ref a, ref b)) => vec![a, b],
+            KeyType::Tri((ref a, ref b, ref c)) => vec![a, b, c],
+            KeyType::Quad((ref a, ref b, ref c, ref d)) => vec![a, b, c, d],
+            KeyType::Quin((ref a, ref b, ref c, ref d, ref e)) => vec![a, b, c, d, e],
+            KeyType::Sex((ref a, ref b, ref c, ref d, ref e, ref f)) => vec![a, b, c, d, e, f],
+        }
+    }
+
+    /// The values of this key as they are compared under the given collation of each column.
+    pub fn collate(&self, collations: &[Collation]) -> Vec<DataType> {
+        self.values()
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| Collation::of(collations, i).key(v).into_owned())
+            .collect()
+    }
 }
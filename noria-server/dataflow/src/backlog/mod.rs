@@ -1,37 +1,66 @@
 use common::SizeOf;
+use eviction::AccessTracker;
 use fnv::FnvBuildHasher;
 use prelude::*;
+use state;
 use std::borrow::Cow;
+use std::time;
 
 use rand::{Rng, ThreadRng};
 use std::sync::Arc;
 
 /// Allocate a new end-user facing result table.
 pub(crate) fn new(cols: usize, key: &[usize]) -> (SingleReadHandle, WriteHandle) {
-    new_inner(cols, key, None)
+    new_collated(cols, key, Vec::new())
+}
+
+/// Allocate a new end-user facing result table whose key columns are compared under the given
+/// collations.
+pub(crate) fn new_collated(
+    cols: usize,
+    key: &[usize],
+    collations: Vec<Collation>,
+) -> (SingleReadHandle, WriteHandle) {
+    new_inner(cols, key, None, EvictionPolicy::Random, None, collations)
 }
 
 /// Allocate a new partially materialized end-user facing result table.
 ///
 /// Misses in this table will call `trigger` to populate the entry, and retry until successful.
+/// Reads are tracked as needed to evict keys according to `eviction_policy`, and to expire keys
+/// that have not been read for `ttl`. Key columns are compared under the given collations, and
+/// `trigger` is given missed keys as they are compared.
 pub(crate) fn new_partial<F>(
     cols: usize,
     key: &[usize],
+    eviction_policy: EvictionPolicy,
+    ttl: Option<time::Duration>,
+    collations: Vec<Collation>,
     trigger: F,
 ) -> (SingleReadHandle, WriteHandle)
 where
     F: Fn(&[DataType]) + 'static + Send + Sync,
 {
-    new_inner(cols, key, Some(Arc::new(trigger)))
+    new_inner(
+        cols,
+        key,
+        Some(Arc::new(trigger)),
+        eviction_policy,
+        ttl,
+        collations,
+    )
 }
 
 fn new_inner(
     cols: usize,
     key: &[usize],
     trigger: Option<Arc<Fn(&[DataType]) + Send + Sync>>,
+    eviction_policy: EvictionPolicy,
+    ttl: Option<time::Duration>,
+    collations: Vec<Collation>,
 ) -> (SingleReadHandle, WriteHandle) {
     let contiguous = {
-        let mut contiguous = true;
+        let mut contiguous = !key.is_empty();
         let mut last = None;
         for &k in key {
             if let Some(last) = last {
@@ -57,25 +86,34 @@ fn new_inner(
         }};
     }
 
+    // a reader without a key holds the whole result under the empty key
     let (r, w) = match key.len() {
-        0 => unreachable!(),
         1 => make!(Single),
         2 => make!(Double),
         _ => make!(Many),
     };
 
+    let access = AccessTracker::new(eviction_policy).map(Arc::new);
+    let idle = ttl.map(|_| Arc::new(AccessTracker::by_time()));
     let w = WriteHandle {
         partial: trigger.is_some(),
         handle: w,
         key: Vec::from(key),
+        collations: collations.clone(),
         cols: cols,
         contiguous,
         mem_size: 0,
+        access: access.clone(),
+        ttl,
+        idle: idle.clone(),
     };
     let r = SingleReadHandle {
         handle: r,
         trigger: trigger,
         key: Vec::from(key),
+        collations,
+        access,
+        idle,
     };
 
     (r, w)
@@ -110,8 +148,14 @@ pub(crate) struct WriteHandle {
     partial: bool,
     cols: usize,
     key: Vec<usize>,
+    // The collation of each key column, or nothing if they are all binary.
+    collations: Vec<Collation>,
     contiguous: bool,
     mem_size: usize,
+    access: Option<Arc<AccessTracker>>,
+    // How long filled keys can go unread before they expire, and when they were last read.
+    ttl: Option<time::Duration>,
+    idle: Option<Arc<AccessTracker>>,
 }
 
 type Key<'a> = Cow<'a, [DataType]>;
@@ -131,6 +175,12 @@ impl<'a> MutWriteHandleEntry<'a> {
             .handle
             .meta_get_and(Cow::Borrowed(&*self.key), |rs| rs.is_empty())
         {
+            if let Some(ref access) = self.handle.access {
+                access.insert(&self.key);
+            }
+            if let Some(ref idle) = self.handle.idle {
+                idle.insert(&self.key);
+            }
             self.handle.handle.clear(self.key)
         } else {
             unreachable!("attempted to fill already-filled key");
@@ -147,6 +197,12 @@ impl<'a> MutWriteHandleEntry<'a> {
             .map(|r| r.0.unwrap_or(0))
             .unwrap_or(0);
         self.handle.mem_size = self.handle.mem_size.checked_sub(size as usize).unwrap();
+        if let Some(ref access) = self.handle.access {
+            access.remove(&self.key);
+        }
+        if let Some(ref idle) = self.handle.idle {
+            idle.remove(&self.key);
+        }
         self.handle.handle.empty(self.key)
     }
 }
@@ -196,10 +252,8 @@ impl WriteHandle {
     where
         K: Into<Key<'a>>,
     {
-        MutWriteHandleEntry {
-            handle: self,
-            key: key.into(),
-        }
+        let key = self.collate(key.into());
+        MutWriteHandleEntry { handle: self, key }
     }
 
     pub(crate) fn with_key<'a, K>(&'a self, key: K) -> WriteHandleEntry<'a>
@@ -207,8 +261,17 @@ impl WriteHandle {
         K: Into<Key<'a>>,
     {
         WriteHandleEntry {
+            key: self.collate(key.into()),
             handle: self,
-            key: key.into(),
+        }
+    }
+
+    /// The values of `key` as they are compared, and so stored, in this table.
+    fn collate<'a>(&self, key: Key<'a>) -> Key<'a> {
+        if self.collations.is_empty() {
+            key
+        } else {
+            Cow::Owned(state::collate_key(&key, &self.collations).into_owned())
         }
     }
 
@@ -240,7 +303,9 @@ impl WriteHandle {
     where
         I: IntoIterator<Item = Record>,
     {
-        let mem_delta = self.handle.add(&self.key[..], self.cols, rs);
+        let mem_delta = self
+            .handle
+            .add(&self.key[..], &self.collations[..], self.cols, rs);
         if mem_delta > 0 {
             self.mem_size += mem_delta as usize;
         } else if mem_delta < 0 {
@@ -255,9 +320,46 @@ impl WriteHandle {
         self.partial
     }
 
-    /// Evict `count` randomly selected keys from state and return them along with the number of
-    /// bytes that will be freed once the underlying `evmap` applies the operation.
-    pub fn evict_random_key(&mut self, rng: &mut ThreadRng) -> u64 {
+    /// Returns the earliest time at which a filled key may expire, if any will.
+    pub(crate) fn next_expiry(&self) -> Option<time::Instant> {
+        self.idle.as_ref()?.next_idle(self.ttl?)
+    }
+
+    /// Returns the filled keys that have gone unread for the TTL as of `now`.
+    ///
+    /// The keys are no longer considered for expiry, but remain filled until they are evicted.
+    pub(crate) fn expired_keys(&self, now: time::Instant) -> Vec<Vec<DataType>> {
+        match (self.ttl, &self.idle) {
+            (Some(ttl), &Some(ref idle)) => idle.idle_keys(ttl, now),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns up to `count` filled keys that the eviction policy would most like to keep, hottest
+    /// first.
+    ///
+    /// Without a policy, there is no notion of which keys are hot, so arbitrary keys are returned.
+    pub(crate) fn hot_keys(&self, count: usize) -> Vec<Vec<DataType>> {
+        match self.access {
+            Some(ref access) => access.hottest(count),
+            None => self.handle.keys().into_iter().take(count).collect(),
+        }
+    }
+
+    /// Evict up to `count` keys chosen by the eviction policy, and return the number of bytes that
+    /// will be freed once the underlying `evmap` applies the operation.
+    ///
+    /// Without a policy, only a single randomly selected key is evicted, since we could otherwise
+    /// count the same key twice.
+    pub fn evict_some_keys(&mut self, count: usize, rng: &mut ThreadRng) -> u64 {
+        if let Some(access) = self.access.clone() {
+            let before = self.mem_size;
+            for key in access.victims(count) {
+                self.mut_with_key(key).mark_hole();
+            }
+            return (before - self.mem_size) as u64;
+        }
+
         let mut bytes_to_be_freed = 0;
         if self.mem_size > 0 {
             if self.handle.is_empty() {
@@ -298,6 +400,9 @@ pub struct SingleReadHandle {
     handle: multir::Handle,
     trigger: Option<Arc<Fn(&[DataType]) + Send + Sync>>,
     key: Vec<usize>,
+    collations: Vec<Collation>,
+    access: Option<Arc<AccessTracker>>,
+    idle: Option<Arc<AccessTracker>>,
 }
 
 impl SingleReadHandle {
@@ -308,8 +413,9 @@ impl SingleReadHandle {
             "tried to trigger a replay for a fully materialized view"
         );
 
-        // trigger a replay to populate
-        (*self.trigger.as_ref().unwrap())(key);
+        // trigger a replay to populate, of the key as it is stored once filled
+        let key = state::collate_key(key, &self.collations);
+        (*self.trigger.as_ref().unwrap())(&key);
     }
 
     /// Find all entries that matched the given conditions.
@@ -324,12 +430,21 @@ impl SingleReadHandle {
     where
         F: FnMut(&[Vec<DataType>]) -> T,
     {
+        let key = state::collate_key(key, &self.collations);
+        let key = &key[..];
         self.handle
             .meta_get_and(key, &mut then)
             .ok_or(())
             .map(|(mut records, meta)| {
                 if records.is_none() && self.trigger.is_none() {
                     records = Some(then(&[]));
+                } else if records.is_some() {
+                    if let Some(ref access) = self.access {
+                        access.touch(key);
+                    }
+                    if let Some(ref idle) = self.idle {
+                        idle.touch(key);
+                    }
                 }
                 (records, meta)
             })
@@ -340,6 +455,11 @@ impl SingleReadHandle {
         self.handle.len()
     }
 
+    /// Returns true if this reader has no key, and so holds the whole result under `&[]`.
+    pub fn is_keyless(&self) -> bool {
+        self.key.is_empty()
+    }
+
     /// Count the number of rows in the reader.
     /// This is a potentially very costly operation, since it will
     /// hold up writers until all rows are iterated through.
@@ -372,7 +492,14 @@ impl ReadHandle {
         match *self {
             ReadHandle::Sharded(ref shards) => {
                 assert_eq!(key.len(), 1);
-                shards[::shard_by(&key[0], shards.len())]
+                // every shard compares the key under the same collation
+                let collation = shards
+                    .iter()
+                    .flat_map(Option::as_ref)
+                    .next()
+                    .map(|s| Collation::of(&s.collations, 0))
+                    .unwrap_or(Collation::Binary);
+                shards[::shard_by(&collation.key(&key[0]), shards.len())]
                     .as_ref()
                     .unwrap()
                     .try_find_and(key, then)
@@ -449,6 +576,102 @@ mod tests {
         );
     }
 
+    #[test]
+    fn keys_expire_after_ttl() {
+        let k: Vec<DataType> = vec![1.into()];
+        let ttl = Some(time::Duration::from_secs(10));
+        let (r, mut w) = new_partial(
+            1,
+            &[0],
+            EvictionPolicy::Random,
+            ttl,
+            Vec::new(),
+            |_: &[DataType]| (),
+        );
+        w.swap();
+
+        w.mut_with_key(&k[..]).mark_filled();
+        w.add(vec![Record::Positive(k.clone())]);
+        w.swap();
+        let filled = w.next_expiry().unwrap();
+        let early = filled - time::Duration::from_millis(1);
+        assert!(w.expired_keys(early).is_empty());
+
+        // reads put off the expiry
+        ::std::thread::sleep(time::Duration::from_millis(5));
+        assert_eq!(r.try_find_and(&k, |rs| rs.len()).unwrap().0, Some(1));
+        assert!(w.expired_keys(filled).is_empty());
+        let read = w.next_expiry().unwrap();
+        assert!(read > filled);
+        assert_eq!(w.expired_keys(read), vec![k.clone()]);
+        assert_eq!(w.next_expiry(), None);
+
+        // keys that are evicted before they expire are forgotten
+        w.mut_with_key(&k[..]).mark_hole();
+        w.swap();
+        w.mut_with_key(&k[..]).mark_filled();
+        assert!(w.next_expiry().is_some());
+        w.mut_with_key(&k[..]).mark_hole();
+        assert_eq!(w.next_expiry(), None);
+    }
+
+    #[test]
+    fn partial_store_fills_keys_as_collated() {
+        use std::sync::Mutex;
+
+        let triggered = Arc::new(Mutex::new(Vec::new()));
+        let (r, mut w) = {
+            let triggered = triggered.clone();
+            new_partial(
+                2,
+                &[1],
+                EvictionPolicy::Random,
+                None,
+                vec![Collation::AsciiCaseInsensitive],
+                move |k: &[DataType]| triggered.lock().unwrap().push(Vec::from(k)),
+            )
+        };
+        w.swap();
+
+        let bob: Vec<DataType> = vec!["Bob".into()];
+        assert_eq!(r.try_find_and(&bob, |rs| rs.len()).unwrap().0, None);
+        r.trigger(&bob);
+        let miss = triggered.lock().unwrap().pop().unwrap();
+        assert_eq!(miss, vec![DataType::from("bob")]);
+
+        // the replay fills the key it was triggered for, with rows in any case
+        w.mut_with_key(&miss[..]).mark_filled();
+        w.add(vec![Record::Positive(vec![1.into(), "BOB".into()])]);
+        w.swap();
+        assert_eq!(r.try_find_and(&bob, |rs| rs.len()).unwrap().0, Some(1));
+
+        w.mut_with_key(&bob[..]).mark_hole();
+        w.swap();
+        assert_eq!(r.try_find_and(&miss, |rs| rs.len()).unwrap().0, None);
+    }
+
+    #[test]
+    fn keyless_store_holds_everything() {
+        let a = vec![1.into(), "a".into()];
+        let b = vec![2.into(), "b".into()];
+
+        let (r, mut w) = new(2, &[]);
+        assert!(r.is_keyless());
+        w.add(vec![
+            Record::Positive(a.clone()),
+            Record::Positive(b.clone()),
+        ]);
+        w.swap();
+        assert_eq!(r.try_find_and(&[], |rs| rs.len()).unwrap().0, Some(2));
+
+        w.add(vec![Record::Negative(a.clone())]);
+        w.swap();
+        assert_eq!(
+            r.try_find_and(&[], |rs| rs.to_vec()).unwrap().0,
+            Some(vec![b.clone()])
+        );
+    }
+
     #[test]
     fn busybusybusy() {
         use std::thread;
@@ -2,6 +2,7 @@ use super::{key_to_double, key_to_single, Key};
 use evmap;
 use fnv::FnvBuildHasher;
 use prelude::*;
+use state::key_value;
 
 pub(super) enum Handle {
     Single(evmap::WriteHandle<DataType, Vec<DataType>, i64, FnvBuildHasher>),
@@ -44,6 +45,17 @@ impl Handle {
         }
     }
 
+    /// All keys in the map, as of the last refresh.
+    pub fn keys(&self) -> Vec<Vec<DataType>> {
+        let mut keys = Vec::new();
+        match *self {
+            Handle::Single(ref h) => h.for_each(|k, _| keys.push(vec![k.clone()])),
+            Handle::Double(ref h) => h.for_each(|k, _| keys.push(vec![k.0.clone(), k.1.clone()])),
+            Handle::Many(ref h) => h.for_each(|k, _| keys.push(k.clone())),
+        }
+        keys
+    }
+
     pub fn refresh(&mut self) {
         match *self {
             Handle::Single(ref mut h) => h.refresh(),
@@ -92,7 +104,7 @@ impl Handle {
         }
     }
 
-    pub fn add<I>(&mut self, key: &[usize], cols: usize, rs: I) -> isize
+    pub fn add<I>(&mut self, key: &[usize], collations: &[Collation], cols: usize, rs: I) -> isize
     where
         I: IntoIterator<Item = Record>,
     {
@@ -102,10 +114,11 @@ impl Handle {
                 assert_eq!(key.len(), 1);
                 for r in rs {
                     debug_assert!(r.len() >= cols);
+                    let k = key_value(&r, key, collations, 0);
                     match r {
                         Record::Positive(r) => {
                             memory_delta += r.deep_size_of() as isize;
-                            h.insert(r[key[0]].clone(), r);
+                            h.insert(k, r);
                         }
                         Record::Negative(r) => {
                             // TODO: evmap will remove the empty vec for a key if we remove the
@@ -113,7 +126,7 @@ impl Handle {
                             // replay, which will produce an empty result. this will work, but is
                             // somewhat inefficient.
                             memory_delta -= r.deep_size_of() as isize;
-                            h.remove(r[key[0]].clone(), r);
+                            h.remove(k, r);
                         }
                     }
                 }
@@ -122,14 +135,18 @@ impl Handle {
                 assert_eq!(key.len(), 2);
                 for r in rs {
                     debug_assert!(r.len() >= cols);
+                    let k = (
+                        key_value(&r, key, collations, 0),
+                        key_value(&r, key, collations, 1),
+                    );
                     match r {
                         Record::Positive(r) => {
                             memory_delta += r.deep_size_of() as isize;
-                            h.insert((r[key[0]].clone(), r[key[1]].clone()), r);
+                            h.insert(k, r);
                         }
                         Record::Negative(r) => {
                             memory_delta -= r.deep_size_of() as isize;
-                            h.remove((r[key[0]].clone(), r[key[1]].clone()), r);
+                            h.remove(k, r);
                         }
                     }
                 }
@@ -137,7 +154,9 @@ impl Handle {
             Handle::Many(ref mut h) => {
                 for r in rs {
                     debug_assert!(r.len() >= cols);
-                    let key = key.iter().map(|&k| &r[k]).cloned().collect();
+                    let key = (0..key.len())
+                        .map(|i| key_value(&r, key, collations, i))
+                        .collect();
                     match r {
                         Record::Positive(r) => {
                             memory_delta += r.deep_size_of() as isize;
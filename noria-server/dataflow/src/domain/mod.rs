@@ -18,6 +18,7 @@ pub use noria::internal::DomainIndex as Index;
 use payload::{ControlReplyPacket, ReplayPieceContext};
 use prelude::*;
 use slog::Logger;
+use state::collate_key;
 use stream_cancel::Valve;
 
 use timekeeper::{RealTime, SimpleTracker, ThreadTime, Timer, TimerSet};
@@ -44,10 +45,14 @@ pub enum ProcessResult {
 pub struct Config {
     pub concurrent_replays: usize,
     pub replay_batch_timeout: time::Duration,
+    pub eviction_policy: EvictionPolicy,
 }
 
 const BATCH_SIZE: usize = 256;
 
+// How often nodes with a memory budget are checked against it.
+const MEMORY_BUDGET_CHECK_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
 #[derive(Debug)]
 enum DomainMode {
     Forwarding,
@@ -184,6 +189,11 @@ impl DomainBuilder {
             replay_request_queue: Default::default(),
             delayed_for_self: Default::default(),
 
+            eviction_policy: self.config.eviction_policy,
+            expiring_readers: Vec::new(),
+            memory_budgets: Map::default(),
+            budgets_checked: None,
+
             group_commit_queues,
 
             state_size: state_size,
@@ -230,6 +240,14 @@ pub struct Domain {
     replay_batch_timeout: time::Duration,
     delayed_for_self: VecDeque<Box<Packet>>,
 
+    eviction_policy: EvictionPolicy,
+    // Readers whose keys expire some time after they are filled.
+    expiring_readers: Vec<LocalNodeIndex>,
+    // The most memory that the state of some nodes may use, in bytes, and when the nodes were
+    // last checked against it (`None` if they should be checked right away).
+    memory_budgets: Map<usize>,
+    budgets_checked: Option<time::Instant>,
+
     group_commit_queues: GroupCommitQueueSet,
 
     state_size: Arc<AtomicUsize>,
@@ -241,6 +259,54 @@ pub struct Domain {
 }
 
 impl Domain {
+    /// Replay the given keys into a reader, except for those the reader already has. Returns the
+    /// number of keys that are missing from the reader.
+    fn request_reader_replay(
+        &mut self,
+        keys: Vec<Vec<DataType>>,
+        cols: &[usize],
+        node: LocalNodeIndex,
+    ) -> usize {
+        // the reader could have raced with us filling in the keys after some *other* reader
+        // requested them, so let's double check that they indeed still miss!
+        let missing: Vec<_> = self.nodes[node]
+            .borrow_mut()
+            .with_reader_mut(|r| {
+                // keys are replayed and filled as the reader compares them
+                let collations = r.collations().to_vec();
+                let w = r
+                    .writer_mut()
+                    .expect("reader replay requested for non-materialized reader");
+                // ensure that all writes have been applied
+                w.swap();
+                keys.into_iter()
+                    .map(|key| collate_key(&key, &collations).into_owned())
+                    .filter(|key| {
+                        w.with_key(&key[..])
+                            .try_find_and(|_| ())
+                            .expect("reader replay requested for non-ready reader")
+                            .0
+                            .is_none()
+                    })
+                    .collect()
+            })
+            .expect("reader replay requested for non-reader node");
+
+        let n = missing.len();
+        for key in missing {
+            // ensure that we haven't already requested a replay of this key
+            if self
+                .reader_triggered
+                .entry(node)
+                .or_default()
+                .insert(key.clone())
+            {
+                self.find_tags_and_replay(key, cols, node);
+            }
+        }
+        n
+    }
+
     fn find_tags_and_replay(
         &mut self,
         miss_key: Vec<DataType>,
@@ -357,6 +423,14 @@ impl Domain {
 
     fn send_partial_replay_request(&mut self, tag: Tag, key: Vec<DataType>) {
         debug_assert!(self.concurrent_replays < self.max_concurrent_replays);
+        // the source shards rows under the collation of the column that our path starts with
+        let collation = {
+            let start = &self.replay_paths[&tag].path[0];
+            match start.partial_key {
+                Some(ref k) => Collation::of(self.nodes[start.node].borrow().collations(), k[0]),
+                None => Collation::Binary,
+            }
+        };
         if let TriggerEndpoint::End {
             ask_all,
             ref mut options,
@@ -391,7 +465,7 @@ impl Domain {
                 0
             } else {
                 assert_eq!(key.len(), 1);
-                ::shard_by(&key[0], options.len())
+                ::shard_by(&collation.key(&key[0]), options.len())
             };
             self.concurrent_replays += 1;
             trace!(self.log, "sending replay request";
@@ -746,7 +820,15 @@ impl Domain {
                     Packet::RemoveNodes { nodes } => {
                         for &node in &nodes {
                             self.nodes[node].borrow_mut().remove();
-                            self.state.remove(node);
+                            if let Some(state) = self.state.remove(node) {
+                                // the node is gone for good, so its state need not survive either
+                                if let Err(e) = state.destroy() {
+                                    error!(self.log, "failed to destroy state of removed node";
+                                           "local" => node.id(),
+                                           "error" => e);
+                                }
+                            }
+                            self.memory_budgets.remove(node);
                             trace!(self.log, "node removed"; "local" => node.id());
                         }
 
@@ -802,6 +884,11 @@ impl Domain {
                             }
                         });
                     }
+                    Packet::RemoveEgressTx { node, dst } => {
+                        self.nodes[node]
+                            .borrow_mut()
+                            .with_egress_mut(move |e| e.remove_tx(dst));
+                    }
                     Packet::UpdateSharder { node, new_txs } => {
                         let mut n = self.nodes[node].borrow_mut();
                         n.with_sharder_mut(move |s| {
@@ -814,22 +901,35 @@ impl Domain {
                             .unwrap();
                     }
                     Packet::StateSizeProbe { node } => {
+                        // readers keep their own state, and don't count its rows
                         let row_count = self.state.get(node).map(|state| state.rows()).unwrap_or(0);
-                        let mem_size = self
-                            .state
-                            .get(node)
-                            .map(|state| state.deep_size_of())
-                            .unwrap_or(0);
+                        let mem_size = self.mem_size(node);
                         self.control_reply_tx
                             .send(ControlReplyPacket::StateSize(row_count, mem_size))
                             .unwrap();
                     }
+                    Packet::SetMemoryBudget { node, max_bytes } => {
+                        match max_bytes {
+                            Some(max_bytes) => {
+                                self.memory_budgets.insert(node, max_bytes);
+                            }
+                            None => {
+                                self.memory_budgets.remove(node);
+                            }
+                        }
+                        self.budgets_checked = None;
+                    }
                     Packet::PrepareState { node, state } => {
                         use payload::InitialState;
                         match state {
                             InitialState::PartialLocal(index) => {
                                 if !self.state.contains_key(node) {
-                                    self.state.insert(node, box MemoryState::default());
+                                    let collations =
+                                        self.nodes[node].borrow().collations().to_vec();
+                                    let state = MemoryState::default()
+                                        .with_collations(collations)
+                                        .with_eviction_policy(self.eviction_policy);
+                                    self.state.insert(node, box state);
                                 }
                                 let state = self.state.get_mut(node).unwrap();
                                 for (key, tags) in index {
@@ -839,9 +939,41 @@ impl Domain {
                                     state.add_key(&key[..], Some(tags));
                                 }
                             }
+                            InitialState::PersistentPartialLocal(index) => {
+                                if !self.state.contains_key(node) {
+                                    let name = format!(
+                                        "{}-partial-{}-{}",
+                                        self.persistence_parameters.log_prefix,
+                                        self.nodes[node].borrow().global_addr().index(),
+                                        self.shard.unwrap_or(0),
+                                    );
+                                    let collations =
+                                        self.nodes[node].borrow().collations().to_vec();
+                                    let state = PersistentState::new(
+                                        name,
+                                        None,
+                                        &self.persistence_parameters,
+                                    )
+                                    .with_collations(collations)
+                                    .without_durability();
+                                    self.state.insert(node, box state);
+                                }
+                                let state = self.state.get_mut(node).unwrap();
+                                for (key, tags) in index {
+                                    info!(self.log, "told to prepare persistent partial state";
+                                           "key" => ?key,
+                                           "tags" => ?tags);
+                                    state.add_key(&key[..], Some(tags));
+                                }
+                            }
                             InitialState::IndexedLocal(index) => {
                                 if !self.state.contains_key(node) {
-                                    self.state.insert(node, box MemoryState::default());
+                                    let collations =
+                                        self.nodes[node].borrow().collations().to_vec();
+                                    let state = MemoryState::default()
+                                        .with_collations(collations)
+                                        .with_eviction_policy(self.eviction_policy);
+                                    self.state.insert(node, box state);
                                 }
                                 let state = self.state.get_mut(node).unwrap();
                                 for idx in index {
@@ -850,6 +982,35 @@ impl Domain {
                                     state.add_key(&idx[..], None);
                                 }
                             }
+                            InitialState::SpillingLocal {
+                                index,
+                                memory_limit,
+                            } => {
+                                if !self.state.contains_key(node) {
+                                    let name = format!(
+                                        "{}-spill-{}-{}",
+                                        self.persistence_parameters.log_prefix,
+                                        self.nodes[node].borrow().global_addr().index(),
+                                        self.shard.unwrap_or(0),
+                                    );
+                                    let collations =
+                                        self.nodes[node].borrow().collations().to_vec();
+                                    let state = SpillingState::new(
+                                        name,
+                                        memory_limit,
+                                        &self.persistence_parameters,
+                                    )
+                                    .with_collations(collations);
+                                    self.state.insert(node, box state);
+                                }
+                                let state = self.state.get_mut(node).unwrap();
+                                for idx in index {
+                                    info!(self.log, "told to prepare spilling state";
+                                           "key" => ?idx,
+                                           "memory_limit" => memory_limit);
+                                    state.add_key(&idx[..], None);
+                                }
+                            }
                             InitialState::PartialGlobal {
                                 gid,
                                 cols,
@@ -891,8 +1052,17 @@ impl Domain {
                                         tx
                                     })
                                     .collect::<Vec<_>>();
-                                let (r_part, w_part) =
-                                    backlog::new_partial(cols, &k[..], move |miss| {
+                                let (ttl, collations) = self.nodes[node]
+                                    .borrow()
+                                    .with_reader(|r| (r.ttl(), r.collations().to_vec()))
+                                    .unwrap();
+                                let (r_part, w_part) = backlog::new_partial(
+                                    cols,
+                                    &k[..],
+                                    self.eviction_policy,
+                                    ttl,
+                                    collations,
+                                    move |miss| {
                                         let n = txs.len();
                                         let tx = if n == 1 {
                                             &txs[0]
@@ -902,7 +1072,8 @@ impl Domain {
                                             &txs[::shard_by(&miss[0], n)]
                                         };
                                         tx.unbounded_send(Vec::from(miss)).unwrap();
-                                    });
+                                    },
+                                );
 
                                 let mut n = self.nodes[node].borrow_mut();
                                 n.with_reader_mut(|r| {
@@ -921,12 +1092,19 @@ impl Domain {
                                     r.set_write_handle(w_part)
                                 })
                                 .unwrap();
+
+                                if ttl.is_some() {
+                                    self.expiring_readers.push(node);
+                                }
                             }
                             InitialState::Global { gid, cols, key } => {
                                 use backlog;
-                                let (r_part, w_part) = backlog::new(cols, &key[..]);
-
                                 let mut n = self.nodes[node].borrow_mut();
+                                let collations =
+                                    n.with_reader(|r| r.collations().to_vec()).unwrap();
+                                let (r_part, w_part) =
+                                    backlog::new_collated(cols, &key[..], collations);
+
                                 n.with_reader_mut(|r| {
                                     assert!(
                                         self.readers
@@ -1015,34 +1193,36 @@ impl Domain {
                         );
                     }
                     Packet::RequestReaderReplay { key, cols, node } => {
-                        // the reader could have raced with us filling in the key after some
-                        // *other* reader requested it, so let's double check that it indeed still
-                        // misses!
-                        let still_miss = self.nodes[node]
-                            .borrow_mut()
-                            .with_reader_mut(|r| {
-                                let w = r
-                                    .writer_mut()
-                                    .expect("reader replay requested for non-materialized reader");
-                                // ensure that all writes have been applied
-                                w.swap();
-                                w.with_key(&key[..])
-                                    .try_find_and(|_| ())
-                                    .expect("reader replay requested for non-ready reader")
-                                    .0
-                                    .is_none()
+                        self.request_reader_replay(vec![key], &cols[..], node);
+                    }
+                    Packet::WarmReader { node, keys } => {
+                        let (partial, cols) = self.nodes[node]
+                            .borrow()
+                            .with_reader(|r| {
+                                let partial = r.writer().map(|w| w.is_partial()).unwrap_or(false);
+                                (partial, r.key().map(Vec::from).unwrap_or_default())
                             })
-                            .expect("reader replay requested for non-reader node");
-
-                        // ensure that we haven't already requested a replay of this key
-                        if still_miss && self
-                            .reader_triggered
-                            .entry(node)
-                            .or_default()
-                            .insert(key.clone())
-                        {
-                            self.find_tags_and_replay(key, &cols[..], node);
-                        }
+                            .expect("warming non-reader node");
+
+                        let total = keys.len();
+                        let pending = if partial {
+                            self.request_reader_replay(keys, &cols[..], node)
+                        } else {
+                            0
+                        };
+
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::Warmed(total - pending, pending))
+                            .unwrap();
+                    }
+                    Packet::HotReaderKeys { node, count } => {
+                        let keys = self.nodes[node]
+                            .borrow()
+                            .with_reader(|r| r.hot_keys(count))
+                            .expect("asked for hot keys of non-reader node");
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::Keys(keys))
+                            .unwrap();
                     }
                     Packet::RequestPartialReplay { tag, key } => {
                         trace!(
@@ -1205,8 +1385,10 @@ impl Domain {
                                         );
 
                                         box PersistentState::new(base_name, base.key(), &params)
+                                            .with_collations(base.collations().to_vec())
                                     }
-                                    _ => box MemoryState::default(),
+                                    _ => box MemoryState::default()
+                                        .with_collations(n.collations().to_vec()),
                                 }
                             };
                             for idx in index {
@@ -1632,6 +1814,16 @@ impl Domain {
                         .with_reader(|r| r.is_materialized())
                         .unwrap_or(false);
                     let dst_is_target = !self.nodes[dst].borrow().is_sender();
+                    // replayed keys are compared under the collation of the destination's key, so
+                    // that keys which only differ in case under a case-insensitive collation are
+                    // replayed, filled and missed on as one
+                    let key_collations = path
+                        .last()
+                        .unwrap()
+                        .partial_key
+                        .as_ref()
+                        .map(|k| Collation::of_columns(self.nodes[dst].borrow().collations(), k))
+                        .unwrap_or_default();
 
                     if dst_is_target {
                         // prune keys and data for keys we're not waiting for
@@ -1665,10 +1857,14 @@ impl Domain {
                                 // yet
                                 let partial_keys =
                                     path.first().unwrap().partial_key.as_ref().unwrap();
+                                let for_keys: HashSet<_> = for_keys
+                                    .iter()
+                                    .map(|k| collate_key(k, &key_collations).into_owned())
+                                    .collect();
                                 data.retain(|r| {
-                                    for_keys.iter().any(|k| {
-                                        partial_keys.iter().enumerate().all(|(i, c)| r[*c] == k[i])
-                                    })
+                                    let k: Vec<_> =
+                                        partial_keys.iter().map(|&c| r[c].clone()).collect();
+                                    for_keys.contains(&collate_key(&k, &key_collations)[..])
                                 });
                             }
                         }
@@ -1763,7 +1959,7 @@ impl Domain {
                             let mut missed_on = HashSet::with_capacity(misses.len());
                             for miss in &misses {
                                 let k: Vec<_> = miss.replay_key_vec().unwrap();
-                                missed_on.insert(k);
+                                missed_on.insert(collate_key(&k, &key_collations).into_owned());
                             }
                             missed_on
                         } else {
@@ -1877,7 +2073,8 @@ impl Domain {
                             for miss in misses {
                                 need_replay.push((
                                     miss.on,
-                                    miss.replay_key_vec().unwrap(),
+                                    collate_key(&miss.replay_key_vec().unwrap(), &key_collations)
+                                        .into_owned(),
                                     miss.lookup_key_vec(),
                                     miss.lookup_idx,
                                     tag,
@@ -1885,10 +2082,9 @@ impl Domain {
                             }
 
                             // we should only finish the replays for keys that *didn't* miss
-                            backfill_keys
-                                .as_mut()
-                                .unwrap()
-                                .retain(|k| !missed_on.contains(k));
+                            backfill_keys.as_mut().unwrap().retain(|k| {
+                                !missed_on.contains(&collate_key(k, &key_collations)[..])
+                            });
 
                             // prune all replayed records for keys where any replayed record for
                             // that key missed.
@@ -1899,12 +2095,9 @@ impl Domain {
                                     // bunch here? what if two key columns are reordered?
                                     // XXX: this clone and collect here is *really* sad
                                     let r = r.rec();
-                                    !missed_on.contains(
-                                        &partial_col
-                                            .iter()
-                                            .map(|&c| r[c].clone())
-                                            .collect::<Vec<_>>(),
-                                    )
+                                    let k: Vec<_> =
+                                        partial_col.iter().map(|&c| r[c].clone()).collect();
+                                    !missed_on.contains(&collate_key(&k, &key_collations)[..])
                                 })
                             });
                         }
@@ -2310,12 +2503,12 @@ impl Domain {
                         if self.nodes[node].borrow().is_dropped() {
                             break; // Node was dropped. Give up.
                         } else if self.nodes[node].borrow().is_reader() {
-                            // we can only evict one key a time here because the freed memory
+                            // we can only evict one random key a time here because the freed memory
                             // calculation is based on the key that *will* be evicted. We may count
-                            // the same individual key twice if we batch evictions here.
+                            // the same individual key twice if we batch random evictions here.
                             let freed_now = self.nodes[node]
                                 .borrow_mut()
-                                .with_reader_mut(|r| r.evict_random_key())
+                                .with_reader_mut(|r| r.evict_some_keys(100))
                                 .unwrap();
 
                             freed += freed_now;
@@ -2324,7 +2517,7 @@ impl Domain {
                             }
                         } else {
                             let (key_columns, keys, bytes) = {
-                                let k = self.state[node].evict_random_keys(100);
+                                let k = self.state[node].evict_some_keys(100);
                                 (k.0.to_vec(), k.1, k.2)
                             };
                             freed += bytes;
@@ -2456,6 +2649,146 @@ impl Domain {
         // no response sent, as worker will read the atomic
     }
 
+    /// Returns how long it will be until the next key in a reader expires, if any will.
+    fn duration_until_expiry(&self) -> Option<time::Duration> {
+        let now = time::Instant::now();
+        self.expiring_readers
+            .iter()
+            .filter_map(|&ni| {
+                self.nodes[ni]
+                    .borrow()
+                    .with_reader(|r| r.next_expiry())
+                    .unwrap()
+            })
+            .min()
+            .map(|at| {
+                if at > now {
+                    at.duration_since(now)
+                } else {
+                    time::Duration::from_millis(0)
+                }
+            })
+    }
+
+    /// The number of bytes used by the state of the given node.
+    fn mem_size(&self, node: LocalNodeIndex) -> u64 {
+        let n = self.nodes[node].borrow();
+        if n.is_reader() {
+            n.with_reader(|r| r.state_size().unwrap_or(0)).unwrap()
+        } else {
+            self.state
+                .get(node)
+                .map(|state| state.deep_size_of())
+                .unwrap_or(0)
+        }
+    }
+
+    /// Returns how long it will be until nodes with a memory budget are checked against it, if
+    /// there are any.
+    fn duration_until_budget_check(&self) -> Option<time::Duration> {
+        if self.memory_budgets.is_empty() {
+            return None;
+        }
+        Some(match self.budgets_checked {
+            Some(checked) => MEMORY_BUDGET_CHECK_INTERVAL
+                .checked_sub(checked.elapsed())
+                .unwrap_or(time::Duration::from_millis(0)),
+            None => time::Duration::from_millis(0),
+        })
+    }
+
+    /// Evict from the nodes whose state has grown beyond their memory budget.
+    ///
+    /// Nodes are checked at most every `MEMORY_BUDGET_CHECK_INTERVAL`, so that keys that were
+    /// just filled can be read before they are evicted again.
+    fn enforce_memory_budgets(&mut self, sends: &mut EnqueuedSends) {
+        if self.duration_until_budget_check() != Some(time::Duration::from_millis(0)) {
+            return;
+        }
+        self.budgets_checked = Some(time::Instant::now());
+
+        let over: Vec<_> = self
+            .memory_budgets
+            .iter()
+            .filter(|&(node, _)| !self.nodes[node].borrow().is_dropped())
+            .filter_map(|(node, &budget)| {
+                let size = self.mem_size(node) as usize;
+                if size > budget {
+                    Some((node, size - budget))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for (node, num_bytes) in over {
+            debug!(self.log, "node over memory budget; evicting";
+                   "node" => node.id(),
+                   "bytes" => num_bytes);
+            self.handle_eviction(
+                box Packet::Evict {
+                    node: Some(node),
+                    num_bytes,
+                },
+                sends,
+            );
+        }
+    }
+
+    /// Evict the keys in readers that have gone unread for the reader's TTL.
+    ///
+    /// The keys are evicted along one of the reader's replay paths, like keys evicted upstream
+    /// would be.
+    fn expire_reader_keys(&mut self, sends: &mut EnqueuedSends) {
+        if self.expiring_readers.is_empty() {
+            return;
+        }
+
+        let now = time::Instant::now();
+        for i in 0..self.expiring_readers.len() {
+            let node = self.expiring_readers[i];
+            let expired = {
+                let n = self.nodes[node].borrow();
+                if n.is_dropped() {
+                    continue;
+                }
+                n.with_reader(|r| r.expired_keys(now)).unwrap()
+            };
+            if expired.is_empty() {
+                continue;
+            }
+
+            // the keys were filled by replays, so they are evicted along the paths that end at
+            // the reader, of which there may be several if it is filled from more than one place
+            let tags: Vec<_> = self
+                .replay_paths
+                .iter()
+                .filter(|&(_, path)| path.path.last().map(|s| s.node) == Some(node))
+                .map(|(&tag, _)| tag)
+                .collect();
+            if tags.is_empty() {
+                warn!(self.log, "not evicting expired reader keys without a replay path";
+                      "node" => node.id(),
+                      "keys" => expired.len());
+                continue;
+            }
+
+            trace!(self.log, "evicting expired reader keys";
+                   "node" => node.id(),
+                   "keys" => expired.len(),
+                   "tags" => tags.len());
+            for tag in tags {
+                self.handle_eviction(
+                    box Packet::EvictKeys {
+                        link: Link::new(node, node),
+                        tag,
+                        keys: expired.clone(),
+                    },
+                    sends,
+                );
+            }
+        }
+    }
+
     pub fn on_event(
         &mut self,
         executor: &mut Executor,
@@ -2466,8 +2799,8 @@ impl Domain {
         //self.total_time.start();
         //self.total_ptime.start();
         let res = match event {
-            PollEvent::ResumePolling => ProcessResult::KeepPolling(
-                self.group_commit_queues.duration_until_flush().or_else(|| {
+            PollEvent::ResumePolling => {
+                let timeout = self.group_commit_queues.duration_until_flush().or_else(|| {
                     let now = time::Instant::now();
                     self.buffered_replay_requests
                         .iter()
@@ -2478,8 +2811,14 @@ impl Domain {
                                 .unwrap_or(time::Duration::from_millis(0))
                         })
                         .min()
-                }),
-            ),
+                });
+                let wakeups = [
+                    timeout,
+                    self.duration_until_expiry(),
+                    self.duration_until_budget_check(),
+                ];
+                ProcessResult::KeepPolling(wakeups.iter().filter_map(|&d| d).min())
+            }
             PollEvent::Process(packet) => {
                 if let Packet::Quit = *packet {
                     return ProcessResult::StopPolling;
@@ -2499,6 +2838,8 @@ impl Domain {
                 while let Some(m) = self.group_commit_queues.flush_if_necessary() {
                     self.handle(m, sends, executor, true);
                 }
+                self.expire_reader_keys(sends);
+                self.enforce_memory_budgets(sends);
 
                 ProcessResult::Processed
             }
@@ -2506,6 +2847,8 @@ impl Domain {
                 while let Some(m) = self.group_commit_queues.flush_if_necessary() {
                     self.handle(m, sends, executor, true);
                 }
+                self.expire_reader_keys(sends);
+                self.enforce_memory_budgets(sends);
 
                 if self.has_buffered_replay_requests {
                     self.handle(box Packet::Spin, sends, executor, true);
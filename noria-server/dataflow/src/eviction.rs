@@ -0,0 +1,423 @@
+use fnv::{FnvBuildHasher, FnvHasher};
+use std::cmp::{self, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use prelude::*;
+
+/// Decides which keys of a partially materialized state are evicted when its domain goes over
+/// its memory budget.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evict keys chosen uniformly at random.
+    Random,
+    /// Evict the keys that were least recently read.
+    Lru,
+    /// Evict the keys that were least frequently read. Read counts are halved after every round
+    /// of evictions, so that keys that were popular a long time ago eventually become cold.
+    Lfu,
+}
+
+impl Default for EvictionPolicy {
+    fn default() -> Self {
+        EvictionPolicy::Random
+    }
+}
+
+pub(crate) fn hash_key<'a, I>(key: I) -> u64
+where
+    I: IntoIterator<Item = &'a DataType>,
+{
+    let mut h = FnvHasher::default();
+    for k in key {
+        k.hash(&mut h);
+    }
+    h.finish()
+}
+
+pub(crate) fn hash_key_type(key: &KeyType) -> u64 {
+    // tuples hash their fields in order, so this agrees with `hash_key` for the same columns
+    let mut h = FnvHasher::default();
+    match *key {
+        KeyType::Single(k) => k.hash(&mut h),
+        KeyType::Double(ref k) => k.hash(&mut h),
+        KeyType::Tri(ref k) => k.hash(&mut h),
+        KeyType::Quad(ref k) => k.hash(&mut h),
+        KeyType::Quin(ref k) => k.hash(&mut h),
+        KeyType::Sex(ref k) => k.hash(&mut h),
+    }
+    h.finish()
+}
+
+// With `EvictionPolicy::Lfu`, a read is worth twice as much as a read in the round of evictions
+// before it, which ages old reads without touching every key after each round. To keep scores
+// from overflowing, they are scaled back down after this many rounds.
+const AGING_ROUNDS: usize = 32;
+
+fn millis(d: Duration) -> usize {
+    d.as_secs() as usize * 1000 + d.subsec_millis() as usize
+}
+
+struct Entry {
+    key: Vec<DataType>,
+    score: AtomicUsize,
+    // The score this key was last queued with in `Tracked::order`.
+    queued: usize,
+}
+
+#[derive(Default)]
+struct Tracked {
+    entries: HashMap<u64, Entry, FnvBuildHasher>,
+    // Tracked keys by the score they were queued with, coldest first. Scores only grow while a
+    // key is tracked, so a key is never colder than it was queued as, and keys that were read
+    // since they were queued are moved back as they come up. Keys that are no longer tracked (or
+    // have been queued again since) are skipped.
+    order: BinaryHeap<Reverse<(usize, u64)>>,
+}
+
+impl Tracked {
+    // Requeue every key with its current score, dropping the positions that are no longer used.
+    fn requeue(&mut self) {
+        let mut order = BinaryHeap::with_capacity(self.entries.len());
+        for (&hash, entry) in self.entries.iter_mut() {
+            entry.queued = entry.score.load(Ordering::Relaxed);
+            order.push(Reverse((entry.queued, hash)));
+        }
+        self.order = order;
+    }
+}
+
+/// Keeps track of how keys in a partially materialized state are read, so that an
+/// `EvictionPolicy` other than `Random` can pick which keys to evict.
+///
+/// Keys are tracked only between being filled and being evicted, and are kept ordered by how
+/// eagerly the policy would evict them, so that picking victims doesn't have to look at every
+/// key. Reads may come from several threads at once (as they do for readers), and only take a
+/// shared lock.
+pub(crate) struct AccessTracker {
+    policy: EvictionPolicy,
+    // The current time with `EvictionPolicy::Lru`, and the current round of evictions with
+    // `EvictionPolicy::Lfu`.
+    clock: AtomicUsize,
+    tracked: RwLock<Tracked>,
+    // For trackers that score keys by when they were last read in milliseconds since this time,
+    // rather than by `clock`.
+    started: Option<Instant>,
+}
+
+impl AccessTracker {
+    /// Construct a tracker for the given policy, or `None` if the policy does not need one.
+    pub(crate) fn new(policy: EvictionPolicy) -> Option<Self> {
+        match policy {
+            EvictionPolicy::Random => None,
+            EvictionPolicy::Lru | EvictionPolicy::Lfu => Some(AccessTracker {
+                policy,
+                clock: AtomicUsize::new(0),
+                tracked: Default::default(),
+                started: None,
+            }),
+        }
+    }
+
+    /// Construct a tracker that scores keys by when they were last read (or filled), so that keys
+    /// that have gone unread for some time can be expired.
+    pub(crate) fn by_time() -> Self {
+        AccessTracker {
+            policy: EvictionPolicy::Lru,
+            clock: AtomicUsize::new(0),
+            tracked: Default::default(),
+            started: Some(Instant::now()),
+        }
+    }
+
+    /// The score of a single read right now.
+    fn read_score(&self) -> usize {
+        if let Some(started) = self.started {
+            return millis(started.elapsed());
+        }
+        match self.policy {
+            EvictionPolicy::Lru => self.clock.fetch_add(1, Ordering::Relaxed),
+            _ => 1 << self.clock.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record(&self, hash: u64) {
+        let tracked = self.tracked.read().unwrap();
+        if let Some(entry) = tracked.entries.get(&hash) {
+            match self.policy {
+                EvictionPolicy::Lru => entry.score.store(self.read_score(), Ordering::Relaxed),
+                _ => {
+                    entry.score.fetch_add(self.read_score(), Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Start tracking a key that has just been filled. If the key is already tracked, this counts
+    /// as a read of it.
+    pub(crate) fn insert(&self, key: &[DataType]) {
+        let hash = hash_key(key);
+        if self.tracked.read().unwrap().entries.contains_key(&hash) {
+            return self.record(hash);
+        }
+
+        let score = self.read_score();
+        let mut tracked = self.tracked.write().unwrap();
+        tracked.entries.insert(
+            hash,
+            Entry {
+                key: key.to_vec(),
+                score: AtomicUsize::new(score),
+                queued: score,
+            },
+        );
+        tracked.order.push(Reverse((score, hash)));
+    }
+
+    /// Stop tracking a key that has been evicted.
+    pub(crate) fn remove(&self, key: &[DataType]) {
+        let mut tracked = self.tracked.write().unwrap();
+        if tracked.entries.remove(&hash_key(key)).is_some()
+            && tracked.order.len() > 2 * tracked.entries.len() + 64
+        {
+            // most of the queue is keys that are no longer tracked
+            tracked.requeue();
+        }
+    }
+
+    /// Record a read of the given key.
+    pub(crate) fn touch(&self, key: &[DataType]) {
+        self.record(hash_key(key))
+    }
+
+    /// Record a read of the given key.
+    pub(crate) fn touch_key_type(&self, key: &KeyType) {
+        self.record(hash_key_type(key))
+    }
+
+    /// Pick the (at most) `count` tracked keys that the policy would rather evict, and stop
+    /// tracking them.
+    pub(crate) fn victims(&self, count: usize) -> Vec<Vec<DataType>> {
+        let mut tracked = self.tracked.write().unwrap();
+        let tracked = &mut *tracked;
+        let victims = Self::take_coldest(tracked, count, usize::max_value());
+
+        if let EvictionPolicy::Lfu = self.policy {
+            // age the reads so far
+            if self.clock.fetch_add(1, Ordering::Relaxed) + 1 == AGING_ROUNDS {
+                self.clock.store(0, Ordering::Relaxed);
+                for entry in tracked.entries.values() {
+                    let n = entry.score.load(Ordering::Relaxed);
+                    entry.score.store(n >> AGING_ROUNDS, Ordering::Relaxed);
+                }
+                tracked.requeue();
+            }
+        }
+
+        victims
+    }
+
+    /// Returns the earliest time at which a tracked key may have gone unread for `idle`.
+    ///
+    /// Only for trackers constructed with `AccessTracker::by_time`.
+    pub(crate) fn next_idle(&self, idle: Duration) -> Option<Instant> {
+        let started = self.started.expect("only timed trackers have idle keys");
+        let tracked = self.tracked.read().unwrap();
+        tracked
+            .order
+            .peek()
+            .map(|&Reverse((queued, _))| started + Duration::from_millis(queued as u64) + idle)
+    }
+
+    /// Stop tracking the keys that have gone unread for at least `idle` as of `now`, and return
+    /// them.
+    ///
+    /// Only for trackers constructed with `AccessTracker::by_time`.
+    pub(crate) fn idle_keys(&self, idle: Duration, now: Instant) -> Vec<Vec<DataType>> {
+        let started = self.started.expect("only timed trackers have idle keys");
+        let (now, idle) = (millis(now.duration_since(started)), millis(idle));
+        if now < idle {
+            return Vec::new();
+        }
+
+        let mut tracked = self.tracked.write().unwrap();
+        Self::take_coldest(&mut tracked, usize::max_value(), now - idle + 1)
+    }
+
+    // Stop tracking the (at most) `count` coldest keys with a score below `below`, and return
+    // them.
+    fn take_coldest(tracked: &mut Tracked, count: usize, below: usize) -> Vec<Vec<DataType>> {
+        let mut victims = Vec::with_capacity(cmp::min(count, tracked.entries.len()));
+        while victims.len() < count {
+            let (queued, hash) = match tracked.order.peek() {
+                Some(&Reverse(e)) if e.0 < below => e,
+                _ => break,
+            };
+            tracked.order.pop();
+            let score = match tracked.entries.get_mut(&hash) {
+                Some(entry) => {
+                    if entry.queued != queued {
+                        continue;
+                    }
+                    entry.queued = entry.score.load(Ordering::Relaxed);
+                    entry.queued
+                }
+                None => continue,
+            };
+
+            if score == queued {
+                victims.push(tracked.entries.remove(&hash).unwrap().key);
+            } else {
+                // the key was read since it was queued
+                tracked.order.push(Reverse((score, hash)));
+            }
+        }
+        victims
+    }
+
+    /// Pick the (at most) `count` tracked keys that the policy would most like to keep, hottest
+    /// first.
+    pub(crate) fn hottest(&self, count: usize) -> Vec<Vec<DataType>> {
+        let tracked = self.tracked.read().unwrap();
+
+        // keep the `count` hottest keys seen so far, with the coldest of them on top
+        let mut hottest = BinaryHeap::with_capacity(count + 1);
+        for entry in tracked.entries.values() {
+            hottest.push(Reverse((entry.score.load(Ordering::Relaxed), &entry.key)));
+            if hottest.len() > count {
+                hottest.pop();
+            }
+        }
+
+        hottest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((_, key))| key.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(n: i32) -> Vec<Vec<DataType>> {
+        (0..n).map(|i| vec![i.into()]).collect()
+    }
+
+    #[test]
+    fn random_needs_no_tracking() {
+        assert!(AccessTracker::new(EvictionPolicy::Random).is_none());
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_read() {
+        let t = AccessTracker::new(EvictionPolicy::Lru).unwrap();
+        for k in keys(4) {
+            t.insert(&k);
+        }
+        t.touch(&[0.into()]);
+        t.touch_key_type(&KeyType::Single(&1.into()));
+
+        let mut victims = t.victims(2);
+        victims.sort();
+        assert_eq!(victims, vec![vec![2.into()], vec![3.into()]]);
+
+        // a key that is filled again after being evicted is tracked anew
+        t.insert(&[2.into()]);
+        t.touch(&[2.into()]);
+        assert_eq!(t.victims(1), vec![vec![0.into()]]);
+
+        // and keys that are no longer tracked are never picked
+        t.remove(&[1.into()]);
+        assert_eq!(t.victims(2), vec![vec![2.into()]]);
+        assert!(t.victims(1).is_empty());
+    }
+
+    #[test]
+    fn hottest_keys_are_kept_tracked() {
+        let t = AccessTracker::new(EvictionPolicy::Lru).unwrap();
+        for k in keys(4) {
+            t.insert(&k);
+        }
+        t.touch(&[1.into()]);
+        t.touch(&[3.into()]);
+
+        assert_eq!(t.hottest(2), vec![vec![3.into()], vec![1.into()]]);
+        // unlike picking victims, this doesn't stop tracking any keys
+        assert_eq!(t.hottest(2), vec![vec![3.into()], vec![1.into()]]);
+    }
+
+    #[test]
+    fn lfu_evicts_least_frequently_read() {
+        let t = AccessTracker::new(EvictionPolicy::Lfu).unwrap();
+        for k in keys(3) {
+            t.insert(&k);
+        }
+        for _ in 0..3 {
+            t.touch(&[0.into()]);
+        }
+        t.touch(&[2.into()]);
+        t.touch(&[2.into()]);
+        t.touch(&[1.into()]);
+
+        assert_eq!(t.victims(1), vec![vec![1.into()]]);
+        assert_eq!(t.victims(1), vec![vec![2.into()]]);
+
+        // reads after a round of evictions count for more than the ones before it
+        t.insert(&[3.into()]);
+        for _ in 0..2 {
+            t.touch(&[3.into()]);
+        }
+        assert_eq!(t.victims(1), vec![vec![0.into()]]);
+    }
+
+    #[test]
+    fn lfu_scales_scores_down() {
+        let t = AccessTracker::new(EvictionPolicy::Lfu).unwrap();
+        t.insert(&[0.into()]);
+        for _ in 0..AGING_ROUNDS - 1 {
+            assert!(t.victims(0).is_empty());
+        }
+        t.insert(&[1.into()]);
+        t.touch(&[1.into()]);
+        assert!(t.victims(0).is_empty());
+
+        // all reads but the last round's are forgotten
+        assert_eq!(t.clock.load(Ordering::Relaxed), 0);
+        assert_eq!(t.victims(1), vec![vec![0.into()]]);
+    }
+
+    #[test]
+    fn timed_tracker_finds_idle_keys() {
+        let t = AccessTracker::by_time();
+        let idle = Duration::from_secs(10);
+        t.insert(&[0.into()]);
+        let first = t.next_idle(idle).unwrap();
+        let early = first - Duration::from_millis(1);
+        assert!(t.idle_keys(idle, early).is_empty());
+
+        // reading a key restarts the time it has been idle
+        ::std::thread::sleep(Duration::from_millis(5));
+        t.touch(&[0.into()]);
+        assert!(t.idle_keys(idle, first).is_empty());
+        let next = t.next_idle(idle).unwrap();
+        assert!(next > first);
+
+        assert_eq!(t.idle_keys(idle, next), vec![vec![0.into()]]);
+        assert_eq!(t.next_idle(idle), None);
+    }
+
+    #[test]
+    fn key_types_hash_like_slices() {
+        let k: Vec<DataType> = vec![1.into(), "a".into()];
+        assert_eq!(
+            hash_key(&k),
+            hash_key_type(&KeyType::Double((k[0].clone(), k[1].clone())))
+        );
+        assert_eq!(hash_key(&k[..1]), hash_key_type(&KeyType::Single(&k[0])));
+    }
+}
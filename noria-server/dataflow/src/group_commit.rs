@@ -102,11 +102,11 @@ impl GroupCommitQueueSet {
 
                     assert_eq!(senders.len(), 0);
                     assert_eq!(merged_dst, dst);
-                    acc.extend(data);
 
                     if let Some(src) = src {
-                        all_senders.push(src);
+                        all_senders.push((src, data.len()));
                     }
+                    acc.extend(data);
 
                     match (&merged_tracer, tracer) {
                         (&Some((mtag, _)), Some((tag, Some(sender)))) => {
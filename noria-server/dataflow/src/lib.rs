@@ -41,6 +41,7 @@ pub mod prelude;
 pub mod state;
 
 mod domain;
+mod eviction;
 mod group_commit;
 mod processing;
 
@@ -54,6 +55,7 @@ pub type Readers =
 pub type DomainConfig = domain::Config;
 
 pub use domain::{Domain, DomainBuilder, Index, PollEvent, ProcessResult};
+pub use eviction::EvictionPolicy;
 pub use payload::Packet;
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
@@ -148,3 +150,4 @@ impl PersistenceParameters {
 }
 
 pub use noria::shard_by;
+pub use noria::JsonPath;
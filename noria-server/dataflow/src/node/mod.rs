@@ -28,6 +28,8 @@ pub struct Node {
     taken: bool,
 
     sharded_by: Sharding,
+    // The collation of each column, or nothing if they are all binary.
+    collations: Vec<Collation>,
 }
 
 // constructors
@@ -39,7 +41,7 @@ impl Node {
         FS: IntoIterator<Item = S2>,
         NT: Into<NodeType>,
     {
-        Node {
+        let mut n = Node {
             name: name.to_string(),
             index: None,
             domain: None,
@@ -50,15 +52,23 @@ impl Node {
             taken: false,
 
             sharded_by: Sharding::None,
+            collations: Vec::new(),
+        };
+        let collations = n.get_base().map(|b| b.collations().to_vec());
+        if let Some(collations) = collations {
+            n.set_collations(collations);
         }
+        n
     }
 
     pub fn mirror<NT: Into<NodeType>>(&self, n: NT) -> Node {
-        Self::new(&*self.name, &self.fields, n)
+        self.named_mirror(n, self.name.clone())
     }
 
     pub fn named_mirror<NT: Into<NodeType>>(&self, n: NT, name: String) -> Node {
-        Self::new(name, &self.fields, n)
+        let mut n = Self::new(name, &self.fields, n);
+        n.collations = self.collations.clone();
+        n
     }
 }
 
@@ -109,8 +119,10 @@ impl Node {
     pub fn on_commit(&mut self, remap: &HashMap<NodeIndex, IndexPair>) {
         // this is *only* overwritten for these asserts.
         assert!(!self.taken);
-        if let NodeType::Internal(ref mut i) = self.inner {
-            i.on_commit(self.index.unwrap().as_global(), remap)
+        match self.inner {
+            NodeType::Internal(ref mut i) => i.on_commit(self.index.unwrap().as_global(), remap),
+            NodeType::Base(ref mut b) => b.on_commit(remap),
+            _ => {}
         }
     }
 }
@@ -240,9 +252,16 @@ impl Node {
 
     pub fn add_column(&mut self, field: &str) -> usize {
         self.fields.push(field.to_string());
+        if !self.collations.is_empty() {
+            self.collations.push(Collation::Binary);
+        }
         self.fields.len() - 1
     }
 
+    pub fn rename_column(&mut self, column: usize, field: &str) {
+        self.fields[column] = field.to_string();
+    }
+
     pub fn name(&self) -> &str {
         &*self.name
     }
@@ -251,6 +270,44 @@ impl Node {
         &self.fields[..]
     }
 
+    /// Compare the values of each column under the given collation.
+    pub fn set_collations(&mut self, collations: Vec<Collation>) {
+        if collations.iter().all(|&c| c == Collation::Binary) {
+            self.collations = Vec::new();
+        } else {
+            self.collations = collations;
+        }
+    }
+
+    /// Compare each column of this internal node under the collation of the parent columns it
+    /// comes from, provided they all agree. Computed columns, and columns whose parents disagree,
+    /// are compared byte-wise.
+    pub fn inherit_collations(&mut self, graph: &Graph) {
+        let collations = (0..self.fields.len())
+            .map(|column| {
+                let mut from = self
+                    .parent_columns(column)
+                    .into_iter()
+                    .map(|(p, c)| match c {
+                        Some(c) => Collation::of(graph[p].collations(), c),
+                        None => Collation::Binary,
+                    });
+                let first = from.next().unwrap_or(Collation::Binary);
+                if from.all(|c| c == first) {
+                    first
+                } else {
+                    Collation::Binary
+                }
+            })
+            .collect();
+        self.set_collations(collations);
+    }
+
+    /// The collation of each column, or nothing if they are all binary.
+    pub fn collations(&self) -> &[Collation] {
+        &self.collations[..]
+    }
+
     pub fn has_domain(&self) -> bool {
         self.domain.is_some()
     }
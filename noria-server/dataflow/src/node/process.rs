@@ -20,6 +20,7 @@ impl Node {
         m.as_mut().unwrap().trace(PacketEvent::Process);
 
         let addr = self.local_addr();
+        let shards = self.sharded_by.shards().unwrap_or(1);
         match self.inner {
             NodeType::Ingress => {
                 let m = m.as_mut().unwrap();
@@ -37,7 +38,31 @@ impl Node {
                         src,
                         mut senders,
                     }) => {
-                        let Input { dst, data, tracer } = unsafe { inner.take() };
+                        let Input {
+                            dst,
+                            mut data,
+                            tracer,
+                        } = unsafe { inner.take() };
+                        let mut generated = b.assign_auto_increment(
+                            addr,
+                            &mut data[..],
+                            state,
+                            on_shard.unwrap_or(0),
+                            shards,
+                        );
+                        let rejected = b.reject_violations(
+                            addr,
+                            &self.fields[..],
+                            &mut data,
+                            &*state,
+                            &self.sharded_by,
+                        );
+                        for &(i, _) in &rejected {
+                            // rejected rows were never inserted
+                            if let Some(v) = generated.get_mut(i) {
+                                *v = None;
+                            }
+                        }
                         let mut rs = b.process(addr, data, &*state);
 
                         // When a replay originates at a base node, we replay the data *through* that
@@ -52,9 +77,25 @@ impl Node {
                         }
 
                         // Send write-ACKs to all the clients with updates that made
-                        // it into this merged packet:
+                        // it into this merged packet, along with any values we generated for
+                        // their inserts and any of their operations that we rejected:
                         if let Some(ex) = executor {
-                            senders.drain(..).for_each(|src| ex.send_back(src, ()));
+                            let mut generated = generated.into_iter();
+                            let mut rejected = rejected.into_iter().peekable();
+                            let mut offset = 0;
+                            senders.drain(..).for_each(|(src, n)| {
+                                let ours = generated.by_ref().take(n).filter_map(|v| v);
+                                let mut ack = InputAck {
+                                    generated: ours.collect(),
+                                    rejected: Vec::new(),
+                                };
+                                while rejected.peek().map(|&(i, _)| i < offset + n) == Some(true) {
+                                    let (i, violation) = rejected.next().unwrap();
+                                    ack.rejected.push((i - offset, violation));
+                                }
+                                offset += n;
+                                ex.send_back(src, ack)
+                            });
                         }
 
                         *m = Some(Box::new(Packet::Message {
@@ -62,7 +103,7 @@ impl Node {
                             src,
                             data: rs,
                             tracer,
-                            senders,
+                            senders: senders.into_iter().map(|(src, _)| src).collect(),
                         }));
                     }
                     Some(ref p) => {
@@ -1,10 +1,20 @@
-use noria::{Modification, Operation, TableOperation};
+use nom_sql::Operator;
+use noria::error::ConstraintViolation;
+use noria::{Condition, Modification, Operation, TableOperation};
 use prelude::*;
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use vec_map::VecMap;
 
+/// A foreign key from some columns of a base node to the columns they reference in another base.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ForeignKey {
+    columns: Vec<usize>,
+    parent: IndexPair,
+    parent_columns: Vec<usize>,
+}
+
 /// Base is used to represent the root nodes of the Noria data flow graph.
 ///
 /// These nodes perform no computation, and their job is merely to persist all received updates and
@@ -13,6 +23,14 @@ use vec_map::VecMap;
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Base {
     primary_key: Option<Vec<usize>>,
+    auto_increment: Option<usize>,
+    // the last value assigned to the auto-increment column, if we know it yet
+    #[serde(skip)]
+    last_auto_increment: Option<i64>,
+    not_null: Vec<usize>,
+    unique: Vec<Vec<usize>>,
+    foreign_keys: Vec<ForeignKey>,
+    collations: Vec<Collation>,
 
     defaults: Vec<DataType>,
     dropped: Vec<usize>,
@@ -37,6 +55,110 @@ impl Base {
         self.primary_key.as_ref().map(|cols| &cols[..])
     }
 
+    /// Builder with an `AUTO_INCREMENT` column, whose value is assigned by the base node for
+    /// inserted rows that leave it empty (i.e., `DataType::None`).
+    pub fn with_auto_increment(mut self, column: usize) -> Base {
+        self.auto_increment = Some(column);
+        self
+    }
+
+    pub fn auto_increment(&self) -> Option<usize> {
+        self.auto_increment
+    }
+
+    /// Builder with columns that may not be `NULL`.
+    ///
+    /// Operations that would leave one of these columns `NULL` are rejected.
+    pub fn with_not_null(mut self, columns: Vec<usize>) -> Base {
+        self.not_null = columns;
+        self
+    }
+
+    /// Builder with an additional `UNIQUE` key.
+    ///
+    /// Operations that would give two rows the same values in these columns are rejected. Rows
+    /// with `NULL` in any of the columns are exempt. A base is only sharded by a column that all
+    /// its unique keys include (see `can_shard_by`).
+    pub fn with_unique(mut self, columns: Vec<usize>) -> Base {
+        self.unique.push(columns);
+        self
+    }
+
+    pub fn unique_keys(&self) -> &[Vec<usize>] {
+        &self.unique[..]
+    }
+
+    /// Builder with a foreign key from `columns` to `parent_columns` of the base `parent`.
+    ///
+    /// Operations that would leave a row whose values in `columns` do not match any row in
+    /// `parent` are rejected. Rows with `NULL` in any of the columns are exempt. Only writes to
+    /// this base are checked; deleting or changing rows of `parent` is never rejected, even if it
+    /// leaves rows here that match no row in `parent`.
+    ///
+    /// The key can only be enforced if `parent` ends up in the same domain as this base, and
+    /// neither is sharded; new bases with foreign keys are never sharded, and join the domain of
+    /// an unsharded parent.
+    pub fn with_foreign_key(
+        mut self,
+        columns: Vec<usize>,
+        parent: NodeIndex,
+        parent_columns: Vec<usize>,
+    ) -> Base {
+        assert_eq!(columns.len(), parent_columns.len());
+        self.foreign_keys.push(ForeignKey {
+            columns,
+            parent: parent.into(),
+            parent_columns,
+        });
+        self
+    }
+
+    /// The bases referenced by foreign keys of this base, along with the referenced columns.
+    pub fn foreign_keys<'a>(&'a self) -> impl Iterator<Item = (NodeIndex, &'a [usize])> + 'a {
+        self.foreign_keys
+            .iter()
+            .map(|fk| (fk.parent.as_global(), &fk.parent_columns[..]))
+    }
+
+    /// Whether every key of this base can still be checked if the base is sharded by `column`.
+    ///
+    /// Each shard only sees its own rows, so all unique keys must include the sharding column, and
+    /// foreign keys can only be checked if the base is not sharded at all.
+    pub fn can_shard_by(&self, column: usize) -> bool {
+        self.foreign_keys.is_empty() && self.unique.iter().all(|cols| cols.contains(&column))
+    }
+
+    /// The bases referenced by foreign keys that this base cannot enforce because they are in a
+    /// different domain.
+    pub fn unenforced_foreign_keys<'a>(&'a self) -> impl Iterator<Item = NodeIndex> + 'a {
+        self.foreign_keys
+            .iter()
+            .filter(|fk| !fk.parent.has_local())
+            .map(|fk| fk.parent.as_global())
+    }
+
+    /// Builder with the collation of each column.
+    ///
+    /// Durable state for the base compares its keys under these collations, as do readers keyed
+    /// by these columns.
+    pub fn with_collations(mut self, collations: Vec<Collation>) -> Base {
+        self.collations = collations;
+        self
+    }
+
+    pub fn collations(&self) -> &[Collation] {
+        &self.collations[..]
+    }
+
+    pub fn on_commit(&mut self, remap: &HashMap<NodeIndex, IndexPair>) {
+        for fk in &mut self.foreign_keys {
+            // parents in other domains do not appear in the remap, and stay unenforced
+            if remap.contains_key(&fk.parent.as_global()) {
+                fk.parent.remap(remap);
+            }
+        }
+    }
+
     /// Add a new column to this base node.
     pub fn add_column(&mut self, default: DataType) -> usize {
         assert!(
@@ -45,6 +167,9 @@ impl Base {
              setting default values for initial columns"
         );
         self.defaults.push(default);
+        if !self.collations.is_empty() {
+            self.collations.push(Collation::Binary);
+        }
         self.unmodified = false;
         self.defaults.len() - 1
     }
@@ -91,6 +216,12 @@ impl Clone for Base {
     fn clone(&self) -> Base {
         Base {
             primary_key: self.primary_key.clone(),
+            auto_increment: self.auto_increment,
+            last_auto_increment: self.last_auto_increment,
+            not_null: self.not_null.clone(),
+            unique: self.unique.clone(),
+            foreign_keys: self.foreign_keys.clone(),
+            collations: self.collations.clone(),
 
             defaults: self.defaults.clone(),
             dropped: self.dropped.clone(),
@@ -103,6 +234,12 @@ impl Default for Base {
     fn default() -> Self {
         Base {
             primary_key: None,
+            auto_increment: None,
+            last_auto_increment: None,
+            not_null: Vec::new(),
+            unique: Vec::new(),
+            foreign_keys: Vec::new(),
+            collations: Vec::new(),
 
             defaults: Vec::new(),
             dropped: Vec::new(),
@@ -111,12 +248,40 @@ impl Default for Base {
     }
 }
 
+// unique and foreign keys are indexed by the materialization planner
+const UNINDEXED: &str = "key of base is not indexed";
+
 fn key_val(i: usize, col: usize, r: &TableOperation) -> &DataType {
     match *r {
         TableOperation::Insert(ref row) => &row[col],
         TableOperation::Delete { ref key } => &key[i],
         TableOperation::Update { ref key, .. } => &key[i],
         TableOperation::InsertOrUpdate { ref row, .. } => &row[col],
+        TableOperation::DeleteWhere { .. } | TableOperation::UpdateWhere { .. } => {
+            unreachable!("predicate operations are expanded before processing")
+        }
+    }
+}
+
+/// Apply the given column modifications to `row`.
+fn apply(row: &mut [DataType], set: &[Modification]) {
+    for (col, m) in set.iter().enumerate() {
+        match *m {
+            Modification::Set(ref v) => row[col] = v.clone(),
+            Modification::Apply(ref op, ref v) => {
+                // like arithmetic on NULL, applying to a value that is not a number gives NULL
+                let new = if row[col].is_numeric() {
+                    match *op {
+                        Operation::Add => &row[col] + v,
+                        Operation::Sub => &row[col] - v,
+                    }
+                } else {
+                    DataType::None
+                };
+                row[col] = new;
+            }
+            Modification::None => {}
+        }
     }
 }
 
@@ -127,11 +292,450 @@ fn key_of<'a>(key_cols: &'a [usize], r: &'a TableOperation) -> impl Iterator<Ite
         .map(move |(i, col)| key_val(i, *col, r))
 }
 
+/// Find all rows in `db` that satisfy every condition in `predicate`, using an index of `db`.
+///
+/// Returns `None` if no index of `db` is fully determined by equality conditions in the predicate.
+fn indexed_rows(db: &State, predicate: &[Condition]) -> Option<Vec<Vec<DataType>>> {
+    let equal_to = |col: usize| {
+        predicate
+            .iter()
+            .find(|c| c.column == col && c.op == Operator::Equal)
+            .map(|c| &c.value)
+    };
+
+    let cols = db
+        .keys()
+        .into_iter()
+        .find(|cols| cols.iter().all(|&col| equal_to(col).is_some()))?;
+    let key: Vec<_> = cols.iter().map(|&col| equal_to(col).unwrap()).collect();
+    match db.lookup(&cols[..], &KeyType::from(key)) {
+        LookupResult::Some(rows) => Some(
+            rows.into_iter()
+                .filter(|r| predicate.iter().all(|c| c.matches(r)))
+                .map(|r| r.into_owned())
+                .collect(),
+        ),
+        LookupResult::Missing => unreachable!("base state is never partial"),
+    }
+}
+
+/// Find all rows in `db` that satisfy every condition in `predicate`.
+///
+/// If no index of `db` can narrow down the candidate rows, all rows are scanned. They are read
+/// into `all` the first time that happens, so a batch with several such predicates only copies
+/// the state once.
+fn matching_rows(
+    db: &State,
+    predicate: &[Condition],
+    all: &mut Option<Vec<Vec<DataType>>>,
+) -> Vec<Vec<DataType>> {
+    if let Some(rows) = indexed_rows(db, predicate) {
+        return rows;
+    }
+
+    all.get_or_insert_with(|| db.cloned_records())
+        .iter()
+        .filter(|r| predicate.iter().all(|c| c.matches(r)))
+        .cloned()
+        .collect()
+}
+
+/// Turn a predicate-based operation into keyed operations on each row it currently matches.
+fn expand_predicate(
+    key_cols: &[usize],
+    db: &State,
+    op: TableOperation,
+    all: &mut Option<Vec<Vec<DataType>>>,
+) -> Vec<TableOperation> {
+    let key = |r: &[DataType]| key_cols.iter().map(|&col| r[col].clone()).collect();
+    match op {
+        TableOperation::DeleteWhere { predicate } => matching_rows(db, &predicate, all)
+            .into_iter()
+            .map(|r| TableOperation::Delete { key: key(&r) })
+            .collect(),
+        TableOperation::UpdateWhere { predicate, set } => matching_rows(db, &predicate, all)
+            .into_iter()
+            .map(|r| TableOperation::Update {
+                key: key(&r),
+                set: set.clone(),
+            })
+            .collect(),
+        op => vec![op],
+    }
+}
+
+/// The rows written, and the rows removed or replaced, by the operations accepted so far in a
+/// batch. Keys are checked against these along with the rows stored at the start of the batch.
+#[derive(Default)]
+struct Written {
+    rows: Vec<Vec<DataType>>,
+    removed: Vec<Vec<DataType>>,
+}
+
+impl Written {
+    /// Find the row with the given primary key as of the operations accepted so far.
+    fn current(&self, key_cols: &[usize], key: &[DataType], db: &State) -> Option<Vec<DataType>> {
+        let has_key = |r: &&Vec<DataType>| key_cols.iter().zip(key).all(|(&c, k)| r[c] == *k);
+        if let Some(r) = self.rows.iter().rev().find(has_key) {
+            return Some(r.clone());
+        }
+
+        let stored = match db.lookup(key_cols, &KeyType::from(key)) {
+            LookupResult::Some(rows) => rows.into_iter().next().map(|r| r.into_owned()),
+            LookupResult::Missing => unreachable!("base state is never partial"),
+        };
+        stored.filter(|r| !self.removed.contains(r))
+    }
+
+    /// Record that `new` was written in place of `old`; either may be missing.
+    fn replace(&mut self, old: Option<Vec<DataType>>, new: Option<Vec<DataType>>) {
+        if let Some(old) = old {
+            match self.rows.iter().position(|r| *r == old) {
+                Some(i) => {
+                    self.rows.remove(i);
+                }
+                None => self.removed.push(old),
+            }
+        }
+        self.rows.extend(new);
+    }
+}
+
 impl Base {
     pub(crate) fn take(&mut self) -> Self {
         Clone::clone(self)
     }
 
+    /// Check that the given operation is one this base can apply at all.
+    ///
+    /// Clients normally check this before sending, but operations sent in bulk (e.g., through
+    /// `Table::perform_all`) are only checked here.
+    fn unsupported(&self, columns: &[String], op: &TableOperation) -> Option<ConstraintViolation> {
+        if let TableOperation::Insert(..) = *op {
+            return None;
+        }
+        if self.primary_key.is_none() {
+            return Some(ConstraintViolation::Unsupported(String::from(
+                "only inserts can be applied to a table without a primary key",
+            )));
+        }
+
+        let set: &[Modification] = match *op {
+            TableOperation::Update { ref set, .. }
+            | TableOperation::UpdateWhere { ref set, .. } => &set[..],
+            TableOperation::InsertOrUpdate { ref update, .. } => &update[..],
+            _ => &[],
+        };
+        let non_numeric = set.iter().find(|m| match **m {
+            Modification::Apply(_, ref v) => !(v.is_numeric() || *v == DataType::None),
+            _ => false,
+        });
+        if let Some(m) = non_numeric {
+            return Some(ConstraintViolation::Unsupported(format!(
+                "modification {:?}",
+                m
+            )));
+        }
+
+        let predicate = op.predicate().unwrap_or(&[]);
+        predicate
+            .iter()
+            .find(|c| c.column >= columns.len() || !c.is_supported())
+            .map(|c| ConstraintViolation::Unsupported(format!("predicate condition {:?}", c)))
+    }
+
+    /// Find the first `NOT NULL` column that the given operation would leave `NULL`, if any.
+    fn null_violation(
+        &self,
+        columns: &[String],
+        op: &TableOperation,
+    ) -> Option<ConstraintViolation> {
+        let is_null = |v: Option<&DataType>| match v {
+            Some(&DataType::None) => true,
+            _ => false,
+        };
+        let set_null = |set: &[Modification], col: usize| match set.get(col) {
+            Some(&Modification::Set(DataType::None)) => true,
+            _ => false,
+        };
+
+        let null = self.not_null.iter().cloned().find(|&col| match *op {
+            TableOperation::Insert(ref row) => is_null(row.get(col)),
+            TableOperation::InsertOrUpdate {
+                ref row,
+                ref update,
+            } => is_null(row.get(col)) || set_null(update, col),
+            TableOperation::Update { ref set, .. }
+            | TableOperation::UpdateWhere { ref set, .. } => set_null(set, col),
+            TableOperation::Delete { .. } | TableOperation::DeleteWhere { .. } => false,
+        });
+        null.map(|col| ConstraintViolation::NotNull(columns[col].clone()))
+    }
+
+    /// Determine the row that the given operation would write, along with the row it replaces.
+    fn write_of(
+        &self,
+        op: &TableOperation,
+        db: Option<&Box<State>>,
+        written: &Written,
+    ) -> Option<(Vec<DataType>, Option<Vec<DataType>>)> {
+        let current = |key: &[DataType]| {
+            let key_cols = &self.primary_key.as_ref()?[..];
+            written.current(key_cols, key, &**db?)
+        };
+        let update = |old: Vec<DataType>, set: &[Modification]| {
+            let mut new = old.clone();
+            apply(&mut new, set);
+            Some((new, Some(old)))
+        };
+
+        match *op {
+            TableOperation::Insert(ref row) => Some((row.clone(), None)),
+            TableOperation::Update { ref key, ref set } => update(current(key)?, set),
+            TableOperation::InsertOrUpdate {
+                ref row,
+                update: ref set,
+            } => {
+                let key_cols = self.primary_key.as_ref()?;
+                let key: Vec<_> = key_cols.iter().map(|&c| row[c].clone()).collect();
+                match current(&key) {
+                    Some(old) => update(old, set),
+                    None => Some((row.clone(), None)),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Find the first unique key or foreign key that the given write (as returned by `write_of`)
+    /// would violate, if any.
+    ///
+    /// `written` holds the changes made by operations accepted earlier in the same batch.
+    fn key_violation(
+        &self,
+        columns: &[String],
+        &(ref row, ref old): &(Vec<DataType>, Option<Vec<DataType>>),
+        db: Option<&Box<State>>,
+        state: &StateMap,
+        written: &Written,
+        sharding: &Sharding,
+    ) -> Option<ConstraintViolation> {
+        let names = |cols: &[usize]| {
+            cols.iter()
+                .map(|&c| columns[c].as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        // the conditions that a row must meet to match `row` in the given columns
+        let matching = |cols: &[usize], other_cols: &[usize]| -> Option<Vec<Condition>> {
+            cols.iter()
+                .zip(other_cols)
+                .map(|(&c, &oc)| match row[c] {
+                    DataType::None => None,
+                    ref v => Some(Condition::new(oc, Operator::Equal, v.clone())),
+                })
+                .collect()
+        };
+
+        for cols in &self.unique {
+            let enforceable = match *sharding {
+                Sharding::ByColumn(c, _) => cols.contains(&c),
+                ref s => s.is_none(),
+            };
+            if !enforceable {
+                continue;
+            }
+            let predicate = match matching(cols, cols) {
+                Some(predicate) => predicate,
+                None => continue,
+            };
+
+            // neither the row being replaced nor rows removed earlier in the batch conflict
+            let is_other = |r: &Vec<DataType>| Some(r) != old.as_ref();
+            let stored = db.map(|db| indexed_rows(&**db, &predicate).expect(UNINDEXED));
+            let mut stored = stored
+                .into_iter()
+                .flatten()
+                .filter(|r| !written.removed.contains(r));
+            if stored.any(|r| is_other(&r))
+                || written
+                    .rows
+                    .iter()
+                    .filter(|r| is_other(r))
+                    .any(|r| predicate.iter().all(|c| c.matches(r)))
+            {
+                return Some(ConstraintViolation::Unique(names(cols)));
+            }
+        }
+
+        if !sharding.is_none() {
+            return None;
+        }
+        for fk in &self.foreign_keys {
+            if !fk.parent.has_local() {
+                continue;
+            }
+            let predicate = match matching(&fk.columns, &fk.parent_columns) {
+                Some(predicate) => predicate,
+                None => continue,
+            };
+            let parent = state
+                .get(*fk.parent)
+                .expect("foreign key references base that is not materialized");
+            if indexed_rows(&**parent, &predicate)
+                .expect(UNINDEXED)
+                .is_empty()
+            {
+                return Some(ConstraintViolation::ForeignKey(names(&fk.columns)));
+            }
+        }
+        None
+    }
+
+    /// Remove all operations that violate a constraint of this base, or that it cannot apply at
+    /// all, from `ops`.
+    ///
+    /// Returns the index (in the original `ops`) of each rejected operation along with the
+    /// constraint it violated, in order.
+    pub(crate) fn reject_violations(
+        &self,
+        us: LocalNodeIndex,
+        columns: &[String],
+        ops: &mut Vec<TableOperation>,
+        state: &StateMap,
+        sharding: &Sharding,
+    ) -> Vec<(usize, ConstraintViolation)> {
+        let db = state.get(us);
+        let check_keys = !self.unique.is_empty() || !self.foreign_keys.is_empty();
+
+        // rows accepted earlier in this batch also count towards unique keys, and rows they
+        // removed no longer do
+        let mut written = Written::default();
+        let mut rejected = Vec::new();
+        for (i, op) in ops.iter().enumerate() {
+            if let Some(violation) = self.unsupported(columns, op) {
+                rejected.push((i, violation));
+                continue;
+            }
+            if let Some(violation) = self.null_violation(columns, op) {
+                rejected.push((i, violation));
+                continue;
+            }
+            if !check_keys {
+                continue;
+            }
+            if let TableOperation::Delete { ref key } = *op {
+                let key_cols = &self.primary_key.as_ref().unwrap()[..];
+                let old = db.and_then(|db| written.current(key_cols, key, &**db));
+                written.replace(old, None);
+                continue;
+            }
+            if let Some(write) = self.write_of(op, db, &written) {
+                match self.key_violation(columns, &write, db, state, &written, sharding) {
+                    Some(violation) => rejected.push((i, violation)),
+                    None => written.replace(write.1, Some(write.0)),
+                }
+            }
+        }
+
+        if !rejected.is_empty() {
+            let mut i = 0;
+            let mut next = rejected.iter().map(|&(i, _)| i).peekable();
+            ops.retain(|_| {
+                let keep = next.peek() != Some(&i);
+                if !keep {
+                    next.next();
+                }
+                i += 1;
+                keep
+            });
+        }
+        rejected
+    }
+
+    /// Assign values to the `AUTO_INCREMENT` column of inserted rows that do not have one.
+    ///
+    /// Shard `shard` of `shards` only ever assigns values `v` with `v % shards == shard`, so the
+    /// shards of a base never hand out the same value, and each value is assigned by the shard
+    /// that it would be sharded to. Returns the value assigned for each operation, if any.
+    pub(crate) fn assign_auto_increment(
+        &mut self,
+        us: LocalNodeIndex,
+        ops: &mut [TableOperation],
+        state: &mut StateMap,
+        shard: usize,
+        shards: usize,
+    ) -> Vec<Option<DataType>> {
+        let col = match self.auto_increment {
+            Some(col) => col,
+            None => return Vec::new(),
+        };
+
+        let mut last = match self.last_auto_increment {
+            Some(last) => last,
+            None => match state.get(us) {
+                // pick up where we left off, or after the largest value if we never recorded one
+                Some(db) => db.auto_increment().unwrap_or_else(|| {
+                    db.cloned_records()
+                        .iter()
+                        .filter_map(|r| match r[col] {
+                            DataType::Int(..) | DataType::BigInt(..) => Some((&r[col]).into()),
+                            _ => None,
+                        })
+                        .max()
+                        .unwrap_or(0)
+                }),
+                None => 0,
+            },
+        };
+        let before = self.last_auto_increment;
+
+        let (shard, shards) = (shard as i64, shards as i64);
+        let generated = ops
+            .iter_mut()
+            .map(|op| {
+                let row = match *op {
+                    TableOperation::Insert(ref mut row)
+                    | TableOperation::InsertOrUpdate { ref mut row, .. } => row,
+                    _ => return None,
+                };
+
+                match row[col] {
+                    DataType::None => {}
+                    DataType::Int(..) | DataType::BigInt(..) => {
+                        // explicitly given values move the counter forward
+                        let v: i64 = (&row[col]).into();
+                        last = last.max(v);
+                        return None;
+                    }
+                    _ => return None,
+                }
+
+                // find the next value that belongs to this shard
+                let next = last + 1;
+                let next = next + (shard - next % shards + shards) % shards;
+                last = next;
+
+                let v = if next <= i64::from(i32::max_value()) {
+                    DataType::Int(next as i32)
+                } else {
+                    DataType::BigInt(next)
+                };
+                row[col] = v.clone();
+                Some(v)
+            })
+            .collect();
+
+        self.last_auto_increment = Some(last);
+        if before != self.last_auto_increment {
+            if let Some(db) = state.get_mut(us) {
+                db.set_auto_increment(last);
+            }
+        }
+
+        generated
+    }
+
     pub(crate) fn process(
         &mut self,
         us: LocalNodeIndex,
@@ -153,15 +757,29 @@ impl Base {
         }
 
         let key_cols = &self.primary_key.as_ref().unwrap()[..];
+        let db = state
+            .get(us)
+            .expect("base with primary key must be materialized");
+
+        // predicate-based operations are resolved against the state as of the start of this
+        // batch, and then handled just like the equivalent keyed operations.
+        if ops.iter().any(|op| op.predicate().is_some()) {
+            let mut all = None;
+            ops = ops
+                .into_iter()
+                .flat_map(|op| expand_predicate(key_cols, &**db, op, &mut all))
+                .collect();
+            if ops.is_empty() {
+                return Records::default();
+            }
+        }
+
         ops.sort_by(|a, b| key_of(key_cols, a).cmp(key_of(key_cols, b)));
 
         // starting key
         let mut this_key: Vec<_> = key_of(key_cols, &ops[0]).cloned().collect();
 
         // starting record state
-        let db = state
-            .get(us)
-            .expect("base with primary key must be materialized");
 
         let get_current = |current_key: &'_ _| {
             match db.lookup(key_cols, &KeyType::from(current_key)) {
@@ -235,21 +853,8 @@ impl Base {
             }
 
             let mut future = current.unwrap().into_owned();
-            for (col, op) in update.into_iter().enumerate() {
-                // XXX: make sure user doesn't update primary key?
-                match op {
-                    Modification::Set(v) => future[col] = v,
-                    Modification::Apply(op, v) => {
-                        let old: i64 = future[col].clone().into();
-                        let delta: i64 = v.into();
-                        future[col] = match op {
-                            Operation::Add => (old + delta).into(),
-                            Operation::Sub => (old - delta).into(),
-                        };
-                    }
-                    Modification::None => {}
-                }
-            }
+            // XXX: make sure user doesn't update primary key?
+            apply(&mut future, &update);
             current = Some(Cow::Owned(future));
         }
 
@@ -307,7 +912,10 @@ mod tests {
         assert_eq!(b.unmodified, true);
     }
 
-    fn test_lots_of_changes_in_same_batch(mut state: Box<State>) {
+    fn setup_base(
+        mut state: Box<State>,
+        key: Vec<usize>,
+    ) -> impl FnMut(Vec<TableOperation>) -> Records {
         use node;
         use prelude::*;
         use std::collections::HashMap;
@@ -320,7 +928,7 @@ mod tests {
             node::NodeType::Source,
         ));
 
-        let b = Base::new(vec![]).with_key(vec![0, 2]);
+        let b = Base::new(vec![]).with_key(key);
         let global = graph.add_node(Node::new("b", &["x", "y", "z"], b));
         graph.add_edge(source, global, ());
         let local = unsafe { LocalNodeIndex::make(0 as u32) };
@@ -345,11 +953,15 @@ mod tests {
         let n = graph[global].take();
         let mut n = n.finalize(&graph);
 
-        let mut one = move |u: Vec<TableOperation>| {
+        move |u: Vec<TableOperation>| {
             let mut m = n.get_base_mut().unwrap().process(local, u, &states);
             node::materialize(&mut m, None, states.get_mut(local));
             m
-        };
+        }
+    }
+
+    fn test_lots_of_changes_in_same_batch(state: Box<State>) {
+        let mut one = setup_base(state, vec![0, 2]);
 
         assert_eq!(
             one(vec![
@@ -402,6 +1014,364 @@ mod tests {
         );
     }
 
+    fn test_predicate_operations(mut state: Box<State>) {
+        use nom_sql::Operator;
+
+        // a secondary index on the second column lets equality predicates avoid a full scan
+        state.add_key(&[1], None);
+        let mut one = setup_base(state, vec![0]);
+
+        one(vec![
+            TableOperation::Insert(vec![1.into(), 5.into(), 10.into()]),
+            TableOperation::Insert(vec![2.into(), 5.into(), 20.into()]),
+            TableOperation::Insert(vec![3.into(), 6.into(), 30.into()]),
+        ]);
+
+        // predicate on an indexed column
+        assert_eq!(
+            one(vec![TableOperation::DeleteWhere {
+                predicate: vec![Condition::new(1, Operator::Equal, 5)],
+            }]),
+            vec![
+                Record::Negative(vec![1.into(), 5.into(), 10.into()]),
+                Record::Negative(vec![2.into(), 5.into(), 20.into()]),
+            ]
+            .into()
+        );
+
+        one(vec![
+            TableOperation::Insert(vec![1.into(), 5.into(), 10.into()]),
+            TableOperation::Insert(vec![2.into(), 5.into(), 20.into()]),
+        ]);
+
+        // predicate on a non-indexed column
+        assert_eq!(
+            one(vec![TableOperation::UpdateWhere {
+                predicate: vec![Condition::new(2, Operator::GreaterOrEqual, 20)],
+                set: vec![
+                    Modification::None,
+                    Modification::Set(7.into()),
+                    Modification::None,
+                ],
+            }]),
+            vec![
+                Record::Negative(vec![2.into(), 5.into(), 20.into()]),
+                Record::Positive(vec![2.into(), 7.into(), 20.into()]),
+                Record::Negative(vec![3.into(), 6.into(), 30.into()]),
+                Record::Positive(vec![3.into(), 7.into(), 30.into()]),
+            ]
+            .into()
+        );
+
+        // several predicates on non-indexed columns in one batch all see the same rows
+        assert_eq!(
+            one(vec![
+                TableOperation::DeleteWhere {
+                    predicate: vec![Condition::new(2, Operator::Equal, 10)],
+                },
+                TableOperation::DeleteWhere {
+                    predicate: vec![Condition::new(2, Operator::Equal, 30)],
+                },
+            ]),
+            vec![
+                Record::Negative(vec![1.into(), 5.into(), 10.into()]),
+                Record::Negative(vec![3.into(), 7.into(), 30.into()]),
+            ]
+            .into()
+        );
+
+        // predicate matching nothing
+        assert_eq!(
+            one(vec![TableOperation::DeleteWhere {
+                predicate: vec![
+                    Condition::new(1, Operator::Equal, 5),
+                    Condition::new(2, Operator::Less, 10),
+                ],
+            }]),
+            Records::default()
+        );
+    }
+
+    #[test]
+    fn predicate_operations() {
+        let state = MemoryState::default();
+        test_predicate_operations(box state);
+    }
+
+    #[test]
+    fn predicate_operations_persistent() {
+        let state = PersistentState::new(
+            String::from("predicate_operations_persistent"),
+            None,
+            &PersistenceParameters::default(),
+        );
+
+        test_predicate_operations(box state);
+    }
+
+    #[test]
+    fn auto_increment() {
+        let local = unsafe { LocalNodeIndex::make(0 as u32) };
+        let mut b = Base::new(vec![]).with_key(vec![0]).with_auto_increment(0);
+        let mut ops = vec![
+            TableOperation::Insert(vec![DataType::None, "a".into()]),
+            TableOperation::Insert(vec![7.into(), "b".into()]),
+            TableOperation::Insert(vec![DataType::None, "c".into()]),
+        ];
+
+        // shard 1 of 3 only assigns values v where v % 3 == 1, and skips past explicit values
+        let mut states = StateMap::new();
+        assert_eq!(
+            b.assign_auto_increment(local, &mut ops[..], &mut states, 1, 3),
+            vec![Some(1.into()), None, Some(10.into())]
+        );
+        assert_eq!(ops[2], TableOperation::Insert(vec![10.into(), "c".into()]));
+
+        // a fresh base picks up after the largest existing value in its state
+        let mut b = Base::new(vec![]).with_key(vec![0]).with_auto_increment(0);
+        let mut state = MemoryState::default();
+        state.add_key(&[0], None);
+        state.process_records(&mut vec![vec![5.into(), "x".into()]].into(), None);
+        states.insert(local, box state);
+        let mut ops = vec![TableOperation::Insert(vec![DataType::None, "y".into()])];
+        assert_eq!(
+            b.assign_auto_increment(local, &mut ops[..], &mut states, 0, 1),
+            vec![Some(6.into())]
+        );
+    }
+
+    #[test]
+    fn not_null() {
+        let local = unsafe { LocalNodeIndex::make(0 as u32) };
+        let b = Base::new(vec![]).with_key(vec![0]).with_not_null(vec![1]);
+        let columns = vec!["id".to_owned(), "title".to_owned()];
+        let states = StateMap::new();
+        let mut ops = vec![
+            TableOperation::Insert(vec![1.into(), DataType::None]),
+            TableOperation::Insert(vec![2.into(), "a".into()]),
+            TableOperation::Update {
+                key: vec![2.into()],
+                set: vec![Modification::None, Modification::Set(DataType::None)],
+            },
+            TableOperation::Delete {
+                key: vec![2.into()],
+            },
+        ];
+
+        let violation = ConstraintViolation::NotNull("title".to_owned());
+        assert_eq!(
+            b.reject_violations(local, &columns[..], &mut ops, &states, &Sharding::None),
+            vec![(0, violation.clone()), (2, violation)]
+        );
+        assert_eq!(
+            ops,
+            vec![
+                TableOperation::Insert(vec![2.into(), "a".into()]),
+                TableOperation::Delete {
+                    key: vec![2.into()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_to_numbers() {
+        let add = |v: DataType| Modification::Apply(Operation::Add, v);
+        let mut row = vec![
+            DataType::Float(1.5),
+            DataType::Decimal(150, 2),
+            1.into(),
+            DataType::None,
+            "a".into(),
+        ];
+        apply(
+            &mut row,
+            &[
+                add(DataType::Float(1.0)),
+                Modification::Apply(Operation::Sub, DataType::Decimal(5, 1)),
+                add(DataType::Decimal(5, 1)),
+                add(1.into()),
+                add(1.into()),
+            ],
+        );
+        assert_eq!(
+            row,
+            vec![
+                DataType::Float(2.5),
+                DataType::Decimal(100, 2),
+                DataType::Decimal(15, 1),
+                DataType::None,
+                DataType::None,
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_operations() {
+        let local = unsafe { LocalNodeIndex::make(0 as u32) };
+        let states = StateMap::new();
+        let columns = vec!["id".to_owned(), "title".to_owned()];
+
+        let keyed = Base::new(vec![]).with_key(vec![0]);
+        let mut ops = vec![
+            TableOperation::DeleteWhere {
+                predicate: vec![Condition::new(1, Operator::Like, "a%")],
+            },
+            TableOperation::DeleteWhere {
+                predicate: vec![Condition::new(2, Operator::Equal, 1)],
+            },
+            TableOperation::DeleteWhere {
+                predicate: vec![Condition::new(1, Operator::Equal, "a")],
+            },
+            TableOperation::Update {
+                key: vec![1.into()],
+                set: vec![
+                    Modification::None,
+                    Modification::Apply(Operation::Add, "a".into()),
+                ],
+            },
+        ];
+        let rejected =
+            keyed.reject_violations(local, &columns[..], &mut ops, &states, &Sharding::None);
+        assert_eq!(
+            rejected.iter().map(|&(i, _)| i).collect::<Vec<_>>(),
+            vec![0, 1, 3]
+        );
+        assert_eq!(ops.len(), 1);
+
+        let unkeyed = Base::new(vec![]);
+        let mut ops = vec![
+            TableOperation::Insert(vec![1.into(), "a".into()]),
+            TableOperation::Delete {
+                key: vec![1.into()],
+            },
+            TableOperation::DeleteWhere {
+                predicate: vec![Condition::new(1, Operator::Equal, "a")],
+            },
+        ];
+        let rejected =
+            unkeyed.reject_violations(local, &columns[..], &mut ops, &states, &Sharding::None);
+        assert_eq!(
+            rejected.iter().map(|&(i, _)| i).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(
+            ops,
+            vec![TableOperation::Insert(vec![1.into(), "a".into()])]
+        );
+    }
+
+    #[test]
+    fn unique_and_foreign_keys() {
+        let us = unsafe { LocalNodeIndex::make(0 as u32) };
+        let parent = unsafe { LocalNodeIndex::make(1 as u32) };
+        let mut parent_ip = IndexPair::from(NodeIndex::new(1));
+        parent_ip.set_local(parent);
+        let mut remap = HashMap::new();
+        remap.insert(NodeIndex::new(1), parent_ip);
+
+        // (id, email, parent_id)
+        let mut b = Base::new(vec![])
+            .with_key(vec![0])
+            .with_unique(vec![1])
+            .with_foreign_key(vec![2], NodeIndex::new(1), vec![0]);
+        b.on_commit(&remap);
+        assert_eq!(b.unenforced_foreign_keys().count(), 0);
+        let columns = vec!["id".to_owned(), "email".to_owned(), "parent_id".to_owned()];
+
+        let mut states = StateMap::new();
+        let mut state = MemoryState::default();
+        state.add_key(&[0], None);
+        state.add_key(&[1], None);
+        state.process_records(
+            &mut vec![vec![1.into(), "a".into(), 10.into()]].into(),
+            None,
+        );
+        states.insert(us, box state);
+        let mut state = MemoryState::default();
+        state.add_key(&[0], None);
+        state.process_records(&mut vec![vec![10.into()]].into(), None);
+        states.insert(parent, box state);
+
+        let mut ops = vec![
+            // duplicates a stored email
+            TableOperation::Insert(vec![2.into(), "a".into(), 10.into()]),
+            TableOperation::Insert(vec![3.into(), "b".into(), 10.into()]),
+            // duplicates an email from earlier in the batch
+            TableOperation::Insert(vec![4.into(), "b".into(), 10.into()]),
+            // references a missing parent
+            TableOperation::Insert(vec![5.into(), "c".into(), 11.into()]),
+            // NULLs are exempt
+            TableOperation::Insert(vec![6.into(), DataType::None, DataType::None]),
+            // a row may keep its own email
+            TableOperation::Update {
+                key: vec![1.into()],
+                set: vec![
+                    Modification::None,
+                    Modification::Set("a".into()),
+                    Modification::None,
+                ],
+            },
+        ];
+        let unique = ConstraintViolation::Unique("email".to_owned());
+        let foreign = ConstraintViolation::ForeignKey("parent_id".to_owned());
+        assert_eq!(
+            b.reject_violations(us, &columns[..], &mut ops, &states, &Sharding::None),
+            vec![(0, unique.clone()), (2, unique), (3, foreign)]
+        );
+        assert_eq!(ops.len(), 3);
+
+        let set_email = |email: &str| {
+            vec![
+                Modification::None,
+                Modification::Set(email.into()),
+                Modification::None,
+            ]
+        };
+        let mut ops = vec![
+            // an email freed up earlier in the batch can be taken
+            TableOperation::Delete {
+                key: vec![1.into()],
+            },
+            TableOperation::Insert(vec![2.into(), "a".into(), 10.into()]),
+            // as can an email that was changed
+            TableOperation::Insert(vec![3.into(), "b".into(), 10.into()]),
+            TableOperation::Update {
+                key: vec![3.into()],
+                set: set_email("c"),
+            },
+            TableOperation::Insert(vec![4.into(), "b".into(), 10.into()]),
+            // a row written earlier in the batch does not conflict with itself
+            TableOperation::Insert(vec![5.into(), "d".into(), 10.into()]),
+            TableOperation::Update {
+                key: vec![5.into()],
+                set: vec![
+                    Modification::None,
+                    Modification::None,
+                    Modification::Set(DataType::None),
+                ],
+            },
+            // but the emails now in use are still taken
+            TableOperation::Insert(vec![6.into(), "c".into(), 10.into()]),
+        ];
+        assert_eq!(
+            b.reject_violations(us, &columns[..], &mut ops, &states, &Sharding::None),
+            vec![(7, ConstraintViolation::Unique("email".to_owned()))]
+        );
+        assert_eq!(ops.len(), 7);
+
+        // a sharded base cannot check keys that do not include the column it is sharded by
+        let mut ops = vec![TableOperation::Insert(vec![
+            2.into(),
+            "a".into(),
+            11.into(),
+        ])];
+        let sharding = Sharding::ByColumn(0, 2);
+        assert!(b
+            .reject_violations(us, &columns[..], &mut ops, &states, &sharding)
+            .is_empty());
+    }
+
     #[test]
     fn lots_of_changes_in_same_batch() {
         let state = MemoryState::default();
@@ -48,6 +48,12 @@ impl Egress {
         self.tags.insert(tag, dst);
     }
 
+    /// Stop sending to the given ingress node, which has been removed.
+    pub fn remove_tx(&mut self, dst_g: NodeIndex) {
+        self.txs.retain(|tx| tx.node != dst_g);
+        self.tags.retain(|_, dst| *dst != dst_g);
+    }
+
     pub fn process(
         &mut self,
         m: &mut Option<Box<Packet>>,
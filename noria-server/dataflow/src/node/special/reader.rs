@@ -1,6 +1,7 @@
 use backlog;
 use noria::channel;
 use prelude::*;
+use std::time;
 
 /// A StreamUpdate reflects the addition or deletion of a row from a reader node.
 #[derive(Clone, Debug, PartialEq)]
@@ -36,6 +37,8 @@ pub struct Reader {
 
     for_node: NodeIndex,
     state: Option<Vec<usize>>,
+    collations: Vec<Collation>,
+    ttl: Option<time::Duration>,
 }
 
 impl Clone for Reader {
@@ -45,7 +48,9 @@ impl Clone for Reader {
             writer: None,
             streamers: self.streamers.clone(),
             state: self.state.clone(),
+            collations: self.collations.clone(),
             for_node: self.for_node,
+            ttl: self.ttl,
         }
     }
 }
@@ -56,7 +61,9 @@ impl Reader {
             writer: None,
             streamers: Vec::new(),
             state: None,
+            collations: Vec::new(),
             for_node,
+            ttl: None,
         }
     }
 
@@ -81,7 +88,9 @@ impl Reader {
             writer: self.writer.take(),
             streamers: mem::replace(&mut self.streamers, Vec::new()),
             state: self.state.clone(),
+            collations: self.collations.clone(),
             for_node: self.for_node,
+            ttl: self.ttl,
         }
     }
 
@@ -109,6 +118,30 @@ impl Reader {
         self.writer = Some(wh);
     }
 
+    /// Turn keys in this reader back into holes once they have gone unread for `ttl`. Only takes
+    /// effect for partial readers, and only if set before the reader's state is prepared.
+    pub fn set_ttl(&mut self, ttl: time::Duration) {
+        self.ttl = Some(ttl);
+    }
+
+    pub fn ttl(&self) -> Option<time::Duration> {
+        self.ttl
+    }
+
+    /// Returns when the next key in this reader expires, if any will.
+    pub fn next_expiry(&self) -> Option<time::Instant> {
+        self.writer.as_ref().and_then(|w| w.next_expiry())
+    }
+
+    /// Returns the keys in this reader that have gone unread for the reader's TTL, and so should
+    /// be evicted.
+    pub fn expired_keys(&self, now: time::Instant) -> Vec<Vec<DataType>> {
+        self.writer
+            .as_ref()
+            .map(|w| w.expired_keys(now))
+            .unwrap_or_else(Vec::new)
+    }
+
     pub fn key(&self) -> Option<&[usize]> {
         self.state.as_ref().map(|s| &s[..])
     }
@@ -121,25 +154,50 @@ impl Reader {
         }
     }
 
+    /// Compare the values of each key column under the given collation, both when rows are added
+    /// to the reader and when keys are looked up.
+    pub fn set_collations(&mut self, collations: Vec<Collation>) {
+        if collations.iter().all(|&c| c == Collation::Binary) {
+            self.collations = Vec::new();
+        } else {
+            self.collations = collations;
+        }
+    }
+
+    /// The collation of each key column, or nothing if they are all binary.
+    pub fn collations(&self) -> &[Collation] {
+        &self.collations[..]
+    }
+
     pub fn state_size(&self) -> Option<u64> {
         use common::SizeOf;
         self.writer.as_ref().map(|w| w.deep_size_of())
     }
 
-    /// Evict a randomly selected key, returning the number of bytes evicted.
+    /// Evict up to `count` keys chosen by the reader's eviction policy, returning the number of
+    /// bytes evicted.
     /// Note that due to how `evmap` applies the evictions asynchronously, we can only evict a
-    /// single key at a time here.
-    pub fn evict_random_key(&mut self) -> u64 {
+    /// single key at a time here when evicting randomly.
+    pub fn evict_some_keys(&mut self, count: usize) -> u64 {
         let mut bytes_freed = 0;
         if let Some(ref mut handle) = self.writer {
             use rand;
             let mut rng = rand::thread_rng();
-            bytes_freed = handle.evict_random_key(&mut rng);
+            bytes_freed = handle.evict_some_keys(count, &mut rng);
             handle.swap();
         }
         bytes_freed
     }
 
+    /// Returns up to `count` of the keys in this reader that its eviction policy would most like
+    /// to keep, hottest first.
+    pub fn hot_keys(&self, count: usize) -> Vec<Vec<DataType>> {
+        self.writer
+            .as_ref()
+            .map(|w| w.hot_keys(count))
+            .unwrap_or_else(Vec::new)
+    }
+
     pub fn on_eviction(&mut self, _key_columns: &[usize], keys: &[Vec<DataType>]) {
         // NOTE: *could* be None if reader has been created but its state hasn't been built yet
         if let Some(w) = self.writer.as_mut() {
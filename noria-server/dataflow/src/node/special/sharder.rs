@@ -9,6 +9,9 @@ pub struct Sharder {
     txs: Vec<(LocalNodeIndex, ReplicaAddr)>,
     sharded: VecMap<Box<Packet>>,
     shard_by: usize,
+    // The collation of the `shard_by` column, so that values that compare equal under it end up
+    // on the same shard.
+    collation: Collation,
 }
 
 impl Clone for Sharder {
@@ -19,15 +22,17 @@ impl Clone for Sharder {
             txs: Vec::new(),
             sharded: Default::default(),
             shard_by: self.shard_by,
+            collation: self.collation,
         }
     }
 }
 
 impl Sharder {
-    pub fn new(by: usize) -> Self {
+    pub fn new(by: usize, collation: Collation) -> Self {
         Self {
             txs: Default::default(),
             shard_by: by,
+            collation,
             sharded: VecMap::default(),
         }
     }
@@ -39,6 +44,7 @@ impl Sharder {
             txs: txs,
             sharded: VecMap::default(),
             shard_by: self.shard_by,
+            collation: self.collation,
         }
     }
 
@@ -61,7 +67,7 @@ impl Sharder {
 
     #[inline]
     fn shard(&self, dt: &DataType) -> usize {
-        ::shard_by(dt, self.txs.len())
+        ::shard_by(&self.collation.key(dt), self.txs.len())
     }
 
     pub fn process(
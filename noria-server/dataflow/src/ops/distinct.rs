@@ -1,6 +1,7 @@
-use std::cmp::Ordering;
+use std::borrow::Cow;
 use std::collections::HashMap;
 
+use ops;
 use prelude::*;
 
 /// This will get distinct records from a set of records compared over a given set of columns
@@ -12,6 +13,9 @@ pub struct Distinct {
     us: Option<IndexPair>,
 
     group_by: Vec<usize>,
+
+    // The collation of each column of the parent, or nothing if they are all binary.
+    collations: Vec<Collation>,
 }
 
 impl Distinct {
@@ -22,6 +26,7 @@ impl Distinct {
             src: src.into(),
             us: None,
             group_by: group_by,
+            collations: Vec::new(),
         }
     }
 }
@@ -67,12 +72,10 @@ impl Ingredient for Distinct {
         rs.sort_by(&pos_comp);
 
         let group_by = &self.group_by;
+        let collations = &self.collations;
 
         let group_cmp = |a: &Record, b: &Record| {
-            group_by
-                .iter()
-                .map(|&col| &a[col])
-                .cmp(group_by.iter().map(|&col| &b[col]))
+            ops::collated(a, group_by, collations).cmp(ops::collated(b, group_by, collations))
         };
 
         // First, we want to be smart about multiple added/removed rows with same group.
@@ -86,29 +89,18 @@ impl Ingredient for Distinct {
 
         for rec in rs {
             let group_by = &self.group_by[..];
-            let group = rec
-                .iter()
-                .enumerate()
-                .filter_map(|(i, v)| {
-                    if self.group_by.iter().any(|col| col == &i) {
-                        Some(v)
-                    } else {
-                        None
-                    }
-                })
-                .cloned()
+            let group = ops::collated(&rec, group_by, collations)
+                .map(Cow::into_owned)
                 .collect::<Vec<_>>();
 
             // Do not take overlapping elements
-            if prev_grp.iter().cmp(group_by.iter().map(|&col| &rec[col])) == Ordering::Equal
-                && prev_pos == rec.is_positive()
-            {
+            if prev_grp == group && prev_pos == rec.is_positive() {
                 continue;
             }
 
             // make ready for the new one
             prev_grp.clear();
-            prev_grp.extend(group_by.iter().map(|&col| &rec[col]).cloned());
+            prev_grp.extend(group.iter().cloned());
             prev_pos = rec.is_positive().clone();
 
             let positive = rec.is_positive();
@@ -139,7 +131,9 @@ impl Ingredient for Distinct {
         "Distinct".into()
     }
 
-    fn on_connected(&mut self, _: &Graph) {}
+    fn on_connected(&mut self, g: &Graph) {
+        self.collations = g[self.src.as_global()].collations().to_vec();
+    }
 
     fn on_commit(&mut self, us: NodeIndex, remap: &HashMap<NodeIndex, IndexPair>) {
         self.src.remap(remap);
@@ -4,6 +4,7 @@ use std::fmt::{self, Display};
 use std::sync;
 
 pub use nom_sql::Operator;
+use noria::JsonPath;
 use prelude::*;
 
 /// Filters incoming records according to some filter.
@@ -38,6 +39,92 @@ impl Display for Value {
 pub enum FilterCondition {
     Comparison(Operator, Value),
     In(Vec<DataType>),
+    IsNull,
+    IsNotNull,
+    /// Applies the inner condition to the unquoted part of a JSON document at the given path
+    /// (i.e., to `col ->> path`).
+    JsonExtract(JsonPath, Box<FilterCondition>),
+    /// Applies the inner condition to the value as folded under the collation of its column.
+    ///
+    /// Construct with `FilterCondition::collate`, which also folds the inner condition's constants.
+    Collated(Collation, Box<FilterCondition>),
+}
+
+impl FilterCondition {
+    /// Evaluate this condition under the given collation of the column it applies to.
+    pub fn collate(self, collation: Collation) -> FilterCondition {
+        if collation == Collation::Binary {
+            return self;
+        }
+
+        let key = |v: DataType| collation.key(&v).into_owned();
+        let inner = match self {
+            FilterCondition::Comparison(op, Value::Constant(v)) => {
+                FilterCondition::Comparison(op, Value::Constant(key(v)))
+            }
+            FilterCondition::In(vs) => FilterCondition::In(vs.into_iter().map(key).collect()),
+            FilterCondition::Collated(_, inner) => return inner.collate(collation),
+            cond => cond,
+        };
+        FilterCondition::Collated(collation, Box::new(inner))
+    }
+
+    /// Returns true if the value `d` in `row` satisfies this condition.
+    ///
+    /// Following SQL, a comparison involving `NULL` is never satisfied; use `IsNull` to match
+    /// `NULL` values.
+    fn matches(&self, d: &DataType, row: &[DataType]) -> bool {
+        match *self {
+            FilterCondition::Comparison(ref op, ref f) => {
+                let v = match *f {
+                    Value::Constant(ref dt) => dt,
+                    Value::Column(c) => &row[c],
+                };
+                if *d == DataType::None || *v == DataType::None {
+                    return false;
+                }
+                match *op {
+                    Operator::Equal => d == v,
+                    Operator::NotEqual => d != v,
+                    Operator::Greater => d > v,
+                    Operator::GreaterOrEqual => d >= v,
+                    Operator::Less => d < v,
+                    Operator::LessOrEqual => d <= v,
+                    // the planner rejects queries that compare with any other operator
+                    _ => false,
+                }
+            }
+            FilterCondition::In(ref fs) => *d != DataType::None && fs.contains(d),
+            FilterCondition::IsNull => *d == DataType::None,
+            FilterCondition::IsNotNull => *d != DataType::None,
+            FilterCondition::JsonExtract(ref path, ref cond) => {
+                cond.matches(&d.json_extract(path).json_unquote(), row)
+            }
+            FilterCondition::Collated(collation, ref cond) => cond.matches(&collation.key(d), row),
+        }
+    }
+}
+
+impl Display for FilterCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FilterCondition::Comparison(ref op, ref x) => write!(f, "{} {}", op, x),
+            FilterCondition::In(ref xs) => write!(
+                f,
+                "IN ({})",
+                xs.iter()
+                    .map(|d| format!("{}", d))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            FilterCondition::IsNull => write!(f, "IS NULL"),
+            FilterCondition::IsNotNull => write!(f, "IS NOT NULL"),
+            FilterCondition::JsonExtract(ref path, ref cond) => write!(f, "->> {} {}", path, cond),
+            FilterCondition::Collated(collation, ref cond) => {
+                write!(f, "{} COLLATE {:?}", cond, collation)
+            }
+        }
+    }
 }
 
 impl Filter {
@@ -84,27 +171,8 @@ impl Ingredient for Filter {
         rs.retain(|r| {
             self.filter.iter().enumerate().all(|(i, fi)| {
                 // check if this filter matches
-                let d = &r[i];
                 if let Some(ref cond) = *fi {
-                    match *cond {
-                        FilterCondition::Comparison(ref op, ref f) => {
-                            let v = match *f {
-                                Value::Constant(ref dt) => dt,
-                                Value::Column(c) => &r[c],
-                            };
-                            match *op {
-                                Operator::Equal => d == v,
-                                Operator::NotEqual => d != v,
-                                Operator::Greater => d > v,
-                                Operator::GreaterOrEqual => d >= v,
-                                Operator::Less => d < v,
-                                Operator::LessOrEqual => d <= v,
-                                Operator::In => unreachable!(),
-                                _ => unimplemented!(),
-                            }
-                        }
-                        FilterCondition::In(ref fs) => fs.contains(d),
-                    }
+                    cond.matches(&r[i], r)
                 } else {
                     // everything matches no condition
                     true
@@ -145,19 +213,7 @@ impl Ingredient for Filter {
                 .iter()
                 .enumerate()
                 .filter_map(|(i, ref e)| match e.as_ref() {
-                    Some(cond) => match *cond {
-                        FilterCondition::Comparison(ref op, ref x) => {
-                            Some(format!("f{} {} {}", i, escape(&format!("{}", op)), x))
-                        }
-                        FilterCondition::In(ref xs) => Some(format!(
-                            "f{} IN ({})",
-                            i,
-                            xs.iter()
-                                .map(|d| format!("{}", d))
-                                .collect::<Vec<_>>()
-                                .join(", ")
-                        )),
-                    },
+                    Some(cond) => Some(format!("f{} {}", i, escape(&format!("{}", cond)))),
                     None => None,
                 })
                 .collect::<Vec<_>>()
@@ -185,24 +241,7 @@ impl Ingredient for Filter {
                     r.iter().enumerate().all(|(i, d)| {
                         // check if this filter matches
                         if let Some(ref cond) = f[i] {
-                            match *cond {
-                                FilterCondition::Comparison(ref op, ref f) => {
-                                    let v = match *f {
-                                        Value::Constant(ref dt) => dt,
-                                        Value::Column(c) => &r[c],
-                                    };
-                                    match *op {
-                                        Operator::Equal => d == v,
-                                        Operator::NotEqual => d != v,
-                                        Operator::Greater => d > v,
-                                        Operator::GreaterOrEqual => d >= v,
-                                        Operator::Less => d < v,
-                                        Operator::LessOrEqual => d <= v,
-                                        _ => unimplemented!(),
-                                    }
-                                }
-                                FilterCondition::In(ref fs) => fs.contains(d),
-                            }
+                            cond.matches(d, r)
                         } else {
                             // everything matches no condition
                             true
@@ -439,4 +478,87 @@ mod tests {
         left = vec![42.into(), "b".into()];
         assert_eq!(g.narrow_one_row(left.clone(), false), vec![left].into());
     }
+
+    #[test]
+    fn it_works_with_nulls() {
+        let mut g = setup(
+            false,
+            Some(&[
+                Some(FilterCondition::Comparison(
+                    Operator::NotEqual,
+                    Value::Constant(1.into()),
+                )),
+                Some(FilterCondition::IsNull),
+            ]),
+        );
+
+        let mut left: Vec<DataType>;
+
+        left = vec![2.into(), DataType::None];
+        assert_eq!(g.narrow_one_row(left.clone(), false), vec![left].into());
+
+        // second condition fails ("a" IS NOT NULL)
+        left = vec![2.into(), "a".into()];
+        assert!(g.narrow_one_row(left.clone(), false).is_empty());
+
+        // first condition fails (NULL != 1 is not true)
+        left = vec![DataType::None, DataType::None];
+        assert!(g.narrow_one_row(left.clone(), false).is_empty());
+
+        let mut g = setup(false, Some(&[Some(FilterCondition::IsNotNull), None]));
+        left = vec![2.into(), DataType::None];
+        assert_eq!(g.narrow_one_row(left.clone(), false), vec![left].into());
+        left = vec![DataType::None, 2.into()];
+        assert!(g.narrow_one_row(left.clone(), false).is_empty());
+    }
+
+    #[test]
+    fn it_works_with_json_paths() {
+        let cond = FilterCondition::Comparison(Operator::Equal, Value::Constant("x".into()));
+        let cond = FilterCondition::JsonExtract("$.a[1]".parse().unwrap(), Box::new(cond));
+        let mut g = setup(false, Some(&[None, Some(cond)]));
+
+        let mut left: Vec<DataType>;
+
+        left = vec![1.into(), DataType::json(r#"{"a": [0, "x"]}"#).unwrap()];
+        assert_eq!(g.narrow_one_row(left.clone(), false), vec![left].into());
+
+        left = vec![1.into(), DataType::json(r#"{"a": [0, "y"]}"#).unwrap()];
+        assert!(g.narrow_one_row(left.clone(), false).is_empty());
+
+        // missing paths and values that are not JSON extract to NULL
+        left = vec![1.into(), DataType::json(r#"{"b": 1}"#).unwrap()];
+        assert!(g.narrow_one_row(left.clone(), false).is_empty());
+        left = vec![1.into(), "x".into()];
+        assert!(g.narrow_one_row(left.clone(), false).is_empty());
+    }
+
+    #[test]
+    fn it_works_with_collations() {
+        let ci = Collation::AsciiCaseInsensitive;
+        let eq = FilterCondition::Comparison(Operator::Equal, Value::Constant("Bob".into()));
+        let is_in = FilterCondition::In(vec!["X".into(), "Y".into()]);
+        let mut g = setup(
+            false,
+            Some(&[Some(is_in.collate(ci)), Some(eq.collate(ci))]),
+        );
+
+        let mut left: Vec<DataType>;
+
+        left = vec!["x".into(), "BOB".into()];
+        assert_eq!(g.narrow_one_row(left.clone(), false), vec![left].into());
+
+        left = vec!["y".into(), "bob".into()];
+        assert_eq!(g.narrow_one_row(left.clone(), false), vec![left].into());
+
+        left = vec!["z".into(), "bob".into()];
+        assert!(g.narrow_one_row(left.clone(), false).is_empty());
+
+        left = vec!["x".into(), "Bobby".into()];
+        assert!(g.narrow_one_row(left.clone(), false).is_empty());
+
+        // binary conditions are left as they are
+        let eq = FilterCondition::Comparison(Operator::Equal, Value::Constant("Bob".into()));
+        assert_eq!(eq.clone().collate(Collation::Binary), eq);
+    }
 }
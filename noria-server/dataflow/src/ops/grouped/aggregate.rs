@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use ops::grouped::GroupedOperation;
 use ops::grouped::GroupedOperator;
 
@@ -5,10 +7,14 @@ use prelude::*;
 
 /// Supported aggregation operators.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[allow(non_camel_case_types)]
 pub enum Aggregation {
-    /// Count the number of records for each group. The value for the `over` column is ignored.
+    /// Count the number of records for each group whose `over` column is not `NULL`.
     COUNT,
-    /// Sum the value of the `over` column for all records of each group.
+    /// Count the number of records for each group. The value for the `over` column is ignored.
+    COUNT_STAR,
+    /// Sum the non-`NULL` values of the `over` column for all records of each group, or `NULL` if
+    /// there are none.
     SUM,
 }
 
@@ -34,6 +40,7 @@ impl Aggregation {
                 op: self,
                 over: over,
                 group: group_by.into(),
+                non_null: HashMap::new(),
             },
         )
     }
@@ -57,10 +64,16 @@ pub struct Aggregator {
     op: Aggregation,
     over: usize,
     group: Vec<usize>,
+
+    /// The number of non-`NULL` values summed for each group, so that `SUM` can tell an empty sum
+    /// (which is `NULL`) from one that happens to be zero.
+    #[serde(skip)]
+    non_null: HashMap<Vec<DataType>, i64>,
 }
 
 impl GroupedOperation for Aggregator {
-    type Diff = i64;
+    /// The change in the aggregated value, and in the number of non-`NULL` values aggregated.
+    type Diff = (i64, i64);
 
     fn setup(&mut self, parent: &Node) {
         assert!(
@@ -74,49 +87,62 @@ impl GroupedOperation for Aggregator {
     }
 
     fn to_diff(&self, r: &[DataType], pos: bool) -> Self::Diff {
+        let sign = if pos { 1 } else { -1 };
         match self.op {
-            Aggregation::COUNT if pos => 1,
-            Aggregation::COUNT => -1,
-            Aggregation::SUM => {
-                let v = match r[self.over] {
-                    DataType::Int(n) => n as i64,
-                    DataType::BigInt(n) => n,
-                    DataType::None => 0,
-                    ref x => unreachable!("tried to aggregate over {:?} on {:?}", x, r),
-                };
-                if pos {
-                    v
-                } else {
-                    0i64 - v
-                }
-            }
+            Aggregation::COUNT if r[self.over] == DataType::None => (0, 0),
+            Aggregation::COUNT | Aggregation::COUNT_STAR => (sign, 0),
+            Aggregation::SUM => match r[self.over] {
+                DataType::Int(n) => (sign * n as i64, sign),
+                DataType::BigInt(n) => (sign * n, sign),
+                DataType::None => (0, 0),
+                ref x => unreachable!("tried to aggregate over {:?} on {:?}", x, r),
+            },
         }
     }
 
     fn apply(
-        &self,
+        &mut self,
+        group: &[DataType],
         current: Option<&DataType>,
         diffs: &mut Iterator<Item = Self::Diff>,
     ) -> DataType {
         let n = match current {
             Some(&DataType::Int(n)) => n as i64,
             Some(&DataType::BigInt(n)) => n,
-            None => 0,
+            Some(&DataType::None) | None => 0,
             _ => unreachable!(),
         };
-        diffs.into_iter().fold(n, |n, d| n + d).into()
+        if self.op != Aggregation::SUM {
+            return diffs.into_iter().fold(n, |n, (d, _)| n + d).into();
+        }
+
+        // a group without a current value is new, or was evicted and is being replayed in full
+        let non_null = match current {
+            Some(&DataType::None) | None => 0,
+            Some(_) => self.non_null.get(group).cloned().unwrap_or(1),
+        };
+        let (n, non_null) = diffs
+            .into_iter()
+            .fold((n, non_null), |(n, c), (d, dc)| (n + d, c + dc));
+        if non_null > 0 {
+            self.non_null.insert(group.to_vec(), non_null);
+            n.into()
+        } else {
+            self.non_null.remove(group);
+            DataType::None
+        }
     }
 
     fn description(&self, detailed: bool) -> String {
         if !detailed {
             return String::from(match self.op {
-                Aggregation::COUNT => "+",
+                Aggregation::COUNT | Aggregation::COUNT_STAR => "+",
                 Aggregation::SUM => "𝛴",
             });
         }
 
         let op_string = match self.op {
-            Aggregation::COUNT => "|*|".into(),
+            Aggregation::COUNT | Aggregation::COUNT_STAR => "|*|".into(),
             Aggregation::SUM => format!("𝛴({})", self.over),
         };
         let group_cols = self
@@ -292,6 +318,33 @@ mod tests {
         }));
     }
 
+    #[test]
+    fn it_skips_nulls() {
+        let mut c = setup(true);
+
+        // a NULL creates the group, but is not counted
+        let rs = c.narrow_one_row(vec![1.into(), DataType::None], true);
+        assert_eq!(rs.len(), 1);
+        assert!(rs.iter().all(|r| r.is_positive() && r[1] == 0.into()));
+
+        let rs = c.narrow_one_row(vec![1.into(), 1.into()], true);
+        assert_eq!(rs.len(), 2);
+        assert!(rs.iter().any(|r| r.is_positive() && r[1] == 1.into()));
+
+        // COUNT(*) counts every row
+        let mut g = ops::test::MockGraph::new();
+        let s = g.add_base("source", &["x", "y"]);
+        g.set_op(
+            "identity",
+            &["x", "ys"],
+            Aggregation::COUNT_STAR.over(s.as_global(), 1, &[0]),
+            true,
+        );
+        let rs = g.narrow_one_row(vec![1.into(), DataType::None], true);
+        assert_eq!(rs.len(), 1);
+        assert!(rs.iter().all(|r| r.is_positive() && r[1] == 1.into()));
+    }
+
     #[test]
     fn it_groups_by_multiple_columns() {
         let mut c = setup_multicolumn(true);
@@ -377,7 +430,54 @@ mod tests {
         }
     }
 
-    // TODO: also test SUM
+    #[test]
+    fn it_sums_to_null_without_values() {
+        let mut g = ops::test::MockGraph::new();
+        let s = g.add_base("source", &["x", "y"]);
+        g.set_op(
+            "identity",
+            &["x", "ys"],
+            Aggregation::SUM.over(s.as_global(), 1, &[0]),
+            true,
+        );
+
+        // a group that only has NULL values sums to NULL
+        let rs = g.narrow_one_row(vec![1.into(), DataType::None], true);
+        assert_eq!(rs, vec![(vec![1.into(), DataType::None], true)].into());
+
+        // a zero is not the same as no value
+        let rs = g.narrow_one_row(vec![1.into(), 0.into()], true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), DataType::None], false),
+                (vec![1.into(), 0.into()], true),
+            ]
+            .into()
+        );
+
+        let rs = g.narrow_one_row(vec![1.into(), 2.into()], true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), 0.into()], false),
+                (vec![1.into(), 2.into()], true),
+            ]
+            .into()
+        );
+
+        // removing the last non-NULL values brings the sum back to NULL
+        g.narrow_one_row((vec![1.into(), 0.into()], false), true);
+        let rs = g.narrow_one_row((vec![1.into(), 2.into()], false), true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), 2.into()], false),
+                (vec![1.into(), DataType::None], true),
+            ]
+            .into()
+        );
+    }
 
     #[test]
     fn it_suggests_indices() {
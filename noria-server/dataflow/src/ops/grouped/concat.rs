@@ -96,7 +96,13 @@ impl GroupConcat {
                     }
                     DataType::Int(ref n) => s.push_str(&n.to_string()),
                     DataType::BigInt(ref n) => s.push_str(&n.to_string()),
-                    DataType::Real(..) => s.push_str(&rec[*i].to_string()),
+                    DataType::Real(..)
+                    | DataType::Float(..)
+                    | DataType::Bool(..)
+                    | DataType::Date(..)
+                    | DataType::Decimal(..)
+                    | DataType::Json(..) => s.push_str(&rec[*i].to_string()),
+                    DataType::ByteArray(ref bytes) => s.push_str(&String::from_utf8_lossy(bytes)),
                     DataType::Timestamp(ref ts) => s.push_str(&ts.format("%+").to_string()),
                     DataType::None => unreachable!(),
                 },
@@ -150,7 +156,8 @@ impl GroupedOperation for GroupConcat {
     }
 
     fn apply(
-        &self,
+        &mut self,
+        _: &[DataType],
         current: Option<&DataType>,
         diffs: &mut Iterator<Item = Self::Diff>,
     ) -> DataType {
@@ -61,6 +61,8 @@ pub struct ExtremumOperator {
 pub enum DiffType {
     Insert(i64),
     Remove(i64),
+    /// A `NULL` was inserted or removed, which never changes the extremum.
+    Null,
 }
 
 impl GroupedOperation for ExtremumOperator {
@@ -81,6 +83,7 @@ impl GroupedOperation for ExtremumOperator {
         let v = match r[self.over] {
             DataType::Int(n) => n as i64,
             DataType::BigInt(n) => n,
+            DataType::None => return DiffType::Null,
             _ => {
                 // the column we're aggregating over is non-numerical (or rather, this value is).
                 // if you've removed a column, chances are the  default value has the wrong type.
@@ -103,28 +106,24 @@ impl GroupedOperation for ExtremumOperator {
     }
 
     fn apply(
-        &self,
+        &mut self,
+        _: &[DataType],
         current: Option<&DataType>,
         diffs: &mut Iterator<Item = Self::Diff>,
     ) -> DataType {
         // Extreme values are those that are at least as extreme as the current min/max (if any).
         // let mut is_extreme_value : Box<Fn(i64) -> bool> = Box::new(|_|true);
-        let mut extreme_values: Vec<i64> = vec![];
-        if let Some(data) = current {
-            match *data {
-                DataType::Int(n) => extreme_values.push(n as i64),
-                DataType::BigInt(n) => extreme_values.push(n),
-                _ => unreachable!(),
-            }
-        };
+        // A group whose values have all been NULL so far has a NULL extremum.
+        let current = current.and_then(|data| match *data {
+            DataType::Int(n) => Some(n as i64),
+            DataType::BigInt(n) => Some(n),
+            DataType::None => None,
+            _ => unreachable!(),
+        });
+        let mut extreme_values: Vec<i64> = current.into_iter().collect();
 
         let is_extreme_value = |x: i64| {
-            if let Some(data) = current {
-                let n = match *data {
-                    DataType::Int(n) => n as i64,
-                    DataType::BigInt(n) => n,
-                    _ => unreachable!(),
-                };
+            if let Some(n) = current {
                 match self.op {
                     Extremum::MAX => x >= n,
                     Extremum::MIN => x <= n,
@@ -154,6 +153,10 @@ impl GroupedOperation for ExtremumOperator {
         if let Some(extreme) = extreme {
             return extreme.into();
         }
+        if current.is_none() {
+            // only NULLs were added
+            return DataType::None;
+        }
 
         // TODO: handle this case by querying into the parent.
         unimplemented!();
@@ -298,6 +301,24 @@ mod tests {
         assert_record_change(key, 7, 5, out);
     }
 
+    #[test]
+    fn it_ignores_nulls() {
+        let mut c = setup(Extremum::MIN, true);
+
+        // a group with only NULLs has a NULL minimum
+        let rs = c.narrow_one_row(vec![1.into(), DataType::None], true);
+        assert_eq!(rs.len(), 1);
+        assert!(rs.iter().all(|r| r.is_positive() && r[1] == DataType::None));
+
+        let rs = c.narrow_one_row(vec![1.into(), 5.into()], true);
+        assert_eq!(rs.len(), 2);
+        assert!(rs.iter().any(|r| r.is_positive() && r[1] == 5.into()));
+
+        // NULLs never become the minimum
+        let rs = c.narrow_one_row(vec![1.into(), DataType::None], true);
+        assert!(rs.is_empty());
+    }
+
     #[test]
     fn it_cancels_out_opposite_records() {
         let mut c = setup(Extremum::MAX, true);
@@ -0,0 +1,380 @@
+use ops::grouped::GroupedOperation;
+use ops::grouped::GroupedOperator;
+
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use prelude::*;
+
+/// The JSON document that a `JsonAggregate` builds for each group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum JsonAggregation {
+    /// An array of the values of the given column, like `JSON_ARRAYAGG`.
+    ArrayAgg(usize),
+    /// An object whose members are named by the values of the first column and hold the values
+    /// of the second, like `JSON_OBJECTAGG`.
+    ObjectAgg(usize, usize),
+}
+
+#[derive(Debug, Clone)]
+pub enum JsonDiff {
+    Element(Value, bool),
+    Member(String, Value, bool),
+    /// A record that is not part of the document, like one with a `NULL` member name.
+    Skip,
+}
+
+/// `JsonAggregate` collects values from all the records in a group into a JSON document.
+///
+/// Like `GroupConcat`, the records are grouped by all the columns that are not aggregated. The
+/// elements of an array are kept ordered by value (`null`s, then booleans, numbers, strings,
+/// arrays and objects), so that the document does not depend on the order in which records
+/// arrive. If several records in a group give an object member the same name, the member holds
+/// the value of the last of them to arrive, and is only removed once all of those records are.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonAggregate {
+    aggregation: JsonAggregation,
+    group: Vec<usize>,
+
+    /// The values that the records of each group give every object member, in order of arrival,
+    /// so that removing one record falls back to the value of another with the same member name.
+    #[serde(skip)]
+    members: HashMap<Vec<DataType>, HashMap<String, Vec<Value>>>,
+}
+
+impl JsonAggregate {
+    /// Construct a new `JsonAggregate` operator.
+    ///
+    /// All columns of the input to this node that are not used by `aggregation` will be used as
+    /// group by parameters.
+    pub fn new(src: NodeIndex, aggregation: JsonAggregation) -> GroupedOperator<JsonAggregate> {
+        GroupedOperator::new(
+            src,
+            JsonAggregate {
+                aggregation,
+                group: Vec::new(),
+                members: HashMap::new(),
+            },
+        )
+    }
+
+    fn columns(&self) -> Vec<usize> {
+        match self.aggregation {
+            JsonAggregation::ArrayAgg(col) => vec![col],
+            JsonAggregation::ObjectAgg(name, value) => vec![name, value],
+        }
+    }
+}
+
+impl GroupedOperation for JsonAggregate {
+    type Diff = JsonDiff;
+
+    fn setup(&mut self, parent: &Node) {
+        // group by all columns
+        let cols = parent.fields().len();
+        let mut group = HashSet::new();
+        group.extend(0..cols);
+        // except the ones that are aggregated
+        for col in self.columns() {
+            assert!(
+                col < cols,
+                "JSON aggregation over fields parent doesn't have"
+            );
+            group.remove(&col);
+        }
+        self.group = group.into_iter().collect();
+    }
+
+    fn group_by(&self) -> &[usize] {
+        &self.group[..]
+    }
+
+    fn to_diff(&self, r: &[DataType], pos: bool) -> Self::Diff {
+        match self.aggregation {
+            JsonAggregation::ArrayAgg(col) => JsonDiff::Element(r[col].to_json(), pos),
+            JsonAggregation::ObjectAgg(name, _) if r[name] == DataType::None => JsonDiff::Skip,
+            JsonAggregation::ObjectAgg(name, value) => {
+                let name = match r[name].to_json() {
+                    Value::String(s) => s,
+                    v => v.to_string(),
+                };
+                JsonDiff::Member(name, r[value].to_json(), pos)
+            }
+        }
+    }
+
+    fn apply(
+        &mut self,
+        group: &[DataType],
+        current: Option<&DataType>,
+        diffs: &mut Iterator<Item = Self::Diff>,
+    ) -> DataType {
+        let current = current.map(|dt| dt.to_json());
+        match self.aggregation {
+            JsonAggregation::ArrayAgg(_) => {
+                let mut elements = match current {
+                    Some(Value::Array(elements)) => elements,
+                    _ => Vec::new(),
+                };
+                for diff in diffs {
+                    if let JsonDiff::Element(v, pos) = diff {
+                        let mut i = match elements.binary_search_by(|e| cmp_json(e, &v)) {
+                            Ok(i) | Err(i) => i,
+                        };
+                        if pos {
+                            elements.insert(i, v);
+                        } else {
+                            // elements that compare equal need not be identical (like 1 and 1.0)
+                            while i > 0 && cmp_json(&elements[i - 1], &v) == Ordering::Equal {
+                                i -= 1;
+                            }
+                            let same = elements[i..]
+                                .iter()
+                                .take_while(|e| cmp_json(e, &v) == Ordering::Equal)
+                                .position(|e| *e == v);
+                            if let Some(j) = same {
+                                elements.remove(i + j);
+                            }
+                        }
+                    }
+                }
+                Value::Array(elements).into()
+            }
+            JsonAggregation::ObjectAgg(..) => {
+                // a group without a current value is new, or was evicted and is being replayed in
+                // full. one with a value but no state was built before we last started.
+                let mut members = match current {
+                    None => HashMap::new(),
+                    Some(current) => self.members.remove(group).unwrap_or_else(|| match current {
+                        Value::Object(members) => {
+                            members.into_iter().map(|(k, v)| (k, vec![v])).collect()
+                        }
+                        _ => HashMap::new(),
+                    }),
+                };
+                for diff in diffs {
+                    match diff {
+                        JsonDiff::Member(name, v, true) => {
+                            members.entry(name).or_insert_with(Vec::new).push(v);
+                        }
+                        JsonDiff::Member(name, v, false) => {
+                            let emptied = match members.get_mut(&name) {
+                                Some(values) => {
+                                    if let Some(i) = values.iter().rposition(|e| *e == v) {
+                                        values.remove(i);
+                                    }
+                                    values.is_empty()
+                                }
+                                None => false,
+                            };
+                            if emptied {
+                                members.remove(&name);
+                            }
+                        }
+                        JsonDiff::Element(..) | JsonDiff::Skip => {}
+                    }
+                }
+
+                let doc: Map<String, Value> = members
+                    .iter()
+                    .map(|(name, values)| (name.clone(), values[values.len() - 1].clone()))
+                    .collect();
+                if !members.is_empty() {
+                    self.members.insert(group.to_vec(), members);
+                }
+                Value::Object(doc).into()
+            }
+        }
+    }
+
+    fn description(&self, detailed: bool) -> String {
+        if !detailed {
+            return String::from("JSON");
+        }
+
+        // Sort group by columns for consistent output.
+        let mut group_cols = self.group.clone();
+        group_cols.sort();
+        let group_cols = group_cols
+            .iter()
+            .map(|g| g.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        match self.aggregation {
+            JsonAggregation::ArrayAgg(col) => format!("JSON[{}] γ[{}]", col, group_cols),
+            JsonAggregation::ObjectAgg(name, value) => {
+                format!("JSON{{{}: {}}} γ[{}]", name, value, group_cols)
+            }
+        }
+    }
+}
+
+/// Order JSON values by type, and then by value within each type, without formatting them.
+fn cmp_json(a: &Value, b: &Value) -> Ordering {
+    fn rank(v: &Value) -> u8 {
+        match *v {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Object(_) => 5,
+        }
+    }
+
+    match (a, b) {
+        (&Value::Bool(a), &Value::Bool(b)) => a.cmp(&b),
+        (&Value::Number(ref a), &Value::Number(ref b)) => {
+            let (a, b) = (a.as_f64().unwrap_or(0.0), b.as_f64().unwrap_or(0.0));
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        }
+        (&Value::String(ref a), &Value::String(ref b)) => a.cmp(b),
+        (&Value::Array(ref a), &Value::Array(ref b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| cmp_json(a, b))
+            .find(|&o| o != Ordering::Equal)
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (&Value::Object(ref a), &Value::Object(ref b)) => a.len().cmp(&b.len()).then_with(|| {
+            a.iter()
+                .zip(b)
+                .map(|((ak, av), (bk, bv))| ak.cmp(bk).then_with(|| cmp_json(av, bv)))
+                .find(|&o| o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        }),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ops;
+
+    fn setup(aggregation: JsonAggregation, fields: &[&str]) -> ops::test::MockGraph {
+        let mut g = ops::test::MockGraph::new();
+        let s = g.add_base("source", fields);
+
+        let c = JsonAggregate::new(s.as_global(), aggregation);
+        g.set_op("json", &["x", "doc"], c, true);
+        g
+    }
+
+    fn json(text: &str) -> DataType {
+        DataType::json(text).unwrap()
+    }
+
+    #[test]
+    fn it_describes() {
+        let c = setup(JsonAggregation::ArrayAgg(1), &["x", "y"]);
+        assert_eq!(c.node().description(true), "JSON[1] γ[0]");
+        let c = setup(JsonAggregation::ObjectAgg(1, 2), &["x", "k", "v"]);
+        assert_eq!(c.node().description(true), "JSON{1: 2} γ[0]");
+    }
+
+    #[test]
+    fn it_builds_arrays() {
+        let mut c = setup(JsonAggregation::ArrayAgg(1), &["x", "y"]);
+
+        let rs = c.narrow_one_row(vec![1.into(), "b".into()], true);
+        assert_eq!(rs, vec![vec![1.into(), json(r#"["b"]"#)]].into());
+
+        // elements are kept in order, regardless of arrival
+        let rs = c.narrow_one_row(vec![1.into(), 2.into()], true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), json(r#"["b"]"#)], false),
+                (vec![1.into(), json(r#"[2, "b"]"#)], true),
+            ]
+            .into()
+        );
+
+        let rs = c.narrow_one_row((vec![1.into(), "b".into()], false), true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), json(r#"[2, "b"]"#)], false),
+                (vec![1.into(), json("[2]")], true),
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn it_builds_objects() {
+        let mut c = setup(JsonAggregation::ObjectAgg(1, 2), &["x", "k", "v"]);
+
+        let rs = c.narrow_one_row(vec![1.into(), "a".into(), 1.into()], true);
+        assert_eq!(rs, vec![vec![1.into(), json(r#"{"a": 1}"#)]].into());
+
+        let rs = c.narrow_one_row(vec![1.into(), 7.into(), DataType::None], true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), json(r#"{"a": 1}"#)], false),
+                (vec![1.into(), json(r#"{"a": 1, "7": null}"#)], true),
+            ]
+            .into()
+        );
+
+        // members with NULL names are left out
+        let rs = c.narrow_one_row(vec![1.into(), DataType::None, 2.into()], true);
+        assert!(rs.is_empty());
+
+        let rs = c.narrow_one_row((vec![1.into(), "a".into(), 1.into()], false), true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), json(r#"{"a": 1, "7": null}"#)], false),
+                (vec![1.into(), json(r#"{"7": null}"#)], true),
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn it_keeps_members_with_repeated_names() {
+        let mut c = setup(JsonAggregation::ObjectAgg(1, 2), &["x", "k", "v"]);
+
+        c.narrow_one_row(vec![1.into(), "a".into(), 1.into()], true);
+        let rs = c.narrow_one_row(vec![1.into(), "a".into(), 2.into()], true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), json(r#"{"a": 1}"#)], false),
+                (vec![1.into(), json(r#"{"a": 2}"#)], true),
+            ]
+            .into()
+        );
+
+        // removing the record that gave the member its value falls back to the other one
+        let rs = c.narrow_one_row((vec![1.into(), "a".into(), 2.into()], false), true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), json(r#"{"a": 2}"#)], false),
+                (vec![1.into(), json(r#"{"a": 1}"#)], true),
+            ]
+            .into()
+        );
+
+        // and so does removing one that did not
+        c.narrow_one_row(vec![1.into(), "a".into(), 3.into()], true);
+        let rs = c.narrow_one_row((vec![1.into(), "a".into(), 1.into()], false), true);
+        assert!(rs.is_empty());
+
+        let rs = c.narrow_one_row((vec![1.into(), "a".into(), 3.into()], false), true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), json(r#"{"a": 3}"#)], false),
+                (vec![1.into(), json("{}")], true),
+            ]
+            .into()
+        );
+    }
+}
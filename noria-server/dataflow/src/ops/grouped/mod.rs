@@ -3,12 +3,14 @@ use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt;
 
+use ops;
 use prelude::*;
 
 // pub mod latest;
 pub mod aggregate;
 pub mod concat;
 pub mod extremum;
+pub mod json;
 
 /// Trait for implementing operations that collapse a group of records into a single record.
 ///
@@ -52,8 +54,12 @@ pub trait GroupedOperation: fmt::Debug + Clone {
 
     /// Given the given `current` value, and a number of changes for a group (`diffs`), compute the
     /// updated group value.
+    ///
+    /// Implementors that keep additional state per `group` must discard it when `current` is
+    /// `None`, as the group is then either new or has been evicted and is being replayed.
     fn apply(
-        &self,
+        &mut self,
+        group: &[DataType],
         current: Option<&DataType>,
         diffs: &mut Iterator<Item = Self::Diff>,
     ) -> DataType;
@@ -74,6 +80,8 @@ pub struct GroupedOperator<T: GroupedOperation> {
     group_by: Vec<usize>,
     out_key: Vec<usize>,
     colfix: Vec<usize>,
+    // The collation of each column of the parent, or nothing if they are all binary.
+    collations: Vec<Collation>,
 }
 
 impl<T: GroupedOperation> GroupedOperator<T> {
@@ -87,6 +95,7 @@ impl<T: GroupedOperation> GroupedOperator<T> {
             group_by: Vec::new(),
             out_key: Vec::new(),
             colfix: Vec::new(),
+            collations: Vec::new(),
         }
     }
 }
@@ -111,6 +120,7 @@ where
 
         // group by all columns
         self.cols = srcn.fields().len();
+        self.collations = srcn.collations().to_vec();
         self.group_by.extend(self.inner.group_by().iter().cloned());
         self.group_by.sort();
         // cache the range of our output keys
@@ -160,11 +170,9 @@ where
         }
 
         let group_by = &self.group_by;
+        let collations = &self.collations;
         let cmp = |a: &Record, b: &Record| {
-            group_by
-                .iter()
-                .map(|&col| &a[col])
-                .cmp(group_by.iter().map(|&col| &b[col]))
+            ops::collated(a, group_by, collations).cmp(ops::collated(b, group_by, collations))
         };
 
         // First, we want to be smart about multiple added/removed rows with same group.
@@ -232,7 +240,11 @@ where
                     });
 
                     // new is the result of applying all diffs for the group to the current value
-                    let new = inner.apply(current.as_ref().map(|v| &**v), &mut diffs as &mut _);
+                    let new = inner.apply(
+                        &group[..],
+                        current.as_ref().map(|v| &**v),
+                        &mut diffs as &mut _,
+                    );
                     match current {
                         Some(ref current) if new == **current => {
                             // no change
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::mem;
@@ -33,6 +34,9 @@ pub struct Join {
     // Key column in the left and right parents respectively
     on: (usize, usize),
 
+    // Collation of the key column in the left and right parents respectively
+    collations: (Collation, Collation),
+
     // Which columns to emit. True means the column is from the left parent, false means from the
     // right
     emit: Vec<(bool, usize)>,
@@ -117,6 +121,7 @@ impl Join {
             left: left.into(),
             right: right.into(),
             on: on,
+            collations: (Collation::Binary, Collation::Binary),
             emit: emit,
             in_place_left_emit,
             in_place_right_emit,
@@ -124,6 +129,40 @@ impl Join {
         }
     }
 
+    // The collation join keys are compared under: that of the key columns if they agree, and
+    // byte-wise otherwise.
+    fn collation(&self) -> Collation {
+        if self.collations.0 == self.collations.1 {
+            self.collations.0
+        } else {
+            Collation::Binary
+        }
+    }
+
+    // Look up the rows of `parent` that join with `key`. The parent's state compares its key
+    // column under the collation of that column, which may match more rows than the join does.
+    fn lookup_key<'a>(
+        &self,
+        parent: LocalNodeIndex,
+        column: usize,
+        key: &DataType,
+        nodes: &DomainNodes,
+        states: &'a StateMap,
+    ) -> Option<Option<Box<Iterator<Item = Cow<'a, [DataType]>> + 'a>>> {
+        let rows = self.lookup(parent, &[column], &KeyType::Single(key), nodes, states);
+        let parent_collation = if parent == *self.left {
+            self.collations.0
+        } else {
+            self.collations.1
+        };
+        if parent_collation == self.collation() {
+            return rows;
+        }
+
+        let key = key.clone();
+        rows.map(|rows| rows.map(|rows| Box::new(rows.filter(move |r| r[column] == key)) as Box<_>))
+    }
+
     fn generate_row(
         &self,
         left: &[DataType],
@@ -220,7 +259,12 @@ impl Ingredient for Join {
         }
     }
 
-    fn on_connected(&mut self, _g: &Graph) {}
+    fn on_connected(&mut self, g: &Graph) {
+        self.collations = (
+            Collation::of(g[self.left.as_global()].collations(), self.on.0),
+            Collation::of(g[self.right.as_global()].collations(), self.on.1),
+        );
+    }
 
     fn on_commit(&mut self, _: NodeIndex, remap: &HashMap<NodeIndex, IndexPair>) {
         self.left.remap(remap);
@@ -279,9 +323,14 @@ impl Ingredient for Join {
         // First, we want to be smart about multiple added/removed rows with the same join key
         // value. For example, if we get a -, then a +, for the same key, we don't want to execute
         // two queries. We'll do this by sorting the batch by our join key.
+        let collation = self.collation();
         let mut rs: Vec<_> = rs.into();
         {
-            let cmp = |a: &Record, b: &Record| a[from_key].cmp(&b[from_key]);
+            let cmp = |a: &Record, b: &Record| {
+                collation
+                    .key(&a[from_key])
+                    .cmp(&collation.key(&b[from_key]))
+            };
             rs.sort_by(cmp);
         }
 
@@ -290,17 +339,31 @@ impl Ingredient for Join {
         while at != rs.len() {
             let mut old_right_count = None;
             let mut new_right_count = None;
-            let prev_join_key = rs[at][from_key].clone();
+            let prev_join_key = collation.key(&rs[at][from_key]).into_owned();
+
+            if prev_join_key == DataType::None {
+                // NULL is never equal to anything, so these records cannot match any row on the
+                // other side. Records from the left side of a left join are still emitted, padded
+                // with NULLs.
+                let start = at;
+                at = rs[at..]
+                    .iter()
+                    .position(|r| *collation.key(&r[from_key]) != prev_join_key)
+                    .map(|p| at + p)
+                    .unwrap_or(rs.len());
+                if self.kind == JoinType::Left && from == *self.left {
+                    for r in &mut rs[start..at] {
+                        let r = mem::replace(r, Record::Positive(Vec::new()));
+                        let (row, positive) = r.extract();
+                        ret.push((self.generate_null(&row), positive).into());
+                    }
+                }
+                continue;
+            }
 
             if from == *self.right && self.kind == JoinType::Left {
                 let rc = self
-                    .lookup(
-                        *self.right,
-                        &[self.on.1],
-                        &KeyType::Single(&prev_join_key),
-                        nodes,
-                        state,
-                    )
+                    .lookup_key(*self.right, self.on.1, &prev_join_key, nodes, state)
                     .unwrap();
 
                 if rc.is_none() {
@@ -315,7 +378,7 @@ impl Ingredient for Join {
                     // (possibly several times over for each a).
                     at = rs[at..]
                         .iter()
-                        .position(|r| r[from_key] != prev_join_key)
+                        .position(|r| *collation.key(&r[from_key]) != prev_join_key)
                         .map(|p| at + p)
                         .unwrap_or(rs.len());
                     continue;
@@ -328,13 +391,7 @@ impl Ingredient for Join {
 
             // get rows from the other side
             let mut other_rows = self
-                .lookup(
-                    other,
-                    &[other_key],
-                    &KeyType::Single(&prev_join_key),
-                    nodes,
-                    state,
-                )
+                .lookup_key(other, other_key, &prev_join_key, nodes, state)
                 .unwrap();
 
             if other_rows.is_none() {
@@ -342,7 +399,7 @@ impl Ingredient for Join {
                 let from = at;
                 at = rs[at..]
                     .iter()
-                    .position(|r| r[from_key] != prev_join_key)
+                    .position(|r| *collation.key(&r[from_key]) != prev_join_key)
                     .map(|p| at + p)
                     .unwrap_or(rs.len());
                 misses.extend((from..at).map(|i| Miss {
@@ -363,7 +420,7 @@ impl Ingredient for Join {
                 // records that existed *before* this batch of records was processed so we know
                 // whether or not to generate +/- NULL rows.
                 if let Some(mut old_rc) = old_right_count {
-                    while at != rs.len() && rs[at][from_key] == prev_join_key {
+                    while at != rs.len() && *collation.key(&rs[at][from_key]) == prev_join_key {
                         if rs[at].is_positive() {
                             old_rc -= 1
                         } else {
@@ -386,7 +443,7 @@ impl Ingredient for Join {
                     let start = at;
                     at = rs[at..]
                         .iter()
-                        .position(|r| r[from_key] != prev_join_key)
+                        .position(|r| *collation.key(&r[from_key]) != prev_join_key)
                         .map(|p| at + p)
                         .unwrap_or(rs.len());
                     misses.extend((start..at).map(|i| Miss {
@@ -405,7 +462,7 @@ impl Ingredient for Join {
                 // we didn't find the end above, so find it now
                 at = rs[at..]
                     .iter()
-                    .position(|r| r[from_key] != prev_join_key)
+                    .position(|r| *collation.key(&r[from_key]) != prev_join_key)
                     .map(|p| at + p)
                     .unwrap_or(rs.len());
             }
@@ -615,6 +672,28 @@ mod tests {
         (g, l, r)
     }
 
+    fn setup_collated(
+        left: Collation,
+        right: Collation,
+    ) -> (ops::test::MockGraph, IndexPair, IndexPair) {
+        let mut g = ops::test::MockGraph::new();
+        let l = g.add_base("left", &["l0", "l1"]);
+        let r = g.add_base("right", &["r0", "r1"]);
+        g.set_collations(l, vec![left]);
+        g.set_collations(r, vec![right]);
+
+        use self::JoinSource::*;
+        let j = Join::new(
+            l.as_global(),
+            r.as_global(),
+            JoinType::Inner,
+            vec![B(0, 0), L(1), R(1)],
+        );
+
+        g.set_op("join", &["j0", "j1", "j2"], j, false);
+        (g, l, r)
+    }
+
     #[test]
     fn it_describes() {
         let (j, l, r) = setup();
@@ -727,6 +806,60 @@ mod tests {
         assert_eq!(rs.len(), 0);
     }
 
+    #[test]
+    fn it_never_matches_nulls() {
+        let (mut j, l, r) = setup();
+        let l_null = vec![DataType::None, "a".into()];
+        let r_null = vec![DataType::None, "x".into()];
+
+        j.seed(r, r_null.clone());
+        let rs = j.one_row(r, r_null.clone(), false);
+        assert_eq!(rs.len(), 0);
+
+        // a NULL key on the left does not match the NULL on the right, but is still emitted
+        j.seed(l, l_null.clone());
+        let rs = j.one_row(l, l_null.clone(), false);
+        assert_eq!(
+            rs,
+            vec![(vec![DataType::None, "a".into(), DataType::None], true)].into()
+        );
+
+        // and a NULL key arriving from the right does not revoke the NULL-padded row
+        j.seed(r, r_null.clone());
+        let rs = j.one_row(r, r_null.clone(), false);
+        assert_eq!(rs.len(), 0);
+    }
+
+    #[test]
+    fn it_compares_keys_under_collations() {
+        let ci = Collation::UnicodeCaseInsensitive;
+        let (mut j, l, r) = setup_collated(ci, ci);
+        j.seed(r, vec!["bob".into(), "x".into()]);
+        j.seed(r, vec!["BOB".into(), "y".into()]);
+        j.seed(r, vec!["alice".into(), "z".into()]);
+        j.seed(l, vec!["Bob".into(), "a".into()]);
+        let rs = j.one_row(l, vec!["Bob".into(), "a".into()], false);
+        assert_eq!(
+            rs,
+            vec![
+                (vec!["Bob".into(), "a".into(), "x".into()], true),
+                (vec!["Bob".into(), "a".into(), "y".into()], true),
+            ]
+            .into()
+        );
+
+        // keys whose columns have different collations are compared byte-wise
+        let (mut j, l, r) = setup_collated(ci, Collation::Binary);
+        j.seed(l, vec!["bob".into(), "a".into()]);
+        j.seed(l, vec!["Bob".into(), "b".into()]);
+        j.seed(r, vec!["Bob".into(), "x".into()]);
+        let rs = j.one_row(r, vec!["Bob".into(), "x".into()], false);
+        assert_eq!(
+            rs,
+            vec![(vec!["Bob".into(), "b".into(), "x".into()], true)].into()
+        );
+    }
+
     #[test]
     fn it_suggests_indices() {
         use std::collections::HashMap;
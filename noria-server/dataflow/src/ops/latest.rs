@@ -1,16 +1,26 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 
+use ops;
 use prelude::*;
 
 /// Latest provides an operator that will maintain the last record for every group.
 ///
 /// Whenever a new record arrives for a group, the latest operator will negative the previous
-/// latest for that group.
+/// latest for that group. If the operator has an ordering column, the latest record is the one
+/// with the greatest value in that column, rather than the one that arrived last.
+///
+/// Since it ignores negative records, the operator is only correct over ancestors that never
+/// retract a record. The SQL planner uses it for queries with `ORDER BY c DESC LIMIT 1` over
+/// tables without a primary key, which can only be inserted into.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Latest {
     us: Option<IndexPair>,
     src: IndexPair,
-    key: usize,
+    key: Vec<usize>,
+    order: Option<usize>,
+    // The collation of each column of the parent, or nothing if they are all binary.
+    collations: Vec<Collation>,
 }
 
 impl Latest {
@@ -19,11 +29,40 @@ impl Latest {
     /// `src` should be the ancestor the operation is performed over, and `keys` should be a list
     /// of fields used to group records by. The latest record *within each group* will be
     /// maintained.
-    pub fn new(src: NodeIndex, key: usize) -> Latest {
+    pub fn new(src: NodeIndex, keys: Vec<usize>) -> Latest {
+        assert!(!keys.is_empty(), "latest needs at least one group column");
         Latest {
             us: None,
             src: src.into(),
-            key: key,
+            key: keys,
+            order: None,
+            collations: Vec::new(),
+        }
+    }
+
+    /// Maintain the record with the greatest value in column `order` for each group, rather than
+    /// the one that arrived last.
+    ///
+    /// This keeps each group's latest record correct even if records arrive out of order, such
+    /// as when `order` holds the time at which a record was produced. Records that are `NULL` in
+    /// column `order` are older than any that are not.
+    pub fn with_order(mut self, order: usize) -> Latest {
+        self.order = Some(order);
+        self
+    }
+
+    /// Returns true if `r` should replace `current` as the latest record of its group.
+    fn supersedes(&self, r: &[DataType], current: &[DataType]) -> bool {
+        match self.order {
+            Some(c) => match (&r[c], &current[c]) {
+                // NULL sorts after everything else, but a record without a time is the oldest
+                (&DataType::None, &DataType::None) => true,
+                (&DataType::None, _) => false,
+                (_, &DataType::None) => true,
+                // on ties, the record that arrived last wins
+                (r, current) => r >= current,
+            },
+            None => true,
         }
     }
 }
@@ -37,7 +76,9 @@ impl Ingredient for Latest {
         vec![self.src.as_global()]
     }
 
-    fn on_connected(&mut self, _: &Graph) {}
+    fn on_connected(&mut self, g: &Graph) {
+        self.collations = g[self.src.as_global()].collations().to_vec();
+    }
 
     fn on_commit(&mut self, us: NodeIndex, remap: &HashMap<NodeIndex, IndexPair>) {
         self.src.remap(remap);
@@ -55,6 +96,31 @@ impl Ingredient for Latest {
     ) -> ProcessingResult {
         debug_assert_eq!(from, *self.src);
 
+        // gather the new records for each group, in the order the groups first appear
+        let mut groups: Vec<(Vec<DataType>, Vec<Vec<DataType>>)> = Vec::new();
+        let mut group_index: HashMap<Vec<DataType>, usize> = HashMap::new();
+        for r in rs {
+            // We don't allow standalone negatives as input to a latest. This is because it
+            // would be very computationally expensive (and currently impossible) to find what
+            // the *previous* latest was if the current latest was revoked.
+            if !r.is_positive() {
+                continue;
+            }
+
+            let r = r.extract().0;
+            let group: Vec<DataType> = ops::collated(&r, &self.key, &self.collations)
+                .map(Cow::into_owned)
+                .collect();
+            let existing = group_index.get(&group).cloned();
+            match existing {
+                Some(i) => groups[i].1.push(r),
+                None => {
+                    group_index.insert(group.clone(), groups.len());
+                    groups.push((group, vec![r]));
+                }
+            }
+        }
+
         // find the current value for each group
         let us = self.us.unwrap();
         let db = state
@@ -62,45 +128,44 @@ impl Ingredient for Latest {
             .expect("latest must have its own state materialized");
 
         let mut misses = Vec::new();
-        let mut out = Vec::with_capacity(rs.len());
-        {
-            let currents = rs.into_iter().filter_map(|r| {
-                // We don't allow standalone negatives as input to a latest. This is because it
-                // would be very computationally expensive (and currently impossible) to find what
-                // the *previous* latest was if the current latest was revoked.
-                if !r.is_positive() {
-                    return None;
-                }
-
-                match db.lookup(&[self.key], &KeyType::Single(&r[self.key])) {
-                    LookupResult::Some(rs) => {
-                        debug_assert!(rs.len() <= 1, "a group had more than 1 result");
-                        Some((r, rs))
+        let mut out = Vec::with_capacity(groups.len());
+        for (group, rs) in groups {
+            match db.lookup(&self.key[..], &KeyType::from(&group[..])) {
+                LookupResult::Some(current) => {
+                    debug_assert!(current.len() <= 1, "a group had more than 1 result");
+
+                    let mut rs = rs.into_iter();
+                    let mut latest = rs.next().unwrap();
+                    for r in rs {
+                        if self.supersedes(&r, &latest) {
+                            latest = r;
+                        }
                     }
-                    LookupResult::Missing => {
-                        // we don't actively materialize holes unless requested by a read. this
-                        // can't be a read, because reads cause replay, which fill holes with an
-                        // empty set before processing!
-                        misses.push(Miss {
-                            on: *us,
-                            lookup_idx: vec![self.key],
-                            lookup_cols: vec![self.key],
-                            replay_cols: replay_key_cols.map(Vec::from),
-                            record: r.extract().0,
-                        });
-                        None
+
+                    match current.into_iter().next() {
+                        Some(ref row) if !self.supersedes(&latest, row) => {
+                            // the group's current latest is still the latest
+                        }
+                        Some(row) => {
+                            // revoke the old latest for this group
+                            out.push(Record::Negative(row.into_owned()));
+                            out.push(Record::Positive(latest));
+                        }
+                        None => out.push(Record::Positive(latest)),
                     }
                 }
-            });
-
-            // buffer emitted records
-            for (r, current_row) in currents {
-                if let Some(row) = current_row.into_iter().next() {
-                    out.push(Record::Negative(row.into_owned()));
+                LookupResult::Missing => {
+                    // we don't actively materialize holes unless requested by a read. this
+                    // can't be a read, because reads cause replay, which fill holes with an
+                    // empty set before processing!
+                    misses.extend(rs.into_iter().map(|r| Miss {
+                        on: *us,
+                        lookup_idx: self.key.clone(),
+                        lookup_cols: self.key.clone(),
+                        replay_cols: replay_key_cols.map(Vec::from),
+                        record: r,
+                    }));
                 }
-
-                // if there was a previous latest for this key, revoke old record
-                out.push(r);
             }
         }
 
@@ -114,7 +179,7 @@ impl Ingredient for Latest {
 
     fn suggest_indexes(&self, this: NodeIndex) -> HashMap<NodeIndex, (Vec<usize>, bool)> {
         // index all key columns
-        Some((this, (vec![self.key], true))).into_iter().collect()
+        Some((this, (self.key.clone(), true))).into_iter().collect()
     }
 
     fn resolve(&self, col: usize) -> Option<Vec<(NodeIndex, usize)>> {
@@ -123,9 +188,18 @@ impl Ingredient for Latest {
 
     fn description(&self, detailed: bool) -> String {
         if !detailed {
-            String::from("⧖")
-        } else {
-            format!("⧖ γ[{}]", self.key)
+            return String::from("⧖");
+        }
+
+        let key_cols = self
+            .key
+            .iter()
+            .map(|k| k.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        match self.order {
+            Some(c) => format!("⧖({}) γ[{}]", c, key_cols),
+            None => format!("⧖ γ[{}]", key_cols),
         }
     }
 
@@ -143,7 +217,24 @@ mod tests {
     fn setup(key: usize, mat: bool) -> ops::test::MockGraph {
         let mut g = ops::test::MockGraph::new();
         let s = g.add_base("source", &["x", "y"]);
-        g.set_op("latest", &["x", "y"], Latest::new(s.as_global(), key), mat);
+        g.set_op(
+            "latest",
+            &["x", "y"],
+            Latest::new(s.as_global(), vec![key]),
+            mat,
+        );
+        g
+    }
+
+    fn setup_ordered() -> ops::test::MockGraph {
+        let mut g = ops::test::MockGraph::new();
+        let s = g.add_base("source", &["x", "y", "t"]);
+        g.set_op(
+            "latest",
+            &["x", "y", "t"],
+            Latest::new(s.as_global(), vec![0, 1]).with_order(2),
+            true,
+        );
         g
     }
 
@@ -246,6 +337,78 @@ mod tests {
         }));
     }
 
+    #[test]
+    fn it_describes_ordered() {
+        let c = setup_ordered();
+        assert_eq!(c.node().description(true), "⧖(2) γ[0, 1]");
+    }
+
+    #[test]
+    fn it_keeps_latest_by_order() {
+        let mut c = setup_ordered();
+
+        let rs = c.narrow_one_row(vec![1.into(), 1.into(), 10.into()], true);
+        assert_eq!(rs, vec![vec![1.into(), 1.into(), 10.into()]].into());
+
+        // groups are made up of all the key columns
+        let rs = c.narrow_one_row(vec![1.into(), 2.into(), 5.into()], true);
+        assert_eq!(rs, vec![vec![1.into(), 2.into(), 5.into()]].into());
+
+        // an older record that arrives late does not replace the latest
+        let rs = c.narrow_one_row(vec![1.into(), 1.into(), 7.into()], true);
+        assert!(rs.is_empty());
+
+        // a newer one does
+        let rs = c.narrow_one_row(vec![1.into(), 1.into(), 12.into()], true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), 1.into(), 10.into()], false),
+                (vec![1.into(), 1.into(), 12.into()], true),
+            ]
+            .into()
+        );
+
+        // only the newest of several records for a group in a batch is kept
+        let u = vec![
+            (vec![1.into(), 2.into(), 9.into()], true),
+            (vec![1.into(), 2.into(), 11.into()], true),
+            (vec![1.into(), 2.into(), 8.into()], true),
+        ];
+        let rs = c.narrow_one(u, true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), 2.into(), 5.into()], false),
+                (vec![1.into(), 2.into(), 11.into()], true),
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn it_keeps_null_order_oldest() {
+        let mut c = setup_ordered();
+
+        let rs = c.narrow_one_row(vec![1.into(), 1.into(), DataType::None], true);
+        assert_eq!(rs, vec![vec![1.into(), 1.into(), DataType::None]].into());
+
+        // any ordered record replaces one without an order
+        let rs = c.narrow_one_row(vec![1.into(), 1.into(), 3.into()], true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), 1.into(), DataType::None], false),
+                (vec![1.into(), 1.into(), 3.into()], true),
+            ]
+            .into()
+        );
+
+        // but a record without an order never replaces one with an order
+        let rs = c.narrow_one_row(vec![1.into(), 1.into(), DataType::None], true);
+        assert!(rs.is_empty());
+    }
+
     #[test]
     fn it_suggests_indices() {
         let me = 1.into();
@@ -9,19 +9,34 @@ pub mod grouped;
 pub mod identity;
 pub mod join;
 pub mod latest;
+pub mod multijoin;
 pub mod project;
 pub mod rewrite;
 pub mod topk;
 pub mod trigger;
 pub mod union;
 
+/// The values of the given columns of `r`, as they are compared under the collation of each
+/// column.
+pub(crate) fn collated<'a>(
+    r: &'a [DataType],
+    columns: &'a [usize],
+    collations: &'a [Collation],
+) -> impl Iterator<Item = Cow<'a, DataType>> + 'a {
+    columns
+        .iter()
+        .map(move |&c| Collation::of(collations, c).key(&r[c]))
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub enum NodeOperator {
     Sum(grouped::GroupedOperator<grouped::aggregate::Aggregator>),
     Extremum(grouped::GroupedOperator<grouped::extremum::ExtremumOperator>),
     Concat(grouped::GroupedOperator<grouped::concat::GroupConcat>),
+    JsonAgg(grouped::GroupedOperator<grouped::json::JsonAggregate>),
     Join(join::Join),
     Latest(latest::Latest),
+    MultiJoin(multijoin::MultiJoin),
     Project(project::Project),
     Union(union::Union),
     Identity(identity::Identity),
@@ -54,8 +69,13 @@ nodeop_from_impl!(
     NodeOperator::Concat,
     grouped::GroupedOperator<grouped::concat::GroupConcat>
 );
+nodeop_from_impl!(
+    NodeOperator::JsonAgg,
+    grouped::GroupedOperator<grouped::json::JsonAggregate>
+);
 nodeop_from_impl!(NodeOperator::Join, join::Join);
 nodeop_from_impl!(NodeOperator::Latest, latest::Latest);
+nodeop_from_impl!(NodeOperator::MultiJoin, multijoin::MultiJoin);
 nodeop_from_impl!(NodeOperator::Project, project::Project);
 nodeop_from_impl!(NodeOperator::Union, union::Union);
 nodeop_from_impl!(NodeOperator::Identity, identity::Identity);
@@ -71,8 +91,10 @@ macro_rules! impl_ingredient_fn_mut {
             NodeOperator::Sum(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Extremum(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Concat(ref mut i) => i.$fn($($arg),*),
+            NodeOperator::JsonAgg(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Join(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Latest(ref mut i) => i.$fn($($arg),*),
+            NodeOperator::MultiJoin(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Project(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Union(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Identity(ref mut i) => i.$fn($($arg),*),
@@ -91,8 +113,10 @@ macro_rules! impl_ingredient_fn_ref {
             NodeOperator::Sum(ref i) => i.$fn($($arg),*),
             NodeOperator::Extremum(ref i) => i.$fn($($arg),*),
             NodeOperator::Concat(ref i) => i.$fn($($arg),*),
+            NodeOperator::JsonAgg(ref i) => i.$fn($($arg),*),
             NodeOperator::Join(ref i) => i.$fn($($arg),*),
             NodeOperator::Latest(ref i) => i.$fn($($arg),*),
+            NodeOperator::MultiJoin(ref i) => i.$fn($($arg),*),
             NodeOperator::Project(ref i) => i.$fn($($arg),*),
             NodeOperator::Union(ref i) => i.$fn($($arg),*),
             NodeOperator::Identity(ref i) => i.$fn($($arg),*),
@@ -283,6 +307,14 @@ pub mod test {
             ip
         }
 
+        /// Compare the columns of the given base under the given collations.
+        pub fn set_collations(&mut self, base: IndexPair, collations: Vec<Collation>) {
+            assert!(self.nut.is_none(), "collations must be set before set_op");
+            self.graph[base.as_global()].set_collations(collations.clone());
+            let state = MemoryState::default().with_collations(collations);
+            self.states.insert(*base, box state);
+        }
+
         pub fn graphviz(&self) -> String {
             let mut s = String::new();
 
@@ -346,10 +378,14 @@ pub mod test {
             assert!(!parents.is_empty(), "node under test should have ancestors");
 
             let i: NodeOperator = i.into();
-            let global = self.graph.add_node(Node::new(name, fields, i));
+            let mut node = Node::new(name, fields, i);
+            node.inherit_collations(&self.graph);
+            let collations = node.collations().to_vec();
+            let global = self.graph.add_node(node);
             let local = unsafe { LocalNodeIndex::make(self.remap.len() as u32) };
             if materialized {
-                self.states.insert(local, box MemoryState::default());
+                let state = MemoryState::default().with_collations(collations);
+                self.states.insert(local, box state);
             }
             for parent in parents {
                 self.graph.add_edge(parent, global, ());
@@ -446,7 +482,8 @@ pub mod test {
             assert!(self.nut.is_some(), "unseed must happen after set_op");
             let global = self.nut.unwrap().as_global();
             let idx = self.graph[global].suggest_indexes(global);
-            let mut state = MemoryState::default();
+            let collations = self.graph[base.as_global()].collations().to_vec();
+            let mut state = MemoryState::default().with_collations(collations);
             for (tbl, (col, _)) in idx {
                 if tbl == base.as_global() {
                     state.add_key(&col[..], None);
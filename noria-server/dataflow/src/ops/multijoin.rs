@@ -0,0 +1,463 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use prelude::*;
+
+/// MultiJoin provides an inner join between any number of views on a single shared key.
+///
+/// Each incoming record is joined by probing the other parents' indexes on the join key directly,
+/// so joining several tables against the same column of a central table takes one node rather
+/// than a chain of binary joins that each keep their own state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiJoin {
+    parents: Vec<IndexPair>,
+
+    // Key column in each of the parents
+    on: Vec<usize>,
+
+    // Collation of the key column in each of the parents
+    collations: Vec<Collation>,
+
+    // Which columns to emit. Each entry gives the parent (by its position in `parents`) and the
+    // column in that parent.
+    emit: Vec<(usize, usize)>,
+}
+
+impl MultiJoin {
+    /// Create a new instance of MultiJoin
+    ///
+    /// `parents` lists each parent along with its join column, and `emit` dictates for each output
+    /// column which parent (by its position in `parents`) and which column of that parent should
+    /// be used.
+    pub fn new(parents: Vec<(NodeIndex, usize)>, emit: Vec<(usize, usize)>) -> Self {
+        assert!(parents.len() >= 2, "a join needs at least two parents");
+        assert!(
+            emit.iter().all(|&(p, _)| p < parents.len()),
+            "join emits a column from a parent it does not have"
+        );
+
+        let (parents, on): (Vec<IndexPair>, Vec<usize>) = parents
+            .into_iter()
+            .map(|(parent, col)| (parent.into(), col))
+            .unzip();
+
+        MultiJoin {
+            collations: vec![Collation::Binary; parents.len()],
+            parents: parents,
+            on: on,
+            emit: emit,
+        }
+    }
+
+    // Find the column of parent `side` that holds the value of output column `col`.
+    fn input_column(&self, side: usize, col: usize) -> Option<usize> {
+        let (parent, c) = self.emit[col];
+        if parent == side {
+            Some(c)
+        } else if c == self.on[parent] {
+            // the join column holds the same value in every parent
+            Some(self.on[side])
+        } else {
+            None
+        }
+    }
+
+    // The collation join keys are compared under: that of the key columns if they all agree, and
+    // byte-wise otherwise.
+    fn collation(&self) -> Collation {
+        let first = self.collations[0];
+        if self.collations.iter().all(|&c| c == first) {
+            first
+        } else {
+            Collation::Binary
+        }
+    }
+
+    fn generate_row(&self, rows: &[&[DataType]]) -> Vec<DataType> {
+        self.emit
+            .iter()
+            .map(|&(parent, col)| rows[parent][col].clone())
+            .collect()
+    }
+}
+
+impl Ingredient for MultiJoin {
+    fn take(&mut self) -> NodeOperator {
+        Clone::clone(self).into()
+    }
+
+    fn ancestors(&self) -> Vec<NodeIndex> {
+        self.parents.iter().map(|p| p.as_global()).collect()
+    }
+
+    fn is_join(&self) -> bool {
+        true
+    }
+
+    fn must_replay_among(&self) -> Option<HashSet<NodeIndex>> {
+        Some(self.parents.iter().map(|p| p.as_global()).collect())
+    }
+
+    fn on_connected(&mut self, g: &Graph) {
+        self.collations = self
+            .parents
+            .iter()
+            .zip(self.on.iter())
+            .map(|(parent, &col)| Collation::of(g[parent.as_global()].collations(), col))
+            .collect();
+    }
+
+    fn on_commit(&mut self, _: NodeIndex, remap: &HashMap<NodeIndex, IndexPair>) {
+        for parent in &mut self.parents {
+            parent.remap(remap);
+        }
+    }
+
+    fn on_input(
+        &mut self,
+        from: LocalNodeIndex,
+        rs: Records,
+        _: &mut Tracer,
+        replay_key_cols: Option<&[usize]>,
+        nodes: &DomainNodes,
+        state: &StateMap,
+    ) -> ProcessingResult {
+        if rs.is_empty() {
+            return ProcessingResult {
+                results: rs,
+                misses: vec![],
+            };
+        }
+
+        let side = self.parents.iter().position(|p| **p == from).unwrap();
+        let from_key = self.on[side];
+
+        let replay_key_cols: Option<Vec<usize>> = replay_key_cols.map(|cols| {
+            cols.iter()
+                .map(|&col| {
+                    // we're getting a partial replay, but the replay key doesn't exist in the
+                    // parent we're getting the replay from?!
+                    self.input_column(side, col).unwrap()
+                })
+                .collect()
+        });
+
+        // as in Join, sort the batch by the join key so that we only probe the other parents once
+        // for each distinct key.
+        let collation = self.collation();
+        let mut rs: Vec<_> = rs.into();
+        rs.sort_by(|a: &Record, b: &Record| {
+            collation
+                .key(&a[from_key])
+                .cmp(&collation.key(&b[from_key]))
+        });
+
+        let mut misses = Vec::new();
+        let mut ret: Vec<Record> = Vec::with_capacity(rs.len());
+        let mut at = 0;
+        while at != rs.len() {
+            let start = at;
+            let join_key = collation.key(&rs[start][from_key]).into_owned();
+            at = rs[at..]
+                .iter()
+                .position(|r| *collation.key(&r[from_key]) != join_key)
+                .map(|p| at + p)
+                .unwrap_or(rs.len());
+
+            if join_key == DataType::None {
+                // NULL is never equal to anything, so these records cannot match any rows
+                continue;
+            }
+
+            // get the matching rows from every other parent
+            let mut other_rows: Vec<Vec<Vec<DataType>>> = Vec::with_capacity(self.parents.len());
+            let mut missed = Vec::new();
+            for (i, parent) in self.parents.iter().enumerate() {
+                if i == side {
+                    other_rows.push(Vec::new());
+                    continue;
+                }
+
+                let rows = self
+                    .lookup(
+                        **parent,
+                        &[self.on[i]],
+                        &KeyType::Single(&join_key),
+                        nodes,
+                        state,
+                    )
+                    .unwrap();
+                // the parent's state compares keys under the collation of its own key column,
+                // which may match more rows than the join does
+                let exact = self.collations[i] != collation;
+                let key = self.on[i];
+                match rows {
+                    Some(rows) => other_rows.push(
+                        rows.filter(|r| !exact || r[key] == join_key)
+                            .map(|r| r.into_owned())
+                            .collect(),
+                    ),
+                    None => {
+                        missed.push(i);
+                        other_rows.push(Vec::new());
+                    }
+                }
+            }
+
+            if !missed.is_empty() {
+                // we missed in at least one of the other parents!
+                for r in &mut rs[start..at] {
+                    // NOTE: we're stealing data here!
+                    let record = mem::replace(&mut **r, Vec::new());
+                    misses.extend(missed.iter().map(|&i| Miss {
+                        on: *self.parents[i],
+                        lookup_idx: vec![self.on[i]],
+                        lookup_cols: vec![from_key],
+                        replay_cols: replay_key_cols.clone(),
+                        record: record.clone(),
+                    }));
+                }
+                continue;
+            }
+
+            if other_rows
+                .iter()
+                .enumerate()
+                .any(|(i, rows)| i != side && rows.is_empty())
+            {
+                // some parent has no rows with this key, so nothing joins
+                continue;
+            }
+
+            for r in &rs[start..at] {
+                // walk the cross product of the other parents' rows, advancing the first parent
+                // fastest
+                let mut next = vec![0; self.parents.len()];
+                loop {
+                    {
+                        let row: Vec<&[DataType]> = (0..self.parents.len())
+                            .map(|i| {
+                                if i == side {
+                                    &r[..]
+                                } else {
+                                    &other_rows[i][next[i]][..]
+                                }
+                            })
+                            .collect();
+                        ret.push((self.generate_row(&row[..]), r.is_positive()).into());
+                    }
+
+                    let mut i = 0;
+                    while i != next.len() {
+                        if i != side {
+                            next[i] += 1;
+                            if next[i] != other_rows[i].len() {
+                                break;
+                            }
+                            next[i] = 0;
+                        }
+                        i += 1;
+                    }
+                    if i == next.len() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        ProcessingResult {
+            results: ret.into(),
+            misses: misses,
+        }
+    }
+
+    fn suggest_indexes(&self, _this: NodeIndex) -> HashMap<NodeIndex, (Vec<usize>, bool)> {
+        self.parents
+            .iter()
+            .zip(self.on.iter())
+            .map(|(parent, &col)| (parent.as_global(), (vec![col], true)))
+            .collect()
+    }
+
+    fn resolve(&self, col: usize) -> Option<Vec<(NodeIndex, usize)>> {
+        let (parent, c) = self.emit[col];
+        Some(vec![(self.parents[parent].as_global(), c)])
+    }
+
+    fn description(&self, detailed: bool) -> String {
+        if !detailed {
+            return String::from("⋈");
+        }
+
+        let emit = self
+            .emit
+            .iter()
+            .map(|&(parent, col)| format!("{}:{}", self.parents[parent].as_global().index(), col))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let on = self
+            .parents
+            .iter()
+            .zip(self.on.iter())
+            .map(|(parent, col)| format!("{}:{}", parent.as_global().index(), col))
+            .collect::<Vec<_>>()
+            .join(" ⋈ ");
+
+        format!("[{}] {}", emit, on)
+    }
+
+    fn parent_columns(&self, col: usize) -> Vec<(NodeIndex, Option<usize>)> {
+        let (parent, c) = self.emit[col];
+        if c == self.on[parent] {
+            // Join column comes from all parents
+            self.parents
+                .iter()
+                .zip(self.on.iter())
+                .map(|(parent, &col)| (parent.as_global(), Some(col)))
+                .collect()
+        } else {
+            vec![(self.parents[parent].as_global(), Some(c))]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ops;
+
+    fn setup() -> (ops::test::MockGraph, IndexPair, IndexPair, IndexPair) {
+        let mut g = ops::test::MockGraph::new();
+        let h = g.add_base("hub", &["h0", "h1"]);
+        let a = g.add_base("a", &["a0", "a1"]);
+        let b = g.add_base("b", &["b0", "b1"]);
+
+        let j = MultiJoin::new(
+            vec![(h.as_global(), 0), (a.as_global(), 0), (b.as_global(), 0)],
+            vec![(0, 0), (0, 1), (1, 1), (2, 1)],
+        );
+
+        g.set_op("join", &["j0", "j1", "j2", "j3"], j, false);
+        (g, h, a, b)
+    }
+
+    #[test]
+    fn it_describes() {
+        let (j, h, a, b) = setup();
+        assert_eq!(
+            j.node().description(true),
+            format!(
+                "[{}:0, {}:1, {}:1, {}:1] {}:0 ⋈ {}:0 ⋈ {}:0",
+                h, h, a, b, h, a, b
+            )
+        );
+    }
+
+    #[test]
+    fn it_works() {
+        let (mut j, h, a, b) = setup();
+        let h_1 = vec![1.into(), "h".into()];
+        let a_x1 = vec![1.into(), "x".into()];
+        let a_y1 = vec![1.into(), "y".into()];
+        let a_z2 = vec![2.into(), "z".into()];
+        let b_p1 = vec![1.into(), "p".into()];
+        let b_q1 = vec![1.into(), "q".into()];
+
+        j.seed(a, a_x1.clone());
+        j.seed(a, a_y1.clone());
+        j.seed(b, b_p1.clone());
+
+        // forward from the hub; should produce one row per combination of matching rows
+        j.seed(h, h_1.clone());
+        let rs = j.one_row(h, h_1.clone(), false);
+        assert_eq!(
+            rs,
+            vec![
+                vec![1.into(), "h".into(), "x".into(), "p".into()],
+                vec![1.into(), "h".into(), "y".into(), "p".into()],
+            ]
+            .into()
+        );
+
+        // forward from an outer parent; should join with the hub and the other outer parent
+        j.seed(b, b_q1.clone());
+        let rs = j.one_row(b, b_q1.clone(), false);
+        assert_eq!(
+            rs,
+            vec![
+                vec![1.into(), "h".into(), "x".into(), "q".into()],
+                vec![1.into(), "h".into(), "y".into(), "q".into()],
+            ]
+            .into()
+        );
+
+        // negatives retract the same rows
+        let rs = j.one_row(a, (a_x1.clone(), false), false);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), "h".into(), "x".into(), "p".into()], false),
+                (vec![1.into(), "h".into(), "x".into(), "q".into()], false),
+            ]
+            .into()
+        );
+
+        // a key that is missing from any one parent produces nothing
+        j.seed(a, a_z2.clone());
+        let rs = j.one_row(a, a_z2.clone(), false);
+        assert_eq!(rs.len(), 0);
+    }
+
+    #[test]
+    fn it_never_matches_nulls() {
+        let (mut j, h, a, b) = setup();
+        let h_null = vec![DataType::None, "h".into()];
+        let a_null = vec![DataType::None, "x".into()];
+        let b_null = vec![DataType::None, "p".into()];
+
+        j.seed(a, a_null.clone());
+        j.seed(b, b_null.clone());
+        j.seed(h, h_null.clone());
+        let rs = j.one_row(h, h_null.clone(), false);
+        assert_eq!(rs.len(), 0);
+    }
+
+    #[test]
+    fn it_suggests_indices() {
+        let me = 3.into();
+        let (g, h, a, b) = setup();
+        let hm: HashMap<_, _> = vec![
+            (h.as_global(), (vec![0], true)),
+            (a.as_global(), (vec![0], true)),
+            (b.as_global(), (vec![0], true)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(g.node().suggest_indexes(me), hm);
+    }
+
+    #[test]
+    fn it_resolves() {
+        let (g, h, a, b) = setup();
+        assert_eq!(g.node().resolve(0), Some(vec![(h.as_global(), 0)]));
+        assert_eq!(g.node().resolve(1), Some(vec![(h.as_global(), 1)]));
+        assert_eq!(g.node().resolve(2), Some(vec![(a.as_global(), 1)]));
+        assert_eq!(g.node().resolve(3), Some(vec![(b.as_global(), 1)]));
+    }
+
+    #[test]
+    fn it_traces_join_column_to_all_parents() {
+        let (g, h, a, b) = setup();
+        assert_eq!(
+            g.node().parent_columns(0),
+            vec![
+                (h.as_global(), Some(0)),
+                (a.as_global(), Some(0)),
+                (b.as_global(), Some(0)),
+            ]
+        );
+        assert_eq!(g.node().parent_columns(2), vec![(a.as_global(), Some(1))]);
+    }
+}
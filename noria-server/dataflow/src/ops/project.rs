@@ -4,6 +4,7 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt;
 
+use noria::JsonPath;
 use prelude::*;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,10 +14,19 @@ pub enum ProjectExpressionBase {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProjectExpression {
-    op: ArithmeticOperator,
-    left: ProjectExpressionBase,
-    right: ProjectExpressionBase,
+pub enum ProjectExpression {
+    Arithmetic {
+        op: ArithmeticOperator,
+        left: ProjectExpressionBase,
+        right: ProjectExpressionBase,
+    },
+    /// The part of a JSON document at `path`, i.e., `JSON_EXTRACT(json, path)`, or
+    /// `json ->> path` if `unquote` is set.
+    JsonExtract {
+        json: ProjectExpressionBase,
+        path: JsonPath,
+        unquote: bool,
+    },
 }
 
 impl ProjectExpression {
@@ -25,12 +35,24 @@ impl ProjectExpression {
         left: ProjectExpressionBase,
         right: ProjectExpressionBase,
     ) -> ProjectExpression {
-        ProjectExpression {
+        ProjectExpression::Arithmetic {
             op: op,
             left: left,
             right: right,
         }
     }
+
+    pub fn json_extract(
+        json: ProjectExpressionBase,
+        path: JsonPath,
+        unquote: bool,
+    ) -> ProjectExpression {
+        ProjectExpression::JsonExtract {
+            json,
+            path,
+            unquote,
+        }
+    }
 }
 
 impl fmt::Display for ProjectExpressionBase {
@@ -44,14 +66,30 @@ impl fmt::Display for ProjectExpressionBase {
 
 impl fmt::Display for ProjectExpression {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let op = match self.op {
-            ArithmeticOperator::Add => "+",
-            ArithmeticOperator::Subtract => "-",
-            ArithmeticOperator::Divide => "/",
-            ArithmeticOperator::Multiply => "*",
-        };
-
-        write!(f, "{} {} {}", self.left, op, self.right)
+        match *self {
+            ProjectExpression::Arithmetic {
+                ref op,
+                ref left,
+                ref right,
+            } => {
+                let op = match *op {
+                    ArithmeticOperator::Add => "+",
+                    ArithmeticOperator::Subtract => "-",
+                    ArithmeticOperator::Divide => "/",
+                    ArithmeticOperator::Multiply => "*",
+                };
+
+                write!(f, "{} {} {}", left, op, right)
+            }
+            ProjectExpression::JsonExtract {
+                ref json,
+                ref path,
+                unquote,
+            } => {
+                let op = if unquote { "->>" } else { "->" };
+                write!(f, "{} {} {}", json, op, path)
+            }
+        }
     }
 }
 
@@ -96,22 +134,41 @@ impl Project {
     }
 }
 
-fn eval_expression(expression: &ProjectExpression, record: &[DataType]) -> DataType {
-    let left = match expression.left {
-        ProjectExpressionBase::Column(i) => &record[i],
-        ProjectExpressionBase::Literal(ref data) => data,
-    };
-
-    let right = match expression.right {
+fn eval_base<'a>(base: &'a ProjectExpressionBase, record: &'a [DataType]) -> &'a DataType {
+    match *base {
         ProjectExpressionBase::Column(i) => &record[i],
         ProjectExpressionBase::Literal(ref data) => data,
-    };
+    }
+}
 
-    match expression.op {
-        ArithmeticOperator::Add => left + right,
-        ArithmeticOperator::Subtract => left - right,
-        ArithmeticOperator::Multiply => left * right,
-        ArithmeticOperator::Divide => left / right,
+fn eval_expression(expression: &ProjectExpression, record: &[DataType]) -> DataType {
+    match *expression {
+        ProjectExpression::Arithmetic {
+            ref op,
+            ref left,
+            ref right,
+        } => {
+            let left = eval_base(left, record);
+            let right = eval_base(right, record);
+            match *op {
+                ArithmeticOperator::Add => left + right,
+                ArithmeticOperator::Subtract => left - right,
+                ArithmeticOperator::Multiply => left * right,
+                ArithmeticOperator::Divide => left / right,
+            }
+        }
+        ProjectExpression::JsonExtract {
+            ref json,
+            ref path,
+            unquote,
+        } => {
+            let part = eval_base(json, record).json_extract(path);
+            if unquote {
+                part.json_unquote()
+            } else {
+                part
+            }
+        }
     }
 }
 
@@ -346,11 +403,11 @@ mod tests {
     }
 
     fn setup_column_arithmetic(op: ArithmeticOperator) -> ops::test::MockGraph {
-        let expression = ProjectExpression {
-            left: ProjectExpressionBase::Column(0),
-            right: ProjectExpressionBase::Column(1),
-            op: op,
-        };
+        let expression = ProjectExpression::new(
+            op,
+            ProjectExpressionBase::Column(0),
+            ProjectExpressionBase::Column(1),
+        );
 
         setup_arithmetic(expression)
     }
@@ -468,11 +525,11 @@ mod tests {
     #[test]
     fn it_forwards_arithmetic_w_literals() {
         let number: DataType = 40.into();
-        let expression = ProjectExpression {
-            left: ProjectExpressionBase::Column(0),
-            right: ProjectExpressionBase::Literal(number),
-            op: ArithmeticOperator::Multiply,
-        };
+        let expression = ProjectExpression::new(
+            ArithmeticOperator::Multiply,
+            ProjectExpressionBase::Column(0),
+            ProjectExpressionBase::Literal(number),
+        );
 
         let mut p = setup_arithmetic(expression);
         let rec = vec![10.into(), 0.into()];
@@ -486,11 +543,11 @@ mod tests {
     fn it_forwards_arithmetic_w_only_literals() {
         let a: DataType = 80.into();
         let b: DataType = 40.into();
-        let expression = ProjectExpression {
-            left: ProjectExpressionBase::Literal(a),
-            right: ProjectExpressionBase::Literal(b),
-            op: ArithmeticOperator::Divide,
-        };
+        let expression = ProjectExpression::new(
+            ArithmeticOperator::Divide,
+            ProjectExpressionBase::Literal(a),
+            ProjectExpressionBase::Literal(b),
+        );
 
         let mut p = setup_arithmetic(expression);
         let rec = vec![0.into(), 0.into()];
@@ -500,6 +557,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_forwards_json_extractions() {
+        let doc = DataType::json(r#"{"a": {"b": "x"}}"#).unwrap();
+        let path: JsonPath = "$.a.b".parse().unwrap();
+
+        let expression =
+            ProjectExpression::json_extract(ProjectExpressionBase::Column(1), path.clone(), false);
+        let mut p = setup_arithmetic(expression);
+        let rec = vec![0.into(), doc.clone()];
+        assert_eq!(
+            p.narrow_one_row(rec.clone(), false),
+            vec![vec![
+                0.into(),
+                doc.clone(),
+                DataType::json("\"x\"").unwrap()
+            ]]
+            .into()
+        );
+
+        let expression =
+            ProjectExpression::json_extract(ProjectExpressionBase::Column(1), path, true);
+        let mut p = setup_arithmetic(expression);
+        assert_eq!(
+            p.narrow_one_row(rec, false),
+            vec![vec![0.into(), doc, "x".into()]].into()
+        );
+    }
+
     fn setup_query_through(
         mut state: Box<State>,
         permutation: &[usize],
@@ -612,11 +697,11 @@ mod tests {
     #[test]
     fn it_queries_through_w_arithmetic_and_literals() {
         let additional = Some(vec![DataType::Int(42)]);
-        let expressions = Some(vec![ProjectExpression {
-            left: ProjectExpressionBase::Column(0),
-            right: ProjectExpressionBase::Column(1),
-            op: ArithmeticOperator::Add,
-        }]);
+        let expressions = Some(vec![ProjectExpression::new(
+            ArithmeticOperator::Add,
+            ProjectExpressionBase::Column(0),
+            ProjectExpressionBase::Column(1),
+        )]);
 
         let state = box MemoryState::default();
         let (p, states) = setup_query_through(state, &[1], additional, expressions);
@@ -627,11 +712,11 @@ mod tests {
     #[test]
     fn it_queries_through_w_arithmetic_and_literals_persistent() {
         let additional = Some(vec![DataType::Int(42)]);
-        let expressions = Some(vec![ProjectExpression {
-            left: ProjectExpressionBase::Column(0),
-            right: ProjectExpressionBase::Column(1),
-            op: ArithmeticOperator::Add,
-        }]);
+        let expressions = Some(vec![ProjectExpression::new(
+            ArithmeticOperator::Add,
+            ProjectExpressionBase::Column(0),
+            ProjectExpressionBase::Column(1),
+        )]);
 
         let state = box PersistentState::new(
             String::from("it_queries_through_w_arithmetic_and_literals_persistent"),
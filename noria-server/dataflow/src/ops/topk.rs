@@ -2,18 +2,25 @@ use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 
+use ops;
 use prelude::*;
 
 use nom_sql::OrderType;
 
 #[derive(Clone, Serialize, Deserialize)]
-struct Order(Vec<(usize, OrderType)>);
+struct Order {
+    columns: Vec<(usize, OrderType)>,
+    // The collation of each column of the ordered rows, or nothing if they are all binary.
+    collations: Vec<Collation>,
+}
 impl Order {
     fn cmp(&self, a: &[DataType], b: &[DataType]) -> Ordering {
-        for &(c, ref order_type) in &self.0 {
+        for &(c, ref order_type) in &self.columns {
+            let collation = Collation::of(&self.collations, c);
+            let (a, b) = (collation.key(&a[c]), collation.key(&b[c]));
             let result = match *order_type {
-                OrderType::OrderAscending => a[c].cmp(&b[c]),
-                OrderType::OrderDescending => b[c].cmp(&a[c]),
+                OrderType::OrderAscending => a.cmp(&b),
+                OrderType::OrderDescending => b.cmp(&a),
             };
             if result != Ordering::Equal {
                 return result;
@@ -25,7 +32,10 @@ impl Order {
 
 impl From<Vec<(usize, OrderType)>> for Order {
     fn from(other: Vec<(usize, OrderType)>) -> Self {
-        Order(other)
+        Order {
+            columns: other,
+            collations: Vec::new(),
+        }
     }
 }
 
@@ -101,6 +111,7 @@ impl Ingredient for TopK {
     fn on_connected(&mut self, g: &Graph) {
         let srcn = &g[self.src.as_global()];
         self.cols = srcn.fields().len();
+        self.order.collations = srcn.collations().to_vec();
     }
 
     fn on_commit(&mut self, us: NodeIndex, remap: &HashMap<NodeIndex, IndexPair>) {
@@ -130,11 +141,9 @@ impl Ingredient for TopK {
         }
 
         let group_by = &self.group_by;
+        let collations = &self.order.collations;
         let group_cmp = |a: &Record, b: &Record| {
-            group_by
-                .iter()
-                .map(|&col| &a[col])
-                .cmp(group_by.iter().map(|&col| &b[col]))
+            ops::collated(a, group_by, collations).cmp(ops::collated(b, group_by, collations))
         };
 
         // First, we want to be smart about multiple added/removed rows with same group.
@@ -222,7 +231,11 @@ impl Ingredient for TopK {
         };
 
         for r in rs {
-            if grp.iter().cmp(group_by.iter().map(|&col| &r[col])) != Ordering::Equal {
+            let in_group = grp
+                .iter()
+                .map(Cow::Borrowed)
+                .eq(ops::collated(&r, group_by, collations));
+            if !in_group {
                 // new group!
 
                 // first, tidy up the old one
@@ -232,7 +245,7 @@ impl Ingredient for TopK {
 
                 // make ready for the new one
                 grp.clear();
-                grp.extend(group_by.iter().map(|&col| &r[col]).cloned());
+                grp.extend(ops::collated(&r, group_by, collations).map(Cow::into_owned));
 
                 // check out current state
                 match db.lookup(&group_by[..], &KeyType::from(&grp[..])) {
@@ -472,6 +485,39 @@ mod tests {
         assert!(a.iter().any(|r| r == &(r15.clone(), true).into()));
     }
 
+    #[test]
+    fn it_groups_and_orders_under_collations() {
+        let ci = Collation::UnicodeCaseInsensitive;
+        let mut g = ops::test::MockGraph::new();
+        let s = g.add_base("source", &["x", "y", "z"]);
+        g.set_collations(s, vec![Collation::Binary, ci, ci]);
+        g.set_op(
+            "topk",
+            &["x", "y", "z"],
+            TopK::new(
+                s.as_global(),
+                vec![(2, OrderType::OrderAscending)],
+                vec![1],
+                1,
+            ),
+            true,
+        );
+
+        let apple: Vec<DataType> = vec![1.into(), "a".into(), "apple".into()];
+        let banana: Vec<DataType> = vec![2.into(), "A".into(), "Banana".into()];
+        let cherry: Vec<DataType> = vec![3.into(), "b".into(), "cherry".into()];
+
+        let a = g.narrow_one_row(apple.clone(), true);
+        assert_eq!(a, vec![apple.clone()].into());
+
+        // "A" is in the same group as "a", and "Banana" sorts after "apple"
+        let a = g.narrow_one_row(banana.clone(), true);
+        assert_eq!(a, vec![(banana, true), (apple, false)].into());
+
+        let a = g.narrow_one_row(cherry.clone(), true);
+        assert_eq!(a, vec![cherry].into());
+    }
+
     #[test]
     fn it_suggests_indices() {
         let (g, _) = setup(false);
@@ -46,7 +46,12 @@ pub enum TriggerEndpoint {
 #[derive(Clone, Serialize, Deserialize)]
 pub enum InitialState {
     PartialLocal(Vec<(Vec<usize>, Vec<Tag>)>),
+    PersistentPartialLocal(Vec<(Vec<usize>, Vec<Tag>)>),
     IndexedLocal(HashSet<Vec<usize>>),
+    SpillingLocal {
+        index: HashSet<Vec<usize>>,
+        memory_limit: usize,
+    },
     PartialGlobal {
         gid: petgraph::graph::NodeIndex,
         cols: usize,
@@ -83,7 +88,9 @@ pub enum Packet {
     Input {
         inner: LocalOrNot<Input>,
         src: Option<SourceChannelIdentifier>,
-        senders: Vec<SourceChannelIdentifier>,
+        /// The clients whose inputs were merged into this one, along with how many operations
+        /// each of them contributed (in order).
+        senders: Vec<(SourceChannelIdentifier, usize)>,
     },
 
     /// Regular data-flow update.
@@ -109,6 +116,13 @@ pub enum Packet {
         num_bytes: usize,
     },
 
+    /// Evict from the target node whenever its state grows beyond `max_bytes`, or stop doing so
+    /// if `max_bytes` is `None`.
+    SetMemoryBudget {
+        node: LocalNodeIndex,
+        max_bytes: Option<usize>,
+    },
+
     /// Evict the indicated keys from the materialization targed by the replay path `tag` (along
     /// with any other materializations below it).
     EvictKeys {
@@ -155,6 +169,12 @@ pub enum Packet {
         new_tag: Option<(Tag, NodeIndex)>,
     },
 
+    /// Stop an egress node from sending to an ingress node that has been removed.
+    RemoveEgressTx {
+        node: LocalNodeIndex,
+        dst: NodeIndex,
+    },
+
     /// Add a shard to a Sharder node.
     ///
     /// Note that this *must* be done *before* the sharder starts being used!
@@ -204,6 +224,19 @@ pub enum Packet {
         key: Vec<DataType>,
     },
 
+    /// Ask domain to replay any of the given keys that are missing from a reader, and report how
+    /// many of them are already present.
+    WarmReader {
+        node: LocalNodeIndex,
+        keys: Vec<Vec<DataType>>,
+    },
+
+    /// Ask domain for the keys of a reader that its eviction policy would most like to keep.
+    HotReaderKeys {
+        node: LocalNodeIndex,
+        count: usize,
+    },
+
     /// Instruct domain to replay the state of a particular node along an existing replay path.
     StartReplay {
         tag: Tag,
@@ -420,6 +453,9 @@ pub enum ControlReplyPacket {
         HashMap<petgraph::graph::NodeIndex, noria::debug::stats::NodeStats>,
     ),
     Booted(usize, SocketAddr),
+    /// (number of keys already present, number of keys still being replayed)
+    Warmed(usize, usize),
+    Keys(Vec<Vec<DataType>>),
 }
 
 impl ControlReplyPacket {
@@ -16,12 +16,14 @@ pub type Graph = petgraph::Graph<Node, Edge>;
 
 // dataflow types
 pub use noria::debug::trace::{Event, PacketEvent, Tracer};
-pub use noria::Input;
+pub use noria::{Input, InputAck};
 pub use payload::{Packet, ReplayPathSegment, SourceChannelIdentifier};
 pub use Sharding;
 
 // domain local state
-pub use state::{LookupResult, MemoryState, PersistentState, RecordResult, Row, State};
+pub use state::{
+    LookupResult, MemoryState, PersistentState, RecordResult, Row, SpillingState, State,
+};
 pub type StateMap = Map<Box<State>>;
 pub type DomainNodes = Map<cell::RefCell<Node>>;
 pub type ReplicaAddr = (DomainIndex, usize);
@@ -30,8 +32,11 @@ pub type ReplicaAddr = (DomainIndex, usize);
 pub use DurabilityMode;
 pub use PersistenceParameters;
 
+// eviction configuration
+pub use EvictionPolicy;
+
 /// Channel coordinator type specialized for domains
 pub type ChannelCoordinator = noria::channel::ChannelCoordinator<(DomainIndex, usize), Box<Packet>>;
 pub trait Executor {
-    fn send_back(&mut self, client: SourceChannelIdentifier, ack: ());
+    fn send_back(&mut self, client: SourceChannelIdentifier, ack: InputAck);
 }
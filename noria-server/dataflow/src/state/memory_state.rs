@@ -12,6 +12,9 @@ pub struct MemoryState {
     state: Vec<SingleState>,
     by_tag: HashMap<Tag, usize>,
     mem_size: u64,
+    eviction_policy: EvictionPolicy,
+    // The collation of each column, or nothing if they are all binary.
+    collations: Vec<Collation>,
 }
 
 impl SizeOf for MemoryState {
@@ -46,8 +49,12 @@ impl State for MemoryState {
             return;
         }
 
-        self.state
-            .push(SingleState::new(columns, partial.is_some()));
+        self.state.push(SingleState::new(
+            columns,
+            Collation::of_columns(&self.collations, columns),
+            partial.is_some(),
+            self.eviction_policy,
+        ));
 
         if !self.state.is_empty() && partial.is_none() {
             // we need to *construct* the index!
@@ -148,10 +155,10 @@ impl State for MemoryState {
         self.state[0].values().flat_map(fix).collect()
     }
 
-    fn evict_random_keys(&mut self, count: usize) -> (&[usize], Vec<Vec<DataType>>, u64) {
+    fn evict_some_keys(&mut self, count: usize) -> (&[usize], Vec<Vec<DataType>>, u64) {
         let mut rng = rand::thread_rng();
         let index = rng.gen_range(0, self.state.len());
-        let (bytes_freed, keys) = self.state[index].evict_random_keys(count, &mut rng);
+        let (bytes_freed, keys) = self.state[index].evict_some_keys(count, &mut rng);
         self.mem_size = self.mem_size.saturating_sub(bytes_freed);
         (self.state[index].key(), keys, bytes_freed)
     }
@@ -169,6 +176,22 @@ impl State for MemoryState {
 }
 
 impl MemoryState {
+    /// Choose keys to evict from partially materialized indices according to `policy`.
+    pub fn with_eviction_policy(mut self, policy: EvictionPolicy) -> Self {
+        assert!(self.state.is_empty());
+        self.eviction_policy = policy;
+        self
+    }
+
+    /// Builder with the collation of each column of the stored rows.
+    ///
+    /// Indices compare the values of their key columns under these collations.
+    pub fn with_collations(mut self, collations: Vec<Collation>) -> Self {
+        assert!(self.state.is_empty());
+        self.collations = collations;
+        self
+    }
+
     /// Returns the index in `self.state` of the index keyed on `cols`, or None if no such index
     /// exists.
     fn state_for(&self, cols: &[usize]) -> Option<usize> {
@@ -271,4 +294,66 @@ mod tests {
             _ => unreachable!(),
         };
     }
+
+    #[test]
+    fn memory_state_compares_keys_under_collations() {
+        let tag = Tag(1);
+        let ci = Collation::UnicodeCaseInsensitive;
+        let mut state = MemoryState::default().with_collations(vec![Collation::Binary, ci]);
+        state.add_key(&[1], None);
+        state.add_key(&[0, 1], Some(vec![tag]));
+        state.mark_filled(vec![1.into(), "BOB".into()], &tag);
+        insert(&mut state, vec![1.into(), "Bob".into()]);
+        insert(&mut state, vec![2.into(), "bob".into()]);
+
+        match state.lookup(&[1], &KeyType::Single(&"BOB".into())) {
+            LookupResult::Some(rows) => assert_eq!(rows.len(), 2),
+            _ => unreachable!(),
+        };
+        match state.lookup(&[0, 1], &KeyType::Double((1.into(), "bob".into()))) {
+            LookupResult::Some(rows) => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        };
+
+        let mut records: Records = vec![(vec![2.into(), "bob".into()], false)].into();
+        state.process_records(&mut records, None);
+        match state.lookup(&[1], &KeyType::Single(&"Bob".into())) {
+            LookupResult::Some(rows) => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        };
+
+        state.mark_hole(&[1.into(), "Bob".into()], &tag);
+        match state.lookup(&[0, 1], &KeyType::Double((1.into(), "bob".into()))) {
+            LookupResult::Missing => {}
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn memory_state_evicts_least_recently_read() {
+        let tag = Tag(1);
+        let mut state = MemoryState::default().with_eviction_policy(EvictionPolicy::Lru);
+        state.add_key(&[0], Some(vec![tag]));
+        for i in 0..3 {
+            state.mark_filled(vec![i.into()], &tag);
+            let mut records: Records = vec![(vec![i.into(), "x".into()], true)].into();
+            state.process_records(&mut records, Some(tag));
+        }
+
+        for &i in &[0, 2] {
+            match state.lookup(&[0], &KeyType::Single(&i.into())) {
+                LookupResult::Some(RecordResult::Borrowed(rows)) => assert_eq!(rows.len(), 1),
+                _ => unreachable!(),
+            }
+        }
+
+        let (key, evicted, bytes) = state.evict_some_keys(1);
+        assert_eq!(key, &[0]);
+        assert_eq!(evicted, vec![vec![1.into()]]);
+        assert!(bytes > 0);
+        match state.lookup(&[0], &KeyType::Single(&1.into())) {
+            LookupResult::Missing => {}
+            _ => unreachable!(),
+        }
+    }
 }
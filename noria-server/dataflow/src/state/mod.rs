@@ -2,6 +2,7 @@ mod keyed_state;
 mod memory_state;
 mod persistent_state;
 mod single_state;
+mod spilling_state;
 
 use std::borrow::Cow;
 use std::ops::Deref;
@@ -13,6 +14,7 @@ use prelude::*;
 
 pub use self::memory_state::MemoryState;
 pub use self::persistent_state::PersistentState;
+pub use self::spilling_state::SpillingState;
 
 pub trait State: SizeOf + Send {
     /// Add an index keyed by the given columns and replayed to by the given partial tags.
@@ -41,13 +43,29 @@ pub trait State: SizeOf + Send {
     /// Return a copy of all records. Panics if the state is only partially materialized.
     fn cloned_records(&self) -> Vec<Vec<DataType>>;
 
-    /// Evict `count` randomly selected keys, returning key colunms of the index chosen to evict
-    /// from along with the keys evicted and the number of bytes evicted.
-    fn evict_random_keys(&mut self, count: usize) -> (&[usize], Vec<Vec<DataType>>, u64);
+    /// Evict `count` keys chosen by the state's `EvictionPolicy`, returning key colunms of the
+    /// index chosen to evict from along with the keys evicted and the number of bytes evicted.
+    fn evict_some_keys(&mut self, count: usize) -> (&[usize], Vec<Vec<DataType>>, u64);
 
     /// Evict the listed keys from the materialization targeted by `tag`, returning the key columns
     /// of the index that was evicted from and the number of bytes evicted.
     fn evict_keys(&mut self, tag: &Tag, keys: &[Vec<DataType>]) -> Option<(&[usize], u64)>;
+
+    /// Returns the last value a base node assigned to its `AUTO_INCREMENT` column, if this state
+    /// has recorded one.
+    fn auto_increment(&self) -> Option<i64> {
+        None
+    }
+
+    /// Record the last value a base node assigned to its `AUTO_INCREMENT` column. Durable states
+    /// persist it along with the next batch of records.
+    fn set_auto_increment(&mut self, _: i64) {}
+
+    /// Discard the state for good, because the node that owns it has been removed from the graph.
+    /// Durable states delete their on-disk data, and return an error if they fail to.
+    fn destroy(self: Box<Self>) -> Result<(), String> {
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -117,3 +135,30 @@ pub enum LookupResult<'a> {
     Some(RecordResult<'a>),
     Missing,
 }
+
+/// The value of the `i`th `key` column of `r`, as it is compared under that column's collation.
+///
+/// `collations` holds the collation of each key column, or nothing if they are all binary.
+pub(crate) fn key_value(
+    r: &[DataType],
+    key: &[usize],
+    collations: &[Collation],
+    i: usize,
+) -> DataType {
+    match collations.get(i) {
+        Some(collation) => collation.key(&r[key[i]]).into_owned(),
+        None => r[key[i]].clone(),
+    }
+}
+
+/// The values of `key` as they are compared under the given collation of each key column.
+pub(crate) fn collate_key<'a>(
+    key: &'a [DataType],
+    collations: &[Collation],
+) -> Cow<'a, [DataType]> {
+    if collations.is_empty() {
+        Cow::Borrowed(key)
+    } else {
+        Cow::Owned(KeyType::from(key).collate(collations))
+    }
+}
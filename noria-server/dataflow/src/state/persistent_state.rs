@@ -1,7 +1,10 @@
 use bincode;
+use fnv::FnvHashMap;
 use itertools::Itertools;
+use rand::{self, Rng};
 use rocksdb::{self, ColumnFamily, SliceTransform, SliceTransformFns, WriteBatch};
 use serde;
+use std::collections::HashMap;
 use tempfile::{tempdir, TempDir};
 
 use common::SizeOf;
@@ -30,12 +33,17 @@ const INDEX_BATCH_SIZE: usize = 100_000;
 struct PersistentMeta {
     indices: Vec<Vec<usize>>,
     epoch: IndexEpoch,
+    auto_increment: Option<i64>,
+    // Whether the indices are partial, in which case they are dropped on recovery.
+    partial: bool,
 }
 
 #[derive(Clone)]
 struct PersistentIndex {
     column_family: ColumnFamily,
     columns: Vec<usize>,
+    // For partial indices, the keys that are not holes, by their serialized prefix.
+    filled: Option<FnvHashMap<Vec<u8>, Vec<DataType>>>,
 }
 
 impl PersistentIndex {
@@ -43,6 +51,7 @@ impl PersistentIndex {
         Self {
             column_family,
             columns,
+            filled: None,
         }
     }
 }
@@ -58,10 +67,26 @@ pub struct PersistentState {
     // Subsequent indices maintain pointers to the data in the first index, and cause an additional
     // read during lookups. When `self.has_unique_index` is true the first index is a primary key,
     // and all its keys are considered unique.
+    //
+    // If the state is partial, every index is partial, and stores its own copy of the rows whose
+    // keys it has filled, keyed like a non-unique primary key.
     indices: Vec<PersistentIndex>,
+    by_tag: HashMap<Tag, usize>,
     seq: IndexSeq,
     epoch: IndexEpoch,
     has_unique_index: bool,
+    // The last value assigned to the base's AUTO_INCREMENT column, which is persisted as part of
+    // the meta information with the next write when `auto_increment_dirty` is set.
+    auto_increment: Option<i64>,
+    auto_increment_dirty: bool,
+    // The collation of each column, if any of them are not binary. Keys are serialized in their
+    // collated form, so that lookups find rows whose keys are equal under the collation.
+    collations: Vec<Collation>,
+    // Whether writes are synced to RocksDB's WAL. State that is rebuilt from its ancestors on
+    // recovery (like spilled operator state) has no need for that.
+    durable: bool,
+    // Where the RocksDB files are stored.
+    path: String,
     // With DurabilityMode::DeleteOnExit,
     // RocksDB files are stored in a temporary directory.
     _directory: Option<TempDir>,
@@ -138,12 +163,25 @@ impl SizeOf for PersistentState {
 
 impl State for PersistentState {
     fn process_records(&mut self, records: &mut Records, partial_tag: Option<Tag>) {
-        assert!(partial_tag.is_none(), "PersistentState can't be partial");
+        if self.is_partial() {
+            return self.process_partial_records(records, partial_tag);
+        }
+
+        assert!(
+            partial_tag.is_none(),
+            "full PersistentState can't take partial records"
+        );
         if records.len() == 0 {
             return;
         }
 
         let mut batch = WriteBatch::default();
+        if self.auto_increment_dirty {
+            let meta = bincode::serialize(&self.meta()).unwrap();
+            batch.put(META_KEY, &meta).unwrap();
+            self.auto_increment_dirty = false;
+        }
+
         for r in records.iter() {
             match *r {
                 Record::Positive(ref r) => {
@@ -155,10 +193,7 @@ impl State for PersistentState {
             }
         }
 
-        // Sync the writes to RocksDB's WAL:
-        let mut opts = rocksdb::WriteOptions::default();
-        opts.set_sync(true);
-        self.db.as_ref().unwrap().write_opt(batch, &opts).unwrap();
+        self.write(batch);
     }
 
     fn lookup(&self, columns: &[usize], key: &KeyType) -> LookupResult {
@@ -169,7 +204,13 @@ impl State for PersistentState {
             .position(|index| &index.columns[..] == columns)
             .expect("lookup on non-indexed column set");
         let cf = self.indices[index_id].column_family;
-        let prefix = Self::serialize_prefix(&key);
+        let prefix = Self::serialize_prefix(&key, &self.collations_of(columns));
+        if let Some(ref filled) = self.indices[index_id].filled {
+            if !filled.contains_key(&prefix) {
+                return LookupResult::Missing;
+            }
+        }
+
         let data = if index_id == 0 && self.has_unique_index {
             // This is a primary key, so we know there's only one row to retrieve
             // (no need to use prefix_iterator).
@@ -192,16 +233,30 @@ impl State for PersistentState {
     }
 
     fn add_key(&mut self, columns: &[usize], partial: Option<Vec<Tag>>) {
-        assert!(partial.is_none(), "Bases can't be partial");
         let existing = self
             .indices
             .iter()
-            .any(|index| &index.columns[..] == columns);
+            .position(|index| &index.columns[..] == columns);
+
+        if let Some(ref tags) = partial {
+            let i = existing.unwrap_or(self.indices.len());
+            for &tag in tags {
+                self.by_tag.insert(tag, i);
+            }
+        }
 
-        if existing {
+        if existing.is_some() {
             return;
         }
 
+        if partial.is_some() {
+            return self.add_partial_key(columns);
+        }
+        assert!(
+            !self.is_partial(),
+            "can't add a full index to a partial PersistentState"
+        );
+
         let cols = Vec::from(columns);
         // We'll store all the pointers (or values if this is index 0) for
         // this index in its own column family:
@@ -215,12 +270,13 @@ impl State for PersistentState {
 
         // Build the new index for existing values:
         if self.indices.len() > 0 {
+            let collations = self.collations_of(columns);
             for chunk in self.all_rows().chunks(INDEX_BATCH_SIZE).into_iter() {
                 let mut batch = WriteBatch::default();
                 for (ref pk, ref value) in chunk {
                     let row: Vec<DataType> = bincode::deserialize(&value).unwrap();
                     let index_key = Self::build_key(&row, columns);
-                    let key = Self::serialize_secondary(&index_key, pk);
+                    let key = Self::serialize_secondary(&index_key, &collations, pk);
                     batch.put_cf(column_family, &key, value).unwrap();
                 }
 
@@ -228,10 +284,7 @@ impl State for PersistentState {
             }
         }
 
-        self.indices.push(PersistentIndex {
-            columns: cols,
-            column_family,
-        });
+        self.indices.push(PersistentIndex::new(column_family, cols));
 
         self.persist_meta();
     }
@@ -244,6 +297,7 @@ impl State for PersistentState {
     }
 
     fn cloned_records(&self) -> Vec<Vec<DataType>> {
+        assert!(!self.is_partial());
         self.all_rows()
             .map(|(_, ref value)| bincode::deserialize(&value).unwrap())
             .collect()
@@ -265,23 +319,74 @@ impl State for PersistentState {
     }
 
     fn is_partial(&self) -> bool {
-        false
+        self.indices.iter().any(|index| index.filled.is_some())
     }
 
-    fn mark_filled(&mut self, _: Vec<DataType>, _: &Tag) {
-        unreachable!("PersistentState can't be partial")
+    fn mark_filled(&mut self, key: Vec<DataType>, tag: &Tag) {
+        let index = &mut self.indices[self.by_tag[tag]];
+        let prefix = {
+            let collations = Collation::of_columns(&self.collations, &index.columns);
+            Self::serialize_prefix(&KeyType::from(&key[..]), &collations)
+        };
+        let filled = index.filled.as_mut().expect("filling full index");
+        assert!(filled.insert(prefix, key).is_none(), "filling filled key");
     }
 
-    fn mark_hole(&mut self, _: &[DataType], _: &Tag) {
-        unreachable!("PersistentState can't be partial")
+    fn mark_hole(&mut self, key: &[DataType], tag: &Tag) {
+        let index = self.by_tag[tag];
+        self.evict_key(index, key);
     }
 
-    fn evict_random_keys(&mut self, _: usize) -> (&[usize], Vec<Vec<DataType>>, u64) {
-        unreachable!("can't evict keys from PersistentState")
+    fn evict_some_keys(&mut self, count: usize) -> (&[usize], Vec<Vec<DataType>>, u64) {
+        assert!(
+            self.is_partial(),
+            "can't evict keys from a full PersistentState"
+        );
+        let mut rng = rand::thread_rng();
+        let index = rng.gen_range(0, self.indices.len());
+
+        // start at a random filled key, and evict the keys that follow it
+        let keys: Vec<_> = {
+            let filled = self.indices[index].filled.as_ref().unwrap();
+            let start = if filled.is_empty() {
+                0
+            } else {
+                rng.gen_range(0, filled.len())
+            };
+            filled
+                .values()
+                .cycle()
+                .skip(start)
+                .take(::std::cmp::min(count, filled.len()))
+                .cloned()
+                .collect()
+        };
+        let bytes_freed: u64 = keys.iter().map(|key| self.evict_key(index, key)).sum();
+        (&self.indices[index].columns[..], keys, bytes_freed)
+    }
+
+    fn evict_keys(&mut self, tag: &Tag, keys: &[Vec<DataType>]) -> Option<(&[usize], u64)> {
+        // as for MemoryState, we may be told to evict from a tag that add_key hasn't been called
+        // for yet.
+        let index = *self.by_tag.get(tag)?;
+        let bytes_freed: u64 = keys.iter().map(|key| self.evict_key(index, key)).sum();
+        Some((&self.indices[index].columns[..], bytes_freed))
+    }
+
+    fn auto_increment(&self) -> Option<i64> {
+        self.auto_increment
+    }
+
+    fn set_auto_increment(&mut self, value: i64) {
+        self.auto_increment = Some(value);
+        self.auto_increment_dirty = true;
     }
 
-    fn evict_keys(&mut self, _: &Tag, _: &[Vec<DataType>]) -> Option<(&[usize], u64)> {
-        unreachable!("can't evict keys from PersistentState")
+    fn destroy(mut self: Box<Self>) -> Result<(), String> {
+        // the database has to be closed before its files can be deleted
+        self.db = None;
+        rocksdb::DB::destroy(&self.db_opts, &self.path)
+            .map_err(|e| format!("failed to delete {}: {}", self.path, e))
     }
 }
 
@@ -329,7 +434,12 @@ impl PersistentState {
             db = DB::open_cf_descriptors(&opts, &full_name, make_cfs());
         }
         let mut db = db.unwrap();
-        let meta = Self::retrieve_and_update_meta(&db);
+        let mut meta = Self::retrieve_and_update_meta(&db);
+        if meta.partial {
+            // Partial indices only ever hold the results of replays, which won't be valid after a
+            // restart, so we'll start over with no indices at all and wait for them to be added:
+            meta.indices.clear();
+        }
         let indices: Vec<PersistentIndex> = meta
             .indices
             .into_iter()
@@ -340,20 +450,32 @@ impl PersistentState {
             })
             .collect();
 
-        // If there are more column families than indices (-1 to account for the default column
-        // family) we probably crashed while trying to build the last index (in Self::add_key), so
-        // we'll throw away our progress and try re-building it again later:
-        if column_family_names.len() - 1 > indices.len() {
-            db.drop_cf(&indices.len().to_string()).unwrap();
+        // If there are more column families than indices (other than the default column family)
+        // we probably crashed while trying to build the last index (in Self::add_key), or the
+        // indices were partial, so we'll throw them away and try re-building them again later:
+        for cf in &column_family_names {
+            let stale = cf
+                .parse::<usize>()
+                .map(|i| i >= indices.len())
+                .unwrap_or(false);
+            if stale {
+                db.drop_cf(cf).unwrap();
+            }
         }
 
         let mut state = Self {
             seq: 0,
             indices,
+            by_tag: HashMap::new(),
             has_unique_index: primary_key.is_some(),
             epoch: meta.epoch,
+            auto_increment: meta.auto_increment,
+            auto_increment_dirty: false,
+            collations: Vec::new(),
+            durable: true,
             db_opts: opts,
             db: Some(db),
+            path: full_name,
             _directory: directory,
         };
 
@@ -375,6 +497,19 @@ impl PersistentState {
         state
     }
 
+    /// Builder with the collation of each column of the stored rows.
+    pub fn with_collations(mut self, collations: Vec<Collation>) -> Self {
+        self.collations = collations;
+        self
+    }
+
+    /// Builder that skips syncing writes to disk and writing them to the WAL, for state that does
+    /// not have to survive a crash.
+    pub fn without_durability(mut self) -> Self {
+        self.durable = false;
+        self
+    }
+
     fn build_options(name: &str, params: &PersistenceParameters) -> rocksdb::Options {
         let mut opts = rocksdb::Options::default();
         opts.set_compression_type(rocksdb::DBCompressionType::Lz4);
@@ -438,6 +573,11 @@ impl PersistentState {
         KeyType::from(columns.iter().map(|i| &row[*i]))
     }
 
+    // The collation of each of the given columns, or nothing if they are all binary.
+    fn collations_of(&self, columns: &[usize]) -> Vec<Collation> {
+        Collation::of_columns(&self.collations, columns)
+    }
+
     fn retrieve_and_update_meta(db: &rocksdb::DB) -> PersistentMeta {
         let indices = db.get(META_KEY).unwrap();
         let mut meta = match indices {
@@ -451,17 +591,21 @@ impl PersistentState {
         meta
     }
 
-    fn persist_meta(&mut self) {
-        let db = self.db.as_ref().unwrap();
+    fn meta(&self) -> PersistentMeta {
         // Stores the columns of self.indices in RocksDB so that we don't rebuild indices on recovery.
         let columns = self.indices.iter().map(|i| i.columns.clone()).collect();
-        let meta = PersistentMeta {
+        PersistentMeta {
             indices: columns,
             epoch: self.epoch,
-        };
+            auto_increment: self.auto_increment,
+            partial: self.is_partial(),
+        }
+    }
 
-        let data = bincode::serialize(&meta).unwrap();
-        db.put(META_KEY, &data).unwrap();
+    fn persist_meta(&mut self) {
+        let data = bincode::serialize(&self.meta()).unwrap();
+        self.db.as_ref().unwrap().put(META_KEY, &data).unwrap();
+        self.auto_increment_dirty = false;
     }
 
     // Our RocksDB keys come in three forms, and are encoded as follows:
@@ -479,13 +623,24 @@ impl PersistentState {
     // unique.
     //
     // Self::serialize_raw_key is responsible for serializing the underlying KeyType tuple directly
-    // (without the enum variant), plus any extra information as described above.
-    fn serialize_raw_key<S: serde::Serialize>(key: &KeyType, extra: S) -> Vec<u8> {
+    // (without the enum variant), plus any extra information as described above. If `collations`
+    // is non-empty, the key values are serialized in their collated form, so that keys that are
+    // equal under the collation of their columns are also equal byte-wise.
+    fn serialize_raw_key<S: serde::Serialize>(
+        key: &KeyType,
+        collations: &[Collation],
+        extra: S,
+    ) -> Vec<u8> {
         fn serialize<K: serde::Serialize, E: serde::Serialize>(k: K, extra: E) -> Vec<u8> {
             let size: u64 = bincode::serialized_size(&k).unwrap();
             bincode::serialize(&(size, k, extra)).unwrap()
         }
 
+        if !collations.is_empty() {
+            let collated = key.collate(collations);
+            return Self::serialize_raw_key(&KeyType::from(&collated), &[], extra);
+        }
+
         match key {
             KeyType::Single(k) => serialize(k, extra),
             KeyType::Double(k) => serialize(k, extra),
@@ -496,16 +651,142 @@ impl PersistentState {
         }
     }
 
-    fn serialize_prefix(key: &KeyType) -> Vec<u8> {
-        Self::serialize_raw_key(key, ())
+    fn serialize_prefix(key: &KeyType, collations: &[Collation]) -> Vec<u8> {
+        Self::serialize_raw_key(key, collations, ())
     }
 
-    fn serialize_secondary(key: &KeyType, raw_primary: &[u8]) -> Vec<u8> {
-        let mut bytes = Self::serialize_raw_key(key, ());
+    fn serialize_secondary(key: &KeyType, collations: &[Collation], raw_primary: &[u8]) -> Vec<u8> {
+        let mut bytes = Self::serialize_raw_key(key, collations, ());
         bytes.extend_from_slice(raw_primary);
         bytes
     }
 
+    fn write(&self, batch: WriteBatch) {
+        // Sync the writes to RocksDB's WAL:
+        let mut opts = rocksdb::WriteOptions::default();
+        opts.set_sync(self.durable);
+        opts.disable_wal(!self.durable);
+        self.db.as_ref().unwrap().write_opt(batch, &opts).unwrap();
+    }
+
+    // Partial indices start out empty, so unlike full ones there is nothing to build. They are
+    // still recorded in the meta information, so that their column families (and the replayed
+    // rows in them) are thrown away when the state is recovered.
+    fn add_partial_key(&mut self, columns: &[usize]) {
+        assert!(
+            !self.has_unique_index && self.indices.iter().all(|i| i.filled.is_some()),
+            "can't add a partial index to a full PersistentState"
+        );
+
+        let index_id = self.indices.len().to_string();
+        let column_family = self
+            .db
+            .as_mut()
+            .unwrap()
+            .create_cf(&index_id, &self.db_opts)
+            .unwrap();
+
+        self.indices.push(PersistentIndex {
+            columns: Vec::from(columns),
+            column_family,
+            filled: Some(FnvHashMap::default()),
+        });
+
+        self.persist_meta();
+    }
+
+    // Applies records to every index that has filled the record's key, or only to the index
+    // targeted by `partial_tag` for replays. Records that miss all indices are dropped.
+    fn process_partial_records(&mut self, records: &mut Records, partial_tag: Option<Tag>) {
+        let targets: Vec<usize> = match partial_tag {
+            Some(tag) => match self.by_tag.get(&tag) {
+                Some(&index) => vec![index],
+                // as for MemoryState, the records may be destined for a downstream
+                // materialization on an old replay path
+                None => return,
+            },
+            None => (0..self.indices.len()).collect(),
+        };
+
+        let mut batch = WriteBatch::default();
+        let mut dirty = false;
+        records.retain(|r| {
+            let mut hit = false;
+            for &i in &targets {
+                let index = &self.indices[i];
+                let key = Self::build_key(r, &index.columns);
+                let collations = Collation::of_columns(&self.collations, &index.columns);
+                let prefix = Self::serialize_prefix(&key, &collations);
+                if !index.filled.as_ref().unwrap().contains_key(&prefix) {
+                    continue;
+                }
+                hit = true;
+
+                match *r {
+                    Record::Positive(ref r) => {
+                        self.seq += 1;
+                        let key =
+                            Self::serialize_raw_key(&key, &collations, (self.epoch, self.seq));
+                        let row = bincode::serialize(r).unwrap();
+                        batch.put_cf(index.column_family, &key, &row).unwrap();
+                        dirty = true;
+                    }
+                    Record::Negative(ref r) => {
+                        // we need to be able to find rows that were added earlier in this batch
+                        if dirty {
+                            self.write(::std::mem::replace(&mut batch, WriteBatch::default()));
+                            dirty = false;
+                        }
+
+                        let db = self.db.as_ref().unwrap();
+                        let found = db
+                            .prefix_iterator_cf(index.column_family, &prefix)
+                            .unwrap()
+                            .find(|(_, raw_value)| {
+                                let value: Vec<DataType> =
+                                    bincode::deserialize(&*raw_value).unwrap();
+                                r == &value[..]
+                            });
+                        if let Some((key, _)) = found {
+                            batch.delete_cf(index.column_family, &key).unwrap();
+                            dirty = true;
+                        }
+                    }
+                }
+            }
+            hit
+        });
+
+        if dirty {
+            self.write(batch);
+        }
+    }
+
+    // Turns `key` into a hole in the given partial index, returning the (approximate) number of
+    // bytes freed.
+    fn evict_key(&mut self, index: usize, key: &[DataType]) -> u64 {
+        let index = &mut self.indices[index];
+        let collations = Collation::of_columns(&self.collations, &index.columns);
+        let prefix = Self::serialize_prefix(&KeyType::from(key), &collations);
+        index
+            .filled
+            .as_mut()
+            .expect("evicting from full index")
+            .remove(&prefix);
+
+        let db = self.db.as_ref().unwrap();
+        let mut batch = WriteBatch::default();
+        let mut bytes_freed = 0;
+        for (key, value) in db.prefix_iterator_cf(index.column_family, &prefix).unwrap() {
+            bytes_freed += (key.len() + value.len()) as u64;
+            batch.delete_cf(index.column_family, &key).unwrap();
+        }
+        let mut opts = rocksdb::WriteOptions::default();
+        opts.disable_wal(!self.durable);
+        db.write_opt(batch, &opts).unwrap();
+        bytes_freed
+    }
+
     // Filters out secondary indices to return an iterator for the actual key-value pairs.
     fn all_rows(&self) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> {
         let db = self.db.as_ref().unwrap();
@@ -522,14 +803,15 @@ impl PersistentState {
     fn insert(&mut self, batch: &mut WriteBatch, r: &[DataType]) {
         let serialized_pk = {
             let pk = Self::build_key(r, &self.indices[0].columns);
+            let collations = self.collations_of(&self.indices[0].columns);
             if self.has_unique_index {
-                Self::serialize_prefix(&pk)
+                Self::serialize_prefix(&pk, &collations)
             } else {
                 // For bases without primary keys we store the actual row values keyed by the index
                 // that was added first. This means that we can't consider the keys unique though, so
                 // we'll append a sequence number.
                 self.seq += 1;
-                Self::serialize_raw_key(&pk, (self.epoch, self.seq))
+                Self::serialize_raw_key(&pk, &collations, (self.epoch, self.seq))
             }
         };
 
@@ -544,7 +826,8 @@ impl PersistentState {
         for index in self.indices[1..].iter() {
             // Construct a key with the index values, and serialize it with bincode:
             let key = Self::build_key(&r, &index.columns);
-            let serialized_key = Self::serialize_secondary(&key, &serialized_pk);
+            let collations = self.collations_of(&index.columns);
+            let serialized_key = Self::serialize_secondary(&key, &collations, &serialized_pk);
             batch
                 .put_cf(index.column_family, &serialized_key, &serialized_row)
                 .unwrap();
@@ -562,7 +845,8 @@ impl PersistentState {
             // Then delete any references that point _exactly_ to that row:
             for index in self.indices[1..].iter() {
                 let key = Self::build_key(&r, &index.columns);
-                let serialized_key = Self::serialize_secondary(&key, primary_key);
+                let collations = self.collations_of(&index.columns);
+                let serialized_key = Self::serialize_secondary(&key, &collations, primary_key);
                 batch
                     .delete_cf(index.column_family, &serialized_key)
                     .unwrap();
@@ -570,7 +854,7 @@ impl PersistentState {
         };
 
         let pk = Self::build_key(&r, &pk_index.columns);
-        let prefix = Self::serialize_prefix(&pk);
+        let prefix = Self::serialize_prefix(&pk, &self.collations_of(&pk_index.columns));
         if self.has_unique_index {
             if cfg!(debug_assertions) {
                 // This would imply that we're trying to delete a different row than the one we
@@ -837,6 +1121,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn persistent_state_collated_keys() {
+        let ci = Collation::AsciiCaseInsensitive;
+        let mut state = setup_persistent("persistent_state_collated_keys")
+            .with_collations(vec![Collation::Binary, ci]);
+        let first: Vec<DataType> = vec![10.into(), "Cat".into()];
+        let second: Vec<DataType> = vec![20.into(), "Bob".into()];
+        state.add_key(&[1], None);
+        state.process_records(&mut vec![first.clone(), second.clone()].into(), None);
+
+        match state.lookup(&[1], &KeyType::Single(&"CAT".into())) {
+            LookupResult::Some(RecordResult::Owned(rows)) => {
+                assert_eq!(rows.len(), 1);
+                assert_eq!(&rows[0], &first);
+            }
+            _ => unreachable!(),
+        }
+
+        state.process_records(&mut vec![(second.clone(), false)].into(), None);
+        match state.lookup(&[1], &KeyType::Single(&"bob".into())) {
+            LookupResult::Some(RecordResult::Owned(rows)) => assert!(rows.is_empty()),
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn persistent_state_recover() {
         let (_dir, name) = get_tmp_path();
@@ -869,6 +1178,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn persistent_state_recover_auto_increment() {
+        let (_dir, name) = get_tmp_path();
+        let mut params = PersistenceParameters::default();
+        params.mode = DurabilityMode::Permanent;
+        {
+            let mut state = PersistentState::new(name.clone(), Some(&[0]), &params);
+            assert_eq!(state.auto_increment(), None);
+            state.set_auto_increment(42);
+            state.process_records(&mut vec![vec![42.into(), "Cat".into()]].into(), None);
+        }
+
+        let state = PersistentState::new(name, Some(&[0]), &params);
+        assert_eq!(state.auto_increment(), Some(42));
+    }
+
+    #[test]
+    fn persistent_state_destroy() {
+        let (_dir, name) = get_tmp_path();
+        let mut params = PersistenceParameters::default();
+        params.mode = DurabilityMode::Permanent;
+        {
+            let mut state = PersistentState::new(name.clone(), Some(&[0]), &params);
+            state.process_records(&mut vec![vec![10.into(), "Cat".into()]].into(), None);
+            Box::new(state).destroy().unwrap();
+        }
+
+        // the records are gone along with the database
+        let state = PersistentState::new(name, Some(&[0]), &params);
+        assert_eq!(state.rows(), 0);
+    }
+
     #[test]
     fn persistent_state_recover_unique_key() {
         let (_dir, name) = get_tmp_path();
@@ -1130,13 +1471,155 @@ mod tests {
         }
     }
 
+    fn lookup_partial(
+        state: &PersistentState,
+        columns: &[usize],
+        key: DataType,
+    ) -> Option<Vec<Vec<DataType>>> {
+        match state.lookup(columns, &KeyType::Single(&key)) {
+            LookupResult::Some(RecordResult::Owned(rows)) => Some(rows),
+            LookupResult::Missing => None,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn persistent_state_partial_holes() {
+        let tag = Tag(1);
+        let mut state = setup_persistent("persistent_state_partial_holes");
+        state.add_key(&[0], Some(vec![tag]));
+        assert!(state.is_partial());
+        assert_eq!(lookup_partial(&state, &[0], 1.into()), None);
+
+        // records for holes are dropped
+        let mut records: Records = vec![vec![1.into(), "a".into()]].into();
+        state.process_records(&mut records, None);
+        assert!(records.is_empty());
+
+        state.mark_filled(vec![1.into()], &tag);
+        assert_eq!(lookup_partial(&state, &[0], 1.into()), Some(vec![]));
+
+        let row: Vec<DataType> = vec![1.into(), "b".into()];
+        let mut records: Records = vec![row.clone()].into();
+        state.process_records(&mut records, None);
+        assert_eq!(records.len(), 1);
+        assert_eq!(lookup_partial(&state, &[0], 1.into()), Some(vec![row]));
+
+        // replays fill the key they're for
+        let row: Vec<DataType> = vec![2.into(), "c".into()];
+        state.mark_filled(vec![2.into()], &tag);
+        let mut records: Records = vec![row.clone()].into();
+        state.process_records(&mut records, Some(tag));
+        assert_eq!(lookup_partial(&state, &[0], 2.into()), Some(vec![row]));
+    }
+
+    #[test]
+    fn persistent_state_partial_remove() {
+        let tag = Tag(1);
+        let mut state = setup_persistent("persistent_state_partial_remove");
+        state.add_key(&[0], Some(vec![tag]));
+        state.mark_filled(vec![1.into()], &tag);
+
+        let mut records: Records = vec![
+            (vec![1.into(), "a".into()], true),
+            (vec![1.into(), "b".into()], true),
+            (vec![1.into(), "a".into()], false),
+        ]
+        .into();
+        state.process_records(&mut records, None);
+        assert_eq!(
+            lookup_partial(&state, &[0], 1.into()),
+            Some(vec![vec![1.into(), "b".into()]])
+        );
+    }
+
+    #[test]
+    fn persistent_state_partial_mark_hole() {
+        let tag = Tag(1);
+        let mut state = setup_persistent("persistent_state_partial_mark_hole");
+        state.add_key(&[0], Some(vec![tag]));
+        state.mark_filled(vec![1.into()], &tag);
+        insert(&mut state, vec![1.into(), "a".into()]);
+
+        state.mark_hole(&[1.into()], &tag);
+        assert_eq!(lookup_partial(&state, &[0], 1.into()), None);
+
+        // the evicted rows are gone for good
+        state.mark_filled(vec![1.into()], &tag);
+        assert_eq!(lookup_partial(&state, &[0], 1.into()), Some(vec![]));
+    }
+
+    #[test]
+    fn persistent_state_partial_evict() {
+        let tag = Tag(1);
+        let mut state = setup_persistent("persistent_state_partial_evict");
+        state.add_key(&[0], Some(vec![tag]));
+        for i in 0..3 {
+            state.mark_filled(vec![i.into()], &tag);
+            insert(&mut state, vec![i.into(), "a".into()]);
+        }
+
+        assert!(state.evict_keys(&Tag(2), &[vec![0.into()]]).is_none());
+        let (key, bytes) = state.evict_keys(&tag, &[vec![0.into()]]).unwrap();
+        assert_eq!(key, &[0]);
+        assert!(bytes > 0);
+        assert_eq!(lookup_partial(&state, &[0], 0.into()), None);
+
+        let (key, evicted, bytes) = state.evict_some_keys(1);
+        assert_eq!(key, &[0]);
+        assert_eq!(evicted.len(), 1);
+        assert!(bytes > 0);
+        assert_eq!(lookup_partial(&state, &[0], evicted[0][0].clone()), None);
+    }
+
+    #[test]
+    fn persistent_state_partial_indices() {
+        let mut state = setup_persistent("persistent_state_partial_indices");
+        state.add_key(&[0], Some(vec![Tag(1)]));
+        state.add_key(&[1], Some(vec![Tag(2)]));
+        state.mark_filled(vec![1.into()], &Tag(1));
+
+        let row: Vec<DataType> = vec![1.into(), 2.into()];
+        insert(&mut state, row.clone());
+        assert_eq!(lookup_partial(&state, &[0], 1.into()), Some(vec![row]));
+        assert_eq!(lookup_partial(&state, &[1], 2.into()), None);
+
+        // the other index was a hole for the row, so it never saw it
+        state.mark_filled(vec![2.into()], &Tag(2));
+        assert_eq!(lookup_partial(&state, &[1], 2.into()), Some(vec![]));
+    }
+
+    #[test]
+    fn persistent_state_partial_recover() {
+        let (_dir, name) = get_tmp_path();
+        let mut params = PersistenceParameters::default();
+        params.mode = DurabilityMode::Permanent;
+        {
+            let mut state = PersistentState::new(name.clone(), None, &params);
+            state.add_key(&[0], Some(vec![Tag(1)]));
+            state.add_key(&[1], Some(vec![Tag(2)]));
+            state.mark_filled(vec![1.into()], &Tag(1));
+            insert(&mut state, vec![1.into(), 2.into()]);
+        }
+
+        // the replayed rows don't survive recovery, and the indices have to be added again
+        let mut state = PersistentState::new(name, None, &params);
+        assert!(!state.is_useful());
+        state.add_key(&[0], Some(vec![Tag(1)]));
+        state.add_key(&[1], Some(vec![Tag(2)]));
+        assert!(state.is_partial());
+        assert_eq!(lookup_partial(&state, &[0], 1.into()), None);
+        state.mark_filled(vec![1.into()], &Tag(1));
+        assert_eq!(lookup_partial(&state, &[0], 1.into()), Some(vec![]));
+    }
+
     #[test]
     fn persistent_state_prefix_transform() {
         let mut state = setup_persistent("persistent_state_prefix_transform");
         state.add_key(&[0], None);
         let data = (DataType::from(1), DataType::from(10));
         let r = KeyType::Double(data.clone());
-        let k = PersistentState::serialize_prefix(&r);
+        let k = PersistentState::serialize_prefix(&r, &[]);
         let mut transform_fns = PrefixTransform;
         let prefix = transform_fns.transform(&k);
         let size: u64 = bincode::deserialize(&prefix).unwrap();
@@ -1150,7 +1633,7 @@ mod tests {
         assert!(prefix <= &k[..]);
 
         // 3) If Compare(k1, k2) <= 0, then Compare(prefix(k1), prefix(k2)) <= 0
-        let other_k = PersistentState::serialize_prefix(&r);
+        let other_k = PersistentState::serialize_prefix(&r, &[]);
         let other_prefix = transform_fns.transform(&other_k);
         assert!(k <= other_k);
         assert!(prefix <= other_prefix);
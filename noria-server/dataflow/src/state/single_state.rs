@@ -2,22 +2,39 @@ use rand::{Rng, ThreadRng};
 use std::rc::Rc;
 
 use common::SizeOf;
+use eviction::AccessTracker;
 use prelude::*;
 use state::keyed_state::KeyedState;
+use state::{collate_key, key_value};
 
 pub struct SingleState {
     key: Vec<usize>,
+    // The collation of each key column, or nothing if they are all binary.
+    collations: Vec<Collation>,
     state: KeyedState,
     partial: bool,
     rows: usize,
+    access: Option<AccessTracker>,
 }
 impl SingleState {
-    pub fn new(columns: &[usize], partial: bool) -> Self {
+    pub fn new(
+        columns: &[usize],
+        collations: Vec<Collation>,
+        partial: bool,
+        eviction_policy: EvictionPolicy,
+    ) -> Self {
         Self {
             key: Vec::from(columns),
+            collations,
             state: columns.into(),
             partial,
             rows: 0,
+            // only partial state is ever evicted from
+            access: if partial {
+                AccessTracker::new(eviction_policy)
+            } else {
+                None
+            },
         }
     }
 
@@ -29,9 +46,17 @@ impl SingleState {
             KeyedState::Single(ref mut map) => {
                 // treat this specially to avoid the extra Vec
                 debug_assert_eq!(self.key.len(), 1);
+                let collated;
+                let key = match self.collations.first() {
+                    Some(collation) => {
+                        collated = collation.key(&r[self.key[0]]).into_owned();
+                        &collated
+                    }
+                    None => &r[self.key[0]],
+                };
                 // i *wish* we could use the entry API here, but it would mean an extra clone
                 // in the common case of an entry already existing for the given key...
-                if let Some(ref mut rs) = map.get_mut(&r[self.key[0]]) {
+                if let Some(ref mut rs) = map.get_mut(key) {
                     self.rows += 1;
                     rs.push(r);
                     return true;
@@ -39,10 +64,13 @@ impl SingleState {
                     // trying to insert a record into partial materialization hole!
                     return false;
                 }
-                map.insert(r[self.key[0]].clone(), vec![r]);
+                map.insert(key.clone(), vec![r]);
             }
             KeyedState::Double(ref mut map) => {
-                let key = (r[self.key[0]].clone(), r[self.key[1]].clone());
+                let key = (
+                    key_value(&r, &self.key, &self.collations, 0),
+                    key_value(&r, &self.key, &self.collations, 1),
+                );
                 match map.entry(key) {
                     Entry::Occupied(mut rs) => rs.get_mut().push(r),
                     Entry::Vacant(..) if self.partial => return false,
@@ -51,9 +79,9 @@ impl SingleState {
             }
             KeyedState::Tri(ref mut map) => {
                 let key = (
-                    r[self.key[0]].clone(),
-                    r[self.key[1]].clone(),
-                    r[self.key[2]].clone(),
+                    key_value(&r, &self.key, &self.collations, 0),
+                    key_value(&r, &self.key, &self.collations, 1),
+                    key_value(&r, &self.key, &self.collations, 2),
                 );
                 match map.entry(key) {
                     Entry::Occupied(mut rs) => rs.get_mut().push(r),
@@ -63,10 +91,10 @@ impl SingleState {
             }
             KeyedState::Quad(ref mut map) => {
                 let key = (
-                    r[self.key[0]].clone(),
-                    r[self.key[1]].clone(),
-                    r[self.key[2]].clone(),
-                    r[self.key[3]].clone(),
+                    key_value(&r, &self.key, &self.collations, 0),
+                    key_value(&r, &self.key, &self.collations, 1),
+                    key_value(&r, &self.key, &self.collations, 2),
+                    key_value(&r, &self.key, &self.collations, 3),
                 );
                 match map.entry(key) {
                     Entry::Occupied(mut rs) => rs.get_mut().push(r),
@@ -76,11 +104,11 @@ impl SingleState {
             }
             KeyedState::Quin(ref mut map) => {
                 let key = (
-                    r[self.key[0]].clone(),
-                    r[self.key[1]].clone(),
-                    r[self.key[2]].clone(),
-                    r[self.key[3]].clone(),
-                    r[self.key[4]].clone(),
+                    key_value(&r, &self.key, &self.collations, 0),
+                    key_value(&r, &self.key, &self.collations, 1),
+                    key_value(&r, &self.key, &self.collations, 2),
+                    key_value(&r, &self.key, &self.collations, 3),
+                    key_value(&r, &self.key, &self.collations, 4),
                 );
                 match map.entry(key) {
                     Entry::Occupied(mut rs) => rs.get_mut().push(r),
@@ -90,12 +118,12 @@ impl SingleState {
             }
             KeyedState::Sex(ref mut map) => {
                 let key = (
-                    r[self.key[0]].clone(),
-                    r[self.key[1]].clone(),
-                    r[self.key[2]].clone(),
-                    r[self.key[3]].clone(),
-                    r[self.key[4]].clone(),
-                    r[self.key[5]].clone(),
+                    key_value(&r, &self.key, &self.collations, 0),
+                    key_value(&r, &self.key, &self.collations, 1),
+                    key_value(&r, &self.key, &self.collations, 2),
+                    key_value(&r, &self.key, &self.collations, 3),
+                    key_value(&r, &self.key, &self.collations, 4),
+                    key_value(&r, &self.key, &self.collations, 5),
                 );
                 match map.entry(key) {
                     Entry::Occupied(mut rs) => rs.get_mut().push(r),
@@ -133,22 +161,26 @@ impl SingleState {
 
         match self.state {
             KeyedState::Single(ref mut map) => {
-                if let Some(ref mut rs) = map.get_mut(&r[self.key[0]]) {
+                let key = key_value(r, &self.key, &self.collations, 0);
+                if let Some(ref mut rs) = map.get_mut(&key) {
                     return do_remove(&mut self.rows, rs);
                 }
             }
             KeyedState::Double(ref mut map) => {
                 // TODO: can we avoid the Clone here?
-                let key = (r[self.key[0]].clone(), r[self.key[1]].clone());
+                let key = (
+                    key_value(r, &self.key, &self.collations, 0),
+                    key_value(r, &self.key, &self.collations, 1),
+                );
                 if let Some(ref mut rs) = map.get_mut(&key) {
                     return do_remove(&mut self.rows, rs);
                 }
             }
             KeyedState::Tri(ref mut map) => {
                 let key = (
-                    r[self.key[0]].clone(),
-                    r[self.key[1]].clone(),
-                    r[self.key[2]].clone(),
+                    key_value(r, &self.key, &self.collations, 0),
+                    key_value(r, &self.key, &self.collations, 1),
+                    key_value(r, &self.key, &self.collations, 2),
                 );
                 if let Some(ref mut rs) = map.get_mut(&key) {
                     return do_remove(&mut self.rows, rs);
@@ -156,10 +188,10 @@ impl SingleState {
             }
             KeyedState::Quad(ref mut map) => {
                 let key = (
-                    r[self.key[0]].clone(),
-                    r[self.key[1]].clone(),
-                    r[self.key[2]].clone(),
-                    r[self.key[3]].clone(),
+                    key_value(r, &self.key, &self.collations, 0),
+                    key_value(r, &self.key, &self.collations, 1),
+                    key_value(r, &self.key, &self.collations, 2),
+                    key_value(r, &self.key, &self.collations, 3),
                 );
                 if let Some(ref mut rs) = map.get_mut(&key) {
                     return do_remove(&mut self.rows, rs);
@@ -167,11 +199,11 @@ impl SingleState {
             }
             KeyedState::Quin(ref mut map) => {
                 let key = (
-                    r[self.key[0]].clone(),
-                    r[self.key[1]].clone(),
-                    r[self.key[2]].clone(),
-                    r[self.key[3]].clone(),
-                    r[self.key[4]].clone(),
+                    key_value(r, &self.key, &self.collations, 0),
+                    key_value(r, &self.key, &self.collations, 1),
+                    key_value(r, &self.key, &self.collations, 2),
+                    key_value(r, &self.key, &self.collations, 3),
+                    key_value(r, &self.key, &self.collations, 4),
                 );
                 if let Some(ref mut rs) = map.get_mut(&key) {
                     return do_remove(&mut self.rows, rs);
@@ -179,12 +211,12 @@ impl SingleState {
             }
             KeyedState::Sex(ref mut map) => {
                 let key = (
-                    r[self.key[0]].clone(),
-                    r[self.key[1]].clone(),
-                    r[self.key[2]].clone(),
-                    r[self.key[3]].clone(),
-                    r[self.key[4]].clone(),
-                    r[self.key[5]].clone(),
+                    key_value(r, &self.key, &self.collations, 0),
+                    key_value(r, &self.key, &self.collations, 1),
+                    key_value(r, &self.key, &self.collations, 2),
+                    key_value(r, &self.key, &self.collations, 3),
+                    key_value(r, &self.key, &self.collations, 4),
+                    key_value(r, &self.key, &self.collations, 5),
                 );
                 if let Some(ref mut rs) = map.get_mut(&key) {
                     return do_remove(&mut self.rows, rs);
@@ -195,6 +227,14 @@ impl SingleState {
     }
 
     pub fn mark_filled(&mut self, key: Vec<DataType>) {
+        let key = if self.collations.is_empty() {
+            key
+        } else {
+            KeyType::from(&key[..]).collate(&self.collations)
+        };
+        if let Some(ref access) = self.access {
+            access.insert(&key);
+        }
         let mut key = key.into_iter();
         let replaced = match self.state {
             KeyedState::Single(ref mut map) => map.insert(key.next().unwrap(), Vec::new()),
@@ -244,6 +284,11 @@ impl SingleState {
     }
 
     pub fn mark_hole(&mut self, key: &[DataType]) -> u64 {
+        let key = collate_key(key, &self.collations);
+        let key = &key[..];
+        if let Some(ref access) = self.access {
+            access.remove(key);
+        }
         let removed = match self.state {
             KeyedState::Single(ref mut map) => map.remove(&key[0]),
             KeyedState::Double(ref mut map) => map.remove(&(key[0].clone(), key[1].clone())),
@@ -281,13 +326,21 @@ impl SingleState {
             .sum()
     }
 
-    /// Evict `count` randomly selected keys from state and return them along with the number of
-    /// bytes freed.
-    pub fn evict_random_keys(
+    /// Evict `count` keys from state and return them along with the number of bytes freed.
+    ///
+    /// Keys are chosen by the eviction policy the state was created with, or at random if it has
+    /// none.
+    pub fn evict_some_keys(
         &mut self,
         count: usize,
         rng: &mut ThreadRng,
     ) -> (u64, Vec<Vec<DataType>>) {
+        if let Some(ref access) = self.access {
+            let keys = access.victims(count);
+            let bytes_freed = keys.iter().map(|k| self.state.evict(k)).sum();
+            return (bytes_freed, keys);
+        }
+
         let mut bytes_freed = 0;
         let mut keys = Vec::with_capacity(count);
         for _ in 0..count {
@@ -303,6 +356,13 @@ impl SingleState {
 
     /// Evicts a specified key from this state, returning the number of bytes freed.
     pub fn evict_keys(&mut self, keys: &[Vec<DataType>]) -> u64 {
+        let collations = &self.collations;
+        let keys: Vec<_> = keys.iter().map(|k| collate_key(k, collations)).collect();
+        if let Some(ref access) = self.access {
+            for k in &keys {
+                access.remove(k);
+            }
+        }
         keys.iter().map(|k| self.state.evict(k)).sum()
     }
 
@@ -326,7 +386,18 @@ impl SingleState {
         self.rows
     }
     pub fn lookup<'a>(&'a self, key: &KeyType) -> LookupResult<'a> {
+        let (values, collated);
+        let key = if self.collations.is_empty() {
+            key
+        } else {
+            values = key.collate(&self.collations);
+            collated = KeyType::from(&values[..]);
+            &collated
+        };
         if let Some(rs) = self.state.lookup(key) {
+            if let Some(ref access) = self.access {
+                access.touch_key_type(key);
+            }
             LookupResult::Some(RecordResult::Borrowed(&rs[..]))
         } else {
             if self.partial() {
@@ -0,0 +1,480 @@
+use std::cmp;
+use std::collections::HashMap;
+
+use fnv::FnvBuildHasher;
+
+use common::SizeOf;
+use eviction::{hash_key, hash_key_type, AccessTracker};
+use prelude::*;
+use state::{MemoryState, PersistentState, RecordResult, State};
+
+// Maximum number of keys moved to disk at a time when the in-memory rows grow too large.
+const SPILL_BATCH_SIZE: usize = 100;
+
+/// SpillingState is a fully materialized state for internal operators that keeps as many keys as
+/// fit within a memory limit in a `MemoryState`, and spills the rows of the rest to a
+/// `PersistentState`.
+///
+/// Rows are spilled a key (in the first index) at a time, starting with the keys that were least
+/// recently written or read through that index. Once a key has been spilled, new rows for it are
+/// also written to disk. Spilled rows are not durable: they are kept in a temporary directory,
+/// and the state is rebuilt through replay on recovery like any other operator state.
+pub struct SpillingState {
+    memory: MemoryState,
+    disk: PersistentState,
+    memory_limit: u64,
+    // The columns of the first index, which decides which rows are spilled together.
+    primary: Vec<usize>,
+    // The keys in the first index that are kept in memory, by how recently they were used.
+    access: AccessTracker,
+    // The keys in the first index that have rows on disk, by their hash, so that lookups of other
+    // keys don't have to go to disk. Keys are forgotten again once all their rows are removed.
+    spilled: HashMap<u64, Vec<Vec<DataType>>, FnvBuildHasher>,
+    // The collation of each column, or nothing if they are all binary. Keys in `access` and
+    // `spilled` are kept as they are compared under these collations.
+    collations: Vec<Collation>,
+}
+
+impl SizeOf for SpillingState {
+    fn size_of(&self) -> u64 {
+        use std::mem::size_of;
+
+        size_of::<Self>() as u64
+    }
+
+    fn deep_size_of(&self) -> u64 {
+        self.memory.deep_size_of()
+    }
+}
+
+impl State for SpillingState {
+    fn add_key(&mut self, columns: &[usize], partial: Option<Vec<Tag>>) {
+        assert!(partial.is_none(), "SpillingState can't be partial");
+        if self.primary.is_empty() {
+            self.primary = columns.to_vec();
+        }
+        self.memory.add_key(columns, None);
+        self.disk.add_key(columns, None);
+    }
+
+    fn is_useful(&self) -> bool {
+        self.memory.is_useful()
+    }
+
+    fn is_partial(&self) -> bool {
+        false
+    }
+
+    fn process_records(&mut self, records: &mut Records, partial_tag: Option<Tag>) {
+        assert!(partial_tag.is_none(), "SpillingState can't be partial");
+        if records.is_empty() {
+            return;
+        }
+
+        let primary = self.primary.clone();
+        let mut to_disk: Vec<Record> = Vec::new();
+        // spilled keys that rows were removed from, which may have none left
+        let mut shrunk = Vec::new();
+        for r in records.iter() {
+            let key: Vec<DataType> = primary
+                .iter()
+                .map(|&c| Collation::of(&self.collations, c).key(&r[c]).into_owned())
+                .collect();
+            let on_disk = match *r {
+                Record::Positive(_) => self.is_spilled(&key),
+                Record::Negative(ref row) => !self.in_memory(&primary, &key, row),
+            };
+
+            if !on_disk {
+                self.memory.process_records(&mut r.clone().into(), None);
+                if r.is_positive() {
+                    self.access.insert(&key);
+                } else if let LookupResult::Some(rs) =
+                    self.memory.lookup(&primary, &KeyType::from(&key[..]))
+                {
+                    if rs.len() == 0 {
+                        // the key is gone, so there is nothing left to spill
+                        self.access.remove(&key);
+                    }
+                }
+            } else if let Record::Negative(ref row) = *r {
+                shrunk.push(key);
+                // rows written in this batch aren't on disk yet, so we can't remove them there
+                match to_disk
+                    .iter()
+                    .position(|d| d.is_positive() && d.rec() == &row[..])
+                {
+                    Some(i) => {
+                        to_disk.remove(i);
+                    }
+                    None => to_disk.push(r.clone()),
+                }
+            } else {
+                to_disk.push(r.clone());
+            }
+        }
+        self.disk.process_records(&mut to_disk.into(), None);
+
+        for key in shrunk {
+            let empty = match self.disk.lookup(&primary, &KeyType::from(&key[..])) {
+                LookupResult::Some(rs) => rs.len() == 0,
+                LookupResult::Missing => unreachable!("PersistentState can't be partial"),
+            };
+            if empty {
+                self.unspill(&key);
+            }
+        }
+
+        self.spill();
+    }
+
+    fn mark_hole(&mut self, _: &[DataType], _: &Tag) {
+        unreachable!("SpillingState can't be partial")
+    }
+
+    fn mark_filled(&mut self, _: Vec<DataType>, _: &Tag) {
+        unreachable!("SpillingState can't be partial")
+    }
+
+    fn lookup<'a>(&'a self, columns: &[usize], key: &KeyType) -> LookupResult<'a> {
+        let is_primary = columns == &self.primary[..];
+        let (values, collated);
+        let key = if self.collations.is_empty() {
+            key
+        } else {
+            values = key.collate(&Collation::of_columns(&self.collations, columns));
+            collated = KeyType::from(&values[..]);
+            &collated
+        };
+        if is_primary {
+            self.access.touch_key_type(key);
+        }
+
+        let in_memory = self.memory.lookup(columns, key);
+        let may_be_spilled = if is_primary {
+            self.spilled.contains_key(&hash_key_type(key))
+        } else {
+            !self.spilled.is_empty()
+        };
+        if !may_be_spilled {
+            return in_memory;
+        }
+
+        let on_disk = match self.disk.lookup(columns, key) {
+            LookupResult::Some(rs) => rs,
+            LookupResult::Missing => unreachable!("PersistentState can't be partial"),
+        };
+        if on_disk.len() == 0 {
+            return in_memory;
+        }
+
+        match in_memory {
+            LookupResult::Some(rs) => {
+                let mut rows: Vec<Vec<DataType>> = rs.into_iter().map(|r| r.into_owned()).collect();
+                rows.extend(on_disk.into_iter().map(|r| r.into_owned()));
+                LookupResult::Some(RecordResult::Owned(rows))
+            }
+            LookupResult::Missing => unreachable!("MemoryState is fully materialized"),
+        }
+    }
+
+    fn rows(&self) -> usize {
+        let spilled = if self.spilled.is_empty() {
+            0
+        } else {
+            self.disk.rows()
+        };
+        self.memory.rows() + spilled
+    }
+
+    fn keys(&self) -> Vec<Vec<usize>> {
+        self.memory.keys()
+    }
+
+    fn cloned_records(&self) -> Vec<Vec<DataType>> {
+        let mut records = self.memory.cloned_records();
+        if !self.spilled.is_empty() {
+            records.extend(self.disk.cloned_records());
+        }
+        records
+    }
+
+    fn evict_some_keys(&mut self, _: usize) -> (&[usize], Vec<Vec<DataType>>, u64) {
+        unreachable!("can't evict keys from SpillingState")
+    }
+
+    fn evict_keys(&mut self, _: &Tag, _: &[Vec<DataType>]) -> Option<(&[usize], u64)> {
+        unreachable!("can't evict keys from SpillingState")
+    }
+}
+
+impl SpillingState {
+    /// Construct a state that keeps at most `memory_limit` bytes of rows in memory. `name` must
+    /// be unique among the states of this worker.
+    pub fn new(name: String, memory_limit: usize, params: &PersistenceParameters) -> Self {
+        // the spilled rows are only useful while we're running
+        let params = PersistenceParameters {
+            mode: DurabilityMode::DeleteOnExit,
+            log_dir: None,
+            ..params.clone()
+        };
+
+        SpillingState {
+            memory: MemoryState::default(),
+            disk: PersistentState::new(name, None, &params).without_durability(),
+            memory_limit: memory_limit as u64,
+            primary: Vec::new(),
+            access: AccessTracker::new(EvictionPolicy::Lru).unwrap(),
+            spilled: Default::default(),
+            collations: Vec::new(),
+        }
+    }
+
+    /// Builder with the collation of each column of the stored rows.
+    ///
+    /// Indices compare the values of their key columns under these collations.
+    pub fn with_collations(mut self, collations: Vec<Collation>) -> Self {
+        self.memory = self.memory.with_collations(collations.clone());
+        self.disk = self.disk.with_collations(collations.clone());
+        self.collations = collations;
+        self
+    }
+
+    // `MemoryState::rows` counts every row once per index
+    fn index_count(&self) -> u64 {
+        self.memory.keys().len() as u64
+    }
+
+    fn is_spilled(&self, key: &[DataType]) -> bool {
+        self.spilled
+            .get(&hash_key(key))
+            .map_or(false, |keys| keys.iter().any(|k| &k[..] == key))
+    }
+
+    fn unspill(&mut self, key: &[DataType]) {
+        let hash = hash_key(key);
+        let none_left = match self.spilled.get_mut(&hash) {
+            Some(keys) => {
+                keys.retain(|k| &k[..] != key);
+                keys.is_empty()
+            }
+            None => false,
+        };
+        if none_left {
+            self.spilled.remove(&hash);
+        }
+    }
+
+    fn in_memory(&self, primary: &[usize], key: &[DataType], row: &[DataType]) -> bool {
+        match self.memory.lookup(primary, &KeyType::from(key)) {
+            LookupResult::Some(rs) => rs.into_iter().any(|r| &r[..] == row),
+            LookupResult::Missing => unreachable!("MemoryState is fully materialized"),
+        }
+    }
+
+    /// Move the rows of the coldest keys to disk until the in-memory rows fit within the limit.
+    fn spill(&mut self) {
+        while self.memory.deep_size_of() > self.memory_limit {
+            // every key has at least one row, so spilling as many keys as the excess is worth in
+            // rows is enough
+            let size = self.memory.deep_size_of();
+            let row_count = self.memory.rows() as u64 / self.index_count();
+            let excess = size - self.memory_limit;
+            let count = (excess * row_count + size - 1) / size;
+            let count = cmp::min(cmp::max(count as usize, 1), SPILL_BATCH_SIZE);
+
+            let victims = self.access.victims(count);
+            if victims.is_empty() {
+                break;
+            }
+
+            let mut rows = Vec::new();
+            for key in victims {
+                match self.memory.lookup(&self.primary, &KeyType::from(&key[..])) {
+                    LookupResult::Some(rs) => rows.extend(rs.into_iter().map(|r| r.into_owned())),
+                    LookupResult::Missing => unreachable!("MemoryState is fully materialized"),
+                }
+                self.spilled
+                    .entry(hash_key(&key))
+                    .or_insert_with(Vec::new)
+                    .push(key);
+            }
+
+            let mut removed: Records = rows.iter().cloned().map(Record::Negative).collect();
+            self.memory.process_records(&mut removed, None);
+            let mut added: Records = rows.into_iter().map(Record::Positive).collect();
+            self.disk.process_records(&mut added, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(prefix: &str, memory_limit: usize) -> SpillingState {
+        let mut state = SpillingState::new(
+            String::from(prefix),
+            memory_limit,
+            &PersistenceParameters::default(),
+        );
+        state.add_key(&[0], None);
+        state
+    }
+
+    fn lookup(state: &SpillingState, columns: &[usize], key: DataType) -> Vec<Vec<DataType>> {
+        match state.lookup(columns, &KeyType::Single(&key)) {
+            LookupResult::Some(rs) => {
+                let mut rows: Vec<_> = rs.into_iter().map(|r| r.into_owned()).collect();
+                rows.sort();
+                rows
+            }
+            LookupResult::Missing => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn spilling_state_stays_in_memory_below_limit() {
+        let mut state = setup("spilling_state_stays_in_memory_below_limit", 1 << 20);
+        let mut records: Records = (0..10)
+            .map(|i| vec![i.into(), "x".into()])
+            .collect::<Vec<Vec<DataType>>>()
+            .into();
+        state.process_records(&mut records, None);
+
+        assert!(state.spilled.is_empty());
+        assert_eq!(state.rows(), 10);
+        assert_eq!(
+            lookup(&state, &[0], 3.into()),
+            vec![vec![3.into(), "x".into()]]
+        );
+    }
+
+    #[test]
+    fn spilling_state_spills_cold_keys() {
+        let mut state = setup("spilling_state_spills_cold_keys", 1);
+        let mut records: Records = (0..10)
+            .map(|i| vec![i.into(), "x".into()])
+            .collect::<Vec<Vec<DataType>>>()
+            .into();
+        state.process_records(&mut records, None);
+
+        // nothing fits in a single byte
+        assert_eq!(state.deep_size_of(), 0);
+        assert_eq!(state.spilled.len(), 10);
+        assert_eq!(state.cloned_records().len(), 10);
+        assert_eq!(
+            lookup(&state, &[0], 3.into()),
+            vec![vec![3.into(), "x".into()]]
+        );
+
+        // new rows for a spilled key go to disk, and are found along with the old ones
+        let mut records: Records = vec![vec![3.into(), "y".into()]].into();
+        state.process_records(&mut records, None);
+        assert_eq!(state.deep_size_of(), 0);
+        assert_eq!(
+            lookup(&state, &[0], 3.into()),
+            vec![vec![3.into(), "x".into()], vec![3.into(), "y".into()]]
+        );
+
+        // and spilled rows can be removed again
+        let mut records: Records = vec![(vec![3.into(), "x".into()], false)].into();
+        state.process_records(&mut records, None);
+        assert_eq!(
+            lookup(&state, &[0], 3.into()),
+            vec![vec![3.into(), "y".into()]]
+        );
+
+        // until the key has no rows left, and is no longer spilled
+        let mut records: Records = vec![(vec![3.into(), "y".into()], false)].into();
+        state.process_records(&mut records, None);
+        assert!(lookup(&state, &[0], 3.into()).is_empty());
+        assert!(!state.is_spilled(&[3.into()]));
+        assert_eq!(state.spilled.len(), 9);
+    }
+
+    #[test]
+    fn spilling_state_forgets_removed_keys() {
+        let mut state = setup("spilling_state_forgets_removed_keys", 1 << 20);
+        let mut records: Records = vec![
+            vec![1.into(), "a".into()],
+            vec![1.into(), "b".into()],
+            vec![2.into(), "c".into()],
+        ]
+        .into();
+        state.process_records(&mut records, None);
+
+        let mut records: Records = vec![
+            (vec![1.into(), "a".into()], false),
+            (vec![2.into(), "c".into()], false),
+        ]
+        .into();
+        state.process_records(&mut records, None);
+
+        // only keys that still have rows in memory are candidates for spilling
+        assert_eq!(state.access.victims(3), vec![vec![1.into()]]);
+    }
+
+    #[test]
+    fn spilling_state_keeps_recently_read_keys() {
+        let row_size = {
+            let mut state = setup("spilling_state_keeps_recently_read_keys_size", 1 << 20);
+            let mut records: Records = vec![vec![0.into(), "x".into()]].into();
+            state.process_records(&mut records, None);
+            state.deep_size_of() as usize
+        };
+
+        // room for two keys
+        let mut state = setup("spilling_state_keeps_recently_read_keys", 2 * row_size);
+        for i in 0..2 {
+            let mut records: Records = vec![vec![i.into(), "x".into()]].into();
+            state.process_records(&mut records, None);
+        }
+        lookup(&state, &[0], 0.into());
+
+        let mut records: Records = vec![vec![2.into(), "x".into()]].into();
+        state.process_records(&mut records, None);
+
+        assert_eq!(state.spilled.len(), 1);
+        assert!(state.is_spilled(&[1.into()]));
+        for i in 0..3 {
+            assert_eq!(lookup(&state, &[0], i.into()).len(), 1);
+        }
+    }
+
+    #[test]
+    fn spilling_state_secondary_index() {
+        let mut state = setup("spilling_state_secondary_index", 1);
+        state.add_key(&[1], None);
+        let mut records: Records = vec![
+            vec![1.into(), "a".into()],
+            vec![2.into(), "a".into()],
+            vec![3.into(), "b".into()],
+        ]
+        .into();
+        state.process_records(&mut records, None);
+
+        assert_eq!(
+            lookup(&state, &[1], "a".into()),
+            vec![vec![1.into(), "a".into()], vec![2.into(), "a".into()]]
+        );
+    }
+
+    #[test]
+    fn spilling_state_insert_and_remove_in_one_batch() {
+        let mut state = setup("spilling_state_insert_and_remove_in_one_batch", 1);
+        let mut records: Records = vec![vec![1.into(), "a".into()]].into();
+        state.process_records(&mut records, None);
+
+        let mut records: Records = vec![
+            (vec![1.into(), "b".into()], true),
+            (vec![1.into(), "b".into()], false),
+        ]
+        .into();
+        state.process_records(&mut records, None);
+        assert_eq!(
+            lookup(&state, &[0], 1.into()),
+            vec![vec![1.into(), "a".into()]]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use node::{MirNode, MirNodeType};
+use MirNodeRef;
+
+/// A deep copy of a MIR graph.
+///
+/// MIR nodes are shared through reference-counted pointers, so cloning a `MirNodeRef` (or any
+/// structure that holds one) still refers to the same node. A `MirGraphCopy` instead duplicates
+/// every node reachable from a set of starting nodes, and rewires the copies to point to each
+/// other, so that the copied graph can be modified without affecting the original.
+pub struct MirGraphCopy {
+    copies: HashMap<*const RefCell<MirNode>, MirNodeRef>,
+}
+
+impl MirGraphCopy {
+    /// Copy all nodes reachable from the nodes in `from`.
+    ///
+    /// Nodes are reachable through their ancestors, children, and any nodes referenced by their
+    /// node type (such as the node a `Reuse` node stands in for).
+    pub fn new<'a, I>(from: I) -> MirGraphCopy
+    where
+        I: IntoIterator<Item = &'a MirNodeRef>,
+    {
+        let mut originals: Vec<MirNodeRef> = Vec::new();
+        let mut copies = HashMap::new();
+
+        // first, make an unconnected copy of every reachable node
+        let mut stack: Vec<MirNodeRef> = from.into_iter().cloned().collect();
+        while let Some(n) = stack.pop() {
+            let ptr: *const RefCell<MirNode> = &*n;
+            if copies.contains_key(&ptr) {
+                continue;
+            }
+
+            {
+                let node = n.borrow();
+                let copy = MirNode {
+                    name: node.name.clone(),
+                    from_version: node.from_version,
+                    columns: node.columns.clone(),
+                    inner: MirNodeType::Identity,
+                    ancestors: vec![],
+                    children: vec![],
+                    flow_node: node.flow_node.clone(),
+                };
+                copies.insert(ptr, Rc::new(RefCell::new(copy)));
+
+                stack.extend(node.ancestors.iter().cloned());
+                stack.extend(node.children.iter().cloned());
+                match node.inner {
+                    MirNodeType::Reuse { node: ref r } | MirNodeType::Leaf { node: ref r, .. } => {
+                        stack.push(r.clone());
+                    }
+                    MirNodeType::Base {
+                        adapted_over: Some(ref a),
+                        ..
+                    } => {
+                        stack.push(a.over.clone());
+                    }
+                    _ => (),
+                }
+            }
+            originals.push(n);
+        }
+
+        // then, wire the copies up to each other in the same way as the originals
+        let copy = MirGraphCopy { copies };
+        for n in originals {
+            let node = n.borrow();
+            let c = copy.get(&n);
+            let mut c = c.borrow_mut();
+            c.ancestors = node.ancestors.iter().map(|a| copy.get(a)).collect();
+            c.children = node.children.iter().map(|ch| copy.get(ch)).collect();
+            c.inner = node.inner.copy_with(|r| copy.get(r));
+        }
+        copy
+    }
+
+    /// Returns the copy of the given original node.
+    ///
+    /// Panics if `n` was not reachable from the nodes the copy was made from.
+    pub fn get(&self, n: &MirNodeRef) -> MirNodeRef {
+        let ptr: *const RefCell<MirNode> = &**n;
+        self.copies[&ptr].clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Column;
+
+    #[test]
+    fn copies_are_independent() {
+        let a = MirNode::new(
+            "a",
+            0,
+            vec![Column::from("aa")],
+            MirNodeType::Base {
+                column_specs: vec![],
+                keys: vec![],
+                unique_keys: vec![],
+                foreign_keys: vec![],
+                adapted_over: None,
+            },
+            vec![],
+            vec![],
+        );
+        let b = MirNode::new(
+            "b",
+            0,
+            vec![Column::from("aa")],
+            MirNodeType::Identity,
+            vec![a.clone()],
+            vec![],
+        );
+        let c = MirNode::new(
+            "c",
+            0,
+            vec![Column::from("aa")],
+            MirNodeType::Leaf {
+                node: b.clone(),
+                keys: vec![Column::from("aa")],
+            },
+            vec![b.clone()],
+            vec![],
+        );
+
+        let copy = MirGraphCopy::new(vec![&a]);
+        let (ca, cb, cc) = (copy.get(&a), copy.get(&b), copy.get(&c));
+        assert!(!Rc::ptr_eq(&a, &ca));
+        assert!(Rc::ptr_eq(&cb.borrow().ancestors()[0], &ca));
+        assert!(Rc::ptr_eq(&ca.borrow().children()[0], &cb));
+        match cc.borrow().inner {
+            MirNodeType::Leaf { ref node, .. } => assert!(Rc::ptr_eq(node, &cb)),
+            _ => unreachable!(),
+        }
+
+        // extending the copy leaves the original untouched
+        MirNode::new(
+            "d",
+            0,
+            vec![Column::from("aa")],
+            MirNodeType::Identity,
+            vec![ca.clone()],
+            vec![],
+        );
+        assert_eq!(ca.borrow().children().len(), 2);
+        assert_eq!(a.borrow().children().len(), 1);
+    }
+}
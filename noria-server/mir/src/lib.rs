@@ -13,6 +13,7 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 mod column;
+pub mod copy;
 pub mod node;
 mod optimize;
 pub mod query;
@@ -1,4 +1,4 @@
-use nom_sql::{ArithmeticExpression, ColumnSpecification, OrderType};
+use nom_sql::{ArithmeticExpression, ColumnSpecification, FunctionExpression, OrderType, SqlType};
 use petgraph::graph::NodeIndex;
 use std::cell::RefCell;
 use std::fmt::{Debug, Display, Error, Formatter};
@@ -10,6 +10,7 @@ use dataflow::ops;
 use dataflow::ops::filter::FilterCondition;
 use dataflow::ops::grouped::aggregate::Aggregation as AggregationKind;
 use dataflow::ops::grouped::extremum::Extremum as ExtremumKind;
+use dataflow::JsonPath;
 use {FlowNode, MirNodeRef};
 
 /// Helper enum to avoid having separate `make_aggregation_node` and `make_extremum_node` functions
@@ -58,23 +59,34 @@ impl MirNode {
         rc_mn
     }
 
-    /// Adapts an existing `Base`-type MIR Node with the specified column additions and removals.
+    /// Adapts an existing `Base`-type MIR Node with the specified column additions, removals and
+    /// renames. Renamed columns are given as pairs of their old and new specification.
     pub fn adapt_base(
         node: MirNodeRef,
         added_cols: Vec<&ColumnSpecification>,
         removed_cols: Vec<&ColumnSpecification>,
+        renamed_cols: Vec<(&ColumnSpecification, &ColumnSpecification)>,
     ) -> MirNodeRef {
         let over_node = node.borrow();
         match over_node.inner {
             MirNodeType::Base {
                 ref column_specs,
                 ref keys,
+                ref unique_keys,
+                ref foreign_keys,
                 ..
             } => {
                 let new_column_specs: Vec<(ColumnSpecification, Option<usize>)> = column_specs
                     .into_iter()
                     .cloned()
                     .filter(|&(ref cs, _)| !removed_cols.contains(&cs))
+                    .map(|(cs, cid)| {
+                        // renamed columns keep their position and base column ID
+                        match renamed_cols.iter().find(|&&(old, _)| *old == cs) {
+                            Some(&(_, new)) => (new.clone(), cid),
+                            None => (cs, cid),
+                        }
+                    })
                     .chain(
                         added_cols
                             .iter()
@@ -92,13 +104,49 @@ impl MirNode {
                     over_node.columns.len() + added_cols.len() - removed_cols.len()
                 );
 
+                let (new_keys, new_unique_keys, new_foreign_keys) = {
+                    let rename = |c: &Column| match renamed_cols
+                        .iter()
+                        .find(|&&(old, _)| Column::from(&old.column) == *c)
+                    {
+                        Some(&(_, new)) => Column::from(&new.column),
+                        None => c.clone(),
+                    };
+                    let removed = |c: &Column| {
+                        removed_cols
+                            .iter()
+                            .any(|&old| Column::from(&old.column) == *c)
+                    };
+                    (
+                        keys.iter().map(&rename).collect(),
+                        unique_keys
+                            .iter()
+                            .map(|uk| uk.iter().map(&rename).collect())
+                            .collect(),
+                        // a foreign key goes away along with its columns
+                        foreign_keys
+                            .iter()
+                            .filter(|fk| !fk.columns.iter().any(&removed))
+                            .map(|fk| ForeignKey {
+                                columns: fk.columns.iter().map(&rename).collect(),
+                                ..fk.clone()
+                            })
+                            .collect(),
+                    )
+                };
                 let new_inner = MirNodeType::Base {
                     column_specs: new_column_specs,
-                    keys: keys.clone(),
+                    keys: new_keys,
+                    unique_keys: new_unique_keys,
+                    foreign_keys: new_foreign_keys,
                     adapted_over: Some(BaseNodeAdaptation {
                         over: node.clone(),
                         columns_added: added_cols.into_iter().cloned().collect(),
                         columns_removed: removed_cols.into_iter().cloned().collect(),
+                        columns_renamed: renamed_cols
+                            .into_iter()
+                            .map(|(old, new)| (old.clone(), new.clone()))
+                            .collect(),
                     }),
                 };
                 return MirNode::new(
@@ -252,6 +300,53 @@ impl MirNode {
         }
     }
 
+    /// Returns the SQL type of column `c`, if it can be traced back to a base table column or is
+    /// computed by an aggregation whose output type is known.
+    pub fn column_type(&self, c: &Column) -> Option<SqlType> {
+        match self.inner {
+            MirNodeType::Base {
+                ref column_specs, ..
+            } => column_specs
+                .iter()
+                .rposition(|cs| Column::from(&cs.0.column) == *c)
+                .map(|id| column_specs[id].0.sql_type.clone()),
+            MirNodeType::Reuse { ref node } => node.borrow().column_type(c),
+            // leaf columns are sanitized copies of the parent's columns, so match them by position
+            MirNodeType::Leaf { .. } => {
+                let id = self.columns.iter().position(|cc| cc == c)?;
+                let parent = self.ancestors[0].borrow();
+                parent.column_type(&parent.columns[id])
+            }
+            _ => {
+                if let Some(a) = self
+                    .ancestors
+                    .iter()
+                    .find(|a| a.borrow().columns().contains(c))
+                {
+                    return a.borrow().column_type(c);
+                }
+
+                // the column is computed by this node
+                match c.function.as_ref().map(|f| &**f) {
+                    Some(&FunctionExpression::Count(..)) | Some(&FunctionExpression::CountStar) => {
+                        Some(SqlType::Bigint(64))
+                    }
+                    Some(&FunctionExpression::Sum(ref arg, _))
+                    | Some(&FunctionExpression::Max(ref arg))
+                    | Some(&FunctionExpression::Min(ref arg)) => {
+                        let arg = Column::from(arg);
+                        self.ancestors
+                            .iter()
+                            .find(|a| a.borrow().columns().contains(&arg))
+                            .and_then(|a| a.borrow().column_type(&arg))
+                    }
+                    Some(&FunctionExpression::GroupConcat(..)) => Some(SqlType::Text),
+                    _ => None,
+                }
+            }
+        }
+    }
+
     pub fn flow_node_addr(&self) -> Result<NodeIndex, String> {
         match self.flow_node {
             Some(FlowNode::New(na)) | Some(FlowNode::Existing(na)) => Ok(na),
@@ -297,8 +392,10 @@ impl MirNode {
                     }
                 }
             }
-            MirNodeType::Project { ref emit, .. } => {
-                for c in emit {
+            MirNodeType::Project {
+                ref emit, ref json, ..
+            } => {
+                for c in emit.iter().chain(json.iter().map(|&(_, ref c, _, _)| c)) {
                     if !columns.contains(&c) {
                         columns.push(c.clone());
                     }
@@ -325,12 +422,24 @@ impl MirNode {
     }
 }
 
-/// Specifies the adapatation of an existing base node by column addition/removal.
+/// Specifies the adapatation of an existing base node by column addition/removal/renaming.
 /// `over` is a `MirNode` of type `Base`.
 pub struct BaseNodeAdaptation {
     pub over: MirNodeRef,
     pub columns_added: Vec<ColumnSpecification>,
     pub columns_removed: Vec<ColumnSpecification>,
+    /// Old and new specification of each renamed column.
+    pub columns_renamed: Vec<(ColumnSpecification, ColumnSpecification)>,
+}
+
+/// A foreign key from some columns of a base to the columns of an existing base.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignKey {
+    pub columns: Vec<Column>,
+    /// The referenced base.
+    pub parent: NodeIndex,
+    /// The absolute column IDs of the referenced columns in `parent`.
+    pub parent_columns: Vec<usize>,
 }
 
 pub enum MirNodeType {
@@ -340,10 +449,12 @@ pub enum MirNodeType {
         group_by: Vec<Column>,
         kind: AggregationKind,
     },
-    /// column specifications, keys (non-compound), tx flag, adapted base
+    /// column specifications, keys (non-compound), unique keys, foreign keys, adapted base
     Base {
         column_specs: Vec<(ColumnSpecification, Option<usize>)>,
         keys: Vec<Column>,
+        unique_keys: Vec<Vec<Column>>,
+        foreign_keys: Vec<ForeignKey>,
         adapted_over: Option<BaseNodeAdaptation>,
     },
     /// over column, group_by columns
@@ -375,16 +486,22 @@ pub enum MirNodeType {
         on_right: Vec<Column>,
         project: Vec<Column>,
     },
-    /// group columns
-    // currently unused
-    #[allow(dead_code)]
+    /// join column in each ancestor, emit columns
+    MultiJoin {
+        on: Vec<Column>,
+        project: Vec<Column>,
+    },
+    /// group columns, and the column that orders records if not their arrival
     Latest {
         group_by: Vec<Column>,
+        order: Option<Column>,
     },
-    /// emit columns
+    /// emit columns, then named arithmetic expressions, JSON extractions (from which column, at
+    /// what path, and whether unquoted) and literals
     Project {
         emit: Vec<Column>,
         arithmetic: Vec<(String, ArithmeticExpression)>,
+        json: Vec<(String, Column, JsonPath, bool)>,
         literals: Vec<(String, DataType)>,
     },
     /// emit columns
@@ -424,6 +541,135 @@ impl MirNodeType {
         format!("{:?}", self)
     }
 
+    /// Returns a copy of this node type in which all references to other MIR nodes have been
+    /// replaced by the result of calling `remap` on them.
+    pub fn copy_with<F>(&self, remap: F) -> MirNodeType
+    where
+        F: Fn(&MirNodeRef) -> MirNodeRef,
+    {
+        match *self {
+            MirNodeType::Aggregation {
+                ref on,
+                ref group_by,
+                ref kind,
+            } => MirNodeType::Aggregation {
+                on: on.clone(),
+                group_by: group_by.clone(),
+                kind: kind.clone(),
+            },
+            MirNodeType::Base {
+                ref column_specs,
+                ref keys,
+                ref unique_keys,
+                ref foreign_keys,
+                ref adapted_over,
+            } => MirNodeType::Base {
+                column_specs: column_specs.clone(),
+                keys: keys.clone(),
+                unique_keys: unique_keys.clone(),
+                foreign_keys: foreign_keys.clone(),
+                adapted_over: adapted_over.as_ref().map(|a| BaseNodeAdaptation {
+                    over: remap(&a.over),
+                    columns_added: a.columns_added.clone(),
+                    columns_removed: a.columns_removed.clone(),
+                    columns_renamed: a.columns_renamed.clone(),
+                }),
+            },
+            MirNodeType::Extremum {
+                ref on,
+                ref group_by,
+                ref kind,
+            } => MirNodeType::Extremum {
+                on: on.clone(),
+                group_by: group_by.clone(),
+                kind: kind.clone(),
+            },
+            MirNodeType::Filter { ref conditions } => MirNodeType::Filter {
+                conditions: conditions.clone(),
+            },
+            MirNodeType::GroupConcat {
+                ref on,
+                ref separator,
+            } => MirNodeType::GroupConcat {
+                on: on.clone(),
+                separator: separator.clone(),
+            },
+            MirNodeType::Identity => MirNodeType::Identity,
+            MirNodeType::Join {
+                ref on_left,
+                ref on_right,
+                ref project,
+            } => MirNodeType::Join {
+                on_left: on_left.clone(),
+                on_right: on_right.clone(),
+                project: project.clone(),
+            },
+            MirNodeType::LeftJoin {
+                ref on_left,
+                ref on_right,
+                ref project,
+            } => MirNodeType::LeftJoin {
+                on_left: on_left.clone(),
+                on_right: on_right.clone(),
+                project: project.clone(),
+            },
+            MirNodeType::Latest {
+                ref group_by,
+                ref order,
+            } => MirNodeType::Latest {
+                group_by: group_by.clone(),
+                order: order.clone(),
+            },
+            MirNodeType::MultiJoin {
+                ref on,
+                ref project,
+            } => MirNodeType::MultiJoin {
+                on: on.clone(),
+                project: project.clone(),
+            },
+            MirNodeType::Project {
+                ref emit,
+                ref arithmetic,
+                ref json,
+                ref literals,
+            } => MirNodeType::Project {
+                emit: emit.clone(),
+                arithmetic: arithmetic.clone(),
+                json: json.clone(),
+                literals: literals.clone(),
+            },
+            MirNodeType::Union { ref emit } => MirNodeType::Union { emit: emit.clone() },
+            MirNodeType::TopK {
+                ref order,
+                ref group_by,
+                k,
+                offset,
+            } => MirNodeType::TopK {
+                order: order.clone(),
+                group_by: group_by.clone(),
+                k,
+                offset,
+            },
+            MirNodeType::Distinct { ref group_by } => MirNodeType::Distinct {
+                group_by: group_by.clone(),
+            },
+            MirNodeType::Reuse { ref node } => MirNodeType::Reuse { node: remap(node) },
+            MirNodeType::Leaf { ref node, ref keys } => MirNodeType::Leaf {
+                node: remap(node),
+                keys: keys.clone(),
+            },
+            MirNodeType::Rewrite {
+                ref value,
+                ref column,
+                ref key,
+            } => MirNodeType::Rewrite {
+                value: value.clone(),
+                column: column.clone(),
+                key: key.clone(),
+            },
+        }
+    }
+
     fn add_column(&mut self, c: Column) {
         match *self {
             MirNodeType::Aggregation {
@@ -445,6 +691,9 @@ impl MirNodeType {
             }
             | MirNodeType::LeftJoin {
                 ref mut project, ..
+            }
+            | MirNodeType::MultiJoin {
+                ref mut project, ..
             } => {
                 project.push(c);
             }
@@ -466,6 +715,11 @@ impl MirNodeType {
             } => {
                 group_by.push(c);
             }
+            MirNodeType::Latest {
+                ref mut group_by, ..
+            } => {
+                group_by.push(c);
+            }
             _ => (),
         }
     }
@@ -517,12 +771,16 @@ impl MirNodeType {
             MirNodeType::Base {
                 column_specs: ref our_column_specs,
                 keys: ref our_keys,
+                unique_keys: ref our_unique_keys,
+                foreign_keys: ref our_foreign_keys,
                 adapted_over: ref our_adapted_over,
             } => {
                 match *other {
                     MirNodeType::Base {
                         ref column_specs,
                         ref keys,
+                        ref unique_keys,
+                        ref foreign_keys,
                         ..
                     } => {
                         // if we are instructed to adapt an earlier base node, we cannot reuse
@@ -538,7 +796,10 @@ impl MirNodeType {
                         // note that as long as we are not adapting a previous base node,
                         // we do *not* need `adapted_over` to *match*, since current reuse
                         // does not depend on how base node was created from an earlier one
-                        our_column_specs == column_specs && our_keys == keys
+                        our_column_specs == column_specs
+                            && our_keys == keys
+                            && our_unique_keys == unique_keys
+                            && our_foreign_keys == foreign_keys
                     }
                     _ => false,
                 }
@@ -579,16 +840,33 @@ impl MirNodeType {
                     _ => false,
                 }
             }
+            MirNodeType::MultiJoin {
+                on: ref our_on,
+                project: ref our_project,
+            } => match *other {
+                MirNodeType::MultiJoin {
+                    ref on,
+                    ref project,
+                } => our_on == on && our_project == project,
+                _ => false,
+            },
             MirNodeType::Project {
                 emit: ref our_emit,
                 literals: ref our_literals,
                 arithmetic: ref our_arithmetic,
+                json: ref our_json,
             } => match *other {
                 MirNodeType::Project {
                     ref emit,
                     ref literals,
                     ref arithmetic,
-                } => our_emit == emit && our_literals == literals && our_arithmetic == arithmetic,
+                    ref json,
+                } => {
+                    our_emit == emit
+                        && our_literals == literals
+                        && our_arithmetic == arithmetic
+                        && our_json == json
+                }
                 _ => false,
             },
             MirNodeType::Distinct {
@@ -634,6 +912,16 @@ impl MirNodeType {
                 MirNodeType::Leaf { ref keys, .. } => keys == our_keys,
                 _ => false,
             },
+            MirNodeType::Latest {
+                group_by: ref our_group_by,
+                order: ref our_order,
+            } => match *other {
+                MirNodeType::Latest {
+                    ref group_by,
+                    ref order,
+                } => group_by == our_group_by && order == our_order,
+                _ => false,
+            },
             MirNodeType::Union { emit: ref our_emit } => match *other {
                 MirNodeType::Union { ref emit } => emit == our_emit,
                 _ => false,
@@ -693,6 +981,7 @@ impl Debug for MirNodeType {
             } => {
                 let op_string = match *kind {
                     AggregationKind::COUNT => format!("|*|({})", on.name.as_str()),
+                    AggregationKind::COUNT_STAR => "|*|".to_owned(),
                     AggregationKind::SUM => format!("𝛴({})", on.name.as_str()),
                 };
                 let group_cols = group_by
@@ -763,6 +1052,12 @@ impl Debug for MirNodeType {
                                         .collect::<Vec<_>>()
                                         .join(", ")
                                 )),
+                                FilterCondition::IsNull => Some(format!("f{} IS NULL", i)),
+                                FilterCondition::IsNotNull => Some(format!("f{} IS NOT NULL", i)),
+                                FilterCondition::JsonExtract(..)
+                                | FilterCondition::Collated(..) => {
+                                    Some(format!("f{} {}", i, escape(&format!("{}", cond))))
+                                }
                             },
                             None => None,
                         })
@@ -828,21 +1123,48 @@ impl Debug for MirNodeType {
                     jc
                 )
             }
-            MirNodeType::Latest { ref group_by } => {
+            MirNodeType::Latest {
+                ref group_by,
+                ref order,
+            } => {
                 let key_cols = group_by
                     .iter()
                     .map(|k| k.name.clone())
                     .collect::<Vec<_>>()
                     .join(", ");
-                write!(f, "⧖ γ[{}]", key_cols)
+                match *order {
+                    Some(ref o) => write!(f, "⧖({}) γ[{}]", o.name, key_cols),
+                    None => write!(f, "⧖ γ[{}]", key_cols),
+                }
+            }
+            MirNodeType::MultiJoin {
+                ref on,
+                ref project,
+            } => {
+                let jc = on
+                    .iter()
+                    .map(|c| c.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(":");
+                write!(
+                    f,
+                    "⋈ [{} on {}]",
+                    project
+                        .iter()
+                        .map(|c| c.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    jc
+                )
             }
             MirNodeType::Project {
                 ref emit,
                 ref literals,
                 ref arithmetic,
+                ref json,
             } => write!(
                 f,
-                "π [{}{}{}]",
+                "π [{}{}{}{}]",
                 emit.iter()
                     .map(|c| c.name.as_str())
                     .collect::<Vec<_>>()
@@ -859,6 +1181,19 @@ impl Debug for MirNodeType {
                             .join(", ")
                     )
                 },
+                if json.is_empty() {
+                    format!("")
+                } else {
+                    format!(
+                        ", {}",
+                        json.iter()
+                            .map(|&(ref n, ref c, ref path, _)| {
+                                format!("{}: {}[{}]", n, c.name, path)
+                            })
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                },
                 if literals.is_empty() {
                     format!("")
                 } else {
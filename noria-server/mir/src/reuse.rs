@@ -240,6 +240,8 @@ mod tests {
             MirNodeType::Base {
                 column_specs: vec![cspec("aa"), cspec("ab")],
                 keys: vec![Column::from("aa")],
+                unique_keys: vec![],
+                foreign_keys: vec![],
                 adapted_over: None,
             },
             vec![],
@@ -252,6 +254,8 @@ mod tests {
             MirNodeType::Base {
                 column_specs: vec![cspec("ba"), cspec("bb")],
                 keys: vec![Column::from("ba")],
+                unique_keys: vec![],
+                foreign_keys: vec![],
                 adapted_over: None,
             },
             vec![],
@@ -326,6 +330,7 @@ mod tests {
             MirNodeType::Project {
                 emit: vec![Column::from("aa")],
                 arithmetic: vec![],
+                json: vec![],
                 literals: vec![],
             },
             vec![c.clone()],
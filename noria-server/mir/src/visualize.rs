@@ -90,10 +90,13 @@ impl GraphViz for MirNodeType {
 
         let mut out = String::new();
 
+        // columns extracted from JSON documents are named like `doc->>'$.a'`, and `>` is special
+        // in record labels
         let print_col = |c: &Column| -> String {
+            let name = c.name.replace('<', "\\<").replace('>', "\\>");
             match c.table {
-                None => c.name.clone(),
-                Some(ref t) => format!("{}.{}", t, c.name),
+                None => name,
+                Some(ref t) => format!("{}.{}", t, name),
             }
         };
 
@@ -105,6 +108,7 @@ impl GraphViz for MirNodeType {
             } => {
                 let op_string = match *kind {
                     AggregationKind::COUNT => format!("\\|*\\|({})", print_col(on)),
+                    AggregationKind::COUNT_STAR => "\\|*\\|".to_owned(),
                     AggregationKind::SUM => format!("𝛴({})", print_col(on)),
                 };
                 let group_cols = group_by
@@ -177,6 +181,12 @@ impl GraphViz for MirNodeType {
                                         .collect::<Vec<_>>()
                                         .join(", ")
                                 )),
+                                FilterCondition::IsNull => Some(format!("f{} IS NULL", i)),
+                                FilterCondition::IsNotNull => Some(format!("f{} IS NOT NULL", i)),
+                                FilterCondition::JsonExtract(..)
+                                | FilterCondition::Collated(..) => {
+                                    Some(format!("f{} {}", i, escape(&format!("{}", cond))))
+                                }
                             },
                             None => None,
                         })
@@ -228,22 +238,37 @@ impl GraphViz for MirNodeType {
                     .join(", ");
                 write!(out, "⋉  | on: {}", jc)?;
             }
-            MirNodeType::Latest { ref group_by } => {
+            MirNodeType::Latest {
+                ref group_by,
+                ref order,
+            } => {
                 let key_cols = group_by
                     .iter()
                     .map(|k| print_col(k))
                     .collect::<Vec<_>>()
                     .join(", ");
-                write!(out, "⧖ | γ: {}", key_cols)?;
+                match *order {
+                    Some(ref o) => write!(out, "⧖({}) | γ: {}", print_col(o), key_cols)?,
+                    None => write!(out, "⧖ | γ: {}", key_cols)?,
+                }
+            }
+            MirNodeType::MultiJoin { ref on, .. } => {
+                let jc = on
+                    .iter()
+                    .map(|c| print_col(c))
+                    .collect::<Vec<_>>()
+                    .join(":");
+                write!(out, "⋈  | on: {}", jc)?;
             }
             MirNodeType::Project {
                 ref emit,
                 ref literals,
                 ref arithmetic,
+                ref json,
             } => {
                 write!(
                     out,
-                    "π: {}{}{}",
+                    "π: {}{}{}{}",
                     emit.iter()
                         .map(|c| print_col(c))
                         .collect::<Vec<_>>()
@@ -260,6 +285,22 @@ impl GraphViz for MirNodeType {
                                 .join(", ")
                         )
                     },
+                    if json.is_empty() {
+                        format!("")
+                    } else {
+                        format!(
+                            ", {}",
+                            json.iter()
+                                .map(|&(ref n, ref c, ref path, _)| format!(
+                                    "{}: {}[{}]",
+                                    print_col(&Column::new(None, n)),
+                                    print_col(c),
+                                    path
+                                ))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    },
                     if literals.is_empty() {
                         format!("")
                     } else {
@@ -1,6 +1,6 @@
 use crate::controller::sql::reuse::ReuseConfigType;
 use crate::controller::{self, ControllerConfig, LocalControllerHandle};
-use dataflow::PersistenceParameters;
+use dataflow::{EvictionPolicy, PersistenceParameters};
 use failure;
 use noria::consensus::{Authority, LocalAuthority};
 use slog;
@@ -52,6 +52,20 @@ impl ControllerBuilder {
         self.config.partial_enabled = false;
     }
 
+    /// Keep at most `memory_limit` bytes of the state of fully materialized operators in memory
+    /// for all subsequent migrations, and spill the rest to disk. The materialization planner
+    /// picks which operators spill: those whose state no other operator looks into.
+    pub fn set_spill_limit(&mut self, memory_limit: usize) {
+        assert_ne!(memory_limit, 0);
+        self.config.spill_limit = Some(memory_limit);
+    }
+
+    /// Keep the state of partially materialized operators on disk for all subsequent migrations.
+    /// Readers keep their state in memory regardless.
+    pub fn persist_partial_state(&mut self) {
+        self.config.persist_partial = true;
+    }
+
     /// Set sharding policy for all subsequent migrations; `None` disables
     pub fn set_sharding(&mut self, shards: Option<usize>) {
         self.config.sharding = shards;
@@ -72,6 +86,12 @@ impl ControllerBuilder {
         self.memory_check_frequency = Some(check_freq);
     }
 
+    /// Set how domains choose which keys to evict from partially materialized state when they
+    /// exceed the memory limit. Keys are evicted at random by default.
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) {
+        self.config.domain_config.eviction_policy = policy;
+    }
+
     /// Set the IP address that the controller should use for listening.
     pub fn set_listen_addr(&mut self, listen_addr: IpAddr) {
         self.listen_addr = listen_addr;
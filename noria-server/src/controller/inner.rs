@@ -3,28 +3,48 @@ use crate::controller::{
     ControllerState, DomainHandle, DomainShardHandle, Migration, Recipe, Worker, WorkerIdentifier,
 };
 use crate::coordination::{CoordinationMessage, CoordinationPayload, DomainDescriptor};
+use dataflow::ops::identity::Identity;
 use dataflow::payload::ControlReplyPacket;
 use dataflow::prelude::*;
 use dataflow::{node, payload, DomainBuilder, DomainConfig};
 use hyper::{self, Method, StatusCode};
 use mio::net::TcpListener;
+use nom_sql::{ColumnConstraint, TableKey};
 use noria::builders::*;
 use noria::channel::tcp::{SendError, TcpSender};
 use noria::consensus::{Authority, Epoch, STATE_KEY};
-use noria::debug::stats::{DomainStats, GraphStats, NodeStats};
-use noria::ActivationResult;
+use noria::debug::stats::{DomainStats, GraphStats, NodeStats, ViewStats};
+use noria::{
+    ActivationResult, ColumnDescription, Description, RecipePlan, RecipeVersion, TableDescription,
+    ViewDescription, WarmProgress,
+};
 use petgraph;
 use petgraph::visit::Bfs;
 use slog;
 use slog::Logger;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::mem;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use std::{cell, io, thread, time};
 use tokio::prelude::*;
 
+/// The number of recipe versions kept in the recipe history.
+const RECIPE_HISTORY_LEN: usize = 64;
+
+/// A reader index added to an existing view by `ControllerInner::add_view_index`.
+struct ViewIndex {
+    /// The name of the view the index was added to.
+    view: String,
+    /// The node that the view reads from.
+    of: NodeIndex,
+    /// The identity node that the index's reader is attached to.
+    node: NodeIndex,
+    /// The columns of the view that the index is keyed by.
+    key: Vec<String>,
+}
+
 /// `Controller` is the core component of the alternate Soup implementation.
 ///
 /// It keeps track of the structure of the underlying data flow graph and its domains. `Controller`
@@ -60,6 +80,14 @@ pub struct ControllerInner {
     pub(super) epoch: Epoch,
 
     pending_recovery: Option<(Vec<String>, usize)>,
+    /// Reader indexes to add again once the recipe has been recovered.
+    pending_view_indexes: Vec<(String, String, Vec<String>)>,
+    recipe_history: Vec<RecipeVersion>,
+
+    /// Memory budgets of views, in bytes, by view name.
+    view_budgets: HashMap<String, usize>,
+    /// Reader indexes added to existing views, by index name.
+    view_indexes: HashMap<String, ViewIndex>,
 
     quorum: usize,
     heartbeat_every: Duration,
@@ -121,6 +149,27 @@ impl DomainReplies {
         }
         stats
     }
+
+    pub(crate) fn wait_for_row_count(&mut self) -> usize {
+        match self.read_n_domain_replies(1).pop().unwrap() {
+            ControlReplyPacket::StateSize(row_count, _) => row_count,
+            r => unreachable!("got unexpected non-size control reply: {:?}", r),
+        }
+    }
+
+    pub(crate) fn wait_for_warmed(&mut self) -> WarmProgress {
+        match self.read_n_domain_replies(1).pop().unwrap() {
+            ControlReplyPacket::Warmed(present, pending) => WarmProgress { present, pending },
+            r => unreachable!("got unexpected non-warmed control reply: {:?}", r),
+        }
+    }
+
+    pub(crate) fn wait_for_keys(&mut self) -> Vec<Vec<DataType>> {
+        match self.read_n_domain_replies(1).pop().unwrap() {
+            ControlReplyPacket::Keys(keys) => keys,
+            r => unreachable!("got unexpected non-keys control reply: {:?}", r),
+        }
+    }
 }
 
 pub(crate) fn graphviz(
@@ -198,7 +247,7 @@ impl ControllerInner {
             (&Method::POST, "/graphviz") => {
                 return Ok(Ok(json::to_string(&self.graphviz(true)).unwrap()))
             }
-            (&Method::GET, "/get_statistics") => {
+            (&Method::GET, "/get_statistics") | (&Method::POST, "/get_statistics") => {
                 return Ok(Ok(json::to_string(&self.get_statistics()).unwrap()))
             }
             _ => {}
@@ -251,6 +300,9 @@ impl ControllerInner {
             (Method::POST, "/view_builder") => json::from_slice(&body)
                 .map_err(|_| StatusCode::BAD_REQUEST)
                 .map(|args| Ok(json::to_string(&self.view_builder(args)).unwrap())),
+            (Method::POST, "/describe") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| Ok(json::to_string(&self.describe(args)).unwrap())),
             (Method::POST, "/extend_recipe") => json::from_slice(&body)
                 .map_err(|_| StatusCode::BAD_REQUEST)
                 .map(|args| {
@@ -263,6 +315,18 @@ impl ControllerInner {
                     self.install_recipe(authority, args)
                         .map(|r| json::to_string(&r).unwrap())
                 }),
+            (Method::POST, "/plan_recipe") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| self.plan_recipe(args).map(|r| json::to_string(&r).unwrap())),
+            (Method::POST, "/recipe_history") => {
+                Ok(Ok(json::to_string(&self.recipe_history).unwrap()))
+            }
+            (Method::POST, "/rollback_recipe") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.rollback_recipe(authority, args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
             (Method::POST, "/set_security_config") => json::from_slice(&body)
                 .map_err(|_| StatusCode::BAD_REQUEST)
                 .map(|args| {
@@ -275,6 +339,27 @@ impl ControllerInner {
                     self.create_universe(args)
                         .map(|r| json::to_string(&r).unwrap())
                 }),
+            (Method::POST, "/set_view_budget") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.set_view_budget(authority, args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/add_view_index") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.add_view_index(authority, args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/warm_view") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| self.warm_view(args).map(|r| json::to_string(&r).unwrap())),
+            (Method::POST, "/view_hot_keys") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.view_hot_keys(args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
             (Method::POST, "/remove_node") => json::from_slice(&body)
                 .map_err(|_| StatusCode::BAD_REQUEST)
                 .map(|args| {
@@ -316,6 +401,8 @@ impl ControllerInner {
                     self.apply_recipe(self.recipe.clone().extend(&r).unwrap())
                         .unwrap();
                 }
+                let indexes = mem::replace(&mut self.pending_view_indexes, Vec::new());
+                self.restore_view_indexes(indexes);
             }
         }
 
@@ -352,6 +439,9 @@ impl ControllerInner {
     }
 
     fn handle_failed_workers(&mut self, failed: Vec<WorkerIdentifier>) {
+        // removing the affected queries also removes the indexes added to their views
+        let indexes = self.view_index_specs();
+
         // first, translate from the affected workers to affected data-flow nodes
         let mut affected_nodes = Vec::new();
         for wi in failed {
@@ -378,6 +468,12 @@ impl ControllerInner {
         // back to original recipe, which should add the query again
         self.apply_recipe(original)
             .expect("failed to activate original recipe");
+
+        let lost = indexes
+            .into_iter()
+            .filter(|&(_, ref name, _)| !self.view_indexes.contains_key(name))
+            .collect();
+        self.restore_view_indexes(lost);
     }
 
     pub(crate) fn handle_heartbeat(&mut self, msg: &CoordinationMessage) -> Result<(), io::Error> {
@@ -413,6 +509,12 @@ impl ControllerInner {
         if !state.config.partial_enabled {
             materializations.disable_partial()
         }
+        if let Some(memory_limit) = state.config.spill_limit {
+            materializations.spill_above(memory_limit);
+        }
+        if state.config.persist_partial {
+            materializations.persist_partial();
+        }
 
         let cc = Arc::new(ChannelCoordinator::new());
         assert_ne!(state.config.quorum, 0);
@@ -452,8 +554,13 @@ impl ControllerInner {
             workers: HashMap::default(),
 
             pending_recovery,
+            pending_view_indexes: state.view_indexes,
+            recipe_history: state.recipe_history,
             last_checked_workers: Instant::now(),
 
+            view_budgets: state.view_budgets,
+            view_indexes: HashMap::default(),
+
             replies: DomainReplies(drx),
         }
     }
@@ -742,9 +849,8 @@ impl ControllerInner {
         reader
     }
 
-    /// Obtain a `ViewBuilder` that can be sent to a client and then used to query a given
-    /// (already maintained) reader node called `name`.
-    pub fn view_builder(&self, name: &str) -> Option<ViewBuilder> {
+    /// Find the reader node for the view with the given name.
+    fn find_view(&self, name: &str) -> Option<NodeIndex> {
         // first try to resolve the node via the recipe, which handles aliasing between identical
         // queries.
         let node = match self.recipe.node_addr_for(name) {
@@ -756,17 +862,28 @@ impl ControllerInner {
             }
         };
 
-        self.find_view_for(node).map(|r| {
+        self.find_view_for(node)
+    }
+
+    /// Obtain a `ViewBuilder` that can be sent to a client and then used to query a given
+    /// (already maintained) reader node called `name`.
+    pub fn view_builder(&self, name: &str) -> Option<ViewBuilder> {
+        self.find_view(name).map(|r| {
             let domain = self.ingredients[r].domain();
             let columns = self.ingredients[r].fields().to_vec();
             let shards = (0..self.domains[&domain].shards())
                 .map(|i| self.read_addrs[&self.domains[&domain].assignment(i)].clone())
                 .collect();
+            let key_collation = self.ingredients[r]
+                .with_reader(|r| Collation::of(r.collations(), 0))
+                .unwrap();
 
             ViewBuilder {
                 local_ports: vec![],
                 node: r,
                 columns,
+                topk: self.recipe.topk_for(name),
+                key_collation,
                 shards,
             }
         })
@@ -820,6 +937,11 @@ impl ControllerInner {
             node.fields().len() - base_operator.get_dropped().len()
         );
         let schema = self.recipe.get_base_schema(base);
+        let json_columns = self.recipe.json_columns(base);
+        let key_collation = key
+            .first()
+            .map(|&c| Collation::of(node.collations(), c))
+            .unwrap_or(Collation::Binary);
 
         Some(TableBuilder {
             local_port: None,
@@ -831,6 +953,134 @@ impl ControllerInner {
             table_name: node.name().to_owned(),
             columns,
             schema,
+            json_columns,
+            key_collation,
+        })
+    }
+
+    /// Describe the schema of the table or view called `name`.
+    pub fn describe(&self, name: &str) -> Option<Description> {
+        if let Some(table) = self.describe_table(name) {
+            return Some(Description::Table(table));
+        }
+        self.describe_view(name).map(Description::View)
+    }
+
+    fn describe_table(&self, base: &str) -> Option<TableDescription> {
+        let ni = match self.recipe.node_addr_for(base) {
+            Ok(ni) => ni,
+            Err(_) => *self.inputs().get(base)?,
+        };
+        let node = &self.ingredients[ni];
+        let base_operator = node.get_base()?;
+
+        let schema = match self.recipe.get_base_schema(base) {
+            Some(schema) => schema,
+            None => {
+                // tables added without a recipe have no SQL schema; all we know is their columns
+                let columns = node
+                    .fields()
+                    .iter()
+                    .enumerate()
+                    .filter(|&(n, _)| !base_operator.get_dropped().contains_key(n))
+                    .map(|(_, name)| ColumnDescription {
+                        name: name.clone(),
+                        sql_type: None,
+                        nullable: true,
+                    })
+                    .collect();
+                return Some(TableDescription {
+                    columns,
+                    primary_key: vec![],
+                    unique_keys: vec![],
+                });
+            }
+        };
+
+        let mut primary_key = vec![];
+        let mut unique_keys = vec![];
+        for key in schema.keys.iter().flat_map(|keys| keys.iter()) {
+            match *key {
+                TableKey::PrimaryKey(ref cols) => {
+                    primary_key = cols.iter().map(|c| c.name.clone()).collect();
+                }
+                TableKey::UniqueKey(_, ref cols) => {
+                    unique_keys.push(cols.iter().map(|c| c.name.clone()).collect());
+                }
+                _ => {}
+            }
+        }
+        for field in &schema.fields {
+            let name = vec![field.column.name.clone()];
+            for constraint in &field.constraints {
+                match *constraint {
+                    ColumnConstraint::PrimaryKey if primary_key.is_empty() => {
+                        primary_key = name.clone();
+                    }
+                    ColumnConstraint::Unique if !unique_keys.contains(&name) => {
+                        unique_keys.push(name.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let columns = schema
+            .fields
+            .iter()
+            .map(|field| ColumnDescription {
+                name: field.column.name.clone(),
+                sql_type: Some(field.sql_type.clone()),
+                nullable: !primary_key.contains(&field.column.name)
+                    && !field.constraints.contains(&ColumnConstraint::NotNull),
+            })
+            .collect();
+
+        Some(TableDescription {
+            columns,
+            primary_key,
+            unique_keys,
+        })
+    }
+
+    fn describe_view(&self, name: &str) -> Option<ViewDescription> {
+        let r = self.find_view(name)?;
+        let reader = &self.ingredients[r];
+        let query = self
+            .view_indexes
+            .get(name)
+            .map_or(name, |index| index.view.as_str());
+        let types = self.recipe.column_types_for(query).unwrap_or_default();
+        let columns: Vec<_> = reader
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(i, name)| ColumnDescription {
+                name: name.clone(),
+                sql_type: types.get(i).cloned().unwrap_or(None),
+                nullable: true,
+            })
+            .collect();
+        let key_columns = reader
+            .with_reader(|r| r.key().map(|key| key.to_vec()))
+            .ok()
+            .and_then(|key| key)
+            .unwrap_or_else(Vec::new)
+            .into_iter()
+            .map(|c| columns[c].clone())
+            .collect();
+        let partial = match self.materializations.get_status(&r, reader) {
+            MaterializationStatus::Partial => true,
+            _ => false,
+        };
+        let shards = self.domains[&reader.domain()].shards();
+
+        Some(ViewDescription {
+            columns,
+            key_columns,
+            partial,
+            sharded: shards > 1,
+            shards,
         })
     }
 
@@ -839,7 +1089,7 @@ impl ControllerInner {
         let workers = &self.workers;
         let replies = &mut self.replies;
         // TODO: request stats from domains in parallel.
-        let domains = self
+        let domains: HashMap<_, (_, HashMap<NodeIndex, _>)> = self
             .domains
             .iter_mut()
             .flat_map(|(di, s)| {
@@ -858,7 +1108,269 @@ impl ControllerInner {
             })
             .collect();
 
-        GraphStats { domains: domains }
+        // views are only materialized in their readers, so a view's size is its reader's size
+        let views = self
+            .ingredients
+            .externals(petgraph::EdgeDirection::Outgoing)
+            .filter(|&n| self.ingredients[n].is_reader())
+            .map(|n| {
+                let name = self.ingredients[n].name().to_owned();
+                let mem_size = domains
+                    .values()
+                    .filter_map(|&(_, ref nodes)| nodes.get(&n))
+                    .map(|ns| ns.mem_size)
+                    .sum();
+                let budget = self.view_budgets.get(&name).map(|&b| b as u64);
+                (name, ViewStats { mem_size, budget })
+            })
+            .collect();
+
+        GraphStats { domains, views }
+    }
+
+    /// Set or clear the memory budget of a view.
+    pub fn set_view_budget<A: Authority + 'static>(
+        &mut self,
+        authority: &Arc<A>,
+        (view, max_bytes): (String, Option<usize>),
+    ) -> Result<(), String> {
+        if self.find_view(&view).is_none() {
+            return Err(format!("no view named {}", view));
+        }
+
+        let mut budgets = self.view_budgets.clone();
+        match max_bytes {
+            Some(max_bytes) => budgets.insert(view.clone(), max_bytes),
+            None => budgets.remove(&view),
+        };
+        if authority
+            .read_modify_write(STATE_KEY, |state: Option<ControllerState>| match state {
+                None => unreachable!(),
+                Some(ref state) if state.epoch > self.epoch => Err(()),
+                Some(mut state) => {
+                    state.view_budgets = budgets.clone();
+                    Ok(state)
+                }
+            })
+            .is_err()
+        {
+            return Err("Failed to persist view budget".to_owned());
+        }
+
+        self.view_budgets = budgets;
+        self.send_view_budget(&view);
+        Ok(())
+    }
+
+    /// Add a reader index called `name` to an existing view, keyed by the given columns of the
+    /// view. The index's reader sits on an identity node below the view's node, so the index
+    /// shares the dataflow that computes the view.
+    ///
+    /// The index is persisted, and added again when the controller recovers.
+    pub fn add_view_index<A: Authority + 'static>(
+        &mut self,
+        authority: &Arc<A>,
+        args: (String, String, Vec<String>),
+    ) -> Result<(), String> {
+        self.create_view_index(args)?;
+        if authority
+            .read_modify_write(STATE_KEY, |state: Option<ControllerState>| match state {
+                None => unreachable!(),
+                Some(ref state) if state.epoch > self.epoch => Err(()),
+                Some(mut state) => {
+                    state.view_indexes = self.view_index_specs();
+                    Ok(state)
+                }
+            })
+            .is_err()
+        {
+            return Err("Failed to persist view index".to_owned());
+        }
+        Ok(())
+    }
+
+    /// The view, name, and key columns of each reader index added to a view, ordered by name.
+    fn view_index_specs(&self) -> Vec<(String, String, Vec<String>)> {
+        let mut specs: Vec<_> = self
+            .view_indexes
+            .iter()
+            .map(|(name, index)| (index.view.clone(), name.clone(), index.key.clone()))
+            .collect();
+        specs.sort_by(|a, b| a.1.cmp(&b.1));
+        specs
+    }
+
+    /// Add the given reader indexes again after the views they belong to have been recovered.
+    fn restore_view_indexes(&mut self, specs: Vec<(String, String, Vec<String>)>) {
+        for spec in specs {
+            let name = spec.1.clone();
+            if let Err(e) = self.create_view_index(spec) {
+                warn!(self.log, "failed to restore view index {}: {}", name, e);
+            }
+        }
+    }
+
+    fn create_view_index(
+        &mut self,
+        (view, name, key): (String, String, Vec<String>),
+    ) -> Result<(), String> {
+        let r = self
+            .find_view(&view)
+            .ok_or_else(|| format!("no view named {}", view))?;
+        if self.find_view(&name).is_some() || self.inputs().contains_key(&name) {
+            return Err(format!("a table or view named {} already exists", name));
+        }
+        if key.is_empty() {
+            return Err(format!("index {} needs at least one key column", name));
+        }
+
+        let columns = self.ingredients[r].fields().to_vec();
+        let key_columns = key
+            .iter()
+            .map(|k| {
+                columns
+                    .iter()
+                    .position(|c| c == k)
+                    .ok_or_else(|| format!("view {} has no column {}", view, k))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let of = self.ingredients[r].with_reader(|r| r.is_for()).unwrap();
+
+        let node = self.migrate(|mig| {
+            let node = mig.add_ingredient(name.clone(), columns, Identity::new(of));
+            mig.maintain(name.clone(), node, &key_columns);
+            node
+        });
+        let index = ViewIndex {
+            view,
+            of,
+            node,
+            key,
+        };
+        self.view_indexes.insert(name, index);
+        Ok(())
+    }
+
+    /// Replay any of the given keys that are missing from a view, without waiting for the replays
+    /// to finish.
+    pub fn warm_view(
+        &mut self,
+        (view, keys): (String, Vec<Vec<DataType>>),
+    ) -> Result<WarmProgress, String> {
+        let r = self
+            .find_view(&view)
+            .ok_or_else(|| format!("no view named {}", view))?;
+        let key_len = self.ingredients[r]
+            .with_reader(|r| r.key().map(|k| k.len()))
+            .unwrap()
+            .ok_or_else(|| format!("view {} has no key", view))?;
+        if let Some(key) = keys.iter().find(|k| k.len() != key_len) {
+            return Err(format!(
+                "key {:?} does not match the key of view {}",
+                key, view
+            ));
+        }
+
+        let domain = self.ingredients[r].domain();
+        let node = self.ingredients[r].local_addr();
+        let shards = self.domains[&domain].shards();
+        let mut shard_keys = vec![Vec::new(); shards];
+        if shards == 1 {
+            shard_keys[0] = keys;
+        } else {
+            if key_len != 1 {
+                return Err(format!(
+                    "cannot warm sharded view {} with compound key",
+                    view
+                ));
+            }
+            let collation = self.ingredients[r]
+                .with_reader(|r| Collation::of(r.collations(), 0))
+                .unwrap();
+            for key in keys {
+                let shard = noria::shard_by(&collation.key(&key[0]), shards);
+                shard_keys[shard].push(key);
+            }
+        }
+
+        let mut progress = WarmProgress::default();
+        for (shard, keys) in shard_keys.into_iter().enumerate() {
+            if keys.is_empty() {
+                continue;
+            }
+
+            self.domains
+                .get_mut(&domain)
+                .unwrap()
+                .send_to_healthy_shard(
+                    shard,
+                    box payload::Packet::WarmReader { node, keys },
+                    &self.workers,
+                )
+                .map_err(|e| format!("failed to warm view {}: {:?}", view, e))?;
+            let p = self.replies.wait_for_warmed();
+            progress.present += p.present;
+            progress.pending += p.pending;
+        }
+        Ok(progress)
+    }
+
+    /// Get up to `count` of the keys in a view that its eviction policy would most like to keep.
+    pub fn view_hot_keys(
+        &mut self,
+        (view, count): (String, usize),
+    ) -> Result<Vec<Vec<DataType>>, String> {
+        let r = self
+            .find_view(&view)
+            .ok_or_else(|| format!("no view named {}", view))?;
+        let domain = self.ingredients[r].domain();
+        let node = self.ingredients[r].local_addr();
+        let shards = self.domains[&domain].shards();
+
+        // each shard ranks its own keys, so take an even share from each
+        let per_shard = (count + shards - 1) / shards;
+        let mut keys = Vec::with_capacity(count);
+        for shard in 0..shards {
+            self.domains
+                .get_mut(&domain)
+                .unwrap()
+                .send_to_healthy_shard(
+                    shard,
+                    box payload::Packet::HotReaderKeys {
+                        node,
+                        count: per_shard,
+                    },
+                    &self.workers,
+                )
+                .map_err(|e| format!("failed to get hot keys of view {}: {:?}", view, e))?;
+            keys.extend(self.replies.wait_for_keys());
+        }
+        keys.truncate(count);
+        Ok(keys)
+    }
+
+    /// Tell the shards of a view about its memory budget, which they enforce on their own. The
+    /// budget is split evenly among the shards.
+    fn send_view_budget(&mut self, view: &str) {
+        let r = match self.find_view(view) {
+            Some(r) => r,
+            None => return,
+        };
+        let domain = self.ingredients[r].domain();
+        let node = self.ingredients[r].local_addr();
+        let max_bytes = self
+            .view_budgets
+            .get(view)
+            .map(|&budget| budget / self.domains[&domain].shards());
+        let packet = box payload::Packet::SetMemoryBudget { node, max_bytes };
+        let dh = self.domains.get_mut(&domain).unwrap();
+        if let Err(e) = dh.send_to_healthy(packet, &self.workers) {
+            // recovery will set up the view, and its budget, again
+            warn!(
+                self.log,
+                "failed to send memory budget of view {}: {:?}", view, e
+            );
+        }
     }
 
     pub fn get_instances(&self) -> Vec<(WorkerIdentifier, bool, Duration)> {
@@ -983,47 +1495,16 @@ impl ControllerInner {
 
         match r {
             Ok(ref ra) => {
-                let (removed_bases, removed_other): (Vec<_>, Vec<_>) = ra
-                    .removed_leaves
-                    .iter()
-                    .cloned()
-                    .partition(|ni| self.ingredients[*ni].is_base());
-
-                // first remove query nodes in reverse topological order
-                let mut topo_removals = Vec::with_capacity(removed_other.len());
-                let mut topo = petgraph::visit::Topo::new(&self.ingredients);
-                while let Some(node) = topo.next(&self.ingredients) {
-                    if removed_other.contains(&node) {
-                        topo_removals.push(node);
-                    }
-                }
-                topo_removals.reverse();
-
-                for leaf in topo_removals {
-                    self.remove_leaf(leaf)?;
-                }
-
-                // now remove bases
-                for base in removed_bases {
-                    // TODO(malte): support removing bases that still have children?
-                    let children: Vec<NodeIndex> = self
-                        .ingredients
-                        .neighbors_directed(base, petgraph::EdgeDirection::Outgoing)
-                        .collect();
-                    // TODO(malte): what about domain crossings? can ingress/egress nodes be left
-                    // behind?
-                    assert_eq!(children.len(), 0);
-                    debug!(
-                        self.log,
-                        "Removing base \"{}\"",
-                        self.ingredients[base].name();
-                        "node" => base.index(),
-                    );
-                    // now drop the (orphaned) base
-                    self.remove_nodes(vec![base].as_slice()).unwrap();
-                }
-
+                let removed = self.remove_recipe_leaves(&ra.removed_leaves);
+                // the recipe has been activated, so it must be installed even if tearing down
+                // the nodes it no longer needs fails
                 self.recipe = new;
+                // views may have been added again, and their readers need to hear of their budgets
+                let budgeted: Vec<_> = self.view_budgets.keys().cloned().collect();
+                for view in budgeted {
+                    self.send_view_budget(&view);
+                }
+                removed?;
             }
             Err(ref e) => {
                 crit!(self.log, "failed to apply recipe: {}", e);
@@ -1036,6 +1517,69 @@ impl ControllerInner {
         r
     }
 
+    /// Remove the given leaves, which an activated recipe no longer needs, from the graph.
+    fn remove_recipe_leaves(&mut self, removed_leaves: &[NodeIndex]) -> Result<(), String> {
+        let (removed_bases, removed_other): (Vec<_>, Vec<_>) = removed_leaves
+            .iter()
+            .cloned()
+            .partition(|ni| self.ingredients[*ni].is_base());
+
+        // first remove query nodes in reverse topological order
+        let mut topo_removals = Vec::with_capacity(removed_other.len());
+        let mut topo = petgraph::visit::Topo::new(&self.ingredients);
+        while let Some(node) = topo.next(&self.ingredients) {
+            if removed_other.contains(&node) {
+                topo_removals.push(node);
+            }
+        }
+        topo_removals.reverse();
+
+        for leaf in topo_removals {
+            // indexes added to the leaf's view have to go first
+            let indexes: Vec<_> = self
+                .view_indexes
+                .iter()
+                .filter(|&(_, index)| index.of == leaf)
+                .map(|(name, _)| name.clone())
+                .collect();
+            for name in indexes {
+                let index = self.view_indexes.remove(&name).unwrap();
+                self.remove_leaf(index.node)?;
+            }
+            self.remove_leaf(leaf)?;
+        }
+
+        // now remove bases
+        for base in removed_bases {
+            // the recipe only drops tables once all dependent queries are gone, and removing those
+            // also removes any egress nodes below the base
+            if self
+                .ingredients
+                .neighbors_directed(base, petgraph::EdgeDirection::Outgoing)
+                .count()
+                > 0
+            {
+                return Err(format!(
+                    "cannot remove base \"{}\", as it still has children",
+                    self.ingredients[base].name()
+                ));
+            }
+            debug!(
+                self.log,
+                "Removing base \"{}\"",
+                self.ingredients[base].name();
+                "node" => base.index(),
+            );
+            // detach the base from the source, so it no longer shows up as an input
+            let edge = self.ingredients.find_edge(self.source, base).unwrap();
+            self.ingredients.remove_edge(edge);
+            // now drop the (orphaned) base
+            self.remove_nodes(vec![base].as_slice())?;
+        }
+
+        Ok(())
+    }
+
     pub fn extend_recipe<A: Authority + 'static>(
         &mut self,
         authority: &Arc<A>,
@@ -1046,6 +1590,7 @@ impl ControllerInner {
         match new.extend(&add_txt) {
             Ok(new) => {
                 let activation_result = self.apply_recipe(new);
+                let version = self.record_recipe_version(&activation_result, &add_txt, true);
                 if authority
                     .read_modify_write(STATE_KEY, |state: Option<ControllerState>| match state {
                         None => unreachable!(),
@@ -1053,6 +1598,9 @@ impl ControllerInner {
                         Some(mut state) => {
                             state.recipe_version = self.recipe.version();
                             state.recipes.push(add_txt.clone());
+                            state.view_indexes = self.view_index_specs();
+                            state.recipe_history.extend(version.clone());
+                            trim_recipe_history(&mut state.recipe_history);
                             Ok(state)
                         }
                     })
@@ -1067,7 +1615,7 @@ impl ControllerInner {
                 // need to restore the old recipe
                 crit!(self.log, "failed to extend recipe: {:?}", e);
                 self.recipe = old;
-                Err("failed to extend recipe".to_owned())
+                Err(format!("failed to extend recipe: {}", e))
             }
         }
     }
@@ -1080,8 +1628,16 @@ impl ControllerInner {
         match Recipe::from_str(&r_txt, Some(self.log.clone())) {
             Ok(r) => {
                 let old = mem::replace(&mut self.recipe, Recipe::blank(None));
-                let new = old.replace(r).unwrap();
+                let new = match old.replace(r) {
+                    Ok(new) => new,
+                    Err((old, e)) => {
+                        crit!(self.log, "failed to replace recipe: {:?}", e);
+                        self.recipe = old;
+                        return Err(format!("failed to replace recipe: {}", e));
+                    }
+                };
                 let activation_result = self.apply_recipe(new);
+                let version = self.record_recipe_version(&activation_result, &r_txt, false);
                 if authority
                     .read_modify_write(STATE_KEY, |state: Option<ControllerState>| match state {
                         None => unreachable!(),
@@ -1089,6 +1645,9 @@ impl ControllerInner {
                         Some(mut state) => {
                             state.recipe_version = self.recipe.version();
                             state.recipes = vec![r_txt.clone()];
+                            state.view_indexes = self.view_index_specs();
+                            state.recipe_history.extend(version.clone());
+                            trim_recipe_history(&mut state.recipe_history);
                             Ok(state)
                         }
                     })
@@ -1105,6 +1664,149 @@ impl ControllerInner {
         }
     }
 
+    /// Work out what installing the given recipe, or extending the current recipe with it if
+    /// `extend` is set, would change in the graph, without changing anything.
+    pub fn plan_recipe(&mut self, (r_txt, extend): (String, bool)) -> Result<RecipePlan, String> {
+        // the incorporator's MIR graph is shared between clones, so plan on a deep copy of it
+        let mut current = self.recipe.clone();
+        current.set_sql_inc(self.recipe.sql_inc().deep_clone());
+        let mut new = if extend {
+            current.extend(&r_txt).map_err(|(_, e)| e)?
+        } else {
+            current
+                .replace(Recipe::from_str(&r_txt, Some(self.log.clone()))?)
+                .map_err(|(_, e)| e)?
+        };
+
+        // activation adds nodes to the graph, so do it on a copy that we discard afterwards. the
+        // migration is never committed, so nothing reaches the domains.
+        let original = self.ingredients.clone();
+        let miglog = self.log.new(o!());
+        let (activation, mut added) = {
+            let mut mig = Migration {
+                mainline: &mut *self,
+                added: Default::default(),
+                columns: Default::default(),
+                readers: Default::default(),
+                context: Default::default(),
+                start: time::Instant::now(),
+                log: miglog,
+            };
+            let activation = new.activate(&mut mig);
+            let readers = mig.readers.values().cloned().collect::<Vec<_>>();
+            let mut added = mig.added;
+            added.extend(readers);
+            (activation, added)
+        };
+        let scratch = mem::replace(&mut self.ingredients, original);
+        let activation = activation.map_err(|e| format!("failed to activate recipe: {}", e))?;
+
+        added.sort();
+        let describe = |ni: NodeIndex| {
+            let n = &scratch[ni];
+            let description = if n.is_internal() {
+                n.description(true)
+            } else if n.is_base() {
+                "table".to_owned()
+            } else {
+                "reader".to_owned()
+            };
+            (n.name().to_owned(), description)
+        };
+        let mut reused: Vec<NodeIndex> = added
+            .iter()
+            .flat_map(|&ni| scratch.neighbors_directed(ni, petgraph::EdgeDirection::Incoming))
+            .filter(|&ni| ni != self.source && !added.contains(&ni))
+            .collect();
+        reused.sort();
+        reused.dedup();
+
+        // new nodes are populated by replays that originate, at worst, at the base tables that
+        // the reused nodes are derived from
+        let mut bases = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = reused.clone();
+        while let Some(ni) = stack.pop() {
+            if !visited.insert(ni) {
+                continue;
+            }
+            if self.ingredients[ni].is_base() {
+                bases.push(ni);
+            } else {
+                stack.extend(
+                    self.ingredients
+                        .neighbors_directed(ni, petgraph::EdgeDirection::Incoming),
+                );
+            }
+        }
+        let mut replay_rows = 0;
+        for base in bases {
+            let domain = self.ingredients[base].domain();
+            let node = self.ingredients[base].local_addr();
+            for shard in 0..self.domains[&domain].shards() {
+                self.domains
+                    .get_mut(&domain)
+                    .unwrap()
+                    .send_to_healthy_shard(
+                        shard,
+                        box payload::Packet::StateSizeProbe { node },
+                        &self.workers,
+                    )
+                    .map_err(|e| format!("failed to probe table {}: {:?}", base.index(), e))?;
+                replay_rows += self.replies.wait_for_row_count();
+            }
+        }
+
+        Ok(RecipePlan {
+            activation,
+            new_nodes: added.into_iter().map(&describe).collect(),
+            reused_nodes: reused.into_iter().map(&describe).collect(),
+            replay_rows,
+        })
+    }
+
+    /// Record the recipe that was just activated in the recipe history, and return the new
+    /// history entry. Nothing is recorded if activation failed.
+    fn record_recipe_version(
+        &mut self,
+        activation_result: &Result<ActivationResult, String>,
+        sql: &str,
+        extension: bool,
+    ) -> Option<RecipeVersion> {
+        if activation_result.is_err() {
+            return None;
+        }
+
+        let version = RecipeVersion {
+            version: self.recipe.version(),
+            sql: sql.to_owned(),
+            extension,
+            installed_at: SystemTime::now(),
+        };
+        self.recipe_history.push(version.clone());
+        trim_recipe_history(&mut self.recipe_history);
+        Some(version)
+    }
+
+    /// Install the recipe as it was in the given version again.
+    pub fn rollback_recipe<A: Authority + 'static>(
+        &mut self,
+        authority: &Arc<A>,
+        version: usize,
+    ) -> Result<ActivationResult, String> {
+        let end = self
+            .recipe_history
+            .iter()
+            .position(|v| v.version == version)
+            .ok_or_else(|| format!("no recipe version {}", version))?;
+        let r_txt = recipe_text(&self.recipe_history[..=end]);
+
+        info!(self.log, "rolling back recipe";
+              "from" => self.recipe.version(),
+              "to" => version);
+        self.install_recipe(authority, r_txt)
+    }
+
     pub fn graphviz(&self, detailed: bool) -> String {
         graphviz(&self.ingredients, detailed, &self.materializations)
     }
@@ -1174,6 +1876,7 @@ impl ControllerInner {
         );
 
         let mut nodes = vec![leaf];
+        let mut detached_egress = vec![];
         while let Some(node) = nodes.pop() {
             let mut parents = self
                 .ingredients
@@ -1193,12 +1896,31 @@ impl ControllerInner {
                         .count() == 0
                 {
                     nodes.push(parent);
+                } else if self.ingredients[parent].is_egress() {
+                    // the egress stays around for its other children, but must stop sending to
+                    // the ingress we are removing
+                    detached_egress.push((parent, node));
                 }
             }
 
             removals.push(node);
         }
 
+        for (egress, ingress) in detached_egress {
+            let domain = self.ingredients[egress].domain();
+            self.domains
+                .get_mut(&domain)
+                .unwrap()
+                .send_to_healthy(
+                    box payload::Packet::RemoveEgressTx {
+                        node: self.ingredients[egress].local_addr(),
+                        dst: ingress,
+                    },
+                    &self.workers,
+                )
+                .map_err(|e| format!("failed to detach egress {}: {:?}", egress.index(), e))?;
+        }
+
         self.remove_nodes(removals.as_slice())
     }
 
@@ -1312,3 +2034,69 @@ impl Drop for ControllerInner {
         }
     }
 }
+
+/// The full text of the recipe as of the last version in `history`.
+fn recipe_text(history: &[RecipeVersion]) -> String {
+    // the recipe consists of the last installed recipe and everything it was extended with. if no
+    // recipe was ever installed, the extensions are of the initial, blank recipe.
+    let start = history.iter().rposition(|v| !v.extension).unwrap_or(0);
+    history[start..]
+        .iter()
+        .map(|v| &v.sql[..])
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Drop the oldest versions from the recipe history once there are more than
+/// `RECIPE_HISTORY_LEN` of them.
+///
+/// The oldest version that is kept is turned into an installation of the full recipe as of that
+/// version, so that it can still be rolled back to without the versions before it.
+fn trim_recipe_history(history: &mut Vec<RecipeVersion>) {
+    if history.len() <= RECIPE_HISTORY_LEN {
+        return;
+    }
+
+    let first = history.len() - RECIPE_HISTORY_LEN;
+    if history[first].extension {
+        history[first].sql = recipe_text(&history[..=first]);
+        history[first].extension = false;
+    }
+    history.drain(..first);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: usize, sql: &str, extension: bool) -> RecipeVersion {
+        RecipeVersion {
+            version,
+            sql: sql.to_owned(),
+            extension,
+            installed_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn it_trims_recipe_history() {
+        let mut history = vec![version(0, "a", false)];
+        for v in 1..RECIPE_HISTORY_LEN + 2 {
+            history.push(version(v, &v.to_string(), true));
+        }
+        let rollback = recipe_text(&history);
+
+        trim_recipe_history(&mut history);
+        assert_eq!(history.len(), RECIPE_HISTORY_LEN);
+        assert_eq!(history[0].version, 2);
+        // the oldest version kept holds everything the versions before it installed
+        assert!(!history[0].extension);
+        assert_eq!(history[0].sql, "a\n1\n2");
+        assert_eq!(recipe_text(&history), rollback);
+
+        // a history that isn't too long is left alone
+        let before = history.clone();
+        trim_recipe_history(&mut history);
+        assert_eq!(history, before);
+    }
+}
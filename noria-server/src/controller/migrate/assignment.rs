@@ -34,6 +34,25 @@ pub fn assign(
         topo_list.push(node);
     }
 
+    // bases only depend on the source, so they can be assigned first. we order them such that a
+    // base referenced by a foreign key comes before the bases that reference it, so that those
+    // can join its domain (see below).
+    let (mut bases, rest): (Vec<_>, Vec<_>) =
+        topo_list.into_iter().partition(|&ni| graph[ni].is_base());
+    let mut topo_list = Vec::with_capacity(bases.len() + rest.len());
+    while !bases.is_empty() {
+        let ready = bases.iter().position(|&ni| {
+            graph[ni]
+                .get_base()
+                .unwrap()
+                .foreign_keys()
+                .all(|(parent, _)| parent == ni || !bases.contains(&parent))
+        });
+        // foreign keys may form a cycle, in which case any base will do
+        topo_list.push(bases.remove(ready.unwrap_or(0)));
+    }
+    topo_list.extend(rest);
+
     let mut next_domain = || {
         *ndomains += 1;
         *ndomains - 1
@@ -52,6 +71,20 @@ pub fn assign(
             }
 
             if n.is_base() {
+                // a base checks its foreign keys against the state of the bases they reference,
+                // which it can only do if it is in the same domain as them, and neither is sharded
+                if n.sharded_by().is_none() {
+                    let parent = n
+                        .get_base()
+                        .unwrap()
+                        .foreign_keys()
+                        .map(|(parent, _)| &graph[parent])
+                        .find(|p| p.has_domain() && p.sharded_by().is_none());
+                    if let Some(parent) = parent {
+                        return parent.domain().index();
+                    }
+                }
+
                 // bases are in a little bit of an awkward position becuase they can't just blindly
                 // join in domains of other bases in the face of sharding. consider the case of two
                 // bases, A and B, where A is sharded by A[0] and B by B[0]. Can they share a
@@ -30,6 +30,9 @@ pub struct Materializations {
 
     partial: HashSet<NodeIndex>,
     partial_enabled: bool,
+    spilled: HashSet<NodeIndex>,
+    spill_limit: Option<usize>,
+    persist_partial: bool,
 
     // TODO: this doesn't belong here
     pub domains_on_path: HashMap<Tag, Vec<DomainIndex>>,
@@ -48,6 +51,9 @@ impl Materializations {
 
             partial: HashSet::default(),
             partial_enabled: true,
+            spilled: HashSet::default(),
+            spill_limit: None,
+            persist_partial: false,
 
             domains_on_path: Default::default(),
 
@@ -64,6 +70,23 @@ impl Materializations {
     pub fn disable_partial(&mut self) {
         self.partial_enabled = false;
     }
+
+    /// Keep at most `memory_limit` bytes of the new full materializations that are chosen to be
+    /// spilled in memory, and spill the rest of their rows to disk.
+    ///
+    /// Only operators whose state no other operator looks into are spilled, since those lookups
+    /// happen while processing writes, and shouldn't have to wait for the disk.
+    pub fn spill_above(&mut self, memory_limit: usize) {
+        self.spill_limit = Some(memory_limit);
+    }
+
+    /// Keep the state of new partial materializations other than readers on disk.
+    ///
+    /// Only the keys that have been replayed are stored, and evicting a key deletes its rows from
+    /// disk again.
+    pub fn persist_partial(&mut self) {
+        self.persist_partial = true;
+    }
 }
 
 impl Materializations {
@@ -112,6 +135,9 @@ impl Materializations {
         // Holds all replay obligations. Keyed by the node whose *parent* should be materialized.
         let mut replay_obligations = HashMap::new();
 
+        // Nodes whose state other operators look into.
+        let mut looked_up = HashSet::new();
+
         // Find indices we need to add.
         for &ni in new {
             let n = &graph[ni];
@@ -137,12 +163,36 @@ impl Materializations {
                 indices.insert(ni, (vec![0], true));
             }
 
+            if let Some(b) = n.get_base() {
+                // bases look up their unique keys when checking new writes, and the columns that
+                // their foreign keys reference in other (unsharded) bases
+                for cols in b.unique_keys() {
+                    lookup_obligations
+                        .entry(ni)
+                        .or_insert_with(HashSet::new)
+                        .insert(cols.clone());
+                }
+                for (parent, cols) in b.foreign_keys() {
+                    if !graph[parent].sharded_by().is_none() {
+                        continue;
+                    }
+                    lookup_obligations
+                        .entry(parent)
+                        .or_insert_with(HashSet::new)
+                        .insert(cols.to_vec());
+                }
+            }
+
+            let by = ni;
             for (ni, (cols, lookup)) in indices {
                 trace!(self.log, "new indexing obligation";
                        "node" => ni.index(),
                        "columns" => ?cols,
                        "lookup" => lookup);
 
+                if lookup && ni != by {
+                    looked_up.insert(ni);
+                }
                 if lookup {
                     lookup_obligations
                         .entry(ni)
@@ -235,6 +285,9 @@ impl Materializations {
                 indices = map_indices(m, mi, &indices).unwrap();
                 m = &graph[mi];
             }
+            if mi != ni || looked_up.contains(&ni) {
+                looked_up.insert(mi);
+            }
 
             for columns in indices {
                 info!(self.log,
@@ -304,6 +357,11 @@ impl Materializations {
                 able = false;
             }
 
+            // readers without a key hold the whole result, so there is nothing to miss on
+            if graph[ni].is_reader() && indexes.iter().any(|index| index.is_empty()) {
+                able = false;
+            }
+
             // we are already fully materialized, so can't be made partial
             if !new.contains(&ni)
                 && self.added.get(&ni).map(|i| i.len()).unwrap_or(0)
@@ -387,6 +445,14 @@ impl Materializations {
                         m.insert(index);
                     }
                 }
+            } else if self.spill_limit.is_some()
+                && new.contains(&ni)
+                && graph[ni].is_internal()
+                && !looked_up.contains(&ni)
+            {
+                // only the operator itself (and replays) will read this state
+                self.spilled.insert(ni);
+                info!(self.log, "spilling full materialization"; "node" => ni.index());
             }
 
             // no matter what happens, we're going to have to fulfill our replay obligations.
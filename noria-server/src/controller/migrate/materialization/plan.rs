@@ -394,10 +394,22 @@ impl<'a> Plan<'a> {
                         .drain()
                         .map(|(k, paths)| (k, paths.into_iter().map(|(tag, _)| tag).collect()))
                         .collect();
-                    InitialState::PartialLocal(indices)
+                    if self.m.persist_partial {
+                        InitialState::PersistentPartialLocal(indices)
+                    } else {
+                        InitialState::PartialLocal(indices)
+                    }
                 } else {
                     let indices = self.tags.drain().map(|(k, _)| k).collect();
-                    InitialState::IndexedLocal(indices)
+                    match self.m.spill_limit {
+                        Some(memory_limit) if self.m.spilled.contains(&self.node) => {
+                            InitialState::SpillingLocal {
+                                index: indices,
+                                memory_limit,
+                            }
+                        }
+                        _ => InitialState::IndexedLocal(indices),
+                    }
                 }
             });
 
@@ -460,7 +472,7 @@ impl<'a> Plan<'a> {
 
             // we want to prefer source paths where we can translate all keys for the purposes of
             // partial -- but this only matters if we haven't already lost some keys.
-            if cols.iter().all(|c| c.is_some()) {
+            if !cols.is_empty() && cols.iter().all(|c| c.is_some()) {
                 let first = cols[0].unwrap();
 
                 let mut universal_src = Vec::new();
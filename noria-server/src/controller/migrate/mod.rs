@@ -24,7 +24,7 @@ use crate::controller::ControllerInner;
 use dataflow::prelude::*;
 use dataflow::{node, payload};
 use std::collections::{HashMap, HashSet};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use petgraph;
 use slog;
@@ -76,10 +76,9 @@ impl<'a> Migration<'a> {
         assert!(!parents.is_empty());
 
         // add to the graph
-        let ni =
-            self.mainline
-                .ingredients
-                .add_node(node::Node::new(name.to_string(), fields, i.into()));
+        let mut n = node::Node::new(name.to_string(), fields, i.into());
+        n.inherit_collations(&self.mainline.ingredients);
+        let ni = self.mainline.ingredients.add_node(n);
         info!(self.log,
               "adding new node";
               "node" => ni.index(),
@@ -200,6 +199,19 @@ impl<'a> Migration<'a> {
         self.columns.push((node, ColumnChange::Drop(column)));
     }
 
+    /// Rename a column of a base node.
+    ///
+    /// Records carry no column names, so only the name the controller hands out to new `Table`
+    /// handles changes; the domains need not be told.
+    pub fn rename_column<S: ToString>(&mut self, node: NodeIndex, column: usize, field: S) {
+        // new nodes should be created with the right column names in the first place
+        assert!(!self.added.iter().any(|&ni| ni == node));
+
+        let base = &mut self.mainline.ingredients[node];
+        assert!(base.is_base());
+        base.rename_column(column, &field.to_string());
+    }
+
     #[cfg(test)]
     pub fn graph(&self) -> &Graph {
         self.mainline.graph()
@@ -242,12 +254,32 @@ impl<'a> Migration<'a> {
         self.ensure_reader_for(n, Some(name));
 
         let ri = self.readers[&n];
+        let collations = Collation::of_columns(self.mainline.ingredients[n].collations(), key);
 
         self.mainline.ingredients[ri]
-            .with_reader_mut(|r| r.set_key(key))
+            .with_reader_mut(|r| {
+                r.set_key(key);
+                r.set_collations(collations);
+            })
             .unwrap();
     }
 
+    /// Expire keys in the reader for the given node once they have gone unread for `ttl`.
+    ///
+    /// Only readers added in this migration can be given a TTL, since existing readers have
+    /// already had their state set up. Returns `false` if `n` has no such reader.
+    pub fn set_reader_ttl(&mut self, n: NodeIndex, ttl: Duration) -> bool {
+        match self.readers.get(&n) {
+            Some(&ri) => {
+                self.mainline.ingredients[ri]
+                    .with_reader_mut(|r| r.set_ttl(ttl))
+                    .unwrap();
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Commit the changes introduced by this `Migration` to the master `Soup`.
     ///
     /// This will spin up an execution thread for each new thread domain, and hook those new
@@ -412,6 +444,14 @@ impl<'a> Migration<'a> {
                         .node_weight_mut(ni)
                         .unwrap()
                         .on_commit(&remap);
+                } else if mainline.ingredients[ni].is_base() {
+                    let n = mainline.ingredients.node_weight_mut(ni).unwrap();
+                    n.on_commit(&mainline.remap[domain]);
+                    for parent in n.get_base().unwrap().unenforced_foreign_keys() {
+                        warn!(log, "foreign key to base in another domain is not enforced";
+                              "node" => ni.index(),
+                              "parent" => parent.index());
+                    }
                 }
             }
         }
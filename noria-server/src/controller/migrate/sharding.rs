@@ -25,6 +25,14 @@ pub fn shard(
         topo_list.push(node);
     }
 
+    // a base checks its foreign keys against the state of the bases they reference, which it can
+    // only do if neither of them is sharded
+    let referenced: HashSet<NodeIndex> = topo_list
+        .iter()
+        .filter_map(|&ni| graph[ni].get_base())
+        .flat_map(|b| b.foreign_keys().map(|(parent, _)| parent))
+        .collect();
+
     // we must keep track of changes we make to the parent of a node, since this remapping must be
     // communicated to the nodes so they know the true identifier of their parent in the graph.
     let mut swaps = HashMap::new();
@@ -175,6 +183,18 @@ pub fn shard(
                 }
                 None => {
                     // base nodes -- what do we shard them by?
+                    let checkable = graph[node].get_base().unwrap().can_shard_by(want_sharding);
+                    if !checkable || referenced.contains(&node) {
+                        // each shard could only check its keys against its own rows
+                        warn!(log, "not sharding base node with keys that span shards";
+                              "node" => ?node);
+                        graph
+                            .node_weight_mut(node)
+                            .unwrap()
+                            .shard_by(Sharding::ForcedNone);
+                        continue;
+                    }
+
                     warn!(log, "sharding base node"; "node" => ?node, "column" => want_sharding);
                     graph
                         .node_weight_mut(node)
@@ -525,7 +545,8 @@ pub fn shard(
 
             // then wire us (n) above the parent instead
             warn!(log, "hoisting sharder above new unsharded node"; "sharder" => ?n, "node" => ?p);
-            let new = graph[grandp].mirror(node::special::Sharder::new(src_col));
+            let collation = Collation::of(graph[grandp].collations(), src_col);
+            let new = graph[grandp].mirror(node::special::Sharder::new(src_col, collation));
             *graph.node_weight_mut(n).unwrap() = new;
             let e = graph.find_edge(grandp, p).unwrap();
             let w = graph.remove_edge(e).unwrap();
@@ -606,7 +627,8 @@ fn reshard(
         }
         Sharding::ByColumn(c, _) => {
             use dataflow::node;
-            let mut n = graph[src].mirror(node::special::Sharder::new(c));
+            let collation = Collation::of(graph[src].collations(), c);
+            let mut n = graph[src].mirror(node::special::Sharder::new(c, collation));
             n.shard_by(graph[src].sharded_by());
             n
         }
@@ -3,14 +3,15 @@ use nom_sql::{
 };
 use std::collections::HashMap;
 
-use common::DataType;
+use common::{Collation, DataType};
 use crate::controller::Migration;
 use dataflow::ops::filter::FilterCondition;
 use dataflow::ops::join::{Join, JoinType};
 use dataflow::ops::latest::Latest;
+use dataflow::ops::multijoin::MultiJoin;
 use dataflow::ops::project::{Project, ProjectExpression, ProjectExpressionBase};
-use dataflow::{node, ops};
-use mir::node::{GroupedNodeType, MirNode, MirNodeType};
+use dataflow::{node, ops, JsonPath};
+use mir::node::{ForeignKey, GroupedNodeType, MirNode, MirNodeType};
 use mir::query::{MirQuery, QueryFlowParts};
 use mir::{Column, FlowNode, MirNodeRef};
 use petgraph::graph::NodeIndex;
@@ -95,15 +96,25 @@ pub fn mir_node_to_flow_parts(mir_node: &mut MirNode, mig: &mut Migration) -> Fl
                 MirNodeType::Base {
                     ref mut column_specs,
                     ref keys,
+                    ref unique_keys,
+                    ref foreign_keys,
                     ref adapted_over,
                 } => match *adapted_over {
-                    None => make_base_node(&name, column_specs.as_mut_slice(), keys, mig),
+                    None => make_base_node(
+                        &name,
+                        column_specs.as_mut_slice(),
+                        keys,
+                        unique_keys,
+                        foreign_keys,
+                        mig,
+                    ),
                     Some(ref bna) => adapt_base_node(
                         bna.over.clone(),
                         mig,
                         column_specs.as_mut_slice(),
                         &bna.columns_added,
                         &bna.columns_removed,
+                        &bna.columns_renamed,
                     ),
                 },
                 MirNodeType::Extremum {
@@ -170,10 +181,20 @@ pub fn mir_node_to_flow_parts(mir_node: &mut MirNode, mig: &mut Migration) -> Fl
                         mig,
                     )
                 }
-                MirNodeType::Latest { ref group_by } => {
+                MirNodeType::Latest {
+                    ref group_by,
+                    ref order,
+                } => {
                     assert_eq!(mir_node.ancestors.len(), 1);
                     let parent = mir_node.ancestors[0].clone();
-                    make_latest_node(&name, parent, mir_node.columns.as_slice(), group_by, mig)
+                    make_latest_node(
+                        &name,
+                        parent,
+                        mir_node.columns.as_slice(),
+                        group_by,
+                        order.as_ref(),
+                        mig,
+                    )
                 }
                 MirNodeType::Leaf { ref keys, .. } => {
                     assert_eq!(mir_node.ancestors.len(), 1);
@@ -208,10 +229,25 @@ pub fn mir_node_to_flow_parts(mir_node: &mut MirNode, mig: &mut Migration) -> Fl
                         mig,
                     )
                 }
+                MirNodeType::MultiJoin {
+                    ref on,
+                    ref project,
+                } => {
+                    assert_eq!(mir_node.ancestors.len(), on.len());
+                    make_multi_join_node(
+                        &name,
+                        mir_node.ancestors(),
+                        mir_node.columns.as_slice(),
+                        on,
+                        project,
+                        mig,
+                    )
+                }
                 MirNodeType::Project {
                     ref emit,
                     ref literals,
                     ref arithmetic,
+                    ref json,
                 } => {
                     assert_eq!(mir_node.ancestors.len(), 1);
                     let parent = mir_node.ancestors[0].clone();
@@ -221,6 +257,7 @@ pub fn mir_node_to_flow_parts(mir_node: &mut MirNode, mig: &mut Migration) -> Fl
                         mir_node.columns.as_slice(),
                         emit,
                         arithmetic,
+                        json,
                         literals,
                         mig,
                     )
@@ -312,6 +349,7 @@ pub(crate) fn adapt_base_node(
     column_specs: &mut [(ColumnSpecification, Option<usize>)],
     add: &Vec<ColumnSpecification>,
     remove: &Vec<ColumnSpecification>,
+    rename: &Vec<(ColumnSpecification, ColumnSpecification)>,
 ) -> FlowNode {
     let na = match over_node.borrow().flow_node {
         None => panic!("adapted base node must have a flow node already!"),
@@ -353,6 +391,19 @@ pub(crate) fn adapt_base_node(
             .expect("base column ID must be set to remove column");
         mig.drop_column(na, cid);
     }
+    for &(ref old, ref new) in rename.iter() {
+        // the renamed column keeps its base column ID, which the adapted node's column specs
+        // have already inherited from the node we are adapting
+        let cid = column_specs
+            .iter()
+            .find(|&&(ref cs, _)| cs == new)
+            .and_then(|&(_, cid)| cid)
+            .expect(&format!(
+                "base column ID must be set to rename column {:?}",
+                old
+            ));
+        mig.rename_column(na, cid, &new.column.name);
+    }
 
     FlowNode::Existing(na)
 }
@@ -365,6 +416,8 @@ pub(crate) fn make_base_node(
     name: &str,
     column_specs: &mut [(ColumnSpecification, Option<usize>)],
     pkey_columns: &Vec<Column>,
+    unique_keys: &[Vec<Column>],
+    foreign_keys: &[ForeignKey],
     mig: &mut Migration,
 ) -> FlowNode {
     // remember the absolute base column ID for potential later removal
@@ -379,7 +432,7 @@ pub(crate) fn make_base_node(
     let column_names = column_names(columns.as_slice());
 
     // note that this defaults to a "None" (= NULL) default value for columns that do not have one
-    // specified; we don't currently handle a "NOT NULL" SQL constraint for defaults
+    // specified; "NOT NULL" constraints are enforced separately by the base node
     let default_values = column_specs
         .iter()
         .map(|&(ref cs, _)| {
@@ -393,6 +446,13 @@ pub(crate) fn make_base_node(
         })
         .collect::<Vec<DataType>>();
 
+    let auto_increment = column_specs.iter().position(|&(ref cs, _)| {
+        cs.constraints.iter().any(|c| match *c {
+            ColumnConstraint::AutoIncrement => true,
+            _ => false,
+        })
+    });
+
     let base = if pkey_columns.len() > 0 {
         let pkey_column_ids = pkey_columns
             .iter()
@@ -408,6 +468,61 @@ pub(crate) fn make_base_node(
     } else {
         node::special::Base::new(default_values)
     };
+    let base = match auto_increment {
+        Some(col) => base.with_auto_increment(col),
+        None => base,
+    };
+    let not_null = column_specs
+        .iter()
+        .enumerate()
+        .filter(|&(_, &(ref cs, _))| {
+            cs.constraints.iter().any(|c| match *c {
+                ColumnConstraint::NotNull => true,
+                _ => false,
+            })
+        })
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let base = if not_null.is_empty() {
+        base
+    } else {
+        base.with_not_null(not_null)
+    };
+    let base = unique_keys.iter().fold(base, |base, key| {
+        let key_column_ids = key
+            .iter()
+            .map(|kc| {
+                column_specs
+                    .iter()
+                    .position(|&(ref cs, _)| Column::from(&cs.column) == *kc)
+                    .unwrap()
+            })
+            .collect();
+        base.with_unique(key_column_ids)
+    });
+    let base = foreign_keys.iter().fold(base, |base, fk| {
+        let column_ids = fk
+            .columns
+            .iter()
+            .map(|c| {
+                column_specs
+                    .iter()
+                    .position(|&(ref cs, _)| Column::from(&cs.column) == *c)
+                    .unwrap()
+            })
+            .collect();
+        base.with_foreign_key(column_ids, fk.parent, fk.parent_columns.clone())
+    });
+
+    let collations = column_specs
+        .iter()
+        .map(|&(ref cs, _)| Collation::of_column(cs))
+        .collect::<Vec<_>>();
+    let base = if collations.iter().all(|&c| c == Collation::Binary) {
+        base
+    } else {
+        base.with_collations(collations)
+    };
 
     FlowNode::New(mig.add_base(name, column_names.as_slice(), base))
 }
@@ -679,11 +794,70 @@ pub(crate) fn make_join_node(
     FlowNode::New(n)
 }
 
+pub(crate) fn make_multi_join_node(
+    name: &str,
+    ancestors: &[MirNodeRef],
+    columns: &[Column],
+    on: &Vec<Column>,
+    proj_cols: &Vec<Column>,
+    mig: &mut Migration,
+) -> FlowNode {
+    let column_names = column_names(columns);
+
+    let join_col_ids = ancestors
+        .iter()
+        .zip(on.iter())
+        .map(|(a, c)| {
+            a.borrow()
+                .columns
+                .iter()
+                .position(|ac| ac == c)
+                .expect(&format!(
+                    "missing join column {:#?} in {:#?}",
+                    c,
+                    a.borrow().columns
+                ))
+        })
+        .collect::<Vec<_>>();
+
+    // the join column is emitted once, from the first ancestor. every other projected column is
+    // emitted from the first ancestor that has it; since the projected join column carries the
+    // other ancestors' join columns as aliases, this also skips those.
+    let mut emitted: Vec<Column> = Vec::new();
+    let mut join_config = Vec::new();
+    for (i, a) in ancestors.iter().enumerate() {
+        for (j, c) in a.borrow().columns.iter().enumerate() {
+            if (i == 0 && j == join_col_ids[0]) || (proj_cols.contains(c) && !emitted.contains(c)) {
+                emitted.push(proj_cols.iter().find(|p| *p == c).unwrap_or(c).clone());
+                join_config.push((i, j));
+            }
+        }
+    }
+    assert_eq!(
+        join_config.len(),
+        proj_cols.len(),
+        "could not resolve output columns projected from join: {:?}",
+        proj_cols
+    );
+
+    let parents = ancestors
+        .iter()
+        .zip(join_col_ids.into_iter())
+        .map(|(a, c)| (a.borrow().flow_node_addr().unwrap(), c))
+        .collect();
+
+    let j = MultiJoin::new(parents, join_config);
+    let n = mig.add_ingredient(String::from(name), column_names.as_slice(), j);
+
+    FlowNode::New(n)
+}
+
 pub(crate) fn make_latest_node(
     name: &str,
     parent: MirNodeRef,
     columns: &[Column],
     group_by: &Vec<Column>,
+    order: Option<&Column>,
     mig: &mut Migration,
 ) -> FlowNode {
     let parent_na = parent.borrow().flow_node_addr().unwrap();
@@ -694,13 +868,11 @@ pub(crate) fn make_latest_node(
         .map(|c| parent.borrow().column_id_for_column(c))
         .collect::<Vec<_>>();
 
-    // latest doesn't support compound group by
-    assert_eq!(group_col_indx.len(), 1);
-    let na = mig.add_ingredient(
-        String::from(name),
-        column_names.as_slice(),
-        Latest::new(parent_na, group_col_indx[0]),
-    );
+    let mut latest = Latest::new(parent_na, group_col_indx);
+    if let Some(order) = order {
+        latest = latest.with_order(parent.borrow().column_id_for_column(order));
+    }
+    let na = mig.add_ingredient(String::from(name), column_names.as_slice(), latest);
     FlowNode::New(na)
 }
 
@@ -724,6 +896,7 @@ pub(crate) fn make_project_node(
     columns: &[Column],
     emit: &Vec<Column>,
     arithmetic: &Vec<(String, ArithmeticExpression)>,
+    json: &Vec<(String, Column, JsonPath, bool)>,
     literals: &Vec<(String, DataType)>,
     mig: &mut Migration,
 ) -> FlowNode {
@@ -746,6 +919,10 @@ pub(crate) fn make_project_node(
                 generate_projection_base(&parent, &e.right),
             )
         })
+        .chain(json.iter().map(|&(_, ref c, ref path, unquote)| {
+            let doc = ProjectExpressionBase::Column(parent.borrow().column_id_for_column(c));
+            ProjectExpression::json_extract(doc, path.clone(), unquote)
+        }))
         .collect();
 
     let n = mig.add_ingredient(
@@ -863,14 +1040,10 @@ pub(crate) fn materialize_leaf_node(
 
     // TODO(malte): consider the case when the projected columns need reordering
 
-    if !key_cols.is_empty() {
-        let key_cols: Vec<_> = key_cols
-            .iter()
-            .map(|c| parent.borrow().column_id_for_column(c))
-            .collect();
-        mig.maintain(name, na, &key_cols[..]);
-    } else {
-        // if no key specified, default to the first column
-        mig.maintain(name, na, &[0]);
-    }
+    // if no key specified, the reader holds the whole result and is read in full
+    let key_cols: Vec<_> = key_cols
+        .iter()
+        .map(|c| parent.borrow().column_id_for_column(c))
+        .collect();
+    mig.maintain(name, na, &key_cols[..]);
 }
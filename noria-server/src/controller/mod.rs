@@ -12,8 +12,8 @@ use crate::coordination::{CoordinationMessage, CoordinationPayload, DomainDescri
 use dataflow::{
     payload::{ControlReplyPacket, SourceChannelIdentifier},
     prelude::Executor,
-    Domain, DomainBuilder, DomainConfig, Packet, PersistenceParameters, PollEvent, ProcessResult,
-    Readers,
+    Domain, DomainBuilder, DomainConfig, EvictionPolicy, Packet, PersistenceParameters, PollEvent,
+    ProcessResult, Readers,
 };
 use failure::{self, ResultExt};
 use fnv::{FnvHashMap, FnvHashSet};
@@ -25,7 +25,7 @@ use hyper::{self, Method, StatusCode};
 use noria::channel::{self, DualTcpStream, TcpSender, CONNECTION_FROM_BASE};
 use noria::consensus::{Authority, Epoch, STATE_KEY};
 use noria::internal::{DomainIndex, LocalOrNot};
-use noria::{ControllerDescriptor, Input};
+use noria::{ControllerDescriptor, Input, InputAck, RecipeVersion};
 use rand;
 use serde_json;
 use slog;
@@ -105,6 +105,8 @@ where
 pub(crate) struct ControllerConfig {
     pub sharding: Option<usize>,
     pub partial_enabled: bool,
+    pub spill_limit: Option<usize>,
+    pub persist_partial: bool,
     pub domain_config: DomainConfig,
     pub persistence: PersistenceParameters,
     pub heartbeat_every: Duration,
@@ -121,9 +123,12 @@ impl Default for ControllerConfig {
             #[cfg(not(test))]
             sharding: None,
             partial_enabled: true,
+            spill_limit: None,
+            persist_partial: false,
             domain_config: DomainConfig {
                 concurrent_replays: 512,
                 replay_batch_timeout: time::Duration::new(0, 10_000),
+                eviction_policy: EvictionPolicy::Random,
             },
             persistence: Default::default(),
             heartbeat_every: Duration::from_secs(1),
@@ -145,6 +150,12 @@ pub(crate) struct ControllerState {
 
     pub recipe_version: usize,
     pub recipes: Vec<String>,
+    #[serde(default)]
+    pub recipe_history: Vec<RecipeVersion>,
+    #[serde(default)]
+    pub view_budgets: HashMap<String, usize>,
+    #[serde(default)]
+    pub view_indexes: Vec<(String, String, Vec<String>)>,
 }
 
 enum Event {
@@ -1007,6 +1018,9 @@ fn instance_campaign<A: Authority + 'static>(
                         epoch,
                         recipe_version: 0,
                         recipes: vec![],
+                        recipe_history: vec![],
+                        view_budgets: HashMap::new(),
+                        view_indexes: Vec::new(),
                     }),
                     Some(ref state) if state.epoch > epoch => Err(()),
                     Some(mut state) => {
@@ -1168,21 +1182,20 @@ impl Replica {
 
         // first, queue up any additional writes we have to do
         let mut err = Vec::new();
-        self.sendback.back.retain(|&streami, n| {
-            use std::ops::SubAssign;
-
+        self.sendback.back.retain(|&streami, acks| {
             let stream = &mut inputs[streami];
 
-            let end = *n;
-            for i in 0..end {
-                match stream.start_send(true) {
+            let mut first = true;
+            while let Some(ack) = acks.pop_front() {
+                match stream.start_send(ack) {
                     Ok(AsyncSink::Ready) => {
-                        if i == 0 {
+                        if first {
                             pending.insert(streami);
+                            first = false;
                         }
-                        n.sub_assign(1);
                     }
-                    Ok(AsyncSink::NotReady(_)) => {
+                    Ok(AsyncSink::NotReady(ack)) => {
+                        acks.push_front(ack);
                         break;
                     }
                     Err(e) => {
@@ -1192,7 +1205,7 @@ impl Replica {
                 }
             }
 
-            *n > 0
+            !acks.is_empty()
         });
 
         if !err.is_empty() {
@@ -1363,15 +1376,14 @@ impl Replica {
 
 #[derive(Default)]
 struct Sendback {
-    // map from inputi to number of (empty) ACKs
-    back: FnvHashMap<usize, usize>,
+    // map from inputi to the ACKs that are yet to be sent on it
+    back: FnvHashMap<usize, VecDeque<InputAck>>,
     pending: FnvHashSet<usize>,
 }
 
 impl Executor for Sendback {
-    fn send_back(&mut self, id: SourceChannelIdentifier, _: ()) {
-        use std::ops::AddAssign;
-        self.back.entry(id.token).or_insert(0).add_assign(1);
+    fn send_back(&mut self, id: SourceChannelIdentifier, ack: InputAck) {
+        self.back.entry(id.token).or_default().push_back(ack);
     }
 }
 
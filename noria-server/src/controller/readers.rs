@@ -5,11 +5,12 @@ use dataflow::Readers;
 use futures::future::{self, Either};
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::{mem, time};
+use std::{cmp, mem, time};
 use tokio;
 use tokio::prelude::*;
 
-use noria::{ReadQuery, ReadReply};
+use nom_sql::OrderType;
+use noria::{sort_by_order, ReadQuery, ReadReply, WarmProgress};
 
 /// If a blocking reader finds itself waiting this long for a backfill to complete, it will
 /// re-issue the replay request. To avoid the system falling over if replays are slow for a little
@@ -29,6 +30,24 @@ fn dup(rs: &[Vec<DataType>]) -> Vec<Vec<DataType>> {
         .collect()
 }
 
+/// Copies out at most `limit` of the rows, in the limit's order, or all of them if there is none.
+fn dup_limited(
+    rs: &[Vec<DataType>],
+    limit: &Option<(Vec<(usize, OrderType)>, usize)>,
+) -> Vec<Vec<DataType>> {
+    match *limit {
+        Some((ref order, k)) => {
+            let mut rs: Vec<_> = rs.iter().collect();
+            sort_by_order(order, &mut rs);
+            rs.truncate(k);
+            rs.into_iter()
+                .map(|r| r.iter().map(|v| v.deep_clone()).collect())
+                .collect()
+        }
+        None => dup(rs),
+    }
+}
+
 pub(crate) fn handle_message(
     m: ReadQuery,
     s: &mut Readers,
@@ -38,6 +57,7 @@ pub(crate) fn handle_message(
             target,
             mut keys,
             block,
+            limit,
         } => {
             let immediate = READERS.with(|readers_cache| {
                 let mut readers_cache = readers_cache.borrow_mut();
@@ -53,7 +73,9 @@ pub(crate) fn handle_message(
                 let found = keys
                     .iter_mut()
                     .map(|key| {
-                        let rs = reader.try_find_and(key, dup).map(|r| r.0);
+                        let rs = reader
+                            .try_find_and(key, |rs| dup_limited(rs, &limit))
+                            .map(|r| r.0);
                         (key, rs)
                     })
                     .enumerate();
@@ -106,6 +128,7 @@ pub(crate) fn handle_message(
                         Either::A(Either::B(BlockingRead {
                             target,
                             keys,
+                            limit,
                             read: ret,
                             truth: s.clone(),
                             retry: tokio::timer::Interval::new(now + retry, retry),
@@ -129,6 +152,58 @@ pub(crate) fn handle_message(
 
             Either::B(future::ok(ReadReply::Size(size)))
         }
+        ReadQuery::Warm { target, keys } => {
+            let progress = READERS.with(|readers_cache| {
+                let mut readers_cache = readers_cache.borrow_mut();
+                let reader = readers_cache.entry(target.clone()).or_insert_with(|| {
+                    let readers = s.lock().unwrap();
+                    readers.get(&target).unwrap().clone()
+                });
+
+                let mut progress = WarmProgress::default();
+                for key in &keys {
+                    match reader.try_find_and(key, |_| ()).map(|r| r.0) {
+                        Ok(Some(())) => progress.present += 1,
+                        Ok(None) => {
+                            reader.trigger(key);
+                            progress.pending += 1;
+                        }
+                        Err(()) => return Err(()),
+                    }
+                }
+                Ok(progress)
+            });
+
+            Either::B(future::ok(ReadReply::Warm(progress)))
+        }
+        ReadQuery::Page {
+            target,
+            offset,
+            limit,
+        } => {
+            let page = READERS.with(|readers_cache| {
+                let mut readers_cache = readers_cache.borrow_mut();
+                let reader = readers_cache.entry(target.clone()).or_insert_with(|| {
+                    let readers = s.lock().unwrap();
+                    readers.get(&target).unwrap().clone()
+                });
+
+                if !reader.is_keyless() {
+                    return Ok(None);
+                }
+
+                // keyless readers are never partial, so the lookup always finds the rows
+                reader
+                    .try_find_and(&[], |rs| {
+                        let start = cmp::min(offset, rs.len());
+                        let end = cmp::min(start.saturating_add(limit), rs.len());
+                        dup(&rs[start..end])
+                    })
+                    .map(|r| r.0)
+            });
+
+            Either::B(future::ok(ReadReply::Page(page)))
+        }
     }
 }
 
@@ -136,6 +211,7 @@ struct BlockingRead {
     read: Vec<Vec<Vec<DataType>>>,
     target: (NodeIndex, usize),
     keys: Vec<Vec<DataType>>,
+    limit: Option<(Vec<(usize, OrderType)>, usize)>,
     truth: Readers,
     retry: tokio::timer::Interval,
     trigger_timeout: time::Duration,
@@ -158,6 +234,7 @@ impl Future for BlockingRead {
             let mut triggered = false;
             let mut missing = false;
             let now = time::Instant::now();
+            let limit = &self.limit;
             for (i, key) in self.keys.iter_mut().enumerate() {
                 if key.is_empty() {
                     // already have this value
@@ -165,7 +242,10 @@ impl Future for BlockingRead {
                     // note that this *does* mean we'll trigger replay multiple times for things
                     // that miss and aren't replayed in time, which is a little sad. but at the
                     // same time, that replay trigger will just be ignored by the target domain.
-                    match reader.try_find_and(key, dup).map(|r| r.0) {
+                    match reader
+                        .try_find_and(key, |rs| dup_limited(rs, limit))
+                        .map(|r| r.0)
+                    {
                         Ok(Some(rs)) => {
                             self.read[i] = rs;
                             key.clear();
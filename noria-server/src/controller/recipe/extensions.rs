@@ -0,0 +1,484 @@
+//! The parts of the recipe syntax that nom-sql cannot parse.
+//!
+//! The prefix that names a recipe query may give a TTL for its reader and a maximum for a
+//! `LIMIT ?` that ends it, and statements may use `FOREIGN KEY` clauses, the `JSON` type and
+//! extractions from JSON documents. `take_extensions` takes all of these out of a query in a
+//! single pass over its tokens, so that quoted strings and identifiers are never mistaken for
+//! them, and leaves behind a query that nom-sql can parse.
+
+use super::is_ident;
+use crate::controller::sql::query_utils::json_extraction_name;
+use crate::controller::sql::ForeignKeyDefinition;
+use noria::JsonPath;
+use std::time::Duration;
+
+/// The parts of a recipe query that `take_extensions` took out of it.
+#[derive(Debug, Default)]
+pub(super) struct Extensions {
+    /// How long keys may go unread in the query's reader before they are evicted, from a
+    /// `TTL n s` or `TTL n ms` in the query's prefix.
+    pub(super) ttl: Option<Duration>,
+    /// The largest limit that a `LIMIT ?` ending the query may be given, from a `LIMIT n` in the
+    /// query's prefix.
+    pub(super) max_limit: Option<u64>,
+    /// Whether the query ends in a `LIMIT ?`, which is left as a `LIMIT 0`.
+    pub(super) limit_param: bool,
+    /// The `FOREIGN KEY` clauses of a `CREATE TABLE` statement, which are removed.
+    pub(super) foreign_keys: Vec<ForeignKeyDefinition>,
+    /// The columns that a `CREATE TABLE` statement declares with the `JSON` type, which is
+    /// replaced with `TEXT`.
+    pub(super) json_columns: Vec<String>,
+    /// The names of the extractions from JSON documents in a `SELECT` statement, which are
+    /// replaced with the placeholder columns named by `json_placeholder`.
+    pub(super) json_extractions: Vec<String>,
+}
+
+/// The name of the placeholder column that stands in for the `i`th JSON extraction of a query
+/// while it is parsed.
+pub(super) fn json_placeholder(i: usize) -> String {
+    format!("__json_extract_{}", i)
+}
+
+/// Takes the extensions out of a recipe query, and returns them.
+///
+/// `doc->>'$.a'` extracts and unquotes the part of `doc` at path `$.a`, while `doc->'$.a'` and
+/// `JSON_EXTRACT(doc, '$.a')` leave it quoted.
+pub(super) fn take_extensions(q: &mut String) -> Result<Extensions, String> {
+    let mut extensions = Extensions::default();
+    let mut edits = {
+        let tokens = Tokens::new(q);
+        let mut edits = Vec::new();
+        extensions
+            .take(&tokens, &mut edits)
+            .map_err(|e| format!("Query \"{}\", {}", q, e))?;
+        edits
+    };
+
+    // replace from the back, so that the positions of earlier edits stay valid
+    edits.sort_by_key(|&(start, _, _)| start);
+    for (start, end, replacement) in edits.into_iter().rev() {
+        q.replace_range(start..end, &replacement);
+    }
+    Ok(extensions)
+}
+
+/// The kinds of tokens that queries are split into.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    /// An identifier, keyword or number, possibly quoted with backticks.
+    Word,
+    /// A string quoted with `'` or `"`.
+    Literal,
+    /// `->`, or `->>` if the extracted part is unquoted.
+    Arrow { unquote: bool },
+    /// Any other character that is not whitespace.
+    Symbol(char),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Token {
+    kind: Kind,
+    start: usize,
+    end: usize,
+}
+
+/// A query split into tokens.
+struct Tokens<'a> {
+    q: &'a str,
+    tokens: Vec<Token>,
+}
+
+/// A replacement of the text between two positions of a query.
+type Edit = (usize, usize, String);
+
+impl<'a> Tokens<'a> {
+    /// Splits `q` into tokens. Within quotes, a quote is escaped either by doubling it or, except
+    /// in backticks, with a backslash. A quote that is never closed extends to the end of `q`.
+    fn new(q: &'a str) -> Self {
+        let bytes = q.as_bytes();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            let start = i;
+            let kind = match bytes[i] {
+                b if b.is_ascii_whitespace() => {
+                    i += 1;
+                    continue;
+                }
+                quote @ b'\'' | quote @ b'"' | quote @ b'`' => {
+                    i += 1;
+                    while i < bytes.len() {
+                        if bytes[i] == b'\\' && quote != b'`' {
+                            i += 2;
+                        } else if bytes[i] == quote && bytes.get(i + 1) == Some(&quote) {
+                            i += 2;
+                        } else if bytes[i] == quote {
+                            i += 1;
+                            break;
+                        } else {
+                            i += 1;
+                        }
+                    }
+                    i = i.min(bytes.len());
+                    if quote == b'`' {
+                        Kind::Word
+                    } else {
+                        Kind::Literal
+                    }
+                }
+                b if is_ident(b) => {
+                    while i < bytes.len() && is_ident(bytes[i]) {
+                        i += 1;
+                    }
+                    Kind::Word
+                }
+                b'-' if bytes[i..].starts_with(b"->") => {
+                    let unquote = bytes.get(i + 2) == Some(&b'>');
+                    i += if unquote { 3 } else { 2 };
+                    Kind::Arrow { unquote }
+                }
+                _ => {
+                    let c = q[i..].chars().next().unwrap();
+                    i += c.len_utf8();
+                    Kind::Symbol(c)
+                }
+            };
+            tokens.push(Token {
+                kind,
+                start,
+                end: i,
+            });
+        }
+        Tokens { q, tokens }
+    }
+
+    fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    fn kind(&self, i: usize) -> Option<Kind> {
+        self.tokens.get(i).map(|t| t.kind)
+    }
+
+    fn text(&self, i: usize) -> &'a str {
+        let t = self.tokens[i];
+        &self.q[t.start..t.end]
+    }
+
+    /// An edit that replaces the tokens from `first` to `last`, inclusive.
+    fn replace(&self, first: usize, last: usize, replacement: &str) -> Edit {
+        (
+            self.tokens[first].start,
+            self.tokens[last].end,
+            replacement.to_owned(),
+        )
+    }
+
+    fn is_symbol(&self, i: usize, c: char) -> bool {
+        self.kind(i) == Some(Kind::Symbol(c))
+    }
+
+    /// Whether the token at `i` is the given keyword, which may not be quoted.
+    fn is_keyword(&self, i: usize, kw: &str) -> bool {
+        self.kind(i) == Some(Kind::Word) && self.text(i).eq_ignore_ascii_case(kw)
+    }
+
+    /// The identifier at `i`, without any backticks.
+    fn ident(&self, i: usize) -> Option<&'a str> {
+        if self.kind(i) != Some(Kind::Word) {
+            return None;
+        }
+        Some(self.text(i).trim_matches('`')).filter(|s| !s.is_empty())
+    }
+
+    /// The string quoted at `i`, with any escaped quotes unescaped.
+    fn literal(&self, i: usize) -> Option<String> {
+        if self.kind(i) != Some(Kind::Literal) {
+            return None;
+        }
+        let s = self.text(i);
+        let quote = s.chars().next()?;
+        if s.len() < 2 || !s.ends_with(quote) {
+            return None;
+        }
+        let mut value = String::with_capacity(s.len() - 2);
+        let mut chars = s[1..s.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' || c == quote {
+                value.extend(chars.next());
+            } else {
+                value.push(c);
+            }
+        }
+        Some(value)
+    }
+
+    /// The JSON path quoted at `i`.
+    fn path(&self, i: usize) -> Option<JsonPath> {
+        self.literal(i)?.parse().ok()
+    }
+
+    /// The parenthesized list of identifiers that starts at `i`, and the token that follows it.
+    fn ident_list(&self, mut i: usize) -> Option<(Vec<String>, usize)> {
+        if !self.is_symbol(i, '(') {
+            return None;
+        }
+        let mut idents = Vec::new();
+        loop {
+            idents.push(self.ident(i + 1)?.to_owned());
+            i += 2;
+            if self.is_symbol(i, ')') {
+                return Some((idents, i + 1));
+            } else if !self.is_symbol(i, ',') {
+                return None;
+            }
+        }
+    }
+
+    /// The column, possibly qualified by its table, that ends at `last`, and the token at which
+    /// it starts.
+    fn column_ending_at(&self, last: usize) -> Option<(usize, Option<String>, String)> {
+        let name = self.ident(last)?.to_owned();
+        if last >= 2 && self.is_symbol(last - 1, '.') {
+            let table = self.ident(last - 2)?.to_owned();
+            Some((last - 2, Some(table), name))
+        } else {
+            Some((last, None, name))
+        }
+    }
+
+    /// The column, possibly qualified by its table, that starts at `first`, and the token that
+    /// follows it.
+    fn column_starting_at(&self, first: usize) -> Option<(Option<String>, String, usize)> {
+        let name = self.ident(first)?.to_owned();
+        if self.is_symbol(first + 1, '.') {
+            let column = self.ident(first + 2)?.to_owned();
+            Some((Some(name), column, first + 3))
+        } else {
+            Some((None, name, first + 1))
+        }
+    }
+}
+
+impl Extensions {
+    fn take(&mut self, t: &Tokens, edits: &mut Vec<Edit>) -> Result<(), String> {
+        let stmt = self.take_prefix(t, edits)?;
+        if t.is_keyword(stmt, "create") {
+            self.take_from_create(t, stmt, edits)
+        } else if t.is_keyword(stmt, "select") {
+            self.take_from_select(t, stmt, edits)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Takes the TTL and maximum limit out of the prefix that names a query, as in
+    /// `QUERY name TTL 100 ms LIMIT 10:`, and returns the token at which the statement starts.
+    fn take_prefix(&mut self, t: &Tokens, edits: &mut Vec<Edit>) -> Result<usize, String> {
+        let colon = match (0..t.len()).find(|&i| t.kind(i) != Some(Kind::Word)) {
+            Some(i) if t.is_symbol(i, ':') => i,
+            _ => return Ok(0),
+        };
+
+        // a name can only be told apart from the TTL and maximum limit by what follows it
+        let mut i = 0;
+        if t.is_keyword(i, "query") || t.is_keyword(i, "view") {
+            i += 1;
+        }
+        if i < colon && !((t.is_keyword(i, "ttl") || t.is_keyword(i, "limit")) && i + 1 < colon) {
+            i += 1;
+        }
+        let first = i;
+
+        if t.is_keyword(i, "ttl") {
+            // the unit may or may not be separated from the number, as in `1 s` and `100ms`
+            let invalid = || String::from("invalid TTL");
+            if i + 1 >= colon {
+                return Err(invalid());
+            }
+            let number = t.text(i + 1);
+            let digits = number.bytes().take_while(u8::is_ascii_digit).count();
+            let n: u64 = number[..digits].parse().map_err(|_| invalid())?;
+            let unit = if digits < number.len() {
+                i += 2;
+                &number[digits..]
+            } else if i + 2 < colon {
+                i += 3;
+                t.text(i - 1)
+            } else {
+                return Err(invalid());
+            };
+            self.ttl = Some(if unit.eq_ignore_ascii_case("s") {
+                Duration::from_secs(n)
+            } else if unit.eq_ignore_ascii_case("ms") {
+                Duration::from_millis(n)
+            } else {
+                return Err(invalid());
+            });
+        }
+
+        if t.is_keyword(i, "limit") {
+            if i + 1 >= colon {
+                return Err(String::from("invalid maximum limit"));
+            }
+            let n = t.text(i + 1).parse();
+            self.max_limit = Some(n.map_err(|_| String::from("invalid maximum limit"))?);
+            i += 2;
+        }
+
+        if i != colon {
+            return Err(String::from("invalid name"));
+        }
+        if i != first {
+            edits.push(t.replace(first, i - 1, ""));
+        }
+        Ok(colon + 1)
+    }
+
+    /// Takes the `FOREIGN KEY` clauses and `JSON` types out of a `CREATE TABLE` statement.
+    fn take_from_create(
+        &mut self,
+        t: &Tokens,
+        stmt: usize,
+        edits: &mut Vec<Edit>,
+    ) -> Result<(), String> {
+        let mut depth = 0;
+        let mut i = stmt;
+        while i < t.len() {
+            if t.is_symbol(i, '(') {
+                depth += 1;
+            } else if t.is_symbol(i, ')') {
+                depth -= 1;
+            } else if depth == 1 && t.is_symbol(i, ',') {
+                // the clause is removed along with the comma that separates it from the preceding
+                // definition, and may be named by a `CONSTRAINT`
+                let mut clause = i + 1;
+                if t.is_keyword(clause, "constraint") {
+                    clause += 1;
+                    if !t.is_keyword(clause, "foreign") {
+                        // the constraint's name
+                        clause += 1;
+                    }
+                }
+                if t.is_keyword(clause, "foreign") {
+                    let (fk, next) = foreign_key(t, clause)
+                        .ok_or_else(|| String::from("invalid foreign key"))?;
+                    edits.push(t.replace(i, next - 1, ""));
+                    self.foreign_keys.push(fk);
+                    i = next;
+                    continue;
+                }
+            } else if depth == 1
+                && t.is_keyword(i, "json")
+                && i >= 2
+                && (t.is_symbol(i - 2, '(') || t.is_symbol(i - 2, ','))
+            {
+                // the type follows the name at the start of a column definition
+                if let Some(name) = t.ident(i - 1) {
+                    edits.push(t.replace(i, i, "text"));
+                    self.json_columns.push(name.to_owned());
+                }
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+
+    /// Takes the extractions from JSON documents, and a `LIMIT ?` that ends the statement, out of
+    /// a `SELECT` statement.
+    fn take_from_select(
+        &mut self,
+        t: &Tokens,
+        stmt: usize,
+        edits: &mut Vec<Edit>,
+    ) -> Result<(), String> {
+        let invalid = || String::from("invalid JSON extraction");
+        let mut limit = None;
+        let mut i = stmt;
+        while i < t.len() {
+            if let Some(Kind::Arrow { unquote }) = t.kind(i) {
+                // `[table.]column->'path'`
+                let (first, table, column) = t.column_ending_at(i - 1).ok_or_else(invalid)?;
+                let path = t.path(i + 1).ok_or_else(invalid)?;
+                let placeholder = self.extract(table, &column, &path, unquote);
+                edits.push(t.replace(first, i + 1, &placeholder));
+                i += 2;
+            } else if t.is_keyword(i, "json_extract") {
+                // `JSON_EXTRACT([table.]column, 'path')`
+                if !t.is_symbol(i + 1, '(') {
+                    return Err(invalid());
+                }
+                let (table, column, comma) = t.column_starting_at(i + 2).ok_or_else(invalid)?;
+                let path = t.path(comma + 1).ok_or_else(invalid)?;
+                if !t.is_symbol(comma, ',') || !t.is_symbol(comma + 2, ')') {
+                    return Err(invalid());
+                }
+                let placeholder = self.extract(table, &column, &path, false);
+                edits.push(t.replace(i, comma + 2, &placeholder));
+                i = comma + 3;
+            } else {
+                if t.is_keyword(i, "limit") {
+                    limit = Some(i);
+                }
+                i += 1;
+            }
+        }
+
+        // only the statement's last LIMIT can end it
+        if let Some(limit) = limit {
+            let ends = |i: usize| i == t.len() || t.is_symbol(i, ';') || t.is_keyword(i, "offset");
+            if t.is_symbol(limit + 1, '?') && ends(limit + 2) {
+                edits.push(t.replace(limit + 1, limit + 1, "0"));
+                self.limit_param = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records an extraction from the JSON documents in `column`, and returns the placeholder
+    /// column that stands in for it.
+    fn extract(
+        &mut self,
+        table: Option<String>,
+        column: &str,
+        path: &JsonPath,
+        unquote: bool,
+    ) -> String {
+        let placeholder = json_placeholder(self.json_extractions.len());
+        let placeholder = match table {
+            Some(table) => format!("{}.{}", table, placeholder),
+            None => placeholder,
+        };
+        self.json_extractions
+            .push(json_extraction_name(column, path, unquote));
+        placeholder
+    }
+}
+
+/// The `FOREIGN KEY [name] (..) REFERENCES table (..)` clause that starts at `i`, and the token
+/// that follows it.
+fn foreign_key(t: &Tokens, mut i: usize) -> Option<(ForeignKeyDefinition, usize)> {
+    if !t.is_keyword(i + 1, "key") {
+        return None;
+    }
+    i += 2;
+    if t.kind(i) == Some(Kind::Word) {
+        i += 1;
+    }
+    let (columns, i) = t.ident_list(i)?;
+    if !t.is_keyword(i, "references") {
+        return None;
+    }
+    let parent = t.ident(i + 1)?.to_owned();
+    let (parent_columns, next) = t.ident_list(i + 2)?;
+    if columns.len() != parent_columns.len() {
+        return None;
+    }
+
+    let fk = ForeignKeyDefinition {
+        columns,
+        parent,
+        parent_columns,
+    };
+    Some((fk, next))
+}
@@ -1,6 +1,7 @@
 use crate::controller::security::SecurityConfig;
+use crate::controller::sql::query_utils::{referred_relations, refers_to_column};
 use crate::controller::sql::reuse::ReuseConfigType;
-use crate::controller::sql::SqlIncorporator;
+use crate::controller::sql::{ForeignKeyDefinition, SqlIncorporator};
 use crate::controller::Migration;
 use dataflow::ops::trigger::Trigger;
 use dataflow::ops::trigger::TriggerEvent;
@@ -11,14 +12,31 @@ use noria::ActivationResult;
 use petgraph::graph::NodeIndex;
 
 use nom::{self, is_alphanumeric, multispace};
-use nom_sql::CreateTableStatement;
+use nom_sql::{
+    ColumnSpecification, ConditionBase, ConditionExpression, CreateTableStatement,
+    FieldDefinitionExpression, OrderType, SqlType, TableKey,
+};
 use slog;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 use std::str;
+use std::time::Duration;
 use std::vec::Vec;
 
+use self::extensions::json_placeholder;
+
+mod extensions;
+
 type QueryID = u64;
 
+/// A change to the columns of an existing table, made by an `ALTER TABLE` statement.
+#[derive(Clone, Debug, PartialEq)]
+enum ColumnAlteration {
+    Add(ColumnSpecification),
+    Drop(String),
+    Rename(String, String),
+}
+
 /// Represents a Soup recipe.
 #[derive(Clone, Debug)]
 pub struct Recipe {
@@ -28,6 +46,20 @@ pub struct Recipe {
     expression_order: Vec<QueryID>,
     /// Named read/write expression aliases, mapping to queries in `expressions`.
     aliases: HashMap<String, QueryID>,
+    /// Time after which unread keys in the reader for a named query are evicted again.
+    ttls: HashMap<String, Duration>,
+    /// Tables dropped by `DROP TABLE` statements, and whether the queries that read from them are
+    /// dropped as well. Applied when the recipe extends or replaces another.
+    dropped_tables: Vec<(String, bool)>,
+    /// Column changes made by `ALTER TABLE` statements. Applied when the recipe extends or
+    /// replaces another, and kept until activation so that renamed columns can be told apart from
+    /// dropped and added ones.
+    altered_tables: Vec<(String, ColumnAlteration)>,
+    /// Foreign keys declared by the `CREATE TABLE` statements of the recipe, by table.
+    foreign_keys: HashMap<String, Vec<ForeignKeyDefinition>>,
+    /// Columns declared with the `JSON` type by the `CREATE TABLE` statements of the recipe, by
+    /// table. They are stored as `TEXT` columns that only hold valid JSON documents.
+    json_columns: HashMap<String, Vec<String>>,
     /// Security configuration
     security_config: Option<SecurityConfig>,
 
@@ -50,6 +82,11 @@ impl PartialEq for Recipe {
         self.expressions == other.expressions
             && self.expression_order == other.expression_order
             && self.aliases == other.aliases
+            && self.ttls == other.ttls
+            && self.dropped_tables == other.dropped_tables
+            && self.altered_tables == other.altered_tables
+            && self.foreign_keys == other.foreign_keys
+            && self.json_columns == other.json_columns
             && self.version == other.version
             && self.prior == other.prior
     }
@@ -69,6 +106,162 @@ fn is_ident(chr: u8) -> bool {
     is_alphanumeric(chr) || chr == '_' as u8
 }
 
+/// Gives the placeholder columns left by `take_extensions` the names of the extractions
+/// that they stand for. Extractions are only supported among the selected columns and in the
+/// `WHERE` clause.
+fn name_json_extractions(expr: &mut SqlQuery, names: &[String]) -> Result<(), String> {
+    fn name(c: &mut nom_sql::Column, names: &[String], named: &mut [bool]) {
+        if let Some(i) = (0..names.len()).find(|&i| c.name == json_placeholder(i)) {
+            c.name = names[i].clone();
+            named[i] = true;
+        }
+    }
+    fn name_in_condition(ce: &mut ConditionExpression, names: &[String], named: &mut [bool]) {
+        match *ce {
+            ConditionExpression::ComparisonOp(ref mut ct)
+            | ConditionExpression::LogicalOp(ref mut ct) => {
+                name_in_condition(&mut ct.left, names, named);
+                name_in_condition(&mut ct.right, names, named);
+            }
+            ConditionExpression::NegationOp(ref mut ce)
+            | ConditionExpression::Bracketed(ref mut ce) => name_in_condition(ce, names, named),
+            ConditionExpression::Base(ConditionBase::Field(ref mut c)) => name(c, names, named),
+            ConditionExpression::Base(_) => (),
+        }
+    }
+
+    let mut named = vec![false; names.len()];
+    if let SqlQuery::Select(ref mut st) = *expr {
+        for field in &mut st.fields {
+            if let FieldDefinitionExpression::Col(ref mut c) = *field {
+                name(c, names, &mut named);
+            }
+        }
+        if let Some(ref mut ce) = st.where_clause {
+            name_in_condition(ce, names, &mut named);
+        }
+    }
+
+    if named.iter().all(|&n| n) {
+        Ok(())
+    } else {
+        Err(String::from(
+            "JSON extraction is only supported among the selected columns and in WHERE clauses",
+        ))
+    }
+}
+
+named!(drop_table<&[u8], (String, bool)>,
+    do_parse!(
+        tag_no_case!("drop") >>
+        multispace >>
+        tag_no_case!("table") >>
+        multispace >>
+        name: map_res!(take_while1!(is_ident), str::from_utf8) >>
+        opt!(complete!(multispace)) >>
+        cascade: opt!(complete!(tag_no_case!("cascade"))) >>
+        opt!(complete!(multispace)) >>
+        tag!(";") >>
+        (name.to_owned(), cascade.is_some())
+    )
+);
+
+/// Returns the columns that make up a table key, for modification.
+fn key_columns(key: &mut TableKey) -> &mut Vec<nom_sql::Column> {
+    match *key {
+        TableKey::PrimaryKey(ref mut cs)
+        | TableKey::UniqueKey(_, ref mut cs)
+        | TableKey::FulltextKey(_, ref mut cs)
+        | TableKey::Key(_, ref mut cs) => cs,
+    }
+}
+
+/// Whether `column` is part of a key of the given table.
+fn is_key_column(ctq: &CreateTableStatement, column: &str) -> bool {
+    use nom_sql::ColumnConstraint;
+
+    let in_constraints = ctq.fields.iter().any(|f| {
+        f.column.name == column
+            && f.constraints.iter().any(|c| match *c {
+                ColumnConstraint::PrimaryKey | ColumnConstraint::Unique => true,
+                _ => false,
+            })
+    });
+    let in_keys = ctq
+        .keys
+        .iter()
+        .flat_map(|keys| keys.iter())
+        .any(|key| match *key {
+            TableKey::PrimaryKey(ref cs)
+            | TableKey::UniqueKey(_, ref cs)
+            | TableKey::FulltextKey(_, ref cs)
+            | TableKey::Key(_, ref cs) => cs.iter().any(|c| c.name == column),
+        });
+    in_constraints || in_keys
+}
+
+/// Parses a single column definition, as it would appear in a `CREATE TABLE` statement.
+fn column_definition(table: &str, definition: &[u8]) -> Result<ColumnSpecification, String> {
+    let definition = str::from_utf8(definition).map_err(|e| e.to_string())?;
+    match sql_parser::parse_query(&format!("CREATE TABLE {} ({});", table, definition)) {
+        Ok(SqlQuery::CreateTable(mut ctq)) => {
+            if ctq.fields.len() == 1 {
+                Ok(ctq.fields.remove(0))
+            } else {
+                Err(format!(
+                    "expected a single column definition: {}",
+                    definition
+                ))
+            }
+        }
+        _ => Err(format!("invalid column definition: {}", definition)),
+    }
+}
+
+named!(alter_table<&[u8], (String, ColumnAlteration)>,
+    do_parse!(
+        tag_no_case!("alter") >>
+        multispace >>
+        tag_no_case!("table") >>
+        multispace >>
+        name: map_res!(take_while1!(is_ident), str::from_utf8) >>
+        multispace >>
+        alteration: alt_complete!(
+            do_parse!(
+                tag_no_case!("add") >>
+                multispace >>
+                tag_no_case!("column") >>
+                multispace >>
+                spec: map_res!(take_until!(";"), |d| column_definition(name, d)) >>
+                (ColumnAlteration::Add(spec))
+            )
+            | do_parse!(
+                tag_no_case!("drop") >>
+                multispace >>
+                tag_no_case!("column") >>
+                multispace >>
+                column: map_res!(take_while1!(is_ident), str::from_utf8) >>
+                (ColumnAlteration::Drop(column.to_owned()))
+            )
+            | do_parse!(
+                tag_no_case!("rename") >>
+                multispace >>
+                tag_no_case!("column") >>
+                multispace >>
+                from: map_res!(take_while1!(is_ident), str::from_utf8) >>
+                multispace >>
+                tag_no_case!("to") >>
+                multispace >>
+                to: map_res!(take_while1!(is_ident), str::from_utf8) >>
+                (ColumnAlteration::Rename(from.to_owned(), to.to_owned()))
+            )
+        ) >>
+        opt!(complete!(multispace)) >>
+        tag!(";") >>
+        (name.to_owned(), alteration)
+    )
+);
+
 named!(query_expr<&[u8], (bool, Option<String>, SqlQuery)>,
     do_parse!(
         prefix: opt!(do_parse!(
@@ -110,6 +303,11 @@ impl Recipe {
             expressions: HashMap::default(),
             expression_order: Vec::default(),
             aliases: HashMap::default(),
+            ttls: HashMap::default(),
+            dropped_tables: Vec::default(),
+            altered_tables: Vec::default(),
+            foreign_keys: HashMap::default(),
+            json_columns: HashMap::default(),
             version: 0,
             prior: None,
             inc: match log {
@@ -153,6 +351,11 @@ impl Recipe {
         self.inc.as_ref().unwrap().get_base_schema(name)
     }
 
+    /// The columns of a base table that were declared with the `JSON` type.
+    pub fn json_columns(&self, name: &str) -> Vec<String> {
+        self.json_columns.get(name).cloned().unwrap_or_default()
+    }
+
     /// Obtains the `NodeIndex` for the node corresponding to a named query or a write type.
     pub fn node_addr_for(&self, name: &str) -> Result<NodeIndex, String> {
         match self.inc {
@@ -178,6 +381,32 @@ impl Recipe {
         }
     }
 
+    /// Resolves `name`, which might be an alias for another identical query, to the name that the
+    /// SQL incorporator knows the query by.
+    fn internal_query_name<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        match self.aliases.get(name) {
+            None => Some(name),
+            Some(ref qid) => {
+                let (ref internal_qn, _, _) = self.expressions[qid];
+                internal_qn.as_ref().map(String::as_str)
+            }
+        }
+    }
+
+    /// Obtains the SQL types of the output columns of a named query.
+    pub fn column_types_for(&self, name: &str) -> Option<Vec<Option<SqlType>>> {
+        self.inc
+            .as_ref()?
+            .get_query_column_types(self.internal_query_name(name)?)
+    }
+
+    /// Obtains the ordering columns and limit of a named query, if it has a limit.
+    pub fn topk_for(&self, name: &str) -> Option<(Vec<(usize, OrderType)>, usize)> {
+        self.inc
+            .as_ref()?
+            .get_query_topk(self.internal_query_name(name)?)
+    }
+
     /// Set recipe's security configuration
     pub fn set_security_config(&mut self, config_text: &str, url: String) {
         let mut config = SecurityConfig::parse(config_text);
@@ -199,9 +428,16 @@ impl Recipe {
         let cleaned_recipe_text = lines.join("\n");
 
         // parse and compute differences to current recipe
-        let parsed_queries = Recipe::parse(&cleaned_recipe_text)?;
-
-        Ok(Recipe::from_queries(parsed_queries, log))
+        let (parsed_queries, ttls, dropped_tables, altered_tables, foreign_keys, json_columns) =
+            Recipe::parse(&cleaned_recipe_text)?;
+
+        let mut recipe = Recipe::from_queries(parsed_queries, log);
+        recipe.ttls = ttls;
+        recipe.dropped_tables = dropped_tables;
+        recipe.altered_tables = altered_tables;
+        recipe.foreign_keys = foreign_keys;
+        recipe.json_columns = json_columns;
+        Ok(recipe)
     }
 
     /// Creates a recipe from a set of pre-parsed `SqlQuery` structures.
@@ -249,6 +485,11 @@ impl Recipe {
             expressions: expressions,
             expression_order: expression_order,
             aliases: aliases,
+            ttls: HashMap::default(),
+            dropped_tables: Vec::default(),
+            altered_tables: Vec::default(),
+            foreign_keys: HashMap::default(),
+            json_columns: HashMap::default(),
             security_config: None,
             version: 0,
             prior: None,
@@ -341,6 +582,26 @@ impl Recipe {
             }
         };
 
+        // a table whose `CREATE TABLE` statement both went away and came back has been altered, and
+        // its existing base is adapted rather than replaced
+        let altered_tables: HashSet<String> = {
+            let table_names = |r: &Recipe, qids: &[QueryID]| -> HashSet<String> {
+                qids.iter()
+                    .filter_map(|qid| match r.expressions[qid].1 {
+                        SqlQuery::CreateTable(ref ctq) => Some(ctq.table.name.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            };
+            match self.prior {
+                Some(ref pr) => table_names(pr, &removed)
+                    .intersection(&table_names(self, &added))
+                    .cloned()
+                    .collect(),
+                None => HashSet::new(),
+            }
+        };
+
         let mut result = ActivationResult {
             new_nodes: HashMap::default(),
             removed_leaves: Vec::default(),
@@ -399,12 +660,37 @@ impl Recipe {
         for qid in added {
             let (n, q, is_leaf) = self.expressions[&qid].clone();
 
-            // add the query
-            let qfp = self
-                .inc
-                .as_mut()
-                .unwrap()
-                .add_parsed_query(q, n.clone(), is_leaf, mig)?;
+            // add the query, or adapt the existing base if this is an altered table
+            let altered = match q {
+                SqlQuery::CreateTable(ref ctq) if altered_tables.contains(&ctq.table.name) => {
+                    Some(ctq.clone())
+                }
+                _ => None,
+            };
+            let qfp = match altered {
+                Some(ctq) => {
+                    let renames = self.column_renames(&ctq.table.name);
+                    self.inc.as_mut().unwrap().alter_base(ctq, &renames, mig)?
+                }
+                None => {
+                    let inc = self.inc.as_mut().unwrap();
+                    if let SqlQuery::CreateTable(ref ctq) = q {
+                        if let Some(fks) = self.foreign_keys.get(&ctq.table.name) {
+                            inc.add_foreign_keys(ctq, fks.clone())?;
+                        }
+                    }
+                    inc.add_parsed_query(q, n.clone(), is_leaf, mig)?
+                }
+            };
+
+            if let Some(&ttl) = n.as_ref().and_then(|n| self.ttls.get(n)) {
+                if !is_leaf || !mig.set_reader_ttl(qfp.query_leaf, ttl) {
+                    return Err(format!(
+                        "can't set a TTL for query {}, which has no new reader",
+                        n.as_ref().unwrap()
+                    ));
+                }
+            }
 
             // If the user provided us with a query name, use that.
             // If not, use the name internally used by the QFP.
@@ -416,37 +702,32 @@ impl Recipe {
             result.new_nodes.insert(query_name, qfp.query_leaf);
         }
 
-        result.removed_leaves = removed
-            .iter()
-            .filter_map(|qid| {
-                let (ref n, ref q, _) = self.prior.as_ref().unwrap().expressions[qid];
-                match q {
-                    SqlQuery::CreateTable(ref ctq) => {
-                        // a base may have many dependent queries, including ones that also lost
-                        // nodes; the code handling `removed_leaves` therefore needs to take care
-                        // not to remove bases while they still have children, or to try removing
-                        // them twice.
-                        self.inc.as_mut().unwrap().remove_base(&ctq.table.name);
-                        match self.prior.as_ref().unwrap().node_addr_for(&ctq.table.name) {
-                            Ok(ni) => Some(ni),
-                            Err(e) => {
-                                crit!(
-                                    self.log,
-                                    "failed to remove base {} whose  address could not be resolved",
-                                    ctq.table.name
-                                );
-                                unimplemented!()
-                            }
-                        }
-                    }
-                    _ => self
-                        .inc
-                        .as_mut()
-                        .unwrap()
-                        .remove_query(n.as_ref().unwrap(), mig),
+        for qid in removed {
+            let (ref n, ref q, _) = self.prior.as_ref().unwrap().expressions[&qid];
+            let inc = self.inc.as_mut().unwrap();
+            match q {
+                SqlQuery::CreateTable(ref ctq) if altered_tables.contains(&ctq.table.name) => {
+                    // the base was adapted to its new schema above
                 }
-            })
-            .collect();
+                SqlQuery::CreateTable(ref ctq) => {
+                    // a base may have many dependent queries, including ones that also lost
+                    // nodes; the code handling `removed_leaves` therefore needs to take care
+                    // not to remove bases while they still have children, or to try removing
+                    // them twice.
+                    let ni = inc.get_query_address(&ctq.table.name).ok_or_else(|| {
+                        format!(
+                            "failed to remove base {} whose address could not be resolved",
+                            ctq.table.name
+                        )
+                    })?;
+                    inc.remove_base(&ctq.table.name);
+                    result.removed_leaves.push(ni);
+                }
+                _ => result
+                    .removed_leaves
+                    .extend(inc.remove_query(n.as_ref().unwrap(), mig)),
+            }
+        }
 
         Ok(result)
     }
@@ -472,6 +753,180 @@ impl Recipe {
         (added_queries, removed_queries)
     }
 
+    /// Rewrite the `CREATE TABLE` statements in the recipe to reflect the column changes made by
+    /// its `ALTER TABLE` statements.
+    fn alter_tables(&mut self) -> Result<(), String> {
+        for &(ref table, ref alteration) in &self.altered_tables {
+            let qid = self
+                .expression_order
+                .iter()
+                .cloned()
+                .find(|qid| match self.expressions[qid].1 {
+                    SqlQuery::CreateTable(ref ctq) => ctq.table.name == *table,
+                    _ => false,
+                })
+                .ok_or_else(|| format!("cannot alter unknown table {}", table))?;
+            let (n, q, is_leaf) = self.expressions.remove(&qid).unwrap();
+            let mut ctq = match q {
+                SqlQuery::CreateTable(ctq) => ctq,
+                _ => unreachable!(),
+            };
+
+            let position = |ctq: &CreateTableStatement, column: &str| {
+                ctq.fields.iter().position(|f| f.column.name == column)
+            };
+            match *alteration {
+                ColumnAlteration::Add(ref spec) => {
+                    if position(&ctq, &spec.column.name).is_some() {
+                        return Err(format!(
+                            "table {} already has a column {}",
+                            table, spec.column.name
+                        ));
+                    }
+                    ctq.fields.push(spec.clone());
+                }
+                ColumnAlteration::Drop(ref column) => {
+                    let pos = position(&ctq, column)
+                        .ok_or_else(|| format!("table {} has no column {}", table, column))?;
+                    if ctq.fields.len() == 1 {
+                        return Err(format!(
+                            "cannot drop {}, the only column of table {}",
+                            column, table
+                        ));
+                    }
+                    if is_key_column(&ctq, column) {
+                        return Err(format!(
+                            "cannot drop {}, a key column of table {}",
+                            column, table
+                        ));
+                    }
+                    let users = self
+                        .expressions
+                        .values()
+                        .filter(|&&(_, ref q, _)| refers_to_column(q, table, column))
+                        .count();
+                    if users > 0 {
+                        return Err(format!(
+                            "cannot drop {} from table {}, which {} queries use",
+                            column, table, users
+                        ));
+                    }
+                    ctq.fields.remove(pos);
+                }
+                ColumnAlteration::Rename(ref from, ref to) => {
+                    let pos = position(&ctq, from)
+                        .ok_or_else(|| format!("table {} has no column {}", table, from))?;
+                    if position(&ctq, to).is_some() {
+                        return Err(format!("table {} already has a column {}", table, to));
+                    }
+                    ctq.fields[pos].column.name = to.clone();
+                    for key in ctq.keys.iter_mut().flat_map(|keys| keys.iter_mut()) {
+                        for c in key_columns(key) {
+                            if c.name == *from {
+                                c.name = to.clone();
+                            }
+                        }
+                    }
+                }
+            }
+
+            // the altered table takes the place of the original one
+            let q = SqlQuery::CreateTable(ctq);
+            let new_qid = hash_query(&q);
+            self.expressions.insert(new_qid, (n, q, is_leaf));
+            for e in self.expression_order.iter_mut().filter(|e| **e == qid) {
+                *e = new_qid;
+            }
+            for a in self.aliases.values_mut().filter(|a| **a == qid) {
+                *a = new_qid;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the columns of `table` renamed by `ALTER TABLE` statements in this recipe, as pairs
+    /// of old and new name.
+    fn column_renames(&self, table: &str) -> Vec<(String, String)> {
+        self.altered_tables
+            .iter()
+            .filter(|&&(ref t, _)| t == table)
+            .filter_map(|&(_, ref alteration)| match *alteration {
+                ColumnAlteration::Rename(ref from, ref to) => Some((from.clone(), to.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Remove the given tables from the recipe. If the flag next to a table is set, all queries
+    /// that read from it, directly or through other queries, are removed along with it;
+    /// otherwise, dropping a table that queries still read from is an error.
+    fn drop_tables(&mut self, tables: &[(String, bool)]) -> Result<(), String> {
+        for &(ref table, cascade) in tables {
+            let base = self
+                .expression_order
+                .iter()
+                .cloned()
+                .find(|qid| match self.expressions[qid].1 {
+                    SqlQuery::CreateTable(ref ctq) => ctq.table.name == *table,
+                    _ => false,
+                })
+                .ok_or_else(|| format!("cannot drop unknown table {}", table))?;
+
+            // queries can only read from tables and queries added before them, so a single pass
+            // in addition order finds everything that transitively reads from the table
+            let mut dropped_relations = vec![table.clone()];
+            let mut dependents = Vec::new();
+            for qid in &self.expression_order {
+                let (_, ref q, _) = self.expressions[qid];
+                if let SqlQuery::CreateTable(_) = *q {
+                    continue;
+                }
+                if referred_relations(q)
+                    .iter()
+                    .any(|r| dropped_relations.contains(r))
+                {
+                    let names: Vec<_> = self
+                        .aliases
+                        .iter()
+                        .filter(|&(_, alias_qid)| alias_qid == qid)
+                        .map(|(name, _)| name.clone())
+                        .collect();
+                    if names.is_empty() {
+                        return Err(format!(
+                            "cannot drop table {}, which an unnamed query reads from",
+                            table
+                        ));
+                    }
+                    dropped_relations.extend(names);
+                    if let SqlQuery::CreateView(ref cvq) = *q {
+                        dropped_relations.push(cvq.name.clone());
+                    }
+                    dependents.push(*qid);
+                }
+            }
+
+            if !dependents.is_empty() && !cascade {
+                return Err(format!(
+                    "cannot drop table {}, which {} queries read from; use DROP TABLE {} CASCADE \
+                     to drop them as well",
+                    table,
+                    dependents.len(),
+                    table
+                ));
+            }
+
+            info!(self.log, "dropping table {}", table; "dependents" => dependents.len());
+            dependents.push(base);
+            self.expressions.retain(|qid, _| !dependents.contains(qid));
+            self.expression_order
+                .retain(|qid| !dependents.contains(qid));
+            self.aliases.retain(|_, qid| !dependents.contains(qid));
+            let aliases = &self.aliases;
+            self.ttls.retain(|name, _| aliases.contains_key(name));
+        }
+        Ok(())
+    }
+
     /// Returns the query expressions in the recipe.
     pub fn expressions(&self) -> Vec<(Option<&String>, &SqlQuery)> {
         self.expressions
@@ -500,6 +955,11 @@ impl Recipe {
             expressions: self.expressions.clone(),
             expression_order: self.expression_order.clone(),
             aliases: self.aliases.clone(),
+            ttls: self.ttls.clone(),
+            dropped_tables: Vec::default(),
+            altered_tables: Vec::default(),
+            foreign_keys: self.foreign_keys.clone(),
+            json_columns: self.json_columns.clone(),
             version: self.version + 1,
             inc: prior_inc,
             log: self.log.clone(),
@@ -516,6 +976,20 @@ impl Recipe {
         }
 
         new.aliases.extend(add_rp.aliases);
+        new.ttls.extend(add_rp.ttls);
+        new.foreign_keys.extend(add_rp.foreign_keys);
+        new.json_columns.extend(add_rp.json_columns);
+
+        new.altered_tables = add_rp.altered_tables;
+        let changed = new
+            .alter_tables()
+            .and_then(|_| new.drop_tables(&add_rp.dropped_tables));
+        if let Err(e) = changed {
+            // hand back the recipe we were extending, unchanged
+            let mut old = *new.prior.take().unwrap();
+            old.inc = new.inc.take();
+            return Err((old, e));
+        }
 
         // return new recipe as replacement for self
         Ok(new)
@@ -537,7 +1011,19 @@ impl Recipe {
         self.inc = Some(new_inc);
     }
 
-    fn parse(recipe_text: &str) -> Result<Vec<(Option<String>, SqlQuery, bool)>, String> {
+    fn parse(
+        recipe_text: &str,
+    ) -> Result<
+        (
+            Vec<(Option<String>, SqlQuery, bool)>,
+            HashMap<String, Duration>,
+            Vec<(String, bool)>,
+            Vec<(String, ColumnAlteration)>,
+            HashMap<String, Vec<ForeignKeyDefinition>>,
+            HashMap<String, Vec<String>>,
+        ),
+        String,
+    > {
         let lines: Vec<&str> = recipe_text
             .lines()
             .filter(|l| !l.is_empty() && !l.starts_with("#"))
@@ -563,6 +1049,33 @@ impl Recipe {
             }
         }
 
+        // DROP TABLE statements change the recipe rather than adding to it
+        let mut dropped_tables = Vec::new();
+        query_strings.retain(|q| match drop_table(q.as_bytes()) {
+            nom::IResult::Done(_, dropped) => {
+                dropped_tables.push(dropped);
+                false
+            }
+            _ => true,
+        });
+
+        // ALTER TABLE statements do, too
+        let mut altered_tables = Vec::new();
+        query_strings.retain(|q| match alter_table(q.as_bytes()) {
+            nom::IResult::Done(_, altered) => {
+                altered_tables.push(altered);
+                false
+            }
+            _ => true,
+        });
+
+        // nom-sql cannot parse TTLs, `LIMIT ?`, foreign keys, the `JSON` type or extractions from
+        // JSON documents, so we take them out of the queries and apply them once they are parsed
+        let query_extensions = query_strings
+            .iter_mut()
+            .map(extensions::take_extensions)
+            .collect::<Result<Vec<_>, _>>()?;
+
         let parsed_queries = query_strings
             .iter()
             .map(|ref q| (q.clone(), query_expr(q.as_bytes())))
@@ -581,13 +1094,63 @@ impl Recipe {
             return Err(format!("Failed to parse recipe!"));
         }
 
-        Ok(parsed_queries
-            .into_iter()
-            .map(|(_, t)| {
-                let pr = t.unwrap().1;
-                (pr.1, pr.2, pr.0)
-            })
-            .collect::<Vec<_>>())
+        let mut ttls = HashMap::default();
+        let mut foreign_keys = HashMap::default();
+        let mut json_columns = HashMap::default();
+        let mut queries = Vec::with_capacity(parsed_queries.len());
+        for ((q, t), ext) in parsed_queries.into_iter().zip(query_extensions) {
+            let (public, name, mut expr) = t.unwrap().1;
+            if let (Some(ref name), Some(ttl)) = (&name, ext.ttl) {
+                ttls.insert(name.clone(), ttl);
+            }
+            if let SqlQuery::CreateTable(ref ctq) = expr {
+                if !ext.foreign_keys.is_empty() {
+                    foreign_keys.insert(ctq.table.name.clone(), ext.foreign_keys);
+                }
+                if !ext.json_columns.is_empty() {
+                    json_columns.insert(ctq.table.name.clone(), ext.json_columns);
+                }
+            }
+            if !ext.json_extractions.is_empty() {
+                name_json_extractions(&mut expr, &ext.json_extractions)
+                    .map_err(|e| format!("Query \"{}\", {}", q, e))?;
+            }
+            match (ext.limit_param, ext.max_limit) {
+                (true, Some(k)) => match expr {
+                    SqlQuery::Select(ref mut st) => {
+                        if let Some(ref mut limit) = st.limit {
+                            limit.limit = k;
+                        }
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Query \"{}\", LIMIT ? is only supported in SELECT queries",
+                            q
+                        ))
+                    }
+                },
+                (true, None) => {
+                    return Err(format!("Query \"{}\" has LIMIT ?, but no maximum limit", q))
+                }
+                (false, Some(_)) => {
+                    return Err(format!(
+                        "Query \"{}\" has a maximum limit, but no LIMIT ?",
+                        q
+                    ))
+                }
+                (false, None) => (),
+            }
+            queries.push((name, expr, public));
+        }
+
+        Ok((
+            queries,
+            ttls,
+            dropped_tables,
+            altered_tables,
+            foreign_keys,
+            json_columns,
+        ))
     }
 
     /// Returns the predecessor from which this `Recipe` was migrated to.
@@ -611,7 +1174,15 @@ impl Recipe {
     /// contained in `new` (but not in `self`) will be added; any contained in `self`, but not in
     /// `new` will be removed.
     /// Consumes `self` and returns a replacement recipe.
-    pub fn replace(mut self, mut new: Recipe) -> Result<Recipe, String> {
+    pub fn replace(mut self, mut new: Recipe) -> Result<Recipe, (Recipe, String)> {
+        let dropped_tables = mem::replace(&mut new.dropped_tables, Vec::new());
+        let changed = new
+            .alter_tables()
+            .and_then(|_| new.drop_tables(&dropped_tables));
+        if let Err(e) = changed {
+            return Err((self, e));
+        }
+
         // generate replacement recipe with correct version and lineage
         new.version = self.version + 1;
         // retain the old incorporator but move it to the new recipe
@@ -750,4 +1321,240 @@ mod tests {
         assert_eq!(r2.expressions.len(), 2);
         assert_eq!(r2.prior, Some(Box::new(r1_copy)));
     }
+
+    #[test]
+    fn it_parses_ttls() {
+        let r_txt = "QUERY a TTL 100ms: SELECT a FROM b WHERE x = ?;\n\
+                     VIEW c ttl 5s: SELECT c FROM b;\n\
+                     QUERY d: SELECT d FROM b;";
+        let r = Recipe::from_str(r_txt, None).unwrap();
+        assert_eq!(r.expressions.len(), 3);
+        assert_eq!(r.ttls.len(), 2);
+        assert_eq!(r.ttls["a"], Duration::from_millis(100));
+        assert_eq!(r.ttls["c"], Duration::from_secs(5));
+
+        // extending the recipe keeps existing TTLs
+        let r = r.extend("QUERY e TTL 1 s: SELECT e FROM b;").unwrap();
+        assert_eq!(r.ttls.len(), 3);
+        assert_eq!(r.ttls["e"], Duration::from_secs(1));
+
+        assert!(Recipe::from_str("QUERY f TTL: SELECT f FROM b;", None).is_err());
+    }
+
+    #[test]
+    fn it_parses_limit_parameters() {
+        let limit = |r: &Recipe, name: &str| match r.expressions[&r.aliases[name]].1 {
+            SqlQuery::Select(ref st) => st.limit.as_ref().map(|l| l.limit),
+            _ => None,
+        };
+
+        let r_txt = "QUERY a LIMIT 50: SELECT a FROM b WHERE x = ? ORDER BY a DESC LIMIT ?;\n\
+                     QUERY c TTL 1s limit 10 : SELECT c FROM b ORDER BY c limit ? OFFSET 0;";
+        let r = Recipe::from_str(r_txt, None).unwrap();
+        assert_eq!(limit(&r, "a"), Some(50));
+        assert_eq!(limit(&r, "c"), Some(10));
+
+        // a LIMIT ? is maintained just like a fixed LIMIT of its maximum
+        let r_txt = "QUERY a LIMIT 5: SELECT a FROM b ORDER BY a LIMIT ?;\n\
+                     QUERY d: SELECT a FROM b ORDER BY a LIMIT 5;";
+        let r = Recipe::from_str(r_txt, None).unwrap();
+        assert_eq!(r.expressions.len(), 1);
+
+        let res = Recipe::from_str("QUERY f: SELECT f FROM b ORDER BY f LIMIT ?;", None);
+        assert!(res.is_err());
+        let res = Recipe::from_str("QUERY g LIMIT 5: SELECT g FROM b ORDER BY g LIMIT 5;", None);
+        assert!(res.is_err());
+
+        // a LIMIT in a string is not a limit parameter
+        let r = Recipe::from_str("QUERY h: SELECT h FROM b WHERE t = 'limit ? offset';", None);
+        assert_eq!(limit(&r.unwrap(), "h"), None);
+    }
+
+    #[test]
+    fn it_parses_foreign_keys() {
+        let r_txt = "CREATE TABLE a (id int, PRIMARY KEY(id));\n\
+                     CREATE TABLE b (id int, a_id int, note varchar(255) DEFAULT 'foreign key', \
+                     PRIMARY KEY(id), CONSTRAINT fk FOREIGN KEY (`a_id`) REFERENCES a (id));";
+        let r = Recipe::from_str(r_txt, None).unwrap();
+        assert_eq!(r.expressions.len(), 2);
+        assert_eq!(
+            r.foreign_keys["b"],
+            vec![ForeignKeyDefinition {
+                columns: vec!["a_id".to_owned()],
+                parent: "a".to_owned(),
+                parent_columns: vec!["id".to_owned()],
+            }]
+        );
+        assert!(!r.foreign_keys.contains_key("a"));
+
+        let r_txt = "CREATE TABLE b (id int, a_id int, FOREIGN KEY (a_id) REFERENCES a (id, x));";
+        assert!(Recipe::from_str(r_txt, None).is_err());
+    }
+
+    #[test]
+    fn it_parses_json() {
+        use nom_sql::Column;
+
+        let r_txt = "CREATE TABLE a (id int, doc JSON NOT NULL, `json` int, PRIMARY KEY(id));\n\
+                     QUERY q: SELECT id, a.doc->>'$.x', JSON_EXTRACT(doc, '$.y[0]') AS y \
+                     FROM a WHERE doc -> '$.\"z\"' = 'a->b';";
+        let r = Recipe::from_str(r_txt, None).unwrap();
+        assert_eq!(r.json_columns["a"], vec!["doc".to_owned()]);
+
+        let st = match r.expressions[&r.aliases["q"]].1 {
+            SqlQuery::Select(ref st) => st.clone(),
+            _ => unreachable!(),
+        };
+        let column = |table: Option<&str>, name: &str, alias: Option<&str>| Column {
+            name: name.to_owned(),
+            alias: alias.map(String::from),
+            table: table.map(String::from),
+            function: None,
+        };
+        assert_eq!(
+            st.fields,
+            vec![
+                FieldDefinitionExpression::Col(column(None, "id", None)),
+                FieldDefinitionExpression::Col(column(Some("a"), "doc->>'$.x'", None)),
+                FieldDefinitionExpression::Col(column(None, "doc->'$.y[0]'", Some("y"))),
+            ]
+        );
+        match st.where_clause {
+            Some(ConditionExpression::ComparisonOp(ref ct)) => assert_eq!(
+                *ct.left,
+                ConditionExpression::Base(ConditionBase::Field(column(None, "doc->'$.z'", None)))
+            ),
+            ref ce => panic!("unexpected WHERE clause {:?}", ce),
+        }
+
+        // paths are quoted strings, which may hold anything that ends an extraction
+        let r_txt = "QUERY p: SELECT JSON_EXTRACT(doc, '$.a)b') AS ab, doc->>'$.\\'c\\'' FROM a;";
+        let r = Recipe::from_str(r_txt, None).unwrap();
+        let st = match r.expressions[&r.aliases["p"]].1 {
+            SqlQuery::Select(ref st) => st.clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            st.fields,
+            vec![
+                FieldDefinitionExpression::Col(column(None, "doc->'$.\"a)b\"'", Some("ab"))),
+                FieldDefinitionExpression::Col(column(None, "doc->>'$.\"'c'\"'", None)),
+            ]
+        );
+
+        // extractions are only supported among the selected columns and in WHERE clauses
+        let r_txt = "QUERY q: SELECT id FROM a ORDER BY doc->>'$.x';";
+        assert!(Recipe::from_str(r_txt, None).is_err());
+        let r_txt = "QUERY q: SELECT doc->>'x' FROM a;";
+        assert!(Recipe::from_str(r_txt, None).is_err());
+    }
+
+    #[test]
+    fn it_drops_tables() {
+        let r_txt = "CREATE TABLE b (a int, c int);\n\
+                     CREATE TABLE d (e int);\n\
+                     QUERY q: SELECT a FROM b WHERE c = ?;\n\
+                     VIEW v: SELECT c FROM b;\n\
+                     QUERY w: SELECT c FROM v;\n\
+                     QUERY x: SELECT e FROM d;";
+        let r = Recipe::from_str(r_txt, None).unwrap();
+        assert_eq!(r.expressions.len(), 6);
+
+        let r_drop = Recipe::from_str("DROP TABLE b;\ndrop table d cascade ;", None).unwrap();
+        assert_eq!(r_drop.expressions.len(), 0);
+        assert_eq!(
+            r_drop.dropped_tables,
+            vec![("b".to_owned(), false), ("d".to_owned(), true)]
+        );
+
+        // queries still read from the table, so dropping it needs a cascade
+        let r = match r.extend("DROP TABLE b;") {
+            Ok(_) => panic!("dropped a table that queries read from"),
+            Err((r, _)) => r,
+        };
+        assert_eq!(r.expressions.len(), 6);
+        assert!(r.clone().extend("DROP TABLE nonexistent;").is_err());
+
+        // the cascade also removes queries that read from the table through a view
+        let r = r.extend("DROP TABLE b CASCADE;").unwrap();
+        assert_eq!(r.expressions.len(), 2);
+        assert!(!r.aliases.contains_key("q"));
+        assert!(!r.aliases.contains_key("v"));
+        assert!(!r.aliases.contains_key("w"));
+        assert!(r.aliases.contains_key("x"));
+
+        // a table without dependents can be dropped without a cascade
+        let r = r.extend("CREATE TABLE f (g int);").unwrap();
+        let r = r.extend("DROP TABLE f;").unwrap();
+        assert_eq!(r.expressions.len(), 2);
+    }
+
+    #[test]
+    fn it_alters_tables() {
+        let r_txt = "CREATE TABLE b (a int, c int, PRIMARY KEY(a));\n\
+                     QUERY q: SELECT c FROM b WHERE a = ?;";
+        let r = Recipe::from_str(r_txt, None).unwrap();
+
+        let r_alter = "ALTER TABLE b ADD COLUMN d varchar(255) DEFAULT 'x';\n\
+                       alter table b rename column c to e;\n\
+                       ALTER TABLE b DROP COLUMN d ;";
+        let r_alter = Recipe::from_str(r_alter, None).unwrap();
+        assert_eq!(r_alter.expressions.len(), 0);
+        assert_eq!(r_alter.altered_tables.len(), 3);
+        match r_alter.altered_tables[0] {
+            (ref t, ColumnAlteration::Add(ref spec)) => {
+                assert_eq!(t, "b");
+                assert_eq!(spec.column.name, "d");
+                assert_eq!(spec.column.table, Some("b".to_owned()));
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            r_alter.altered_tables[1],
+            (
+                "b".to_owned(),
+                ColumnAlteration::Rename("c".into(), "e".into())
+            )
+        );
+        assert_eq!(
+            r_alter.altered_tables[2],
+            ("b".to_owned(), ColumnAlteration::Drop("d".into()))
+        );
+
+        // the altered table replaces the original one in the recipe
+        let r = r
+            .extend("ALTER TABLE b ADD COLUMN d int;\nALTER TABLE b RENAME COLUMN a TO f;")
+            .unwrap();
+        assert_eq!(r.expressions.len(), 2);
+        let ctq = r
+            .expressions
+            .values()
+            .filter_map(|&(_, ref q, _)| match *q {
+                SqlQuery::CreateTable(ref ctq) => Some(ctq),
+                _ => None,
+            })
+            .next()
+            .unwrap();
+        let fields: Vec<_> = ctq.fields.iter().map(|f| &f.column.name[..]).collect();
+        assert_eq!(fields, vec!["f", "c", "d"]);
+        assert!(is_key_column(ctq, "f"));
+        assert_eq!(
+            r.column_renames("b"),
+            vec![("a".to_owned(), "f".to_owned())]
+        );
+
+        // invalid alterations leave the recipe unchanged
+        let r = match r.extend("ALTER TABLE b DROP COLUMN f;") {
+            Ok(_) => panic!("dropped a key column"),
+            Err((r, _)) => r,
+        };
+        assert!(r.clone().extend("ALTER TABLE b DROP COLUMN a;").is_err());
+        assert!(r.clone().extend("ALTER TABLE b DROP COLUMN c;").is_err());
+        assert!(r.clone().extend("ALTER TABLE b ADD COLUMN c int;").is_err());
+        assert!(r.clone().extend("ALTER TABLE x DROP COLUMN c;").is_err());
+        assert!(r.clone().extend("ALTER TABLE b ADD COLUMN (;").is_err());
+
+        // columns that no query uses can still be dropped
+        assert!(r.extend("ALTER TABLE b DROP COLUMN d;").is_ok());
+    }
 }
@@ -1,8 +1,8 @@
 use crate::controller::sql::mir::SqlToMirConverter;
 use crate::controller::sql::query_graph::{JoinRef, QueryGraph, QueryGraphEdge};
 use dataflow::ops::join::JoinType;
-use mir::MirNodeRef;
-use nom_sql::ConditionTree;
+use mir::{Column, MirNodeRef};
+use nom_sql::{ConditionBase, ConditionExpression, ConditionTree, Operator};
 use std::collections::{HashMap, HashSet};
 
 struct JoinChain {
@@ -25,6 +25,16 @@ impl JoinChain {
     }
 }
 
+// A set of inner join predicates that all equate the same column of a hub relation with a column
+// of some other relation that takes part in no other join. These are joined by a single
+// multi-way join node rather than a chain of binary joins.
+struct Star {
+    hub: String,
+    hub_col: Column,
+    outer: Vec<(String, Column)>,
+    jrefs: Vec<JoinRef>,
+}
+
 // Generate join nodes for the query.
 // This is done by creating/merging join chains as each predicate is added.
 // If a predicate's parent tables appear in a previous predicate, the
@@ -33,6 +43,8 @@ impl JoinChain {
 // If a predicate's parent tables haven't been used by any previous predicate,
 // a new join chain is started for the current predicate. And we assume that
 // a future predicate will bring these chains together.
+// Predicates that form a star (see `Star`) are joined by a single multi-way join node, which is
+// added when the first of them comes up in the join order.
 pub fn make_joins(
     mir_converter: &SqlToMirConverter,
     name: &str,
@@ -44,7 +56,45 @@ pub fn make_joins(
     let mut join_chains = Vec::new();
     let mut node_count = node_count;
 
+    let stars = find_stars(qg);
+
     for jref in qg.join_order.iter() {
+        if let Some(star) = stars.iter().find(|s| s.jrefs.contains(jref)) {
+            if star.jrefs[0] != *jref {
+                // already joined along with the rest of its star
+                continue;
+            }
+
+            let hub_chain = pick_join_chain(&star.hub, &mut join_chains, node_for_rel);
+            let outer_chains: Vec<JoinChain> = star
+                .outer
+                .iter()
+                .map(|&(ref rel, _)| pick_join_chain(rel, &mut join_chains, node_for_rel))
+                .collect();
+
+            let jn = mir_converter.make_multi_join_node(
+                &format!("{}_n{}", name, node_count),
+                &star.hub_col,
+                hub_chain.last_node.clone(),
+                star.outer
+                    .iter()
+                    .zip(outer_chains.iter())
+                    .map(|(&(_, ref c), chain)| (c.clone(), chain.last_node.clone()))
+                    .collect(),
+            );
+
+            // merge node chains
+            let new_chain = outer_chains
+                .into_iter()
+                .fold(hub_chain, |acc, chain| acc.merge_chain(chain, jn.clone()));
+            join_chains.push(new_chain);
+
+            node_count += 1;
+
+            join_nodes.push(jn);
+            continue;
+        }
+
         let (join_type, jp) = from_join_ref(jref, &qg);
         let (left_chain, right_chain) =
             pick_join_chains(&jref.src, &jref.dst, &mut join_chains, node_for_rel);
@@ -78,27 +128,87 @@ fn from_join_ref<'a>(jref: &JoinRef, qg: &'a QueryGraph) -> (JoinType, &'a Condi
     }
 }
 
-fn pick_join_chains(
-    src: &String,
-    dst: &String,
+// Finds the stars among the query's join predicates. Only stars with at least two outer
+// relations are returned, since a single predicate is just a binary join.
+fn find_stars(qg: &QueryGraph) -> Vec<Star> {
+    let mut joins_per_rel: HashMap<&str, usize> = HashMap::new();
+    for jref in qg.join_order.iter() {
+        *joins_per_rel.entry(jref.src.as_str()).or_insert(0) += 1;
+        *joins_per_rel.entry(jref.dst.as_str()).or_insert(0) += 1;
+    }
+    let is_outer = |c: &nom_sql::Column| match c.table {
+        Some(ref t) => joins_per_rel.get(t.as_str()) == Some(&1),
+        None => false,
+    };
+
+    let mut stars: Vec<Star> = Vec::new();
+    for jref in qg.join_order.iter() {
+        let (join_type, jp) = from_join_ref(jref, qg);
+        if join_type != JoinType::Inner || jp.operator != Operator::Equal {
+            continue;
+        }
+        let (l, r) = match (&*jp.left, &*jp.right) {
+            (
+                &ConditionExpression::Base(ConditionBase::Field(ref l)),
+                &ConditionExpression::Base(ConditionBase::Field(ref r)),
+            ) => (l, r),
+            _ => continue,
+        };
+
+        let (hub, outer) = match (is_outer(l), is_outer(r)) {
+            (false, true) => (l, r),
+            (true, false) => (r, l),
+            // neither side can be joined on its own, or this is an isolated binary join
+            _ => continue,
+        };
+
+        let hub_col = Column::from(hub);
+        let outer = (outer.table.clone().unwrap(), Column::from(outer));
+        match stars.iter().position(|s| s.hub_col == hub_col) {
+            Some(i) => {
+                stars[i].outer.push(outer);
+                stars[i].jrefs.push(jref.clone());
+            }
+            None => stars.push(Star {
+                hub: hub.table.clone().unwrap(),
+                hub_col: hub_col,
+                outer: vec![outer],
+                jrefs: vec![jref.clone()],
+            }),
+        }
+    }
+
+    stars.retain(|s| s.outer.len() > 1);
+    stars
+}
+
+// Returns the join predicates of each star in the query, in the order in which they are joined.
+pub(crate) fn star_joins(qg: &QueryGraph) -> Vec<Vec<JoinRef>> {
+    find_stars(qg).into_iter().map(|s| s.jrefs).collect()
+}
+
+fn pick_join_chain(
+    rel: &String,
     join_chains: &mut Vec<JoinChain>,
     node_for_rel: &HashMap<&str, MirNodeRef>,
-) -> (JoinChain, JoinChain) {
-    let left_chain = match join_chains.iter().position(|chain| chain.has_table(src)) {
+) -> JoinChain {
+    match join_chains.iter().position(|chain| chain.has_table(rel)) {
         Some(idx) => join_chains.swap_remove(idx),
         None => JoinChain {
-            tables: vec![src.clone()].into_iter().collect(),
-            last_node: node_for_rel[src.as_str()].clone(),
+            tables: vec![rel.clone()].into_iter().collect(),
+            last_node: node_for_rel[rel.as_str()].clone(),
         },
-    };
+    }
+}
 
-    let right_chain = match join_chains.iter().position(|chain| chain.has_table(dst)) {
-        Some(idx) => join_chains.swap_remove(idx),
-        None => JoinChain {
-            tables: vec![dst.clone()].into_iter().collect(),
-            last_node: node_for_rel[dst.as_str()].clone(),
-        },
-    };
+fn pick_join_chains(
+    src: &String,
+    dst: &String,
+    join_chains: &mut Vec<JoinChain>,
+    node_for_rel: &HashMap<&str, MirNodeRef>,
+) -> (JoinChain, JoinChain) {
+    let left_chain = pick_join_chain(src, join_chains, node_for_rel);
+    let right_chain = pick_join_chain(dst, join_chains, node_for_rel);
 
     (left_chain, right_chain)
 }
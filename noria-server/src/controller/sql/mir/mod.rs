@@ -1,20 +1,24 @@
-use mir::node::{GroupedNodeType, MirNode, MirNodeType};
+use mir::copy::MirGraphCopy;
+use mir::node::{ForeignKey, GroupedNodeType, MirNode, MirNodeType};
 use mir::query::MirQuery;
 pub use mir::{Column, MirNodeRef};
-use noria::DataType;
+use noria::{Collation, DataType};
 use petgraph::graph::NodeIndex;
 // TODO(malte): remove if possible
 use dataflow::ops::filter::FilterCondition;
 use dataflow::ops::join::JoinType;
+use dataflow::JsonPath;
 pub use mir::FlowNode;
 
+use crate::controller::sql::passes::count_star_rewrite::COUNT_STAR_COLUMN;
 use crate::controller::sql::query_graph::{OutputColumn, QueryGraph};
 use crate::controller::sql::query_signature::Signature;
+use crate::controller::sql::query_utils::json_extraction;
 use nom_sql::{
     ArithmeticExpression, ColumnSpecification, CompoundSelectOperator, ConditionBase,
     ConditionExpression, ConditionTree, Literal, Operator, SqlQuery, TableKey,
 };
-use nom_sql::{LimitClause, OrderClause, SelectStatement};
+use nom_sql::{LimitClause, OrderClause, OrderType, SelectStatement, SqlType};
 
 use slog;
 use std::collections::{HashMap, HashSet};
@@ -23,13 +27,15 @@ use std::ops::Deref;
 use std::vec::Vec;
 
 use crate::controller::sql::security::Universe;
-use crate::controller::sql::UniverseId;
+use crate::controller::sql::{ForeignKeyDefinition, UniverseId};
 
 mod grouped;
 mod join;
 mod rewrite;
 mod security;
 
+pub(crate) use self::join::star_joins;
+
 fn sanitize_leaf_column(c: &mut Column, view_name: &str) {
     c.table = Some(view_name.to_string());
     c.function = None;
@@ -37,6 +43,35 @@ fn sanitize_leaf_column(c: &mut Column, view_name: &str) {
 }
 
 /// Returns all collumns used in a predicate
+/// Returns the column that orders a query newest first, if the query keeps only the newest row
+/// and orders by nothing else.
+fn latest_order(order: &Option<OrderClause>, limit: &LimitClause) -> Option<Column> {
+    if limit.limit != 1 || limit.offset != 0 {
+        return None;
+    }
+    match *order {
+        Some(ref o) if o.columns.len() == 1 => match o.columns[0] {
+            (ref c, OrderType::OrderDescending) => Some(Column::from(c)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns true if `node` never retracts a row, because it only filters and projects a base table
+/// without a primary key, which can only be inserted into.
+fn is_insert_only(node: &MirNodeRef) -> bool {
+    let n = node.borrow();
+    match n.inner {
+        MirNodeType::Base { ref keys, .. } => keys.is_empty(),
+        MirNodeType::Filter { .. } | MirNodeType::Project { .. } | MirNodeType::Identity => {
+            is_insert_only(&n.ancestors()[0])
+        }
+        MirNodeType::Reuse { ref node } => is_insert_only(node),
+        _ => false,
+    }
+}
+
 fn predicate_columns(ce: &ConditionExpression) -> HashSet<Column> {
     use nom_sql::ConditionExpression::*;
 
@@ -46,9 +81,15 @@ fn predicate_columns(ce: &ConditionExpression) -> HashSet<Column> {
             cols.extend(predicate_columns(&ct.left));
             cols.extend(predicate_columns(&ct.right));
         }
-        Base(ConditionBase::Field(ref c)) => {
-            cols.insert(Column::from(c));
-        }
+        Base(ConditionBase::Field(ref c)) => match json_extraction(c) {
+            // filters extract parts of JSON documents from the document's column
+            Some((source, _, _)) => {
+                cols.insert(Column::from(source));
+            }
+            None => {
+                cols.insert(Column::from(c));
+            }
+        },
         Bracketed(ref ce) => {
             cols.extend(predicate_columns(&ce));
         }
@@ -89,7 +130,7 @@ fn value_columns_needed_for_predicates(
                 },
                 oc.clone(),
             )),
-            OutputColumn::Data(_) => None,
+            OutputColumn::Data(_) | OutputColumn::JsonExtract(_) => None,
         })
         .filter(|(c, _)| pred_columns.contains(c))
         .collect()
@@ -99,6 +140,8 @@ fn value_columns_needed_for_predicates(
 pub struct SqlToMirConverter {
     base_schemas: HashMap<String, Vec<(usize, Vec<ColumnSpecification>)>>,
     current: HashMap<String, usize>,
+    /// Foreign keys declared for bases that have not been added yet.
+    foreign_keys: HashMap<String, Vec<ForeignKeyDefinition>>,
     log: slog::Logger,
     nodes: HashMap<(String, usize), MirNodeRef>,
     schema_version: usize,
@@ -112,6 +155,7 @@ impl Default for SqlToMirConverter {
         SqlToMirConverter {
             base_schemas: HashMap::default(),
             current: HashMap::default(),
+            foreign_keys: HashMap::default(),
             log: slog::Logger::root(slog::Discard, o!()),
             nodes: HashMap::default(),
             schema_version: 0,
@@ -128,6 +172,23 @@ impl SqlToMirConverter {
         }
     }
 
+    /// Returns all MIR nodes known to the converter.
+    pub fn nodes(&self) -> impl Iterator<Item = &MirNodeRef> {
+        self.nodes.values()
+    }
+
+    /// Returns a copy of the converter whose nodes are the corresponding nodes in `copy`.
+    pub fn with_nodes_from(&self, copy: &MirGraphCopy) -> Self {
+        SqlToMirConverter {
+            nodes: self
+                .nodes
+                .iter()
+                .map(|(k, n)| (k.clone(), copy.get(n)))
+                .collect(),
+            ..self.clone()
+        }
+    }
+
     /// Set universe in which the conversion will happen.
     /// We need this, because different universes will have different
     /// security policies and therefore different nodes that are not
@@ -154,6 +215,18 @@ impl SqlToMirConverter {
             })
     }
 
+    /// The collation of a column of a base table; columns of views are compared byte-wise.
+    fn collation_of(&self, column: &nom_sql::Column) -> Collation {
+        column
+            .table
+            .as_ref()
+            .and_then(|t| self.base_schemas.get(t))
+            .and_then(|schemas| schemas.last())
+            .and_then(|&(_, ref specs)| specs.iter().find(|cs| cs.column.name == column.name))
+            .map(Collation::of_column)
+            .unwrap_or(Collation::Binary)
+    }
+
     /// Converts a condition tree stored in the `ConditionExpr` returned by the SQL parser
     /// and adds its to a vector of conditions.
     fn to_conditions(
@@ -169,6 +242,11 @@ impl SqlToMirConverter {
             ConditionExpression::Base(ConditionBase::Field(ref f)) => f.clone(),
             _ => unimplemented!(),
         };
+        // a part of a JSON document is compared by extracting it from the document's column
+        let (l, extract) = match json_extraction(&l) {
+            Some((source, path, _)) => (source, Some(path)),
+            None => (l, None),
+        };
         use dataflow::ops::filter;
         let f = Some(match *ct.right.as_ref() {
             ConditionExpression::Base(ConditionBase::Literal(Literal::Integer(ref i))) => {
@@ -183,12 +261,14 @@ impl SqlToMirConverter {
                     filter::Value::Constant(DataType::from(s.clone())),
                 )
             }
-            ConditionExpression::Base(ConditionBase::Literal(Literal::Null)) => {
-                FilterCondition::Comparison(
-                    ct.operator.clone(),
-                    filter::Value::Constant(DataType::None),
-                )
-            }
+            // the parser turns `x IS NULL` into `x = NULL`, and `x IS NOT NULL` into `x != NULL`
+            ConditionExpression::Base(ConditionBase::Literal(Literal::Null)) => match ct.operator {
+                Operator::Equal => FilterCondition::IsNull,
+                Operator::NotEqual => FilterCondition::IsNotNull,
+                ref op => {
+                    FilterCondition::Comparison(op.clone(), filter::Value::Constant(DataType::None))
+                }
+            },
             ConditionExpression::Base(ConditionBase::LiteralList(ref ll)) => {
                 FilterCondition::In(ll.iter().map(|l| DataType::from(l.clone())).collect())
             }
@@ -200,6 +280,12 @@ impl SqlToMirConverter {
                 FilterCondition::Comparison(ct.operator.clone(), filter::Value::Column(fi))
             }
             _ => unimplemented!(),
+        })
+        .map(|f| match extract {
+            // like MySQL, `doc->'$.a' = 'x'` compares the part as a JSON value, which is what
+            // comparing its unquoted form amounts to
+            Some(path) => FilterCondition::JsonExtract(path, Box::new(f)),
+            None => f.collate(self.collation_of(&l)),
         });
 
         let absolute_column_ids: Vec<usize> = columns
@@ -253,6 +339,7 @@ impl SqlToMirConverter {
                     emit: columns.clone(),
                     literals: vec![],
                     arithmetic: vec![],
+                    json: vec![],
                 },
                 vec![parent.clone()],
                 vec![],
@@ -409,11 +496,76 @@ impl SqlToMirConverter {
         }
     }
 
-    pub fn named_base_to_mir(&mut self, name: &str, query: &SqlQuery) -> MirQuery {
+    /// Returns the SQL types of the columns of the current leaf node for `name`, or `None` if there
+    /// is no such node. The type of a column is `None` if it cannot be determined.
+    pub fn column_types(&self, name: &str) -> Option<Vec<Option<SqlType>>> {
+        let v = self.current.get(name)?;
+        let leaf = self.nodes.get(&(name.to_string(), *v))?.borrow();
+        let types = leaf.columns().iter().map(|c| leaf.column_type(c)).collect();
+        Some(types)
+    }
+
+    /// Returns the ordering and limit of the TopK or Latest node that computes the current leaf for
+    /// `name`, with the ordering columns given as indices into the leaf's columns. Returns `None`
+    /// if the leaf is not computed by such a node, or if it does not include all the ordering
+    /// columns.
+    pub fn leaf_topk(&self, name: &str) -> Option<(Vec<(usize, OrderType)>, usize)> {
+        let v = self.current.get(name)?;
+        let leaf = self.nodes.get(&(name.to_string(), *v))?;
+        // leaf columns are sanitized copies of the parent's columns, so match against those
+        let mut n = match leaf.borrow().inner {
+            MirNodeType::Leaf { .. } => leaf.borrow().ancestors()[0].clone(),
+            _ => return None,
+        };
+        let columns = n.borrow().columns().to_vec();
+        loop {
+            let next = match n.borrow().inner {
+                MirNodeType::TopK { ref order, k, .. } => {
+                    let order = match *order {
+                        None => vec![],
+                        Some(ref order) => order
+                            .iter()
+                            .map(|&(ref c, ref o)| {
+                                columns
+                                    .iter()
+                                    .position(|cc| cc == c)
+                                    .map(|i| (i, o.clone()))
+                            })
+                            .collect::<Option<_>>()?,
+                    };
+                    return Some((order, k));
+                }
+                // a Latest node keeps the single row that sorts first in descending order
+                MirNodeType::Latest {
+                    order: Some(ref order),
+                    ..
+                } => {
+                    let i = columns.iter().position(|cc| cc == order)?;
+                    return Some((vec![(i, OrderType::OrderDescending)], 1));
+                }
+                MirNodeType::Project { .. } | MirNodeType::Identity => {
+                    n.borrow().ancestors()[0].clone()
+                }
+                MirNodeType::Reuse { ref node } => node.clone(),
+                _ => return None,
+            };
+            n = next;
+        }
+    }
+
+    /// Computes the MIR for a base table. If a base of the same name exists already, columns
+    /// named in `renames` (as old and new name) are treated as renamed rather than as removed
+    /// and added.
+    pub fn named_base_to_mir(
+        &mut self,
+        name: &str,
+        query: &SqlQuery,
+        renames: &[(String, String)],
+    ) -> MirQuery {
         match *query {
             SqlQuery::CreateTable(ref ctq) => {
                 assert_eq!(name, ctq.table.name);
-                let n = self.make_base_node(&name, &ctq.fields, ctq.keys.as_ref());
+                let n = self.make_base_node(&name, &ctq.fields, ctq.keys.as_ref(), renames);
                 let node_id = (String::from(name), self.schema_version);
                 if !self.nodes.contains_key(&node_id) {
                     self.nodes.insert(node_id, n.clone());
@@ -525,7 +677,11 @@ impl SqlToMirConverter {
         name: &str,
         cols: &Vec<ColumnSpecification>,
         keys: Option<&Vec<TableKey>>,
+        renames: &[(String, String)],
     ) -> MirNodeRef {
+        // foreign keys only apply to the new base node below; reused and adapted bases keep theirs
+        let foreign_keys = self.foreign_keys.remove(name).unwrap_or_default();
+
         // have we seen a base of this name before?
         if self.base_schemas.contains_key(name) {
             let mut existing_schemas: Vec<(usize, Vec<ColumnSpecification>)> =
@@ -560,34 +716,58 @@ impl SqlToMirConverter {
                         existing_sv
                     );
 
-                    // Find out if this is a simple case of adding or removing a column
+                    // apply any renames to the existing schema, so that renamed columns match
+                    // their new specification
+                    let renamed_schema: Vec<ColumnSpecification> = schema
+                        .iter()
+                        .map(|c| {
+                            let mut c = c.clone();
+                            for &(ref from, ref to) in renames {
+                                if c.column.name == *from {
+                                    c.column.name = to.clone();
+                                }
+                            }
+                            c
+                        })
+                        .collect();
+
+                    // Find out if this is a simple case of adding, removing or renaming a column
                     let mut columns_added = Vec::new();
                     let mut columns_removed = Vec::new();
+                    let mut columns_renamed = Vec::new();
                     let mut columns_unchanged = Vec::new();
                     for c in cols {
-                        if !schema.contains(c) {
+                        if !renamed_schema.contains(c) {
                             // new column
                             columns_added.push(c);
                         } else {
                             columns_unchanged.push(c);
                         }
                     }
-                    for c in schema {
-                        if !cols.contains(c) {
+                    for (c, rc) in schema.iter().zip(renamed_schema.iter()) {
+                        if !cols.contains(rc) {
                             // dropped column
                             columns_removed.push(c);
+                        } else if c != rc {
+                            // renamed column
+                            let new = cols.iter().find(|&nc| nc == rc).unwrap();
+                            columns_renamed.push((c, new));
                         }
                     }
 
                     if columns_unchanged.len() > 0
-                        && (columns_added.len() > 0 || columns_removed.len() > 0)
+                        && (columns_added.len() > 0
+                            || columns_removed.len() > 0
+                            || columns_renamed.len() > 0)
                     {
                         error!(
                             self.log,
-                            "base {}: add columns {:?}, remove columns {:?} over v{}",
+                            "base {}: add columns {:?}, remove columns {:?}, rename columns {:?} \
+                             over v{}",
                             name,
                             columns_added,
                             columns_removed,
+                            columns_renamed,
                             existing_sv
                         );
                         let existing_node = self.nodes[&(String::from(name), existing_sv)].clone();
@@ -613,6 +793,13 @@ impl SqlToMirConverter {
                                     ));
                             columns.remove(pos);
                         }
+                        for &(old, new) in &columns_renamed {
+                            let pos = columns.iter().position(|cc| cc == old).expect(&format!(
+                                "couldn't find column \"{:#?}\", which we're renaming",
+                                old
+                            ));
+                            columns[pos] = new.clone();
+                        }
                         assert_eq!(
                             columns.len(),
                             existing_node.borrow().columns().len() + columns_added.len()
@@ -623,7 +810,12 @@ impl SqlToMirConverter {
                         let base_schemas = self.base_schemas.entry(String::from(name)).or_default();
                         base_schemas.push((self.schema_version, columns.clone()));
 
-                        return MirNode::adapt_base(existing_node, columns_added, columns_removed);
+                        return MirNode::adapt_base(
+                            existing_node,
+                            columns_added,
+                            columns_removed,
+                            columns_renamed,
+                        );
                     } else {
                         info!(self.log, "base table has complex schema change");
                         break;
@@ -653,6 +845,46 @@ impl SqlToMirConverter {
                 .collect(),
         };
         assert!(primary_keys.len() <= 1);
+        let unique_keys: Vec<Vec<Column>> = match keys {
+            None => vec![],
+            Some(keys) => keys
+                .iter()
+                .filter_map(|k| match *k {
+                    TableKey::UniqueKey(_, ref key_cols) => {
+                        Some(key_cols.iter().map(|kc| Column::from(kc)).collect())
+                    }
+                    _ => None,
+                })
+                .collect(),
+        };
+
+        // resolve the referenced columns to the absolute column IDs of the referenced bases
+        let foreign_keys: Vec<ForeignKey> = foreign_keys
+            .into_iter()
+            .map(|fk| {
+                let column = |name: &String| {
+                    let cs = cols.iter().find(|cs| cs.column.name == *name).unwrap();
+                    Column::from(&cs.column)
+                };
+                let parent_specs = &self.base_schemas[&fk.parent].last().unwrap().1;
+                let parent = self.nodes[&(fk.parent.clone(), self.current[&fk.parent])].borrow();
+                ForeignKey {
+                    columns: fk.columns.iter().map(column).collect(),
+                    parent: parent.flow_node_addr().unwrap(),
+                    parent_columns: fk
+                        .parent_columns
+                        .iter()
+                        .map(|name| {
+                            let cs = parent_specs
+                                .iter()
+                                .find(|cs| cs.column.name == *name)
+                                .unwrap();
+                            parent.column_id_for_column(&Column::from(&cs.column))
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
 
         // remember the schema for this version
         let base_schemas = self.base_schemas.entry(String::from(name)).or_default();
@@ -679,6 +911,8 @@ impl SqlToMirConverter {
                         MirNodeType::Base {
                             column_specs: cols.iter().map(|cs| (cs.clone(), None)).collect(),
                             keys: key_cols.iter().map(|kc| Column::from(kc)).collect(),
+                            unique_keys,
+                            foreign_keys,
                             adapted_over: None,
                         },
                         vec![],
@@ -695,6 +929,8 @@ impl SqlToMirConverter {
                 MirNodeType::Base {
                     column_specs: cols.iter().map(|cs| (cs.clone(), None)).collect(),
                     keys: vec![],
+                    unique_keys,
+                    foreign_keys,
                     adapted_over: None,
                 },
                 vec![],
@@ -825,6 +1061,33 @@ impl SqlToMirConverter {
 
         let mut out_nodes = Vec::new();
 
+        // COUNT(*) ignores the value of the column it counts over, so any column of the parent
+        // that is not grouped by will do. If every column is grouped by, we project a constant
+        // column to count over instead.
+        let is_count_star = match *func_col.function.as_ref().unwrap().deref() {
+            Count(ref col, _) => col.name == COUNT_STAR_COLUMN,
+            _ => false,
+        };
+        let parent_columns = parent.borrow().columns().to_vec();
+        let (parent, count_star_over) =
+            match parent_columns.iter().find(|c| !group_cols.contains(c)) {
+                Some(c) => (parent, c.clone()),
+                None if is_count_star => {
+                    let proj = self.make_project_node(
+                        &format!("{}_count_star", name),
+                        parent,
+                        parent_columns.iter().collect(),
+                        vec![],
+                        vec![],
+                        vec![(String::from(COUNT_STAR_COLUMN), DataType::from(0))],
+                        false,
+                    );
+                    out_nodes.push(proj.clone());
+                    (proj, Column::new(None, COUNT_STAR_COLUMN))
+                }
+                None => (parent, Column::new(None, COUNT_STAR_COLUMN)),
+            };
+
         let mknode = |over: &Column, t: GroupedNodeType, distinct: bool| {
             if distinct {
                 let new_name = name.clone().to_owned() + "_distinct";
@@ -860,18 +1123,20 @@ impl SqlToMirConverter {
                 GroupedNodeType::Aggregation(Aggregation::SUM),
                 distinct,
             ),
+            Count(ref col, _) if col.name == COUNT_STAR_COLUMN => mknode(
+                &count_star_over,
+                GroupedNodeType::Aggregation(Aggregation::COUNT_STAR),
+                false,
+            ),
             Count(ref col, distinct) => mknode(
                 &Column::from(col),
                 GroupedNodeType::Aggregation(Aggregation::COUNT),
                 distinct,
             ),
             CountStar => {
-                // XXX(malte): there is no "over" column, but our aggregation operators' API
-                // requires one to be specified, so we earlier rewrote it to use the last parent
-                // column (see passes/count_star_rewrite.rs). However, this isn't *entirely*
-                // faithful to COUNT(*) semantics, because COUNT(*) is supposed to count all
-                // rows including those with NULL values, and we don't have a mechanism to do that
-                // (but we also don't have a NULL value, so maybe we're okay).
+                // there is no "over" column, but our aggregation operators' API requires one to
+                // be specified, so we earlier rewrote it to count over a placeholder column (see
+                // passes/count_star_rewrite.rs).
                 panic!("COUNT(*) should have been rewritten earlier!")
             }
             Max(ref col) => mknode(
@@ -1051,16 +1316,75 @@ impl SqlToMirConverter {
         )
     }
 
+    fn make_multi_join_node(
+        &self,
+        name: &str,
+        hub_col: &Column,
+        hub_node: MirNodeRef,
+        outer: Vec<(Column, MirNodeRef)>,
+    ) -> MirNodeRef {
+        // as for binary joins, we project all columns of all parents, except that the outer
+        // parents' join columns are not repeated; they become aliases of the hub's join column
+        let mut hub_col = hub_col.clone();
+        for &(ref c, _) in &outer {
+            hub_col.add_alias(c);
+        }
+
+        let mut fields = hub_node.borrow().columns().to_vec();
+        for &(_, ref n) in &outer {
+            fields.extend(n.borrow().columns().iter().cloned());
+        }
+        let fields: Vec<Column> = fields
+            .into_iter()
+            .filter_map(|mut f| {
+                if outer.iter().any(|&(ref c, _)| f == *c) {
+                    // drop instances of outer join columns
+                    None
+                } else if f == hub_col {
+                    // N.B.: must come *after* the check above, since `hub_col` now also equals
+                    // the outer join columns via its aliases.
+                    for &(ref c, _) in &outer {
+                        f.add_alias(c);
+                    }
+                    Some(f)
+                } else {
+                    Some(f)
+                }
+            })
+            .collect();
+
+        let mut on = vec![hub_col];
+        let mut ancestors = vec![hub_node];
+        for (c, n) in outer {
+            on.push(c);
+            ancestors.push(n);
+        }
+
+        let inner = MirNodeType::MultiJoin {
+            on: on,
+            project: fields.clone(),
+        };
+        trace!(self.log, "Added multi-way join node {:?}", inner);
+        MirNode::new(name, self.schema_version, fields, inner, ancestors, vec![])
+    }
+
     fn make_projection_helper(
         &self,
         name: &str,
         parent: MirNodeRef,
         fn_col: &Column,
     ) -> MirNodeRef {
+        // COUNT(*) does not need the value of any particular column, but does need some column
+        let fn_col = if fn_col.name == COUNT_STAR_COLUMN {
+            parent.borrow().columns()[0].clone()
+        } else {
+            fn_col.clone()
+        };
         self.make_project_node(
             name,
             parent,
-            vec![fn_col],
+            vec![&fn_col],
+            vec![],
             vec![],
             vec![(String::from("grp"), DataType::from(0 as i32))],
             false,
@@ -1073,6 +1397,7 @@ impl SqlToMirConverter {
         parent_node: MirNodeRef,
         proj_cols: Vec<&Column>,
         arithmetic: Vec<(String, ArithmeticExpression)>,
+        json: Vec<(String, Column, JsonPath, bool)>,
         literals: Vec<(String, DataType)>,
         is_leaf: bool,
     ) -> MirNodeRef {
@@ -1081,6 +1406,7 @@ impl SqlToMirConverter {
         let names: Vec<String> = arithmetic
             .iter()
             .map(|&(ref n, _)| n.clone())
+            .chain(json.iter().map(|&(ref n, ..)| n.clone()))
             .chain(literals.iter().map(|&(ref n, _)| n.clone()))
             .collect();
 
@@ -1115,6 +1441,7 @@ impl SqlToMirConverter {
                 emit: emit_cols,
                 literals: literals,
                 arithmetic: arithmetic,
+                json: json,
             },
             vec![parent_node.clone()],
             vec![],
@@ -1179,6 +1506,28 @@ impl SqlToMirConverter {
         )
     }
 
+    fn make_latest_node(
+        &self,
+        name: &str,
+        parent: MirNodeRef,
+        group_by: Vec<&Column>,
+        order: Column,
+    ) -> MirNodeRef {
+        let combined_columns = parent.borrow().columns().iter().cloned().collect();
+
+        MirNode::new(
+            name,
+            self.schema_version,
+            combined_columns,
+            MirNodeType::Latest {
+                group_by: group_by.into_iter().cloned().collect(),
+                order: Some(order),
+            },
+            vec![parent.clone()],
+            vec![],
+        )
+    }
+
     fn make_predicate_nodes(
         &self,
         name: &str,
@@ -1301,6 +1650,7 @@ impl SqlToMirConverter {
                         }
                         OutputColumn::Data(_) => None,
                         OutputColumn::Literal(_) => None,
+                        OutputColumn::JsonExtract(_) => None,
                     })
                     .collect();
             let projected_literals: Vec<(String, DataType)> = arith_and_lit_columns_needed
@@ -1311,6 +1661,7 @@ impl SqlToMirConverter {
                     OutputColumn::Literal(ref lc) => {
                         Some((lc.name.clone(), DataType::from(&lc.value)))
                     }
+                    OutputColumn::JsonExtract(_) => None,
                 })
                 .collect();
 
@@ -1326,6 +1677,7 @@ impl SqlToMirConverter {
                 parent.clone(),
                 passthru_cols.iter().collect(),
                 projected_arithmetic,
+                vec![],
                 projected_literals,
                 false,
             );
@@ -1610,7 +1962,7 @@ impl SqlToMirConverter {
                     node_for_rel[sorted_rels.last().unwrap()].clone()
                 };
 
-                // 7. Potentially insert TopK node below the final node
+                // 7. Potentially insert TopK (or Latest) node below the final node
                 // XXX(malte): this adds a bogokey if there are no parameter columns to do the TopK
                 // over, but we could end up in a stick place if we reconcile/combine multiple
                 // queries (due to security universes or due to compound select queries) that do
@@ -1626,6 +1978,7 @@ impl SqlToMirConverter {
                             final_node.clone(),
                             cols.iter().collect(),
                             vec![],
+                            vec![],
                             vec![("bogokey".into(), DataType::from(0 as i32))],
                             false,
                         );
@@ -1641,15 +1994,27 @@ impl SqlToMirConverter {
                             .collect()
                     };
 
-                    let topk_node = self.make_topk_node(
-                        &format!("q_{:x}_n{}{}", qg.signature().hash, new_node_count, uformat),
-                        final_node,
-                        group_by.iter().collect(),
-                        &st.order,
-                        limit,
-                    );
-                    func_nodes.push(topk_node.clone());
-                    final_node = topk_node;
+                    // the newest row of each group of an insert-only input never needs replacing
+                    // by an older one, so a Latest node can keep it without TopK's parent lookups
+                    let node_name =
+                        format!("q_{:x}_n{}{}", qg.signature().hash, new_node_count, uformat);
+                    let node = match latest_order(&st.order, limit) {
+                        Some(order) if is_insert_only(&final_node) => self.make_latest_node(
+                            &node_name,
+                            final_node,
+                            group_by.iter().collect(),
+                            order,
+                        ),
+                        _ => self.make_topk_node(
+                            &node_name,
+                            final_node,
+                            group_by.iter().collect(),
+                            &st.order,
+                            limit,
+                        ),
+                    };
+                    func_nodes.push(node.clone());
+                    final_node = node;
                     new_node_count += 1;
                 }
 
@@ -1703,6 +2068,7 @@ impl SqlToMirConverter {
                         OutputColumn::Arithmetic(_) => None,
                         OutputColumn::Data(ref c) => Some(Column::from(c)),
                         OutputColumn::Literal(_) => None,
+                        OutputColumn::JsonExtract(_) => None,
                     })
                     .collect()
             } else {
@@ -1739,9 +2105,23 @@ impl SqlToMirConverter {
                     }
                     OutputColumn::Data(_) => None,
                     OutputColumn::Literal(_) => None,
+                    OutputColumn::JsonExtract(_) => None,
+                })
+                .collect();
+            let projected_json: Vec<(String, Column, JsonPath, bool)> = qg
+                .columns
+                .iter()
+                .filter_map(|oc| match *oc {
+                    OutputColumn::JsonExtract(ref jc) => Some((
+                        jc.name.clone(),
+                        Column::from(&jc.column),
+                        jc.path.clone(),
+                        jc.unquote,
+                    )),
+                    _ => None,
                 })
                 .collect();
-            let mut projected_literals: Vec<(String, DataType)> = qg
+            let projected_literals: Vec<(String, DataType)> = qg
                 .columns
                 .iter()
                 .filter_map(|oc| match *oc {
@@ -1755,20 +2135,10 @@ impl SqlToMirConverter {
                             None
                         }
                     }
+                    OutputColumn::JsonExtract(_) => None,
                 })
                 .collect();
 
-            // if this query does not have any parameters, we must add a bogokey
-            let has_bogokey = if has_leaf && qg.parameters().is_empty() {
-                // only add the bogokey if we haven't already added it prior to a TopK above
-                if !projected_columns.contains(&Column::new(None, "bogokey")) {
-                    projected_literals.push(("bogokey".into(), DataType::from(0 as i32)));
-                }
-                true
-            } else {
-                false
-            };
-
             let ident = if has_leaf {
                 format!("q_{:x}_n{}{}", qg.signature().hash, new_node_count, uformat)
             } else {
@@ -1780,6 +2150,7 @@ impl SqlToMirConverter {
                 final_node,
                 projected_columns.iter().collect(),
                 projected_arithmetic,
+                projected_json,
                 projected_literals,
                 !has_leaf,
             );
@@ -1800,14 +2171,12 @@ impl SqlToMirConverter {
                     })
                     .collect();
 
-                let query_params = if has_bogokey {
-                    vec![Column::new(None, "bogokey")]
-                } else {
-                    qg.parameters()
-                        .into_iter()
-                        .map(|c| Column::from(c))
-                        .collect()
-                };
+                // queries without parameters have no key, and are read in full
+                let query_params = qg
+                    .parameters()
+                    .into_iter()
+                    .map(|c| Column::from(c))
+                    .collect();
 
                 let leaf_node = MirNode::new(
                     name,
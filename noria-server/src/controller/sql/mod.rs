@@ -2,7 +2,7 @@ mod mir;
 mod passes;
 mod query_graph;
 mod query_signature;
-mod query_utils;
+pub(crate) mod query_utils;
 pub mod reuse;
 pub mod security;
 
@@ -10,6 +10,7 @@ use self::mir::{MirNodeRef, SqlToMirConverter};
 use self::query_graph::{to_query_graph, QueryGraph};
 use self::query_signature::Signature;
 use self::reuse::{ReuseConfig, ReuseConfigType};
+use ::mir::copy::MirGraphCopy;
 use ::mir::query::{MirQuery, QueryFlowParts};
 use ::mir::reuse as mir_reuse;
 use ::mir::Column;
@@ -17,17 +18,29 @@ use crate::controller::mir_to_flow::mir_query_to_flow_parts;
 use crate::controller::Migration;
 use dataflow::prelude::DataType;
 use nom_sql::parser as sql_parser;
-use nom_sql::{ArithmeticBase, CreateTableStatement, SqlQuery};
+use nom_sql::{ArithmeticBase, CreateTableStatement, OrderType, SqlQuery, SqlType};
 use nom_sql::{CompoundSelectOperator, CompoundSelectStatement, SelectStatement};
 use petgraph::graph::NodeIndex;
 
 use slog;
 use std::collections::HashMap;
+use std::iter;
 use std::str;
 use std::vec::Vec;
 
 pub type UniverseId = (DataType, Option<DataType>);
 
+/// A `FOREIGN KEY` clause of a `CREATE TABLE` statement, which nom-sql does not parse.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignKeyDefinition {
+    /// The referencing columns of the new table.
+    pub columns: Vec<String>,
+    /// The referenced table.
+    pub parent: String,
+    /// The referenced columns of `parent`, in the same order as `columns`.
+    pub parent_columns: Vec<String>,
+}
+
 #[derive(Clone, Debug)]
 enum QueryGraphReuse {
     ExactMatch(MirNodeRef),
@@ -111,6 +124,41 @@ impl SqlIncorporator {
         self.reuse_type = reuse_type;
     }
 
+    /// Returns a copy of the incorporator that shares no MIR nodes with `self`.
+    ///
+    /// A plain `clone` still refers to the same MIR graph, which incorporating new queries
+    /// modifies; queries can be added to a deep clone without affecting the original.
+    pub fn deep_clone(&self) -> Self {
+        let copy = {
+            let query_nodes = self
+                .base_mir_queries
+                .values()
+                .chain(self.mir_queries.values())
+                .flat_map(|mq| mq.roots.iter().chain(iter::once(&mq.leaf)));
+            MirGraphCopy::new(self.mir_converter.nodes().chain(query_nodes))
+        };
+        let copy_query = |mq: &MirQuery| MirQuery {
+            name: mq.name.clone(),
+            roots: mq.roots.iter().map(|n| copy.get(n)).collect(),
+            leaf: copy.get(&mq.leaf),
+        };
+
+        SqlIncorporator {
+            mir_converter: self.mir_converter.with_nodes_from(&copy),
+            base_mir_queries: self
+                .base_mir_queries
+                .iter()
+                .map(|(k, mq)| (k.clone(), copy_query(mq)))
+                .collect(),
+            mir_queries: self
+                .mir_queries
+                .iter()
+                .map(|(k, mq)| (k.clone(), copy_query(mq)))
+                .collect(),
+            ..self.clone()
+        }
+    }
+
     /// Incorporates a single query into via the flow graph migration in `mig`. The `query`
     /// argument is a string that holds a parameterized SQL query, and the `name` argument supplies
     /// an optional name for the query. If no `name` is specified, the table name is used in the
@@ -149,6 +197,56 @@ impl SqlIncorporator {
         }
     }
 
+    /// Changes the schema of an existing base table to the one given in `ctq`. The existing base
+    /// node is adapted in place, so queries that read from it keep running. `renames` lists the
+    /// renamed columns as pairs of old and new name; any other difference between the schemas is
+    /// an added or dropped column.
+    pub fn alter_base(
+        &mut self,
+        ctq: CreateTableStatement,
+        renames: &[(String, String)],
+        mig: &mut Migration,
+    ) -> Result<QueryFlowParts, String> {
+        let name = ctq.table.name.clone();
+        if !self.base_schemas.contains_key(&name) {
+            return Err(format!("cannot alter unknown table {}", name));
+        }
+
+        let q = self.rewrite_query(SqlQuery::CreateTable(ctq), mig)?;
+        let qfp = self.add_base_via_mir(&name, &q, renames, mig);
+        self.leaf_addresses.insert(name, qfp.query_leaf);
+
+        Ok(qfp)
+    }
+
+    /// Declares foreign keys for the table created by `ctq`, which is added next. Each key must
+    /// reference columns of a table that already exists.
+    pub fn add_foreign_keys(
+        &mut self,
+        ctq: &CreateTableStatement,
+        fks: Vec<ForeignKeyDefinition>,
+    ) -> Result<(), String> {
+        let has_column =
+            |ctq: &CreateTableStatement, c: &String| ctq.fields.iter().any(|f| f.column.name == *c);
+        for fk in &fks {
+            let parent = self.base_schemas.get(&fk.parent).ok_or_else(|| {
+                format!(
+                    "foreign key of table {} references unknown table {}",
+                    ctq.table.name, fk.parent
+                )
+            })?;
+            if let Some(c) = fk.columns.iter().find(|c| !has_column(ctq, c)) {
+                return Err(format!("table {} has no column {}", ctq.table.name, c));
+            }
+            if let Some(c) = fk.parent_columns.iter().find(|c| !has_column(parent, c)) {
+                return Err(format!("table {} has no column {}", fk.parent, c));
+            }
+        }
+
+        self.mir_converter.add_foreign_keys(&ctq.table.name, fks);
+        Ok(())
+    }
+
     pub fn get_base_schema(&self, name: &str) -> Option<CreateTableStatement> {
         self.base_schemas.get(name).cloned()
     }
@@ -167,6 +265,33 @@ impl SqlIncorporator {
         }
     }
 
+    /// Looks up information about a given query's MIR leaf using `f`. Queries that reuse another
+    /// query's leaf wholesale have no MIR of their own, so for those, the other query's leaf is
+    /// used.
+    fn with_mir_leaf<T, F>(&self, name: &str, f: F) -> Option<T>
+    where
+        F: Fn(&str) -> Option<T>,
+    {
+        f(name).or_else(|| {
+            let na = self.leaf_addresses.get(name)?;
+            self.leaf_addresses
+                .iter()
+                .filter(|&(n, nn)| nn == na && n != name)
+                .filter_map(|(n, _)| f(n))
+                .next()
+        })
+    }
+
+    /// Retrieves the SQL types of the output columns of a given query's leaf view.
+    pub fn get_query_column_types(&self, name: &str) -> Option<Vec<Option<SqlType>>> {
+        self.with_mir_leaf(name, |n| self.mir_converter.column_types(n))
+    }
+
+    /// Retrieves the ordering columns and limit of a given query's leaf view, if it has a limit.
+    pub fn get_query_topk(&self, name: &str) -> Option<(Vec<(usize, OrderType)>, usize)> {
+        self.with_mir_leaf(name, |n| self.mir_converter.leaf_topk(n))
+    }
+
     pub fn is_leaf_address(&self, ni: NodeIndex) -> bool {
         self.leaf_addresses.values().any(|nn| *nn == ni)
     }
@@ -275,6 +400,7 @@ impl SqlIncorporator {
                             !is_function
                         }
                         OutputColumn::Data(ref dc) => dc.function.is_none(),
+                        OutputColumn::JsonExtract(_) => true,
                     });
 
                     if predicates_match && no_grouped_columns {
@@ -418,10 +544,13 @@ impl SqlIncorporator {
         &mut self,
         query_name: &str,
         query: &SqlQuery,
+        renames: &[(String, String)],
         mut mig: &mut Migration,
     ) -> QueryFlowParts {
         // first, compute the MIR representation of the SQL query
-        let mut mir = self.mir_converter.named_base_to_mir(query_name, query);
+        let mut mir = self
+            .mir_converter
+            .named_base_to_mir(query_name, query, renames);
 
         trace!(self.log, "Base node MIR: {:#?}", mir);
 
@@ -612,11 +741,12 @@ impl SqlIncorporator {
             );
         }
 
+        self.leaf_addresses.remove(name);
         let mir = self
             .base_mir_queries
-            .get(name)
+            .remove(name)
             .expect(&format!("tried to remove unknown base {}", name));
-        self.mir_converter.remove_base(name, mir)
+        self.mir_converter.remove_base(name, &mir)
     }
 
     fn register_query(
@@ -829,7 +959,7 @@ impl SqlIncorporator {
             .coalesce_key_definitions()
             .expand_stars(&self.view_schemas)
             .expand_implied_tables(&self.view_schemas)
-            .rewrite_count_star(&self.view_schemas))
+            .rewrite_count_star())
     }
 
     fn nodes_for_named_query(
@@ -873,7 +1003,9 @@ impl SqlIncorporator {
                     .unwrap()
             }
             SqlQuery::Select(sq) => self.add_select_query(&query_name, &sq, is_leaf, mig)?.0,
-            ref q @ SqlQuery::CreateTable { .. } => self.add_base_via_mir(&query_name, &q, mig),
+            ref q @ SqlQuery::CreateTable { .. } => {
+                self.add_base_via_mir(&query_name, &q, &[], mig)
+            }
             ref q @ _ => panic!("unhandled query type in recipe: {:?}", q),
         };
 
@@ -1066,8 +1198,8 @@ mod tests {
             assert_eq!(new_join_view.fields(), &["id", "author", "title", "name"]);
             // leaf node
             let new_leaf_view = get_node(&inc, mig, &q.unwrap().name);
-            assert_eq!(new_leaf_view.fields(), &["name", "title", "bogokey"]);
-            assert_eq!(new_leaf_view.description(true), "π[3, 2, lit: 0]");
+            assert_eq!(new_leaf_view.fields(), &["name", "title"]);
+            assert_eq!(new_leaf_view.description(true), "π[3, 2]");
         });
     }
 
@@ -1107,8 +1239,8 @@ mod tests {
             assert_eq!(filter.description(true), "σ[f0 = 42]");
             // leaf view node
             let edge = get_node(&inc, mig, &res.unwrap().name);
-            assert_eq!(edge.fields(), &["name", "bogokey"]);
-            assert_eq!(edge.description(true), "π[1, lit: 0]");
+            assert_eq!(edge.fields(), &["name"]);
+            assert_eq!(edge.description(true), "π[1]");
         });
     }
 
@@ -1159,8 +1291,8 @@ mod tests {
             assert_eq!(agg_view.description(true), "|*| γ[0]");
             // check edge view
             let edge_view = get_node(&inc, mig, &res.unwrap().name);
-            assert_eq!(edge_view.fields(), &["votes", "bogokey"]);
-            assert_eq!(edge_view.description(true), "π[1, lit: 0]");
+            assert_eq!(edge_view.fields(), &["votes"]);
+            assert_eq!(edge_view.description(true), "π[1]");
         });
     }
 
@@ -1238,6 +1370,33 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_deep_clones_without_sharing_mir() {
+        // set up graph
+        let mut g = integration::build_local("it_deep_clones_without_sharing_mir");
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(
+                inc.add_query("CREATE TABLE users (id int, name varchar(40));", None, mig)
+                    .is_ok()
+            );
+            let base_children = |inc: &SqlIncorporator| {
+                inc.base_mir_queries["users"].leaf.borrow().children().len()
+            };
+            assert_eq!(base_children(&inc), 0);
+
+            // adding a query to the deep clone adds MIR nodes below its copy of the base only
+            let mut copy = inc.deep_clone();
+            assert!(
+                copy.add_query("SELECT id, name FROM users WHERE users.id = ?;", None, mig)
+                    .is_ok()
+            );
+            assert!(base_children(&copy) > 0);
+            assert_eq!(base_children(&inc), 0);
+            assert_eq!(inc.mir_queries.len(), 0);
+        });
+    }
+
     #[test]
     fn it_reuses_with_different_parameter() {
         // set up graph
@@ -1310,6 +1469,41 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_incorporates_in_parameter() {
+        // set up graph
+        let mut g = integration::build_local("it_incorporates_in_parameter");
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            // Establish a base write type
+            assert!(
+                inc.add_query("CREATE TABLE users (id int, name varchar(40));", None, mig)
+                    .is_ok()
+            );
+
+            // Add a query with an `IN (?)` parameter
+            let res = inc.add_query(
+                "SELECT id, name FROM users WHERE users.id IN (?);",
+                None,
+                mig,
+            );
+            assert!(res.is_ok());
+            let qfp = res.unwrap();
+            // the parameter column keys the view
+            assert_eq!(get_node(&inc, mig, &qfp.name).fields(), &["id", "name"]);
+            let leaf = qfp.query_leaf;
+
+            // Add the same query with an equality parameter
+            let ncount = mig.graph().node_count();
+            let res = inc.add_query("SELECT id, name FROM users WHERE users.id = ?;", None, mig);
+            assert!(res.is_ok());
+            // should have added no more nodes, and ended up with the same leaf node
+            let qfp = res.unwrap();
+            assert_eq!(mig.graph().node_count(), ncount);
+            assert_eq!(qfp.query_leaf, leaf);
+        });
+    }
+
     #[test]
     fn it_reuses_by_extending_existing_query() {
         use super::sql_parser;
@@ -1406,12 +1600,10 @@ mod tests {
             let agg_view = get_node(&inc, mig, &format!("q_{:x}_n0", qid));
             assert_eq!(agg_view.fields(), &["grp", "count"]);
             assert_eq!(agg_view.description(true), "|*| γ[1]");
-            // check edge view -- note that it's not actually currently possible to read from
-            // this for a lack of key (the value would be the key). Hence, the view also has a
-            // bogokey column.
+            // check edge view -- it has no key, and is read in full
             let edge_view = get_node(&inc, mig, &res.unwrap().name);
-            assert_eq!(edge_view.fields(), &["count", "bogokey"]);
-            assert_eq!(edge_view.description(true), "π[1, lit: 0]");
+            assert_eq!(edge_view.fields(), &["count"]);
+            assert_eq!(edge_view.description(true), "π[1]");
         });
     }
 
@@ -1441,7 +1633,7 @@ mod tests {
             // added the aggregation, a project helper, the edge view, and reader
             assert_eq!(mig.graph().node_count(), 5);
             // check aggregation view
-            let f = Box::new(FunctionExpression::Count(Column::from("votes.aid"), false));
+            let f = Box::new(FunctionExpression::Count(Column::from("votes.*"), false));
             let qid = query_id_hash(
                 &["computed_columns", "votes"],
                 &[&Column::from("votes.userid")],
@@ -1455,12 +1647,51 @@ mod tests {
             let agg_view = get_node(&inc, mig, &format!("q_{:x}_n0", qid));
             assert_eq!(agg_view.fields(), &["userid", "count"]);
             assert_eq!(agg_view.description(true), "|*| γ[0]");
-            // check edge view -- note that it's not actually currently possible to read from
-            // this for a lack of key (the value would be the key). Hence, the view also has a
-            // bogokey column.
+            // check edge view -- it has no key, and is read in full
             let edge_view = get_node(&inc, mig, &res.unwrap().name);
-            assert_eq!(edge_view.fields(), &["count", "bogokey"]);
-            assert_eq!(edge_view.description(true), "π[1, lit: 0]");
+            assert_eq!(edge_view.fields(), &["count"]);
+            assert_eq!(edge_view.description(true), "π[1]");
+        });
+    }
+
+    #[test]
+    fn it_incorporates_count_star_over_grouped_columns() {
+        let mut g = integration::build_local("it_incorporates_count_star_over_grouped_columns");
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query("CREATE TABLE votes (userid int, aid int);", None, mig)
+                .is_ok());
+            let ncount = mig.graph().node_count();
+            // every column is grouped by, so there is no column left to count over
+            let res = inc.add_query(
+                "SELECT userid, aid, COUNT(*) AS count FROM votes GROUP BY votes.userid, votes.aid;",
+                None,
+                mig,
+            );
+            assert!(res.is_ok());
+            // added a projection of a constant column, the aggregation, the edge view, and reader
+            assert_eq!(mig.graph().node_count(), ncount + 4);
+        });
+    }
+
+    #[test]
+    fn it_rejects_unsupported_operators() {
+        let mut g = integration::build_local("it_rejects_unsupported_operators");
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query("CREATE TABLE users (id int, name varchar(40));", None, mig)
+                .is_ok());
+            let ncount = mig.graph().node_count();
+            assert!(inc
+                .add_query(
+                    "SELECT users.id FROM users WHERE users.name LIKE 'a%';",
+                    None,
+                    mig
+                )
+                .is_err());
+            assert_eq!(mig.graph().node_count(), ncount);
         });
     }
 
@@ -1514,7 +1745,7 @@ mod tests {
             // views
             // leaf view
             let leaf_view = get_node(&inc, mig, "q_3");
-            assert_eq!(leaf_view.fields(), &["name", "title", "uid", "bogokey"]);
+            assert_eq!(leaf_view.fields(), &["name", "title", "uid"]);
         });
     }
 
@@ -1577,7 +1808,141 @@ mod tests {
             );
             // leaf view
             let leaf_view = get_node(&inc, mig, "q_3");
-            assert_eq!(leaf_view.fields(), &["name", "title", "uid", "bogokey"]);
+            assert_eq!(leaf_view.fields(), &["name", "title", "uid"]);
+        });
+    }
+
+    #[test]
+    fn it_incorporates_star_join() {
+        use petgraph::EdgeDirection;
+
+        // set up graph
+        let mut g = integration::build_local("it_incorporates_star_join");
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(
+                inc.add_query(
+                    "CREATE TABLE articles (aid int, title varchar(255));",
+                    None,
+                    mig
+                )
+                .is_ok()
+            );
+            assert!(
+                inc.add_query("CREATE TABLE votes (aid int, uid int);", None, mig)
+                    .is_ok()
+            );
+            assert!(
+                inc.add_query(
+                    "CREATE TABLE comments (aid int, body varchar(255));",
+                    None,
+                    mig
+                )
+                .is_ok()
+            );
+
+            // votes and comments both join articles on the same column, and nothing else
+            let q = "SELECT articles.title, votes.uid, comments.body \
+                     FROM articles, votes, comments \
+                     WHERE articles.aid = votes.aid \
+                     AND articles.aid = comments.aid;";
+            let q = inc.add_query(q, None, mig);
+            assert!(q.is_ok());
+            let qid = query_id_hash(
+                &["articles", "comments", "votes"],
+                &[
+                    &Column::from("articles.aid"),
+                    &Column::from("comments.aid"),
+                    &Column::from("votes.aid"),
+                ],
+                &[
+                    &Column::from("articles.title"),
+                    &Column::from("votes.uid"),
+                    &Column::from("comments.body"),
+                ],
+            );
+            // a single join node over all three tables
+            let join_name = format!("q_{:x}_n0", qid);
+            let join_view = get_node(&inc, mig, &join_name);
+            assert_eq!(join_view.fields(), &["aid", "title", "body", "uid"]);
+            let na = inc.get_flow_node_address(&join_name, 0).unwrap();
+            assert_eq!(
+                mig.graph()
+                    .neighbors_directed(na, EdgeDirection::Incoming)
+                    .count(),
+                3
+            );
+            assert!(
+                inc.get_flow_node_address(&format!("q_{:x}_n1", qid), 0)
+                    .is_none()
+            );
+            // leaf view
+            let leaf_view = get_node(&inc, mig, "q_3");
+            assert_eq!(leaf_view.fields(), &["title", "uid", "body"]);
+        });
+    }
+
+    #[test]
+    fn it_reuses_star_join() {
+        // set up graph
+        let mut g = integration::build_local("it_reuses_star_join");
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(
+                inc.add_query(
+                    "CREATE TABLE articles (aid int, title varchar(255));",
+                    None,
+                    mig
+                )
+                .is_ok()
+            );
+            assert!(
+                inc.add_query("CREATE TABLE votes (aid int, uid int);", None, mig)
+                    .is_ok()
+            );
+            assert!(
+                inc.add_query(
+                    "CREATE TABLE comments (aid int, body varchar(255));",
+                    None,
+                    mig
+                )
+                .is_ok()
+            );
+            assert!(
+                inc.add_query("CREATE TABLE users (id int, name varchar(40));", None, mig)
+                    .is_ok()
+            );
+
+            let q = "SELECT articles.title, votes.uid, comments.body \
+                     FROM articles, votes, comments \
+                     WHERE articles.aid = votes.aid \
+                     AND articles.aid = comments.aid;";
+            let res = inc.add_query(q, None, mig);
+            assert!(res.is_ok());
+            let leaf = res.unwrap().query_leaf;
+
+            // the same star, projected in a different order, reuses its multi-way join
+            let ncount = mig.graph().node_count();
+            let q = "SELECT comments.body, votes.uid, articles.title \
+                     FROM articles, votes, comments \
+                     WHERE articles.aid = comments.aid \
+                     AND articles.aid = votes.aid;";
+            let res = inc.add_query(q, None, mig);
+            assert!(res.is_ok());
+            // should have added two more nodes (project and reader)
+            let qfp = res.unwrap();
+            assert_eq!(mig.graph().node_count(), ncount + 2);
+            assert_ne!(qfp.query_leaf, leaf);
+
+            // joining users through votes leaves no star, so these joins are all binary, and
+            // must not be ordered to line up with the star's
+            let q = "SELECT articles.title, users.name \
+                     FROM articles, votes, comments, users \
+                     WHERE articles.aid = votes.aid \
+                     AND articles.aid = comments.aid \
+                     AND votes.uid = users.id;";
+            let res = inc.add_query(q, None, mig);
+            assert!(res.is_ok());
         });
     }
 
@@ -1620,11 +1985,8 @@ mod tests {
             assert_eq!(new_join_view.fields(), &["id", "author", "title", "name"]);
             // leaf node
             let new_leaf_view = get_node(&inc, mig, &q.unwrap().name);
-            assert_eq!(
-                new_leaf_view.fields(),
-                &["id", "name", "author", "title", "bogokey"]
-            );
-            assert_eq!(new_leaf_view.description(true), "π[1, 3, 1, 2, lit: 0]");
+            assert_eq!(new_leaf_view.fields(), &["id", "name", "author", "title"]);
+            assert_eq!(new_leaf_view.description(true), "π[1, 3, 1, 2]");
         });
     }
 
@@ -1668,8 +2030,8 @@ mod tests {
 
             // leaf view node
             let edge = get_node(&inc, mig, &res.unwrap().name);
-            assert_eq!(edge.fields(), &["name", "1", "bogokey"]);
-            assert_eq!(edge.description(true), "π[1, lit: 1, lit: 0]");
+            assert_eq!(edge.fields(), &["name", "1"]);
+            assert_eq!(edge.description(true), "π[1, lit: 1]");
         });
     }
 
@@ -1693,10 +2055,10 @@ mod tests {
 
             // leaf view node
             let edge = get_node(&inc, mig, &res.unwrap().name);
-            assert_eq!(edge.fields(), &["2 * users.age", "twenty", "bogokey"]);
+            assert_eq!(edge.fields(), &["2 * users.age", "twenty"]);
             assert_eq!(
                 edge.description(true),
-                "π[(lit: 2) * 1, (lit: 2) * (lit: 10), lit: 0]"
+                "π[(lit: 2) * 1, (lit: 2) * (lit: 10)]"
             );
         });
     }
@@ -1741,8 +2103,8 @@ mod tests {
             assert_eq!(new_join_view.fields(), &["id", "author", "title", "name"]);
             // leaf node
             let new_leaf_view = get_node(&inc, mig, &q.unwrap().name);
-            assert_eq!(new_leaf_view.fields(), &["name", "title", "bogokey"]);
-            assert_eq!(new_leaf_view.description(true), "π[3, 2, lit: 0]");
+            assert_eq!(new_leaf_view.fields(), &["name", "title"]);
+            assert_eq!(new_leaf_view.description(true), "π[3, 2]");
         });
     }
 
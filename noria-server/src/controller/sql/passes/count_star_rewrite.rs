@@ -1,100 +1,48 @@
-use nom_sql::{
-    Column, ConditionBase, ConditionExpression, ConditionTree, FieldDefinitionExpression, SqlQuery,
-    Table,
-};
+use nom_sql::{Column, FieldDefinitionExpression, SqlQuery, Table};
 
-use std::collections::HashMap;
+/// The name of the placeholder column that `COUNT(*)` is rewritten to count over.
+///
+/// `COUNT(*)` counts every row, so there is no real column to count over. The placeholder carries
+/// the table whose rows are counted, and is replaced with some column of that table when the
+/// aggregation is built.
+pub const COUNT_STAR_COLUMN: &str = "*";
 
 pub trait CountStarRewrite {
-    fn rewrite_count_star(self, write_schemas: &HashMap<String, Vec<String>>) -> SqlQuery;
-}
-
-fn extract_condition_columns(ce: &ConditionExpression) -> Vec<Column> {
-    match *ce {
-        ConditionExpression::LogicalOp(ConditionTree {
-            box ref left,
-            box ref right,
-            ..
-        }) => extract_condition_columns(left)
-            .into_iter()
-            .chain(extract_condition_columns(right).into_iter())
-            .collect(),
-        ConditionExpression::ComparisonOp(ConditionTree {
-            box ref left,
-            box ref right,
-            ..
-        }) => {
-            let mut cols = vec![];
-            match *left {
-                ConditionExpression::Base(ConditionBase::Field(ref f)) => cols.push(f.clone()),
-                _ => (),
-            }
-            match *right {
-                ConditionExpression::Base(ConditionBase::Field(ref f)) => cols.push(f.clone()),
-                _ => (),
-            }
-
-            cols
-        }
-        ConditionExpression::NegationOp(ref inner) => extract_condition_columns(inner),
-        ConditionExpression::Bracketed(ref inner) => extract_condition_columns(inner),
-        ConditionExpression::Base(_) => unreachable!(),
-    }
+    fn rewrite_count_star(self) -> SqlQuery;
 }
 
 impl CountStarRewrite for SqlQuery {
-    fn rewrite_count_star(self, write_schemas: &HashMap<String, Vec<String>>) -> SqlQuery {
+    fn rewrite_count_star(self) -> SqlQuery {
         use nom_sql::FunctionExpression::*;
 
-        let rewrite_count_star =
-            |c: &mut Column, tables: &Vec<Table>, avoid_columns: &Vec<Column>| {
-                assert!(tables.len() > 0);
-                if let Some(box CountStar) = c.function {
-                    let bogo_table = tables.get(0).unwrap();
-                    let mut schema_iter = write_schemas.get(&bogo_table.name).unwrap().iter();
-                    let mut bogo_column = schema_iter.next().unwrap();
-                    while avoid_columns
-                        .iter()
-                        .position(|c| c.name == *bogo_column)
-                        .is_some()
-                    {
-                        bogo_column = schema_iter
-                            .next()
-                            .expect("ran out of columns trying to pick a bogo column for COUNT(*)");
-                    }
-
-                    c.function = Some(Box::new(Count(
-                        Column {
-                            name: bogo_column.clone(),
-                            alias: None,
-                            table: Some(bogo_table.name.clone()),
-                            function: None,
-                        },
-                        false,
-                    )));
-                }
-            };
+        let rewrite_count_star = |c: &mut Column, tables: &Vec<Table>| {
+            assert!(tables.len() > 0);
+            if let Some(box CountStar) = c.function {
+                let bogo_table = tables.get(0).unwrap();
+                c.function = Some(Box::new(Count(
+                    Column {
+                        name: String::from(COUNT_STAR_COLUMN),
+                        alias: None,
+                        table: Some(bogo_table.name.clone()),
+                        function: None,
+                    },
+                    false,
+                )));
+            }
+        };
 
         let err = "Must apply StarExpansion pass before CountStarRewrite"; // for wrapping
         match self {
             SqlQuery::Select(mut sq) => {
                 // Expand within field list
                 let tables = sq.tables.clone();
-                let mut avoid_cols = vec![];
-                match sq.group_by {
-                    Some(ref gbc) => avoid_cols.extend(gbc.columns.clone()),
-                    None => (),
-                };
-                if let Some(ref w) = sq.where_clause {
-                    avoid_cols.extend(extract_condition_columns(w));
-                }
                 for field in sq.fields.iter_mut() {
                     match field {
                         &mut FieldDefinitionExpression::All => panic!(err),
                         &mut FieldDefinitionExpression::AllInTable(_) => panic!(err),
                         &mut FieldDefinitionExpression::Value(_) => (),
                         &mut FieldDefinitionExpression::Col(ref mut c) => {
-                            rewrite_count_star(c, &tables, &avoid_cols)
+                            rewrite_count_star(c, &tables)
                         }
                     }
                 }
@@ -111,7 +59,6 @@ impl CountStarRewrite for SqlQuery {
 mod tests {
     use super::CountStarRewrite;
     use nom_sql::{Column, FieldDefinitionExpression, SqlQuery};
-    use std::collections::HashMap;
 
     #[test]
     fn it_expands_count_star() {
@@ -120,15 +67,10 @@ mod tests {
 
         // SELECT COUNT(*) FROM users;
         // -->
-        // SELECT COUNT(users.id) FROM users;
+        // SELECT COUNT(users.*) FROM users;
         let q = parse_query("SELECT COUNT(*) FROM users;").unwrap();
-        let mut schema = HashMap::new();
-        schema.insert(
-            "users".into(),
-            vec!["id".into(), "name".into(), "age".into()],
-        );
 
-        let res = q.rewrite_count_star(&schema);
+        let res = q.rewrite_count_star();
         match res {
             SqlQuery::Select(tq) => {
                 assert_eq!(
@@ -138,7 +80,7 @@ mod tests {
                         alias: None,
                         table: None,
                         function: Some(Box::new(FunctionExpression::Count(
-                            Column::from("users.id"),
+                            Column::from("users.*"),
                             false,
                         ))),
                     })]
@@ -156,15 +98,10 @@ mod tests {
 
         // SELECT COUNT(*) FROM users GROUP BY id;
         // -->
-        // SELECT COUNT(users.name) FROM users GROUP BY id;
+        // SELECT COUNT(users.*) FROM users GROUP BY id;
         let q = parse_query("SELECT COUNT(*) FROM users GROUP BY id;").unwrap();
-        let mut schema = HashMap::new();
-        schema.insert(
-            "users".into(),
-            vec!["id".into(), "name".into(), "age".into()],
-        );
 
-        let res = q.rewrite_count_star(&schema);
+        let res = q.rewrite_count_star();
         match res {
             SqlQuery::Select(tq) => {
                 assert_eq!(
@@ -174,7 +111,7 @@ mod tests {
                         alias: None,
                         table: None,
                         function: Some(Box::new(FunctionExpression::Count(
-                            Column::from("users.name"),
+                            Column::from("users.*"),
                             false,
                         ))),
                     })]
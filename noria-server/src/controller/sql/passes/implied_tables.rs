@@ -3,6 +3,7 @@ use nom_sql::{
     FieldValueExpression, JoinRightSide, SelectStatement, SqlQuery, Table,
 };
 
+use crate::controller::sql::query_utils::json_extraction;
 use std::collections::HashMap;
 
 pub trait ImpliedTableExpansion {
@@ -82,6 +83,11 @@ fn rewrite_selection(
     // passed as `write_schemas`; this is not something the parser or the expansion pass can
     // know on their own). Panics if no match is found or the match is ambiguous.
     let find_table = |f: &Column, tables_in_query: &Vec<Table>| -> Option<String> {
+        // a part extracted from a JSON document belongs to the table of the document's column
+        let name = match json_extraction(f) {
+            Some((source, _, _)) => source.name,
+            None => f.name.clone(),
+        };
         let mut matches = write_schemas
             .iter()
             .filter(|&(t, _)| {
@@ -98,7 +104,7 @@ fn rewrite_selection(
                 }
             })
             .filter_map(|(t, ws)| {
-                let num_matching = ws.iter().filter(|c| **c == f.name).count();
+                let num_matching = ws.iter().filter(|c| **c == name).count();
                 assert!(num_matching <= 1);
                 if num_matching == 1 {
                     Some((*t).clone())
@@ -8,7 +8,7 @@ impl KeyDefinitionCoalescing for SqlQuery {
     fn coalesce_key_definitions(self) -> SqlQuery {
         match self {
             SqlQuery::CreateTable(mut ctq) => {
-                // TODO(malte): only handles primary keys so far!
+                // TODO(malte): only handles primary and unique keys so far!
                 let pkeys: Vec<&ColumnSpecification> = ctq
                     .fields
                     .iter()
@@ -30,6 +30,20 @@ impl KeyDefinitionCoalescing for SqlQuery {
                         }
                     }
                 }
+
+                // columns declared UNIQUE each make up a single-column unique key
+                let ukeys: Vec<TableKey> = ctq
+                    .fields
+                    .iter()
+                    .filter(|cs| cs.constraints.contains(&ColumnConstraint::Unique))
+                    .map(|cs| TableKey::UniqueKey(None, vec![cs.column.clone()]))
+                    .collect();
+                for new_key in ukeys {
+                    let ks = ctq.keys.get_or_insert_with(Vec::new);
+                    if !ks.contains(&new_key) {
+                        ks.push(new_key);
+                    }
+                }
                 SqlQuery::CreateTable(ctq)
             }
             x => x,
@@ -88,4 +102,33 @@ mod tests {
             _ => panic!(),
         }
     }
+
+    #[test]
+    fn it_coalesces_unique_keys() {
+        use nom_sql::CreateTableStatement;
+
+        // CREATE TABLE t (id text, val text UNIQUE)
+        // -->
+        // CREATE TABLE t (id text, val text UNIQUE, UNIQUE KEY (val))
+        let q = CreateTableStatement {
+            table: Table::from("t"),
+            fields: vec![
+                ColumnSpecification::new(Column::from("t.id"), SqlType::Text),
+                ColumnSpecification::with_constraints(
+                    Column::from("t.val"),
+                    SqlType::Text,
+                    vec![ColumnConstraint::Unique],
+                ),
+            ],
+            keys: None,
+        };
+
+        match SqlQuery::CreateTable(q).coalesce_key_definitions() {
+            SqlQuery::CreateTable(ctq) => assert_eq!(
+                ctq.keys,
+                Some(vec![TableKey::UniqueKey(None, vec![Column::from("t.val")])])
+            ),
+            _ => panic!(),
+        }
+    }
 }
@@ -5,6 +5,8 @@ use nom_sql::{
     JoinRightSide, Literal, Operator, Table,
 };
 
+use crate::controller::sql::query_utils::json_extraction;
+use noria::JsonPath;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
@@ -25,11 +27,22 @@ pub struct ArithmeticColumn {
     pub expression: ArithmeticExpression,
 }
 
+/// The part of the JSON documents in `column` at `path`, unquoted if `unquote` is set.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct JsonExtractColumn {
+    pub name: String,
+    pub table: Option<String>,
+    pub column: Column,
+    pub path: JsonPath,
+    pub unquote: bool,
+}
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum OutputColumn {
     Data(Column),
     Arithmetic(ArithmeticColumn),
     Literal(LiteralColumn),
+    JsonExtract(JsonExtractColumn),
 }
 
 impl Ord for OutputColumn {
@@ -49,6 +62,11 @@ impl Ord for OutputColumn {
                 ref name,
                 ref table,
                 ..
+            })
+            | OutputColumn::JsonExtract(JsonExtractColumn {
+                ref name,
+                ref table,
+                ..
             }) => match *other {
                 OutputColumn::Arithmetic(ArithmeticColumn {
                     name: ref other_name,
@@ -64,6 +82,11 @@ impl Ord for OutputColumn {
                     name: ref other_name,
                     table: ref other_table,
                     ..
+                })
+                | OutputColumn::JsonExtract(JsonExtractColumn {
+                    name: ref other_name,
+                    table: ref other_table,
+                    ..
                 }) => {
                     if table.is_some() && other_table.is_some() {
                         match table.cmp(&other_table) {
@@ -96,6 +119,11 @@ impl PartialOrd for OutputColumn {
                 ref name,
                 ref table,
                 ..
+            })
+            | OutputColumn::JsonExtract(JsonExtractColumn {
+                ref name,
+                ref table,
+                ..
             }) => match *other {
                 OutputColumn::Arithmetic(ArithmeticColumn {
                     name: ref other_name,
@@ -111,6 +139,11 @@ impl PartialOrd for OutputColumn {
                     name: ref other_name,
                     table: ref other_table,
                     ..
+                })
+                | OutputColumn::JsonExtract(JsonExtractColumn {
+                    name: ref other_name,
+                    table: ref other_table,
+                    ..
                 }) => {
                     if table.is_some() && other_table.is_some() {
                         match table.cmp(&other_table) {
@@ -423,6 +456,13 @@ fn classify_conditionals(
                                 }
                             }
                         }
+                        // `IN (?)` takes a list of values for a single parameter, which clients
+                        // look up as several keys
+                        ConditionBase::LiteralList(ref ll) if *ll == [Literal::Placeholder] => {
+                            if let ConditionBase::Field(ref lf) = *l {
+                                params.push(lf.clone());
+                            }
+                        }
                         ConditionBase::LiteralList(_) => (),
                         ConditionBase::NestedSelect(_) => unimplemented!(),
                     }
@@ -455,6 +495,44 @@ fn classify_conditionals(
     }
 }
 
+/// Checks that every comparison in `ce` uses an operator that filters and joins can evaluate.
+fn check_operators(ce: &ConditionExpression) -> Result<(), String> {
+    match *ce {
+        ConditionExpression::LogicalOp(ref ct) => {
+            check_operators(ct.left.as_ref())?;
+            check_operators(ct.right.as_ref())
+        }
+        ConditionExpression::ComparisonOp(ref ct) => match ct.operator {
+            Operator::Equal
+            | Operator::NotEqual
+            | Operator::Greater
+            | Operator::GreaterOrEqual
+            | Operator::Less
+            | Operator::LessOrEqual
+            | Operator::In => Ok(()),
+            ref op => Err(format!("unsupported operator {} in predicate", op)),
+        },
+        ConditionExpression::Bracketed(ref inner) | ConditionExpression::NegationOp(ref inner) => {
+            check_operators(inner.as_ref())
+        }
+        ConditionExpression::Base(_) => Ok(()),
+    }
+}
+
+/// Whether `ce` compares a part extracted from a JSON document.
+fn extracts_json(ce: &ConditionExpression) -> bool {
+    match *ce {
+        ConditionExpression::LogicalOp(ref ct) | ConditionExpression::ComparisonOp(ref ct) => {
+            extracts_json(ct.left.as_ref()) || extracts_json(ct.right.as_ref())
+        }
+        ConditionExpression::Bracketed(ref inner) | ConditionExpression::NegationOp(ref inner) => {
+            extracts_json(inner.as_ref())
+        }
+        ConditionExpression::Base(ConditionBase::Field(ref c)) => json_extraction(c).is_some(),
+        ConditionExpression::Base(_) => false,
+    }
+}
+
 pub fn to_query_graph(st: &SelectStatement) -> Result<QueryGraph, String> {
     let mut qg = QueryGraph::new();
 
@@ -488,7 +566,12 @@ pub fn to_query_graph(st: &SelectStatement) -> Result<QueryGraph, String> {
                                 }
                                 Some(t) => {
                                     if *t == rel {
-                                        Some(c.clone())
+                                        // the relation provides the documents that parts are
+                                        // extracted from
+                                        match json_extraction(c) {
+                                            Some((source, _, _)) => Some(source),
+                                            None => Some(c.clone()),
+                                        }
                                     } else {
                                         None
                                     }
@@ -630,6 +713,8 @@ pub fn to_query_graph(st: &SelectStatement) -> Result<QueryGraph, String> {
     }
 
     if let Some(ref cond) = st.where_clause {
+        check_operators(cond)?;
+
         let mut local_predicates = HashMap::new();
         let mut global_predicates = Vec::new();
         let mut query_parameters = Vec::new();
@@ -643,6 +728,20 @@ pub fn to_query_graph(st: &SelectStatement) -> Result<QueryGraph, String> {
             &mut query_parameters,
         );
 
+        // parts of JSON documents are extracted by filters on the documents' relation, so they
+        // can only be compared with constants
+        let extracted = |c: &Column| json_extraction(c).is_some();
+        if query_parameters.iter().any(extracted)
+            || global_predicates.iter().any(extracts_json)
+            || join_predicates
+                .iter()
+                .any(|jp| jp.contained_columns().into_iter().any(extracted))
+        {
+            return Err(String::from(
+                "parts of JSON documents can only be compared with constants",
+            ));
+        }
+
         for (_, ces) in local_predicates.iter_mut() {
             *ces = split_conjunctions(ces.clone());
         }
@@ -766,10 +865,22 @@ pub fn to_query_graph(st: &SelectStatement) -> Result<QueryGraph, String> {
                     expression: a.clone(),
                 }));
             }
-            FieldDefinitionExpression::Col(ref c) => {
-                add_computed_column(&mut qg, c);
-                qg.columns.push(OutputColumn::Data(c.clone()));
-            }
+            FieldDefinitionExpression::Col(ref c) => match json_extraction(c) {
+                Some((column, path, unquote)) => {
+                    qg.columns
+                        .push(OutputColumn::JsonExtract(JsonExtractColumn {
+                            name: c.alias.clone().unwrap_or_else(|| c.name.clone()),
+                            table: None,
+                            column,
+                            path,
+                            unquote,
+                        }));
+                }
+                None => {
+                    add_computed_column(&mut qg, c);
+                    qg.columns.push(OutputColumn::Data(c.clone()));
+                }
+            },
         }
     }
 
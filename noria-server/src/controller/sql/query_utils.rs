@@ -1,4 +1,9 @@
-use nom_sql::{ConditionBase, ConditionExpression, SqlQuery, Table};
+use nom_sql::{
+    ArithmeticBase, Column, ConditionBase, ConditionExpression, FieldDefinitionExpression,
+    FieldValueExpression, JoinConstraint, JoinRightSide, SelectSpecification, SelectStatement,
+    SqlQuery, Table,
+};
+use noria::JsonPath;
 
 pub trait ReferredTables {
     fn referred_tables(&self) -> Vec<Table>;
@@ -53,3 +58,221 @@ impl ReferredTables for ConditionExpression {
         tables
     }
 }
+
+/// Returns the names of all tables and views that `q` reads from, including those that are only
+/// mentioned in joins or subqueries.
+pub fn referred_relations(q: &SqlQuery) -> Vec<String> {
+    let mut relations = Vec::new();
+    match *q {
+        SqlQuery::Select(ref sq) => select_relations(sq, &mut relations),
+        SqlQuery::CompoundSelect(ref csq) => {
+            for &(_, ref sq) in &csq.selects {
+                select_relations(sq, &mut relations);
+            }
+        }
+        SqlQuery::CreateView(ref cvq) => match *cvq.definition {
+            SelectSpecification::Simple(ref sq) => select_relations(sq, &mut relations),
+            SelectSpecification::Compound(ref csq) => {
+                for &(_, ref sq) in &csq.selects {
+                    select_relations(sq, &mut relations);
+                }
+            }
+        },
+        SqlQuery::Insert(ref iq) => relations.push(iq.table.name.clone()),
+        _ => (),
+    }
+    relations.sort();
+    relations.dedup();
+    relations
+}
+
+fn select_relations(sq: &SelectStatement, relations: &mut Vec<String>) {
+    relations.extend(sq.tables.iter().map(|t| t.name.clone()));
+    for jc in &sq.join {
+        join_relations(&jc.right, relations);
+    }
+    if let Some(ref ce) = sq.where_clause {
+        condition_relations(ce, relations);
+    }
+}
+
+fn join_relations(right: &JoinRightSide, relations: &mut Vec<String>) {
+    match *right {
+        JoinRightSide::Table(ref t) => relations.push(t.name.clone()),
+        JoinRightSide::Tables(ref ts) => relations.extend(ts.iter().map(|t| t.name.clone())),
+        JoinRightSide::NestedSelect(ref sq, _) => select_relations(sq, relations),
+        JoinRightSide::NestedJoin(ref jc) => join_relations(&jc.right, relations),
+    }
+}
+
+fn condition_relations(ce: &ConditionExpression, relations: &mut Vec<String>) {
+    match *ce {
+        ConditionExpression::ComparisonOp(ref ct) | ConditionExpression::LogicalOp(ref ct) => {
+            condition_relations(&ct.left, relations);
+            condition_relations(&ct.right, relations);
+        }
+        ConditionExpression::NegationOp(ref ce) | ConditionExpression::Bracketed(ref ce) => {
+            condition_relations(ce, relations)
+        }
+        ConditionExpression::Base(ConditionBase::NestedSelect(ref sq)) => {
+            select_relations(sq, relations)
+        }
+        ConditionExpression::Base(_) => (),
+    }
+}
+
+/// Returns true if `q` uses `column` of `table`, either by name or through a `*` field.
+///
+/// Columns that are not qualified by a table are taken to belong to `table` whenever the query
+/// reads from it, so a column of the same name in another joined table also counts.
+pub fn refers_to_column(q: &SqlQuery, table: &str, column: &str) -> bool {
+    match *q {
+        SqlQuery::Select(ref sq) => select_refers_to(sq, table, column),
+        SqlQuery::CompoundSelect(ref csq) => csq
+            .selects
+            .iter()
+            .any(|&(_, ref sq)| select_refers_to(sq, table, column)),
+        SqlQuery::CreateView(ref cvq) => match *cvq.definition {
+            SelectSpecification::Simple(ref sq) => select_refers_to(sq, table, column),
+            SelectSpecification::Compound(ref csq) => csq
+                .selects
+                .iter()
+                .any(|&(_, ref sq)| select_refers_to(sq, table, column)),
+        },
+        _ => false,
+    }
+}
+
+fn select_refers_to(sq: &SelectStatement, table: &str, column: &str) -> bool {
+    // the names that `table` goes by in this query, which are none if it does not read from it
+    let mut names = Vec::new();
+    {
+        let mut add = |t: &Table| {
+            if t.name == table {
+                names.push(t.name.clone());
+                names.extend(t.alias.clone());
+            }
+        };
+        sq.tables.iter().for_each(&mut add);
+        for jc in &sq.join {
+            match jc.right {
+                JoinRightSide::Table(ref t) => add(t),
+                JoinRightSide::Tables(ref ts) => ts.iter().for_each(&mut add),
+                _ => (),
+            }
+        }
+    }
+
+    let uses = |c: &Column| column_is(c, &names, column);
+    let uses_in = |ce: &ConditionExpression| condition_refers_to(ce, &names, table, column);
+    let in_fields = sq.fields.iter().any(|f| match *f {
+        FieldDefinitionExpression::All => !names.is_empty(),
+        FieldDefinitionExpression::AllInTable(ref t) => names.contains(t),
+        FieldDefinitionExpression::Col(ref c) => uses(c),
+        FieldDefinitionExpression::Value(FieldValueExpression::Arithmetic(ref a)) => {
+            [&a.left, &a.right].iter().any(|b| match **b {
+                ArithmeticBase::Column(ref c) => uses(c),
+                _ => false,
+            })
+        }
+        FieldDefinitionExpression::Value(FieldValueExpression::Literal(_)) => false,
+    });
+    let in_joins = sq.join.iter().any(|jc| {
+        let in_constraint = match jc.constraint {
+            JoinConstraint::On(ref ce) => uses_in(ce),
+            JoinConstraint::Using(ref cols) => cols.iter().any(|c| uses(c)),
+        };
+        in_constraint || join_refers_to(&jc.right, table, column)
+    });
+    let in_where = sq.where_clause.as_ref().map_or(false, |ce| uses_in(ce));
+    let in_group_by = sq.group_by.as_ref().map_or(false, |gbc| {
+        gbc.columns.iter().any(|c| uses(c)) || gbc.having.as_ref().map_or(false, |ce| uses_in(ce))
+    });
+    let in_order = sq
+        .order
+        .as_ref()
+        .map_or(false, |oc| oc.columns.iter().any(|&(ref c, _)| uses(c)));
+
+    in_fields || in_joins || in_where || in_group_by || in_order
+}
+
+fn join_refers_to(right: &JoinRightSide, table: &str, column: &str) -> bool {
+    match *right {
+        JoinRightSide::NestedSelect(ref sq, _) => select_refers_to(sq, table, column),
+        JoinRightSide::NestedJoin(ref jc) => join_refers_to(&jc.right, table, column),
+        _ => false,
+    }
+}
+
+fn condition_refers_to(
+    ce: &ConditionExpression,
+    names: &[String],
+    table: &str,
+    column: &str,
+) -> bool {
+    match *ce {
+        ConditionExpression::ComparisonOp(ref ct) | ConditionExpression::LogicalOp(ref ct) => {
+            condition_refers_to(&ct.left, names, table, column)
+                || condition_refers_to(&ct.right, names, table, column)
+        }
+        ConditionExpression::NegationOp(ref ce) | ConditionExpression::Bracketed(ref ce) => {
+            condition_refers_to(ce, names, table, column)
+        }
+        ConditionExpression::Base(ConditionBase::Field(ref c)) => column_is(c, names, column),
+        ConditionExpression::Base(ConditionBase::NestedSelect(ref sq)) => {
+            select_refers_to(sq, table, column)
+        }
+        ConditionExpression::Base(_) => false,
+    }
+}
+
+// Whether `c` is, or is computed from, the column named `column` of a table that goes by one of
+// `names`.
+fn column_is(c: &Column, names: &[String], column: &str) -> bool {
+    use nom_sql::FunctionExpression::*;
+
+    if let Some(ref f) = c.function {
+        return match **f {
+            Avg(ref fe, _)
+            | Count(ref fe, _)
+            | Sum(ref fe, _)
+            | Min(ref fe)
+            | Max(ref fe)
+            | GroupConcat(ref fe, _) => column_is(fe, names, column),
+            _ => false,
+        };
+    }
+    let name = match json_extraction(c) {
+        Some((source, _, _)) => source.name,
+        None => c.name.clone(),
+    };
+    !names.is_empty() && name == column && c.table.as_ref().map_or(true, |t| names.contains(t))
+}
+
+/// The name of the column that extracts the part at `path` of the JSON documents in `column`,
+/// unquoted if `unquote` is set, like `doc->>'$.a'`.
+pub fn json_extraction_name(column: &str, path: &JsonPath, unquote: bool) -> String {
+    let arrow = if unquote { "->>" } else { "->" };
+    format!("{}{}'{}'", column, arrow, path)
+}
+
+/// If `c` extracts part of a JSON document (see `json_extraction_name`), returns the column that
+/// holds the document, the path of the part, and whether it is unquoted.
+pub fn json_extraction(c: &Column) -> Option<(Column, JsonPath, bool)> {
+    let arrow = c.name.find("->")?;
+    let rest = &c.name[arrow + "->".len()..];
+    let unquote = rest.starts_with('>');
+    let quoted = if unquote { &rest[1..] } else { rest };
+    if quoted.len() < 2 || !quoted.starts_with('\'') || !quoted.ends_with('\'') {
+        return None;
+    }
+    let path = quoted[1..quoted.len() - 1].parse().ok()?;
+
+    let source = Column {
+        name: c.name[..arrow].to_owned(),
+        alias: None,
+        table: c.table.clone(),
+        function: None,
+    };
+    Some((source, path, unquote))
+}
@@ -1,7 +1,9 @@
+use crate::controller::sql::mir::star_joins;
 use crate::controller::sql::query_graph::{JoinRef, QueryGraph, QueryGraphEdge};
 use crate::controller::sql::reuse::helpers::predicate_implication::predicate_is_equivalent;
 use nom_sql::ConditionTree;
 use std::collections::HashSet;
+use std::slice;
 use std::vec::Vec;
 
 use std::mem;
@@ -126,11 +128,29 @@ fn from_join_ref<'a>(jref: &JoinRef, qg: &'a QueryGraph) -> &'a ConditionTree {
     }
 }
 
+// Finds the join in `qg` whose predicate is equivalent to that of `jref` in `eqg`.
+fn equivalent_join<'a>(
+    qg: &'a QueryGraph,
+    eqg: &QueryGraph,
+    jref: &JoinRef,
+) -> Option<&'a JoinRef> {
+    let ejp = from_join_ref(jref, eqg);
+    qg.join_order
+        .iter()
+        .find(|new_jref| predicate_is_equivalent(from_join_ref(new_jref, qg), ejp))
+}
+
 pub fn reorder_joins(qg: &mut QueryGraph, reuse_candidates: &Vec<(ReuseType, (u64, &QueryGraph))>) {
+    // The joins of a star are planned as a single multi-way join node rather than a chain of
+    // binary joins (see `sql::mir::join`). So a star can only be shared as a whole, with another
+    // query that joins the same predicates as a star too.
+    let stars = star_joins(qg);
+
     let mut join_chains = Vec::new();
     // For each reuse candidate, let's find the common join
     // chains it has with the new query graph.
     for &(_, (_, eqg)) in reuse_candidates {
+        let existing_stars = star_joins(eqg);
         let mut existing_join_chains = Vec::new();
         let mut shared_join_chains = Vec::new();
 
@@ -147,24 +167,43 @@ pub fn reorder_joins(qg: &mut QueryGraph, reuse_candidates: &Vec<(ReuseType, (u6
                 continue;
             }
 
-            let ejp = from_join_ref(&existing_jref, eqg);
-
-            // look in the new query graph for an equivalent join predicate.
-            let mut found = false;
-            for new_jref in qg.join_order.iter() {
-                let njp = from_join_ref(&new_jref, qg);
-                // if we find an equivalent join, add it to the new query's join chains
-                if predicate_is_equivalent(njp, ejp) {
-                    extend_chains(&mut shared_join_chains, new_jref);
-                    found = true;
-                    break;
+            // the joins of a star are all looked at along with its first one
+            let existing_star = existing_stars.iter().find(|s| s.contains(existing_jref));
+            let existing_jrefs = match existing_star {
+                Some(star) if star[0] != *existing_jref => continue,
+                Some(star) => &star[..],
+                None => slice::from_ref(existing_jref),
+            };
+
+            // look in the new query graph for equivalent join predicates, which must also be
+            // planned alike: either all as the same star, or as a single binary join.
+            let planned_alike = |new_jrefs: &Vec<&JoinRef>| {
+                let star = stars.iter().find(|s| s.contains(new_jrefs[0]));
+                match (star, existing_star) {
+                    (Some(star), Some(_)) => {
+                        star.len() == new_jrefs.len()
+                            && new_jrefs.iter().all(|&jref| star.contains(jref))
+                    }
+                    (None, None) => true,
+                    _ => false,
                 }
-            }
-            // if no equivalent predicate was found, the chain in
-            // the existing query graph has diverged from the chain
-            // in the new query graph, so we stop exploring it
-            if !found {
-                existing_chain.stopped = true;
+            };
+            let new_jrefs = existing_jrefs
+                .iter()
+                .map(|jref| equivalent_join(qg, eqg, jref))
+                .collect::<Option<Vec<_>>>()
+                .filter(planned_alike);
+            match new_jrefs {
+                // if we find equivalent joins, add them to the new query's join chains, in the
+                // order of the existing query so that a star joins its relations in the same order
+                Some(new_jrefs) => {
+                    for new_jref in new_jrefs {
+                        extend_chains(&mut shared_join_chains, new_jref);
+                    }
+                }
+                // otherwise, the chain in the existing query graph has diverged from the chain
+                // in the new query graph, so we stop exploring it
+                None => existing_chain.stopped = true,
             }
         }
 
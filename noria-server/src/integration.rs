@@ -6,13 +6,16 @@ use dataflow::ops::grouped::aggregate::Aggregation;
 use dataflow::ops::identity::Identity;
 use dataflow::ops::join::JoinSource::*;
 use dataflow::ops::join::{Join, JoinSource, JoinType};
+use dataflow::ops::latest::Latest;
 use dataflow::ops::project::Project;
 use dataflow::ops::union::Union;
 use dataflow::{DurabilityMode, PersistenceParameters};
+use nom_sql::{ColumnConstraint, Literal, SqlType};
 use noria::consensus::LocalAuthority;
-use noria::DataType;
+use noria::error::{ConstraintViolation, TableError, ViewError};
+use noria::{ColumnDescription, DataType, Description, WarmProgress};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
 use std::{env, thread};
@@ -509,6 +512,1029 @@ fn it_works_with_sql_recipe() {
     assert_eq!(result[0][0], 2.into());
 }
 
+#[test]
+fn it_assigns_auto_increment_keys() {
+    let mut g = build_local("it_assigns_auto_increment_keys");
+    let sql = "
+        CREATE TABLE Article (id int AUTO_INCREMENT, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut mutator = g.table("Article").unwrap();
+    let mut getter = g.view("ArticleById").unwrap();
+
+    let a = mutator.insert(vec![DataType::None, "a".into()]).unwrap();
+    let b = mutator.insert(vec![DataType::None, "b".into()]).unwrap();
+    let (a, b) = (a.unwrap(), b.unwrap());
+    assert_ne!(a, b);
+    // explicitly provided keys are kept
+    assert_eq!(mutator.insert(vec![10.into(), "c".into()]).unwrap(), None);
+    // and inserts without a key are spread across the shards of the table
+    let ids: Vec<_> = (0..4)
+        .map(|_| mutator.insert(vec![DataType::None, "d".into()]).unwrap())
+        .map(Option::unwrap)
+        .collect();
+    let shards: HashSet<_> = ids.iter().map(|id| noria::shard_by(id, 2)).collect();
+    assert_eq!(shards.len(), 2);
+    sleep();
+
+    assert_eq!(
+        getter.lookup(&[a.clone()], true).unwrap(),
+        vec![vec![a, "a".into()]]
+    );
+    assert_eq!(
+        getter.lookup(&[b.clone()], true).unwrap(),
+        vec![vec![b, "b".into()]]
+    );
+    assert_eq!(
+        getter.lookup(&[10.into()], true).unwrap(),
+        vec![vec![10.into(), "c".into()]]
+    );
+}
+
+#[test]
+fn it_enforces_view_memory_budgets() {
+    let mut g = build_local_unsharded("it_enforces_view_memory_budgets");
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut mutator = g.table("Article").unwrap();
+    let mut getter = g.view("ArticleById").unwrap();
+
+    for i in 0..100 {
+        mutator
+            .insert(vec![i.into(), format!("Article #{}", i).into()])
+            .unwrap();
+    }
+    sleep();
+    for i in 0..100 {
+        assert_eq!(getter.lookup(&[i.into()], true).unwrap().len(), 1);
+    }
+
+    let stats = g.statistics().unwrap();
+    let before = &stats.views["ArticleById"];
+    assert!(before.mem_size > 0);
+    assert_eq!(before.budget, None);
+
+    // the budget is enforced as soon as the view's domain hears of it
+    g.set_view_budget("ArticleById", Some(1)).unwrap();
+    sleep();
+
+    let stats = g.statistics().unwrap();
+    let after = &stats.views["ArticleById"];
+    assert!(after.mem_size < before.mem_size);
+    assert_eq!(after.budget, Some(1));
+
+    // evicted keys are simply replayed again
+    assert_eq!(
+        getter.lookup(&[0.into()], true).unwrap(),
+        vec![vec![0.into(), "Article #0".into()]]
+    );
+
+    assert!(g.set_view_budget("NoSuchView", Some(1)).is_err());
+    g.set_view_budget("ArticleById", None).unwrap();
+    assert_eq!(g.statistics().unwrap().views["ArticleById"].budget, None);
+}
+
+#[test]
+fn it_rolls_back_recipes() {
+    let mut g = build_local("it_rolls_back_recipes");
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;",
+    )
+    .unwrap();
+    g.extend_recipe("QUERY ArticleByTitle: SELECT id, title FROM Article WHERE title = ?;")
+        .unwrap();
+
+    let history = g.recipe_history().unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(!history[0].extension);
+    assert!(history[1].extension);
+    assert!(history[0].version < history[1].version);
+    assert!(history[1].sql.contains("ArticleByTitle"));
+
+    let mut mutator = g.table("Article").unwrap();
+    mutator.insert(vec![1.into(), "Article #1".into()]).unwrap();
+    sleep();
+
+    // going back to the first version removes the query added since
+    let ar = g.rollback_recipe(history[0].version).unwrap();
+    assert_eq!(ar.expressions_removed, 1);
+    assert_eq!(ar.expressions_added, 0);
+    assert!(g.view("ArticleByTitle").is_err());
+    let mut getter = g.view("ArticleById").unwrap();
+    assert_eq!(
+        getter.lookup(&[1.into()], true).unwrap(),
+        vec![vec![1.into(), "Article #1".into()]]
+    );
+
+    // the rollback is a version of its own
+    let history = g.recipe_history().unwrap();
+    assert_eq!(history.len(), 3);
+    assert!(!history[2].extension);
+    assert!(history[2].version > history[1].version);
+
+    assert!(g.rollback_recipe(history[2].version + 1).is_err());
+}
+
+#[test]
+fn it_plans_recipes_without_applying_them() {
+    let mut g = build_local("it_plans_recipes_without_applying_them");
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;",
+    )
+    .unwrap();
+    let mut mutator = g.table("Article").unwrap();
+    mutator.insert(vec![1.into(), "Article #1".into()]).unwrap();
+    mutator.insert(vec![2.into(), "Article #2".into()]).unwrap();
+    sleep();
+
+    let addition = "QUERY ArticleByTitle: SELECT id, title FROM Article WHERE title = ?;";
+    let outputs = g.outputs().unwrap();
+    let plan = g.plan_recipe(addition, true).unwrap();
+    assert_eq!(plan.activation.expressions_added, 1);
+    assert_eq!(plan.activation.expressions_removed, 0);
+    assert!(plan.activation.new_nodes.contains_key("ArticleByTitle"));
+    let new_nodes: Vec<_> = plan.new_nodes.iter().map(|(n, _)| &n[..]).collect();
+    assert!(new_nodes.contains(&"ArticleByTitle"));
+    assert!(!plan.reused_nodes.is_empty());
+    assert_eq!(plan.replay_rows, 2);
+
+    // planning left the graph and the recipe as they were
+    assert_eq!(g.outputs().unwrap(), outputs);
+    assert!(g.view("ArticleByTitle").is_err());
+    assert_eq!(g.recipe_history().unwrap().len(), 1);
+
+    // replacing the recipe would remove the existing query
+    let plan = g
+        .plan_recipe(
+            "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));",
+            false,
+        )
+        .unwrap();
+    assert_eq!(plan.activation.expressions_removed, 1);
+    assert!(plan.new_nodes.is_empty());
+
+    // and the planned change can still be applied for real
+    let ar = g.extend_recipe(addition).unwrap();
+    assert_eq!(ar.expressions_added, 1);
+    let mut getter = g.view("ArticleByTitle").unwrap();
+    assert_eq!(
+        getter.lookup(&["Article #2".into()], true).unwrap(),
+        vec![vec![2.into(), "Article #2".into()]]
+    );
+}
+
+#[test]
+fn it_drops_tables() {
+    let mut g = build_local("it_drops_tables");
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+         CREATE TABLE Vote (aid int, uid int);
+         QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+         QUERY VoteCount: SELECT aid, COUNT(uid) AS votes FROM Vote WHERE aid = ? GROUP BY aid;",
+    )
+    .unwrap();
+    let mut article = g.table("Article").unwrap();
+    article.insert(vec![1.into(), "Article #1".into()]).unwrap();
+    sleep();
+
+    // a query still reads from the table
+    assert!(g.extend_recipe("DROP TABLE Article;").is_err());
+    assert!(g.extend_recipe("DROP TABLE Comment;").is_err());
+    assert!(g.table("Article").is_ok());
+    assert!(g.view("ArticleById").is_ok());
+
+    g.extend_recipe("DROP TABLE Article CASCADE;").unwrap();
+    assert!(g.table("Article").is_err());
+    assert!(g.view("ArticleById").is_err());
+    assert!(!g.inputs().unwrap().contains_key("Article"));
+    assert!(!g.outputs().unwrap().contains_key("ArticleById"));
+
+    // the other table and its queries keep working
+    let mut vote = g.table("Vote").unwrap();
+    let mut getter = g.view("VoteCount").unwrap();
+    vote.insert(vec![1.into(), 1.into()]).unwrap();
+    vote.insert(vec![1.into(), 2.into()]).unwrap();
+    sleep();
+    assert_eq!(
+        getter.lookup(&[1.into()], true).unwrap(),
+        vec![vec![1.into(), 2.into()]]
+    );
+
+    // and the dropped table can be created again from scratch
+    g.extend_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;",
+    )
+    .unwrap();
+    let mut getter = g.view("ArticleById").unwrap();
+    assert!(getter.lookup(&[1.into()], true).unwrap().is_empty());
+}
+
+#[test]
+fn it_alters_tables() {
+    let mut g = build_local("it_alters_tables");
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), author int, PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;",
+    )
+    .unwrap();
+    let mut article = g.table("Article").unwrap();
+    article
+        .insert(vec![1.into(), "Article #1".into(), 10.into()])
+        .unwrap();
+    sleep();
+    let mut getter = g.view("ArticleById").unwrap();
+    let inputs = g.inputs().unwrap();
+
+    g.extend_recipe("ALTER TABLE Article ADD COLUMN score int DEFAULT 0;")
+        .unwrap();
+    g.extend_recipe("ALTER TABLE Article DROP COLUMN author;")
+        .unwrap();
+    let res = g.extend_recipe("ALTER TABLE Article DROP COLUMN title;");
+    assert!(res.is_err(), "dropped a column that ArticleById uses");
+    g.extend_recipe("ALTER TABLE Article RENAME COLUMN title TO headline;")
+        .unwrap();
+
+    // key columns, and columns that are already gone, cannot be dropped
+    let res = g.extend_recipe("ALTER TABLE Article DROP COLUMN id;");
+    assert!(res.is_err());
+    let res = g.extend_recipe("ALTER TABLE Article DROP COLUMN author;");
+    assert!(res.is_err());
+
+    // the existing base was adapted rather than replaced
+    assert_eq!(g.inputs().unwrap(), inputs);
+    let mut article = g.table("Article").unwrap();
+    assert_eq!(article.columns(), &["id", "headline", "score"]);
+    article
+        .insert(vec![2.into(), "Article #2".into(), 5.into()])
+        .unwrap();
+    sleep();
+
+    // the existing query keeps running
+    assert_eq!(
+        getter.lookup(&[1.into()], true).unwrap(),
+        vec![vec![1.into(), "Article #1".into()]]
+    );
+    assert_eq!(
+        getter.lookup(&[2.into()], true).unwrap(),
+        vec![vec![2.into(), "Article #2".into()]]
+    );
+
+    // and new queries see the new schema, with the default filled in for old rows
+    g.extend_recipe("QUERY ArticleScore: SELECT id, headline, score FROM Article WHERE id = ?;")
+        .unwrap();
+    let mut getter = g.view("ArticleScore").unwrap();
+    assert_eq!(
+        getter.lookup(&[1.into()], true).unwrap(),
+        vec![vec![1.into(), "Article #1".into(), 0.into()]]
+    );
+    assert_eq!(
+        getter.lookup(&[2.into()], true).unwrap(),
+        vec![vec![2.into(), "Article #2".into(), 5.into()]]
+    );
+}
+
+#[test]
+fn it_describes_tables_and_views() {
+    let mut g = build_local_unsharded("it_describes_tables_and_views");
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255) NOT NULL, author int, PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+         QUERY ArticleCount: SELECT author, COUNT(id) AS n FROM Article \
+                             WHERE author = ? GROUP BY author;",
+    )
+    .unwrap();
+
+    let column = |name: &str, sql_type, nullable| ColumnDescription {
+        name: name.to_owned(),
+        sql_type: Some(sql_type),
+        nullable,
+    };
+
+    match g.describe("Article").unwrap() {
+        Description::Table(table) => {
+            assert_eq!(
+                table.columns,
+                vec![
+                    column("id", SqlType::Int(32), false),
+                    column("title", SqlType::Varchar(255), false),
+                    column("author", SqlType::Int(32), true),
+                ]
+            );
+            assert_eq!(table.primary_key, vec!["id".to_owned()]);
+            assert!(table.unique_keys.is_empty());
+        }
+        d => panic!("expected a table, got {:?}", d),
+    }
+
+    match g.describe("ArticleById").unwrap() {
+        Description::View(view) => {
+            assert_eq!(
+                view.columns,
+                vec![
+                    column("id", SqlType::Int(32), true),
+                    column("title", SqlType::Varchar(255), true),
+                ]
+            );
+            assert_eq!(view.key_columns, vec![column("id", SqlType::Int(32), true)]);
+            assert!(view.partial);
+            assert!(!view.sharded);
+            assert_eq!(view.shards, 1);
+        }
+        d => panic!("expected a view, got {:?}", d),
+    }
+
+    match g.describe("ArticleCount").unwrap() {
+        Description::View(view) => {
+            assert_eq!(
+                view.columns,
+                vec![
+                    column("author", SqlType::Int(32), true),
+                    column("n", SqlType::Bigint(64), true),
+                ]
+            );
+            assert_eq!(
+                view.key_columns,
+                vec![column("author", SqlType::Int(32), true)]
+            );
+        }
+        d => panic!("expected a view, got {:?}", d),
+    }
+
+    assert!(g.describe("NoSuchView").is_err());
+}
+
+#[test]
+fn it_looks_up_in_parameters() {
+    let mut g = build_local_unsharded("it_looks_up_in_parameters");
+    g.install_recipe(
+        "CREATE TABLE Article (id int, author int, score int, PRIMARY KEY(id));
+         QUERY ArticlesById: SELECT id, author, score FROM Article WHERE id IN (?);
+         QUERY TopArticles: SELECT id, author, score FROM Article \
+                            WHERE author IN (?) ORDER BY score DESC LIMIT 2;",
+    )
+    .unwrap();
+    let mut article = g.table("Article").unwrap();
+    for &(id, author, score) in &[(1, 10, 5), (2, 10, 7), (3, 20, 6), (4, 20, 1), (5, 30, 9)] {
+        article
+            .insert(vec![id.into(), author.into(), score.into()])
+            .unwrap();
+    }
+    sleep();
+
+    // each value is looked up as a separate key, and the results are merged
+    let mut getter = g.view("ArticlesById").unwrap();
+    let mut res = getter
+        .lookup_in(&[vec![1.into(), 3.into(), 6.into(), 1.into()]], true)
+        .unwrap();
+    res.sort();
+    assert_eq!(
+        res,
+        vec![
+            vec![1.into(), 10.into(), 5.into()],
+            vec![3.into(), 20.into(), 6.into()],
+        ]
+    );
+
+    // the ORDER BY and LIMIT apply across all the values, not just to each one
+    let mut getter = g.view("TopArticles").unwrap();
+    assert_eq!(
+        getter
+            .lookup_in(&[vec![10.into(), 20.into()]], true)
+            .unwrap(),
+        vec![
+            vec![2.into(), 10.into(), 7.into()],
+            vec![3.into(), 20.into(), 6.into()],
+        ]
+    );
+}
+
+#[test]
+fn it_looks_up_with_limit() {
+    let mut g = build_local("it_looks_up_with_limit");
+    g.install_recipe(
+        "CREATE TABLE Article (id int, author int, score int, PRIMARY KEY(id));
+         QUERY TopArticles LIMIT 3: SELECT id, author, score FROM Article \
+                                    WHERE author = ? ORDER BY score DESC LIMIT ?;
+         QUERY ArticlesByAuthor: SELECT id, author, score FROM Article WHERE author = ?;",
+    )
+    .unwrap();
+    let mut article = g.table("Article").unwrap();
+    for &(id, author, score) in &[(1, 10, 5), (2, 10, 7), (3, 10, 6), (4, 10, 1), (5, 20, 9)] {
+        article
+            .insert(vec![id.into(), author.into(), score.into()])
+            .unwrap();
+    }
+    sleep();
+
+    let mut getter = g.view("TopArticles").unwrap();
+    assert_eq!(
+        getter.lookup_with_limit(&[10.into()], 1, true).unwrap(),
+        vec![vec![2.into(), 10.into(), 7.into()]]
+    );
+    assert_eq!(
+        getter.lookup_with_limit(&[10.into()], 2, true).unwrap(),
+        vec![
+            vec![2.into(), 10.into(), 7.into()],
+            vec![3.into(), 10.into(), 6.into()],
+        ]
+    );
+
+    // the view only keeps up to its maximum limit of results
+    let res = getter.lookup_with_limit(&[10.into()], 10, true).unwrap();
+    assert_eq!(res.len(), 3);
+
+    // views without a LIMIT cannot be looked up with one
+    let mut getter = g.view("ArticlesByAuthor").unwrap();
+    match getter.lookup_with_limit(&[10.into()], 1, true) {
+        Err(ViewError::NoLimit) => {}
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn it_adds_view_indexes() {
+    let mut g = build_local("it_adds_view_indexes");
+    g.install_recipe(
+        "CREATE TABLE Article (id int, author int, title varchar(255), PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, author, title FROM Article WHERE id = ?;",
+    )
+    .unwrap();
+    let mut article = g.table("Article").unwrap();
+    article
+        .insert(vec![1.into(), 10.into(), "Article #1".into()])
+        .unwrap();
+    article
+        .insert(vec![2.into(), 10.into(), "Article #2".into()])
+        .unwrap();
+    article
+        .insert(vec![3.into(), 20.into(), "Article #3".into()])
+        .unwrap();
+    sleep();
+
+    let res = g.add_view_index("ArticleById", "ArticlesByAuthor", &["nope"]);
+    assert!(res.is_err());
+    g.add_view_index("ArticleById", "ArticlesByAuthor", &["author"])
+        .unwrap();
+    let res = g.add_view_index("ArticleById", "ArticlesByAuthor", &["title"]);
+    assert!(res.is_err());
+
+    // the index is looked up by its own key, and the view by its original one
+    let mut getter = g.view("ArticlesByAuthor").unwrap();
+    assert_eq!(getter.columns(), &["id", "author", "title"]);
+    let mut res = getter.lookup(&[10.into()], true).unwrap();
+    res.sort();
+    assert_eq!(
+        res,
+        vec![
+            vec![1.into(), 10.into(), "Article #1".into()],
+            vec![2.into(), 10.into(), "Article #2".into()],
+        ]
+    );
+    let mut getter = g.view("ArticleById").unwrap();
+    assert_eq!(
+        getter.lookup(&[3.into()], true).unwrap(),
+        vec![vec![3.into(), 20.into(), "Article #3".into()]]
+    );
+
+    // removing the view also removes its indexes
+    g.install_recipe(
+        "CREATE TABLE Article (id int, author int, title varchar(255), PRIMARY KEY(id));",
+    )
+    .unwrap();
+    assert!(g.view("ArticlesByAuthor").is_err());
+}
+
+#[test]
+fn it_warms_partial_views() {
+    let mut g = build_local_unsharded("it_warms_partial_views");
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut mutator = g.table("Article").unwrap();
+    let mut getter = g.view("ArticleById").unwrap();
+
+    for i in 0..10 {
+        mutator
+            .insert(vec![i.into(), format!("Article #{}", i).into()])
+            .unwrap();
+    }
+    sleep();
+
+    let keys: Vec<Vec<DataType>> = (0..5).map(|i| vec![i.into()]).collect();
+    assert_eq!(
+        getter.warm(keys.clone()).unwrap(),
+        WarmProgress {
+            present: 0,
+            pending: 5
+        }
+    );
+    sleep();
+    assert_eq!(
+        getter.warm(keys).unwrap(),
+        WarmProgress {
+            present: 5,
+            pending: 0
+        }
+    );
+    // warmed keys are hits even for non-blocking lookups
+    assert_eq!(
+        getter.lookup(&[0.into()], false).unwrap(),
+        vec![vec![0.into(), "Article #0".into()]]
+    );
+
+    // a snapshot of hot keys can be used to warm the view through the controller
+    let mut keys = g.view_hot_keys("ArticleById", 3).unwrap();
+    assert_eq!(keys.len(), 3);
+    keys.push(vec![9.into()]);
+    assert_eq!(
+        g.warm_view("ArticleById", keys.clone()).unwrap(),
+        WarmProgress {
+            present: 3,
+            pending: 1
+        }
+    );
+    sleep();
+    assert!(g.warm_view("ArticleById", keys).unwrap().is_done());
+
+    assert!(g
+        .warm_view("ArticleById", vec![vec![1.into(), 2.into()]])
+        .is_err());
+    assert!(g.warm_view("NoSuchView", vec![vec![1.into()]]).is_err());
+}
+
+#[test]
+fn it_rejects_compound_keys_for_sharded_views() {
+    let mut g = build_local("it_rejects_compound_keys_for_sharded_views");
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut getter = g.view("ArticleById").unwrap();
+
+    let keys = vec![vec![1.into(), 2.into()]];
+    match getter.warm(keys.clone()) {
+        Err(ViewError::CompoundKey) => {}
+        r => panic!("unexpected result: {:?}", r),
+    }
+    match getter.multi_lookup(keys, true) {
+        Err(ViewError::CompoundKey) => {}
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn it_expires_reader_keys_after_ttl() {
+    let mut g = build_local_unsharded("it_expires_reader_keys_after_ttl");
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById TTL 1s: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut mutator = g.table("Article").unwrap();
+    let mut getter = g.view("ArticleById").unwrap();
+
+    mutator.insert(vec![1.into(), "Article #1".into()]).unwrap();
+    sleep();
+    assert_eq!(getter.lookup(&[1.into()], true).unwrap().len(), 1);
+    assert!(g.statistics().unwrap().views["ArticleById"].mem_size > 0);
+
+    // once the key has gone unread for the TTL, the reader no longer holds it
+    let mut waited = Duration::from_millis(0);
+    while g.statistics().unwrap().views["ArticleById"].mem_size > 0 {
+        assert!(waited < Duration::from_secs(30), "key never expired");
+        thread::sleep(get_settle_time());
+        waited += get_settle_time();
+    }
+
+    // but it is simply replayed again on the next read
+    mutator.insert(vec![2.into(), "Article #2".into()]).unwrap();
+    sleep();
+    assert_eq!(
+        getter.lookup(&[1.into()], true).unwrap(),
+        vec![vec![1.into(), "Article #1".into()]]
+    );
+}
+
+#[test]
+fn it_spills_full_state_to_disk() {
+    let mut builder = ControllerBuilder::default();
+    builder.set_sharding(None);
+    builder.set_persistence(get_persistence_params("it_spills_full_state_to_disk"));
+    builder.disable_partial();
+    // nothing fits, so all the aggregation's state lives on disk
+    builder.set_spill_limit(1);
+    let mut g = builder.build_local().unwrap();
+
+    let sql = "
+        CREATE TABLE Vote (aid int, uid int);
+        QUERY VoteCount: SELECT aid, COUNT(uid) AS votes FROM Vote WHERE aid = ? GROUP BY aid;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut mutator = g.table("Vote").unwrap();
+    let mut getter = g.view("VoteCount").unwrap();
+
+    for aid in 1..4 {
+        for uid in 0..aid {
+            mutator.insert(vec![aid.into(), uid.into()]).unwrap();
+        }
+    }
+    sleep();
+
+    for aid in 1..4 {
+        assert_eq!(
+            getter.lookup(&[aid.into()], true).unwrap(),
+            vec![vec![aid.into(), aid.into()]]
+        );
+    }
+
+    // updates to spilled aggregates are applied like any other
+    mutator.insert(vec![1.into(), 10.into()]).unwrap();
+    mutator.insert(vec![2.into(), 10.into()]).unwrap();
+    sleep();
+    assert_eq!(
+        getter.lookup(&[1.into()], true).unwrap(),
+        vec![vec![1.into(), 2.into()]]
+    );
+    assert_eq!(
+        getter.lookup(&[2.into()], true).unwrap(),
+        vec![vec![2.into(), 3.into()]]
+    );
+}
+
+#[test]
+fn it_keeps_partial_state_on_disk() {
+    let mut builder = ControllerBuilder::default();
+    builder.set_sharding(None);
+    builder.set_persistence(get_persistence_params("it_keeps_partial_state_on_disk"));
+    builder.persist_partial_state();
+    let mut g = builder.build_local().unwrap();
+
+    let sql = "
+        CREATE TABLE Vote (aid int, uid int);
+        QUERY VoteCount: SELECT aid, COUNT(uid) AS votes FROM Vote WHERE aid = ? GROUP BY aid;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut mutator = g.table("Vote").unwrap();
+    let mut getter = g.view("VoteCount").unwrap();
+
+    for aid in 1..4 {
+        for uid in 0..aid {
+            mutator.insert(vec![aid.into(), uid.into()]).unwrap();
+        }
+    }
+    sleep();
+
+    // the aggregation's state is filled in by replays, like it would be in memory
+    for aid in 1..4 {
+        assert_eq!(
+            getter.lookup(&[aid.into()], true).unwrap(),
+            vec![vec![aid.into(), aid.into()]]
+        );
+    }
+
+    mutator.insert(vec![1.into(), 10.into()]).unwrap();
+    sleep();
+    assert_eq!(
+        getter.lookup(&[1.into()], true).unwrap(),
+        vec![vec![1.into(), 2.into()]]
+    );
+}
+
+#[test]
+fn it_fills_defaults_and_enforces_not_null() {
+    let mut g = build_local("it_fills_defaults_and_enforces_not_null");
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255) NOT NULL, votes int DEFAULT 0, PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title, votes FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut mutator = g.table("Article").unwrap();
+    let mut getter = g.view("ArticleById").unwrap();
+
+    let votes = &mutator.schema().unwrap().fields[2];
+    assert!(votes
+        .constraints
+        .contains(&ColumnConstraint::DefaultValue(Literal::Integer(0))));
+
+    mutator
+        .insert_named(&[("id", 1.into()), ("title", "a".into())])
+        .unwrap();
+    match mutator.insert_named(&[("id", 2.into())]) {
+        Err(TableError::ConstraintViolation(ConstraintViolation::NotNull(ref c))) => {
+            assert_eq!(c, "title")
+        }
+        r => panic!("expected NOT NULL violation, got {:?}", r),
+    }
+    assert!(mutator.insert_named(&[("nope", 2.into())]).is_err());
+    sleep();
+
+    assert_eq!(
+        getter.lookup(&[1.into()], true).unwrap(),
+        vec![vec![1.into(), "a".into(), 0.into()]]
+    );
+    assert!(getter.lookup(&[2.into()], true).unwrap().is_empty());
+}
+
+#[test]
+fn it_stores_values_as_column_types() {
+    let mut g = build_local("it_stores_values_as_column_types");
+    let sql = "
+        CREATE TABLE Item (id int, price decimal(10, 2), weight double, added date, data blob, PRIMARY KEY(id));
+        QUERY ItemById: SELECT id, price, weight, added, data FROM Item WHERE id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut mutator = g.table("Item").unwrap();
+    let mut getter = g.view("ItemById").unwrap();
+
+    mutator
+        .insert(vec![
+            1.into(),
+            (2.5).into(),
+            3.into(),
+            "2018-10-03".into(),
+            "xyz".into(),
+        ])
+        .unwrap();
+    sleep();
+
+    let rows = getter.lookup(&[1.into()], true).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0][1], DataType::Decimal(250, 2));
+    assert_eq!(rows[0][2], DataType::Float(3.0));
+    match rows[0][3] {
+        DataType::Date(..) => assert_eq!(rows[0][3].to_string(), "2018-10-03"),
+        ref v => panic!("expected a date, got {:?}", v),
+    }
+    assert_eq!(rows[0][4], b"xyz".to_vec().into());
+}
+
+#[test]
+fn it_applies_null_semantics() {
+    let mut g = build_local("it_applies_null_semantics");
+    let sql = "
+        CREATE TABLE Post (id int, author int, editor int, PRIMARY KEY(id));
+        QUERY Edited: SELECT Post.author, COUNT(Post.editor) AS n FROM Post WHERE Post.author = ? GROUP BY Post.author;
+        QUERY All: SELECT Post.author, COUNT(*) AS n FROM Post WHERE Post.author = ? GROUP BY Post.author;
+        QUERY Unedited: SELECT Post.id FROM Post WHERE Post.editor IS NULL AND Post.author = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut mutator = g.table("Post").unwrap();
+    let mut edited = g.view("Edited").unwrap();
+    let mut all = g.view("All").unwrap();
+    let mut unedited = g.view("Unedited").unwrap();
+
+    mutator
+        .insert(vec![1.into(), 1.into(), DataType::None])
+        .unwrap();
+    mutator.insert(vec![2.into(), 1.into(), 2.into()]).unwrap();
+    mutator
+        .insert(vec![3.into(), 1.into(), DataType::None])
+        .unwrap();
+    sleep();
+
+    let rows = edited.lookup(&[1.into()], true).unwrap();
+    assert_eq!(rows, vec![vec![1.into(), 1.into()]]);
+    let rows = all.lookup(&[1.into()], true).unwrap();
+    assert_eq!(rows, vec![vec![1.into(), 3.into()]]);
+    let mut rows = unedited.lookup(&[1.into()], true).unwrap();
+    rows.sort();
+    assert_eq!(rows, vec![vec![1.into()], vec![3.into()]]);
+}
+
+#[test]
+fn it_compares_text_under_column_collations() {
+    let mut g = build_local("it_compares_text_under_column_collations");
+    let sql = "
+        CREATE TABLE User (id int, name varchar(255) COLLATE utf8mb4_general_ci, PRIMARY KEY(id));
+        CREATE TABLE Post (id int, author varchar(255) COLLATE utf8mb4_general_ci, PRIMARY KEY(id));
+        QUERY UserByName: SELECT User.id FROM User WHERE User.name = ?;
+        QUERY Bobs: SELECT User.id FROM User WHERE User.name = 'BOB';
+        QUERY PostsByUser: SELECT Post.id FROM User JOIN Post ON (User.name = Post.author) WHERE User.id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut users = g.table("User").unwrap();
+    let mut posts = g.table("Post").unwrap();
+    let mut by_name = g.view("UserByName").unwrap();
+    let mut bobs = g.view("Bobs").unwrap();
+    let mut posts_by_user = g.view("PostsByUser").unwrap();
+
+    users.insert(vec![1.into(), "Bob".into()]).unwrap();
+    users.insert(vec![2.into(), "Alice".into()]).unwrap();
+    posts.insert(vec![1.into(), "bob".into()]).unwrap();
+    sleep();
+
+    let rows = by_name.lookup(&["BOB".into()], true).unwrap();
+    assert_eq!(rows, vec![vec![1.into()]]);
+    let rows = by_name.lookup(&["alice".into()], true).unwrap();
+    assert_eq!(rows, vec![vec![2.into()]]);
+    let rows = bobs.all().unwrap();
+    assert_eq!(rows, vec![vec![1.into()]]);
+    let rows = posts_by_user.lookup(&[1.into()], true).unwrap();
+    assert_eq!(rows, vec![vec![1.into()]]);
+}
+
+#[test]
+fn it_extracts_parts_of_json_columns() {
+    let mut g = build_local("it_extracts_parts_of_json_columns");
+    let sql = "
+        CREATE TABLE Event (id int, doc json, PRIMARY KEY(id));
+        QUERY EventKind: SELECT Event.id, doc->>'$.kind', doc->'$.tags[0]' AS tag \
+                         FROM Event WHERE Event.id = ?;
+        QUERY Clicks: SELECT Event.id FROM Event WHERE doc->>'$.kind' = 'click';
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut events = g.table("Event").unwrap();
+    let mut kinds = g.view("EventKind").unwrap();
+    let mut clicks = g.view("Clicks").unwrap();
+
+    events
+        .insert(vec![1.into(), r#"{"kind": "click", "tags": ["a"]}"#.into()])
+        .unwrap();
+    events
+        .insert(vec![2.into(), r#"{"kind": "view", "tags": [2]}"#.into()])
+        .unwrap();
+    match events.insert(vec![3.into(), "{\"kind\":".into()]) {
+        Err(TableError::InvalidJson(ref column)) => assert_eq!(column, "doc"),
+        r => panic!("expected an invalid JSON document, got {:?}", r),
+    }
+    sleep();
+
+    let rows = kinds.lookup(&[1.into()], true).unwrap();
+    assert_eq!(
+        rows,
+        vec![vec![
+            1.into(),
+            "click".into(),
+            DataType::json("\"a\"").unwrap()
+        ]]
+    );
+    let rows = kinds.lookup(&[2.into()], true).unwrap();
+    assert_eq!(
+        rows,
+        vec![vec![2.into(), "view".into(), DataType::json("2").unwrap()]]
+    );
+    assert!(kinds.lookup(&[3.into()], true).unwrap().is_empty());
+    assert_eq!(clicks.all().unwrap(), vec![vec![1.into()]]);
+}
+
+#[test]
+fn it_enforces_unique_keys() {
+    let mut g = build_local_unsharded("it_enforces_unique_keys");
+    let sql = "
+        CREATE TABLE User (id int, email varchar(255) UNIQUE, PRIMARY KEY(id));
+        QUERY UserById: SELECT id, email FROM User WHERE id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut mutator = g.table("User").unwrap();
+    let mut getter = g.view("UserById").unwrap();
+
+    mutator.insert(vec![1.into(), "a".into()]).unwrap();
+    let rows: Vec<Vec<DataType>> = vec![vec![2.into(), "b".into()], vec![3.into(), "a".into()]];
+    match mutator.insert_then_wait(rows) {
+        Err(TableError::ConstraintViolation(ConstraintViolation::Unique(ref key))) => {
+            assert_eq!(key, "email")
+        }
+        r => panic!("expected unique key violation, got {:?}", r),
+    }
+    sleep();
+
+    // the rows that did not violate the key were still inserted
+    assert_eq!(
+        getter.lookup(&[2.into()], true).unwrap(),
+        vec![vec![2.into(), "b".into()]]
+    );
+    assert!(getter.lookup(&[3.into()], true).unwrap().is_empty());
+}
+
+#[test]
+fn it_enforces_unique_keys_when_sharded() {
+    // the unique key does not include the primary key the base would be sharded by, so the base
+    // must stay unsharded for the key to be checked against all rows
+    let mut g = build_local("it_enforces_unique_keys_when_sharded");
+    let sql = "
+        CREATE TABLE User (id int, email varchar(255) UNIQUE, PRIMARY KEY(id));
+        QUERY UserById: SELECT id, email FROM User WHERE id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut mutator = g.table("User").unwrap();
+    let mut getter = g.view("UserById").unwrap();
+
+    for id in 1..5 {
+        let email = format!("{}@example.com", id);
+        mutator.insert(vec![id.into(), email.into()]).unwrap();
+    }
+    sleep();
+
+    for id in 5..9 {
+        let email = format!("{}@example.com", id - 4);
+        match mutator.insert_then_wait(vec![vec![id.into(), email.into()]]) {
+            Err(TableError::ConstraintViolation(ConstraintViolation::Unique(ref key))) => {
+                assert_eq!(key, "email")
+            }
+            r => panic!("expected unique key violation, got {:?}", r),
+        }
+    }
+    sleep();
+
+    for id in 5..9 {
+        assert!(getter.lookup(&[id.into()], true).unwrap().is_empty());
+    }
+}
+
+#[test]
+fn it_enforces_foreign_keys() {
+    let mut g = build_local_unsharded("it_enforces_foreign_keys");
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        CREATE TABLE Vote (id int, article_id int, PRIMARY KEY(id),
+            CONSTRAINT fk_article FOREIGN KEY (article_id) REFERENCES Article(id));
+        QUERY VoteById: SELECT id, article_id FROM Vote WHERE id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut article = g.table("Article").unwrap();
+    let mut vote = g.table("Vote").unwrap();
+    let mut getter = g.view("VoteById").unwrap();
+
+    article.insert(vec![1.into(), "a".into()]).unwrap();
+    sleep();
+
+    vote.insert_then_wait(vec![vec![1.into(), 1.into()]])
+        .unwrap();
+    match vote.insert_then_wait(vec![vec![2.into(), 2.into()]]) {
+        Err(TableError::ConstraintViolation(ConstraintViolation::ForeignKey(ref key))) => {
+            assert_eq!(key, "article_id")
+        }
+        r => panic!("expected foreign key violation, got {:?}", r),
+    }
+    sleep();
+
+    assert_eq!(
+        getter.lookup(&[1.into()], true).unwrap(),
+        vec![vec![1.into(), 1.into()]]
+    );
+    assert!(getter.lookup(&[2.into()], true).unwrap().is_empty());
+}
+
+#[test]
+fn it_works_with_sql_dml() {
+    let mut g = build_local("it_works_with_sql_dml");
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), votes int, PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title, votes FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut getter = g.view("ArticleById").unwrap();
+
+    let n = g
+        .execute(
+            "INSERT INTO Article (id, title, votes) VALUES (?, ?, 0), (2, 'two', 0);",
+            &[1.into(), "one".into()],
+        )
+        .unwrap();
+    assert_eq!(n, Some(2));
+    sleep();
+    assert_eq!(
+        getter.lookup(&[1.into()], true).unwrap(),
+        vec![vec![1.into(), "one".into(), 0.into()]]
+    );
+
+    g.execute(
+        "UPDATE Article SET votes = votes + 1, title = ? WHERE id = ?;",
+        &["uno".into(), 1.into()],
+    )
+    .unwrap();
+    sleep();
+    assert_eq!(
+        getter.lookup(&[1.into()], true).unwrap(),
+        vec![vec![1.into(), "uno".into(), 1.into()]]
+    );
+
+    g.execute("DELETE FROM Article WHERE id = ?;", &[2.into()])
+        .unwrap();
+    sleep();
+    assert!(getter.lookup(&[2.into()], true).unwrap().is_empty());
+
+    // non-key predicates are evaluated at the base table
+    g.execute("DELETE FROM Article WHERE votes = ?;", &[1.into()])
+        .unwrap();
+    sleep();
+    assert!(getter.lookup(&[1.into()], true).unwrap().is_empty());
+
+    assert!(g
+        .execute("DELETE FROM Article WHERE id = 1 OR id = 2;", &[])
+        .is_err());
+}
+
 #[test]
 fn it_works_with_vote() {
     let mut g = build_local("it_works_with_vote");
@@ -763,6 +1789,45 @@ fn it_recovers_persisted_bases() {
     }
 }
 
+#[test]
+fn it_recovers_view_indexes() {
+    let authority = Arc::new(LocalAuthority::new());
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("it_recovers_view_indexes");
+    let persistence_params = PersistenceParameters::new(
+        DurabilityMode::Permanent,
+        Duration::from_millis(1),
+        Some(path.to_string_lossy().into()),
+        1,
+    );
+
+    {
+        let mut g = ControllerBuilder::default();
+        g.set_persistence(persistence_params.clone());
+        let mut g = g.build(authority.clone()).unwrap();
+        g.install_recipe(
+            "CREATE TABLE Article (id int, author int, PRIMARY KEY(id));
+             QUERY ArticleById: SELECT id, author FROM Article WHERE id = ?;",
+        )
+        .unwrap();
+        g.add_view_index("ArticleById", "ArticlesByAuthor", &["author"])
+            .unwrap();
+
+        let mut article = g.table("Article").unwrap();
+        article.insert(vec![1.into(), 10.into()]).unwrap();
+        sleep();
+    }
+
+    let mut g = ControllerBuilder::default();
+    g.set_persistence(persistence_params);
+    let mut g = g.build(authority.clone()).unwrap();
+    let mut getter = g.view("ArticlesByAuthor").unwrap();
+    assert_eq!(
+        getter.lookup(&[10.into()], true).unwrap(),
+        vec![vec![1.into(), 10.into()]]
+    );
+}
+
 #[test]
 fn mutator_churn() {
     let mut g = build_local("mutator_churn");
@@ -968,11 +2033,47 @@ fn it_works_with_function_arithmetic() {
     // Let writes propagate:
     sleep();
 
-    let result = getter.lookup(&[0.into()], true).unwrap();
+    let result = getter.all().unwrap();
     assert_eq!(result.len(), 1);
     assert_eq!(result[0][0], DataType::from(max_price * 2));
 }
 
+#[test]
+fn it_reads_unparameterized_views_in_full() {
+    let mut g = build_local("it_reads_unparameterized_views_in_full");
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY AllArticles: SELECT id, title FROM Article;
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut articles = g.table("Article").unwrap();
+    let mut all = g.view("AllArticles").unwrap();
+    let mut by_id = g.view("ArticleById").unwrap();
+
+    // enough rows to need several pages when read a page at a time
+    let n = 2500;
+    articles
+        .insert_all((0..n).map(|i| vec![i.into(), format!("Article #{}", i).into()]))
+        .unwrap();
+    sleep();
+
+    let mut rows = all.all().unwrap();
+    assert_eq!(rows.len(), n as usize);
+    rows.sort();
+    assert_eq!(rows[42], vec![42.into(), "Article #42".into()]);
+
+    assert_eq!(all.page(10, 5).unwrap().len(), 5);
+    assert_eq!(all.page(n as usize - 3, 5).unwrap().len(), 3);
+    assert!(all.page(n as usize, 5).unwrap().is_empty());
+
+    // views with parameters must be looked up by key
+    match by_id.all() {
+        Err(ViewError::Parameterized) => {}
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
 #[test]
 fn votes() {
     // set up graph
@@ -1582,6 +2683,141 @@ fn full_aggregation_with_bogokey() {
     );
 }
 
+#[test]
+fn it_keeps_latest_by_compound_key_and_order() {
+    let mut g = build_local("it_keeps_latest_by_compound_key_and_order");
+    g.migrate(|mig| {
+        let readings = mig.add_base(
+            "readings",
+            &["device", "sensor", "time", "value"],
+            Base::default(),
+        );
+        let current = mig.add_ingredient(
+            "current",
+            &["device", "sensor", "time", "value"],
+            Latest::new(readings, vec![0, 1]).with_order(2),
+        );
+        mig.maintain_anonymous(current, &[0, 1]);
+    });
+
+    let mut readings = g.table("readings").unwrap();
+    let mut current = g.view("current").unwrap();
+
+    readings
+        .insert(vec![1.into(), 1.into(), 10.into(), "a".into()])
+        .unwrap();
+    readings
+        .insert(vec![1.into(), 2.into(), 10.into(), "b".into()])
+        .unwrap();
+    // this reading was taken first, but arrives last
+    readings
+        .insert(vec![1.into(), 1.into(), 5.into(), "c".into()])
+        .unwrap();
+    sleep();
+
+    assert_eq!(
+        current.lookup(&[1.into(), 1.into()], true).unwrap(),
+        vec![vec![1.into(), 1.into(), 10.into(), "a".into()]]
+    );
+    assert_eq!(
+        current.lookup(&[1.into(), 2.into()], true).unwrap(),
+        vec![vec![1.into(), 2.into(), 10.into(), "b".into()]]
+    );
+
+    readings
+        .insert(vec![1.into(), 1.into(), 20.into(), "d".into()])
+        .unwrap();
+    sleep();
+
+    assert_eq!(
+        current.lookup(&[1.into(), 1.into()], true).unwrap(),
+        vec![vec![1.into(), 1.into(), 20.into(), "d".into()]]
+    );
+}
+
+#[test]
+fn it_plans_latest_from_sql() {
+    let mut g = build_local("it_plans_latest_from_sql");
+    g.install_recipe(
+        "CREATE TABLE readings (device int, sensor int, taken int, reading varchar(255));
+         QUERY current: SELECT device, sensor, taken, reading FROM readings \
+                        WHERE device = ? AND sensor = ? ORDER BY taken DESC LIMIT 1;",
+    )
+    .unwrap();
+    // the table can only be inserted into, so the newest reading is kept by a Latest node
+    assert!(g.graphviz().unwrap().contains("⧖"));
+
+    let mut readings = g.table("readings").unwrap();
+    let mut current = g.view("current").unwrap();
+
+    readings
+        .insert(vec![1.into(), 1.into(), DataType::None, "a".into()])
+        .unwrap();
+    readings
+        .insert(vec![1.into(), 1.into(), 10.into(), "b".into()])
+        .unwrap();
+    // this reading was taken first, but arrives last
+    readings
+        .insert(vec![1.into(), 1.into(), 5.into(), "c".into()])
+        .unwrap();
+    // and this one has no time, so it is older than all the others
+    readings
+        .insert(vec![1.into(), 1.into(), DataType::None, "d".into()])
+        .unwrap();
+    sleep();
+
+    assert_eq!(
+        current.lookup(&[1.into(), 1.into()], true).unwrap(),
+        vec![vec![1.into(), 1.into(), 10.into(), "b".into()]]
+    );
+}
+
+#[test]
+fn it_joins_star_schema_queries() {
+    let mut g = build_local("it_joins_star_schema_queries");
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        CREATE TABLE Vote (aid int, uid int);
+        CREATE TABLE Comment (aid int, body varchar(255));
+        QUERY ArticleActivity: SELECT Article.title, Vote.uid, Comment.body \
+            FROM Article, Vote, Comment \
+            WHERE Article.id = Vote.aid AND Article.id = Comment.aid AND Article.id = ?;
+    ";
+    g.install_recipe(sql).unwrap();
+    let mut articles = g.table("Article").unwrap();
+    let mut votes = g.table("Vote").unwrap();
+    let mut comments = g.table("Comment").unwrap();
+    let mut activity = g.view("ArticleActivity").unwrap();
+
+    articles.insert(vec![1.into(), "a".into()]).unwrap();
+    articles.insert(vec![2.into(), "b".into()]).unwrap();
+    votes.insert(vec![1.into(), 10.into()]).unwrap();
+    votes.insert(vec![1.into(), 11.into()]).unwrap();
+    votes.insert(vec![2.into(), 12.into()]).unwrap();
+    comments.insert(vec![1.into(), "x".into()]).unwrap();
+    sleep();
+
+    let mut rows = activity.lookup(&[1.into()], true).unwrap();
+    rows.sort();
+    assert_eq!(
+        rows,
+        vec![
+            vec!["a".into(), 10.into(), "x".into()],
+            vec!["a".into(), 11.into(), "x".into()],
+        ]
+    );
+    // the second article has no comments yet
+    assert!(activity.lookup(&[2.into()], true).unwrap().is_empty());
+
+    comments.insert(vec![2.into(), "y".into()]).unwrap();
+    sleep();
+
+    assert_eq!(
+        activity.lookup(&[2.into()], true).unwrap(),
+        vec![vec!["b".into(), 12.into(), "y".into()]]
+    );
+}
+
 #[test]
 fn crossing_migration() {
     // set up graph
@@ -2271,8 +3507,8 @@ fn remove_query() {
     mutb.insert(vec![1.into(), "4".into(), "5".into()]).unwrap();
     sleep();
 
-    assert_eq!(qa.lookup(&[0.into()], true).unwrap().len(), 2);
-    assert_eq!(qb.lookup(&[0.into()], true).unwrap().len(), 1);
+    assert_eq!(qa.all().unwrap().len(), 2);
+    assert_eq!(qb.all().unwrap().len(), 1);
 
     // Remove qb and check that the graph still functions as expected.
     g.install_recipe(r2_txt).unwrap();
@@ -2284,6 +3520,6 @@ fn remove_query() {
         .unwrap();
     sleep();
 
-    assert_eq!(qa.lookup(&[0.into()], true).unwrap().len(), 3);
-    assert_eq!(qb.lookup(&[0.into()], true).unwrap().len(), 1);
+    assert_eq!(qa.all().unwrap().len(), 3);
+    assert_eq!(qb.all().unwrap().len(), 1);
 }
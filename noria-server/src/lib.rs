@@ -370,7 +370,7 @@ mod integration;
 
 pub use crate::controller::sql::reuse::ReuseConfigType;
 pub use crate::controller::{ControllerBuilder, LocalControllerHandle};
-pub use dataflow::{DurabilityMode, PersistenceParameters};
+pub use dataflow::{DurabilityMode, EvictionPolicy, PersistenceParameters};
 pub use noria::consensus::LocalAuthority;
 pub use noria::*;
 pub use petgraph::graph::NodeIndex;
@@ -3,7 +3,7 @@ extern crate clap;
 extern crate noria_server;
 extern crate slog;
 
-use noria_server::{ControllerBuilder, ReuseConfigType, ZookeeperAuthority};
+use noria_server::{ControllerBuilder, EvictionPolicy, ReuseConfigType, ZookeeperAuthority};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
@@ -79,6 +79,26 @@ fn main() {
                 .requires("memory")
                 .help("Frequency at which to check the state size against the memory limit [in milliseconds]."),
         )
+        .arg(
+            Arg::with_name("eviction")
+                .long("eviction")
+                .takes_value(true)
+                .possible_values(&["random", "lru", "lfu"])
+                .default_value("random")
+                .help("How to choose keys to evict from partially materialized state when over the memory limit."),
+        )
+        .arg(
+            Arg::with_name("spill")
+                .long("spill-above")
+                .takes_value(true)
+                .default_value("0")
+                .help("Memory, in bytes, each fully materialized operator that the planner chooses to spill can use before spilling state to disk [0 = never spill]."),
+        )
+        .arg(
+            Arg::with_name("persist-partial")
+                .long("persist-partial")
+                .help("Keep the state of partially materialized operators other than readers on disk."),
+        )
         .arg(
             Arg::with_name("noreuse")
                 .long("no-reuse")
@@ -120,6 +140,7 @@ fn main() {
     let zookeeper_addr = matches.value_of("zookeeper").unwrap();
     let memory = value_t_or_exit!(matches, "memory", usize);
     let memory_check_freq = value_t_or_exit!(matches, "memory_check_freq", u64);
+    let spill = value_t_or_exit!(matches, "spill", usize);
     let quorum = value_t_or_exit!(matches, "quorum", usize);
     let persistence_threads = value_t_or_exit!(matches, "persistence-threads", i32);
     let flush_ns = value_t_or_exit!(matches, "flush-timeout", u32);
@@ -137,11 +158,23 @@ fn main() {
     if memory > 0 {
         builder.set_memory_limit(memory, Duration::from_millis(memory_check_freq));
     }
+    builder.set_eviction_policy(match matches.value_of("eviction").unwrap() {
+        "random" => EvictionPolicy::Random,
+        "lru" => EvictionPolicy::Lru,
+        "lfu" => EvictionPolicy::Lfu,
+        _ => unreachable!(),
+    });
     builder.set_sharding(sharding);
     builder.set_quorum(quorum);
     if matches.is_present("nopartial") {
         builder.disable_partial();
     }
+    if spill > 0 {
+        builder.set_spill_limit(spill);
+    }
+    if matches.is_present("persist-partial") {
+        builder.persist_partial_state();
+    }
     if matches.is_present("noreuse") {
         builder.set_reuse(ReuseConfigType::NoReuse);
     }
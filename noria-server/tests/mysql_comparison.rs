@@ -40,6 +40,9 @@ enum Type {
 
 impl Type {
     pub fn make_datatype(&self, value: &str) -> DataType {
+        if value == "NULL" {
+            return DataType::None;
+        }
         match *self {
             Type::Int => i64::from_str(value).unwrap().into(),
             Type::Text => value.into(),
@@ -181,7 +184,14 @@ fn generate_target_results(schemas: &BTreeMap<String, Schema>) {
             );
             let mut insert = pool.prepare(query).unwrap();
             for row in table.data.as_ref().unwrap().iter() {
-                if let Err(msg) = insert.execute(row.clone()) {
+                let values = row
+                    .iter()
+                    .map(|v| match &**v {
+                        "NULL" => mysql::Value::NULL,
+                        v => v.into(),
+                    })
+                    .collect();
+                if let Err(msg) = insert.execute(Params::Positional(values)) {
                     println!(
                         "MySQL insert query failed for table: {}, values: {:?}",
                         table_name, row
@@ -312,7 +322,15 @@ fn check_query(
                         DataType::BigInt(i) => i.to_string(),
                         DataType::Real(i, f) => ((i as f64) + (f as f64) * 1.0e-9).to_string(),
                         DataType::Text(_) | DataType::TinyText(_) => v.into(),
-                        DataType::Timestamp(_) => unimplemented!(),
+                        DataType::Float(..)
+                        | DataType::Bool(..)
+                        | DataType::Date(..)
+                        | DataType::Decimal(..)
+                        | DataType::Json(..) => v.to_string(),
+                        DataType::Timestamp(ts) => ts.format("%Y-%m-%d %H:%M:%S").to_string(),
+                        DataType::ByteArray(ref bytes) => {
+                            String::from_utf8_lossy(&bytes[..]).into_owned()
+                        }
                     })
                     .collect()
             })
@@ -14,6 +14,7 @@ use serde::{Deserialize, Serialize};
 use tokio::prelude::*;
 
 use super::{DeserializeReceiver, NonBlockingWriter, ReceiveError};
+use crate::InputAck;
 
 #[derive(Debug, Fail)]
 pub enum SendError {
@@ -138,9 +139,9 @@ pub enum RecvError {
 }
 
 pub enum DualTcpStream<S, T, T2, D> {
-    Passthrough(AsyncBincodeStream<S, T, bool, D>),
+    Passthrough(AsyncBincodeStream<S, T, InputAck, D>),
     Upgrade(
-        AsyncBincodeStream<S, T2, bool, D>,
+        AsyncBincodeStream<S, T2, InputAck, D>,
         Box<FnMut(T2) -> T + Send + Sync>,
     ),
 }
@@ -153,7 +154,8 @@ impl<S, T, T2> From<S> for DualTcpStream<S, T, T2, SyncDestination> {
 
 impl<S, T, T2> DualTcpStream<S, T, T2, SyncDestination> {
     pub fn upgrade<F: 'static + FnMut(T2) -> T + Send + Sync>(stream: S, f: F) -> Self {
-        let s: AsyncBincodeStream<S, T2, bool, SyncDestination> = AsyncBincodeStream::from(stream);
+        let s: AsyncBincodeStream<S, T2, InputAck, SyncDestination> =
+            AsyncBincodeStream::from(stream);
         DualTcpStream::Upgrade(s, Box::new(f))
     }
 
@@ -168,9 +170,9 @@ impl<S, T, T2> DualTcpStream<S, T, T2, SyncDestination> {
 impl<S, T, T2, D> Sink for DualTcpStream<S, T, T2, D>
 where
     S: AsyncWrite,
-    AsyncBincodeWriter<S, bool, D>: Sink<SinkItem = bool, SinkError = bincode::Error>,
+    AsyncBincodeWriter<S, InputAck, D>: Sink<SinkItem = InputAck, SinkError = bincode::Error>,
 {
-    type SinkItem = bool;
+    type SinkItem = InputAck;
     type SinkError = bincode::Error;
     fn start_send(
         &mut self,
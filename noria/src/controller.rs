@@ -1,10 +1,12 @@
 #[cfg(debug_assertions)]
 use assert_infrequent;
 use crate::consensus::{self, Authority};
+use crate::data::DataType;
 use crate::debug::stats;
+use crate::dml::{self, Dml};
 use crate::table::{Table, TableBuilder, TableRpc};
-use crate::view::{View, ViewBuilder, ViewRpc};
-use crate::ActivationResult;
+use crate::view::{View, ViewBuilder, ViewRpc, WarmProgress};
+use crate::{ActivationResult, Description, RecipePlan, RecipeVersion};
 use failure::{self, ResultExt};
 use futures::{
     sync::{mpsc, oneshot},
@@ -235,6 +237,13 @@ impl<A: Authority> ControllerHandle<A> {
             })
     }
 
+    /// Describe the schema of the table or view with the given name.
+    pub fn describe(&mut self, name: &str) -> Result<Description, failure::Error> {
+        self.rpc::<_, Option<Description>>("describe", name)
+            .context(format!("describing {}", name))?
+            .ok_or_else(|| format_err!("no table or view named {}", name))
+    }
+
     /// Obtain a `Table` that allows you to perform writes, deletes, and other operations on the
     /// given base table.
     pub fn table(&mut self, name: &str) -> Result<Table, failure::Error> {
@@ -243,6 +252,10 @@ impl<A: Authority> ControllerHandle<A> {
         #[cfg(debug_assertions)]
         assert_infrequent::at_most(200);
 
+        self.build_table(name)
+    }
+
+    fn build_table(&mut self, name: &str) -> Result<Table, failure::Error> {
         self.rpc::<_, Option<TableBuilder>>("table_builder", name)
             .context(format!("building Table for {}", name))?
             .ok_or_else(|| format_err!("view {} does not exist", name))
@@ -261,11 +274,126 @@ impl<A: Authority> ControllerHandle<A> {
             })
     }
 
+    /// Execute a single SQL `INSERT`, `UPDATE`, or `DELETE` statement against a base table.
+    ///
+    /// Placeholders (`?`) in the statement are replaced by the values in `params`, in the order
+    /// they appear. The `WHERE` clause of `UPDATE` and `DELETE` statements must be a conjunction of
+    /// comparisons between columns and values, and `UPDATE` can only set columns to values or
+    /// increment/decrement them (e.g., `SET votes = votes + 1`).
+    ///
+    /// Returns the number of affected rows if it is known. This is only the case for inserts, as
+    /// updates and deletes are applied asynchronously at the base table.
+    ///
+    /// The table's schema is fetched from the controller on every call, so statements always see
+    /// the current recipe, even if another client has changed it. Use `table` for bulk writes.
+    pub fn execute(
+        &mut self,
+        sql: &str,
+        params: &[DataType],
+    ) -> Result<Option<usize>, failure::Error> {
+        let q = nom_sql::parser::parse_query(sql)
+            .map_err(|e| format_err!("failed to parse statement: {}", e))?;
+        let name = dml::target_table(&q)?.to_owned();
+
+        let mut table = self.build_table(&name)?;
+
+        let defaults = table.defaults();
+        match dml::resolve(&q, table.columns(), &defaults, table.key(), params.to_vec())
+            .context(String::from(sql))?
+        {
+            Dml::Insert(rows) => {
+                let n = rows.len();
+                table.insert_all(rows)?;
+                Ok(Some(n))
+            }
+            Dml::Delete(key) => {
+                table.delete(key)?;
+                Ok(None)
+            }
+            Dml::Update(key, set) => {
+                table.update(key, set)?;
+                Ok(None)
+            }
+            Dml::DeleteWhere(predicate) => {
+                table.delete_where(predicate)?;
+                Ok(None)
+            }
+            Dml::UpdateWhere(predicate, set) => {
+                table.update_where(predicate, set)?;
+                Ok(None)
+            }
+        }
+    }
+
     /// Get statistics about the time spent processing different parts of the graph.
     pub fn statistics(&mut self) -> Result<stats::GraphStats, failure::Error> {
         Ok(self.rpc("get_statistics", &()).context("getting stats")?)
     }
 
+    /// Limit the memory used by the given view's state to `max_bytes`, or remove its limit if
+    /// `max_bytes` is `None`.
+    ///
+    /// The limit is split evenly among the view's shards, and each shard evicts keys whenever
+    /// it grows beyond its share. Limits are kept across controller restarts, and also apply to
+    /// views of the same name that are added later. Views are not prioritized against each other
+    /// beyond their limits, and limits can only be set through this method, not in a recipe.
+    pub fn set_view_budget(
+        &mut self,
+        view: &str,
+        max_bytes: Option<usize>,
+    ) -> Result<(), failure::Error> {
+        self.rpc::<_, ()>("set_view_budget", &(view, max_bytes))
+            .context(format!("setting memory budget of view {}", view))?;
+        Ok(())
+    }
+
+    /// Add a reader index called `name` to the given view, keyed by the given columns of the
+    /// view.
+    ///
+    /// The index is looked up like any other view, using `view(name)`, and shares the dataflow
+    /// that computes the original view rather than duplicating it. It is removed along with the
+    /// view it was added to.
+    pub fn add_view_index(
+        &mut self,
+        view: &str,
+        name: &str,
+        key: &[&str],
+    ) -> Result<(), failure::Error> {
+        self.rpc::<_, ()>("add_view_index", &(view, name, key))
+            .context(format!("adding index {} to view {}", name, view))?;
+        Ok(())
+    }
+
+    /// Replay any of the given keys that are missing from the given view, without waiting for the
+    /// replays to finish.
+    ///
+    /// Warming with the same keys again reports how many of them have been replayed since. See
+    /// also `View::warm`.
+    pub fn warm_view(
+        &mut self,
+        view: &str,
+        keys: Vec<Vec<DataType>>,
+    ) -> Result<WarmProgress, failure::Error> {
+        Ok(self
+            .rpc("warm_view", &(view, keys))
+            .context(format!("warming view {}", view))?)
+    }
+
+    /// Get up to `count` keys that are currently present in the given view, most recently (or
+    /// frequently, depending on the eviction policy) read first.
+    ///
+    /// Taking this snapshot before shutting down, and passing it to `warm_view` afterwards, lets
+    /// a restarted partial view start out with the keys that were hot before.
+    pub fn view_hot_keys(
+        &mut self,
+        view: &str,
+        count: usize,
+    ) -> Result<Vec<Vec<DataType>>, failure::Error> {
+        Ok(self
+            .rpc("view_hot_keys", &(view, count))
+            .context(format!("getting hot keys of view {}", view))?)
+    }
+
     /// Flush all partial state, evicting all rows present.
     pub fn flush_partial(&mut self) -> Result<(), failure::Error> {
         self.rpc("flush_partial", &())
@@ -290,6 +418,34 @@ impl<A: Authority> ControllerHandle<A> {
             .context(String::from(new_recipe))?)
     }
 
+    /// Work out what installing the given recipe would change in the data-flow graph, without
+    /// changing it. If `extend` is set, the plan is for extending the current recipe instead.
+    pub fn plan_recipe(
+        &mut self,
+        recipe: &str,
+        extend: bool,
+    ) -> Result<RecipePlan, failure::Error> {
+        Ok(self
+            .rpc("plan_recipe", (recipe, extend))
+            .context(String::from(recipe))?)
+    }
+
+    /// List the versions of the recipe that have been installed, oldest first.
+    pub fn recipe_history(&mut self) -> Result<Vec<RecipeVersion>, failure::Error> {
+        Ok(self
+            .rpc("recipe_history", &())
+            .context("fetching recipe history")?)
+    }
+
+    /// Go back to the recipe as it was in the given version from `recipe_history`.
+    ///
+    /// This installs the old recipe as a new version, so the rollback can itself be rolled back.
+    pub fn rollback_recipe(&mut self, version: usize) -> Result<ActivationResult, failure::Error> {
+        Ok(self
+            .rpc::<_, ActivationResult>("rollback_recipe", version)
+            .context(format!("rolling back to recipe version {}", version))?)
+    }
+
     /// Fetch a graphviz description of the dataflow graph.
     pub fn graphviz(&mut self) -> Result<String, failure::Error> {
         Ok(self
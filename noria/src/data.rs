@@ -1,12 +1,17 @@
 use arccstr::ArcCStr;
 
-use chrono::{self, NaiveDateTime};
+use chrono::{self, NaiveDate, NaiveDateTime};
 
-use nom_sql::Literal;
+use nom_sql::{ColumnConstraint, ColumnSpecification, Literal, Operator, SqlType};
+
+use serde_json::{self, Value as JsonValue};
+
+use crate::json::JsonPath;
 
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::ops::{Add, Div, Mul, Sub};
+use std::sync::Arc;
 
 const FLOAT_PRECISION: f64 = 1_000_000_000.0;
 const TINYTEXT_WIDTH: usize = 15;
@@ -19,7 +24,7 @@ const TINYTEXT_WIDTH: usize = 15;
 /// Note that cloning a `DataType` using the `Clone` trait is possible, but may result in cache
 /// contention on the reference counts for de-duplicated strings. Use `DataType::deep_clone` to
 /// clone the *value* of a `DataType` without danger of contention.
-#[derive(Eq, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[warn(variant_size_differences)]
 pub enum DataType {
     /// An empty value.
@@ -37,6 +42,268 @@ pub enum DataType {
     TinyText([u8; TINYTEXT_WIDTH]),
     /// A timestamp for date/time types.
     Timestamp(NaiveDateTime),
+    /// A double-precision floating point value.
+    Float(f64),
+    /// A boolean value.
+    Bool(bool),
+    /// A reference-counted byte string, for binary types like `BLOB`.
+    ByteArray(Arc<Vec<u8>>),
+    /// A calendar date without a time.
+    Date(NaiveDate),
+    /// An exact decimal value. The first field is the value scaled by `10^scale`, and the second
+    /// is the scale (i.e., the number of digits after the decimal point), which is at most 18.
+    Decimal(i64, u8),
+    /// A JSON document, stored as compact text with the members of each object in sorted order.
+    Json(ArcCStr),
+}
+
+/// How the text in a column is compared.
+///
+/// `DataType` itself always compares text byte-wise. A column's collation is instead applied where
+/// its values are compared or looked up: by filters, joins, grouping and ordering, in the keys of
+/// materialized state and readers, and when sharding by the column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Collation {
+    /// Byte-wise comparison, as for MySQL's `binary` and `*_bin` collations.
+    Binary,
+    /// Case-insensitive comparison of ASCII letters, with all other characters compared byte-wise.
+    AsciiCaseInsensitive,
+    /// Case-insensitive comparison using Unicode lowercase folding.
+    UnicodeCaseInsensitive,
+}
+
+impl Collation {
+    /// The collation to use for a MySQL collation name, like `utf8mb4_general_ci`.
+    ///
+    /// Returns `None` for collations that are neither binary nor case-insensitive.
+    pub fn from_name(name: &str) -> Option<Collation> {
+        let name = name.to_ascii_lowercase();
+        if name == "binary" || name.ends_with("_bin") {
+            Some(Collation::Binary)
+        } else if name.ends_with("_ci") {
+            if name.starts_with("ascii_") || name.starts_with("latin1_") {
+                Some(Collation::AsciiCaseInsensitive)
+            } else {
+                Some(Collation::UnicodeCaseInsensitive)
+            }
+        } else {
+            None
+        }
+    }
+
+    /// The collation declared for a column with `COLLATE`.
+    ///
+    /// Columns without a declared collation, or with one we do not support, are binary.
+    pub fn of_column(spec: &ColumnSpecification) -> Collation {
+        spec.constraints
+            .iter()
+            .filter_map(|c| match *c {
+                ColumnConstraint::Collation(ref name) => Collation::from_name(name),
+                _ => None,
+            })
+            .next()
+            .unwrap_or(Collation::Binary)
+    }
+
+    /// The collation of `column`, given the collation of each column, or nothing if they are all
+    /// binary.
+    pub fn of(collations: &[Collation], column: usize) -> Collation {
+        collations.get(column).cloned().unwrap_or(Collation::Binary)
+    }
+
+    /// The collation of each of the given columns, or nothing if they are all binary.
+    pub fn of_columns(collations: &[Collation], columns: &[usize]) -> Vec<Collation> {
+        let collations: Vec<_> = columns
+            .iter()
+            .map(|&c| Collation::of(collations, c))
+            .collect();
+        if collations.iter().all(|&c| c == Collation::Binary) {
+            Vec::new()
+        } else {
+            collations
+        }
+    }
+
+    /// The form of `s` that is compared byte-wise under this collation.
+    pub fn fold<'a>(self, s: &'a str) -> Cow<'a, str> {
+        let lowercase = |s: &str| !s.bytes().any(|b| b.is_ascii_uppercase());
+        match self {
+            Collation::Binary => Cow::Borrowed(s),
+            Collation::AsciiCaseInsensitive if lowercase(s) => Cow::Borrowed(s),
+            Collation::AsciiCaseInsensitive => Cow::Owned(s.to_ascii_lowercase()),
+            Collation::UnicodeCaseInsensitive if s.is_ascii() && lowercase(s) => Cow::Borrowed(s),
+            Collation::UnicodeCaseInsensitive => {
+                Cow::Owned(s.chars().flat_map(char::to_lowercase).collect())
+            }
+        }
+    }
+
+    /// The value that `v` is equal to byte-wise under this collation, for use as a key.
+    ///
+    /// Only text is affected, and it is only copied if folding changes it.
+    pub fn key<'a>(self, v: &'a DataType) -> Cow<'a, DataType> {
+        match *v {
+            DataType::Text(..) | DataType::TinyText(..) if self != Collation::Binary => {
+                let s: Cow<str> = v.into();
+                match self.fold(&s) {
+                    Cow::Borrowed(_) => Cow::Borrowed(v),
+                    Cow::Owned(folded) => Cow::Owned(DataType::from(folded)),
+                }
+            }
+            _ => Cow::Borrowed(v),
+        }
+    }
+}
+
+/// The largest scale a `DataType::Decimal` can have; `10^19` no longer fits in an `i64`.
+const MAX_DECIMAL_SCALE: u8 = 18;
+
+fn pow10(exp: u8) -> i128 {
+    10i128.pow(u32::from(exp))
+}
+
+/// Rescale the mantissa of a decimal from scale `from` to scale `to`, rounding half away from
+/// zero if digits are dropped.
+fn rescale(mantissa: i128, from: u8, to: u8) -> i128 {
+    if to >= from {
+        mantissa * pow10(to - from)
+    } else {
+        let div = pow10(from - to);
+        let (q, r) = (mantissa / div, mantissa % div);
+        if r.abs() * 2 >= div {
+            q + mantissa.signum()
+        } else {
+            q
+        }
+    }
+}
+
+/// Strip trailing zeroes from a decimal, so that equal values have the same representation.
+fn normalize_decimal(mut mantissa: i128, mut scale: u8) -> (i128, u8) {
+    while scale > 0 && mantissa % 10 == 0 {
+        mantissa /= 10;
+        scale -= 1;
+    }
+    (mantissa, scale)
+}
+
+/// Construct a decimal from a wide mantissa, dropping fractional digits if it does not fit.
+///
+/// Like MySQL in non-strict mode, gives `NULL` if even the integer part does not fit.
+fn make_decimal(mut mantissa: i128, mut scale: u8) -> DataType {
+    while mantissa > i128::from(i64::max_value()) || mantissa < i128::from(i64::min_value()) {
+        if scale == 0 {
+            return DataType::None;
+        }
+        mantissa = rescale(mantissa, scale, scale - 1);
+        scale -= 1;
+    }
+    DataType::Decimal(mantissa as i64, scale)
+}
+
+/// Perform an exact arithmetic operation on two decimals given as `(mantissa, scale)`.
+fn decimal_arithmetic(op: &str, (am, ascale): (i64, u8), (bm, bscale): (i64, u8)) -> DataType {
+    let (am, bm) = (i128::from(am), i128::from(bm));
+    match op {
+        "+" | "-" => {
+            let scale = ascale.max(bscale);
+            let (a, b) = (rescale(am, ascale, scale), rescale(bm, bscale, scale));
+            make_decimal(if op == "+" { a + b } else { a - b }, scale)
+        }
+        "*" => {
+            let scale = ascale.saturating_add(bscale);
+            let target = scale.min(MAX_DECIMAL_SCALE);
+            make_decimal(rescale(am * bm, scale, target), target)
+        }
+        "/" => {
+            // like MySQL, keep four more digits than the dividend
+            let scale = (ascale + 4).min(MAX_DECIMAL_SCALE);
+            let shift = pow10(scale + bscale - ascale);
+            match am.checked_mul(shift) {
+                Some(a) if bm != 0 => make_decimal(a / bm, scale),
+                _ => DataType::None,
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Map floats to bits such that values that compare equal have the same bits.
+fn float_bits(f: f64) -> u64 {
+    if f == 0.0 {
+        0
+    } else if f.is_nan() {
+        ::std::f64::NAN.to_bits()
+    } else {
+        f.to_bits()
+    }
+}
+
+/// A total order over floats, where NaN is greater than all other values.
+fn float_cmp(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b)
+        .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+/// Split a finite float into `(mantissa, exponent)` such that its value is `mantissa * 2^exponent`
+/// exactly, and the mantissa is odd (or zero).
+fn float_parts(f: f64) -> (i128, i32) {
+    let bits = f.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i32;
+    let mut mantissa = i128::from(bits & ((1 << 52) - 1));
+    let mut exponent = if biased == 0 {
+        -1074
+    } else {
+        mantissa |= 1 << 52;
+        biased - 1075
+    };
+    if mantissa == 0 {
+        return (0, 0);
+    }
+    let zeroes = mantissa.trailing_zeros();
+    mantissa >>= zeroes;
+    exponent += zeroes as i32;
+    if bits >> 63 == 1 {
+        mantissa = -mantissa;
+    }
+    (mantissa, exponent)
+}
+
+/// Compare two decimals given as `(mantissa, scale)`.
+fn decimal_cmp((am, ascale): (i128, u8), (bm, bscale): (i128, u8)) -> Ordering {
+    let scale = ascale.max(bscale);
+    rescale(am, ascale, scale).cmp(&rescale(bm, bscale, scale))
+}
+
+/// Compare a float to the decimal `mantissa / 10^scale` exactly.
+///
+/// Floats of `2^64` or more in magnitude are assumed to be larger than any such decimal, which
+/// holds for all decimals returned by `DataType::exact_decimal`.
+fn float_decimal_cmp(f: f64, (mantissa, scale): (i128, u8)) -> Ordering {
+    if !f.is_finite() {
+        return float_cmp(f, 0.0);
+    }
+    let (fm, exponent) = float_parts(f);
+    if exponent >= 64 {
+        return fm.cmp(&0);
+    }
+    if exponent >= 0 {
+        return decimal_cmp((fm << exponent, 0), (mantissa, scale));
+    }
+
+    // f is fm / 2^-exponent, so compare fm * 10^scale with mantissa * 2^-exponent
+    let lhs = fm * pow10(scale);
+    let rhs = if exponent > -127 {
+        mantissa.checked_mul(1 << -exponent)
+    } else {
+        None
+    };
+    match rhs {
+        Some(rhs) => lhs.cmp(&rhs),
+        // the decimal is zero, or further from zero than the float
+        None if mantissa == 0 => lhs.cmp(&0),
+        None => 0.cmp(&mantissa),
+    }
 }
 
 impl fmt::Display for DataType {
@@ -59,6 +326,27 @@ impl fmt::Display for DataType {
                 }
             }
             DataType::Timestamp(ts) => write!(f, "{}", ts.format("%c")),
+            DataType::Float(x) => write!(f, "{}", x),
+            DataType::Bool(b) => write!(f, "{}", b),
+            DataType::ByteArray(ref bytes) => {
+                write!(f, "0x")?;
+                for b in bytes.iter() {
+                    write!(f, "{:02x}", b)?;
+                }
+                Ok(())
+            }
+            DataType::Date(d) => write!(f, "{}", d.format("%Y-%m-%d")),
+            DataType::Json(ref s) => write!(f, "{}", s.to_string_lossy()),
+            DataType::Decimal(m, scale) => {
+                let div = 10i64.pow(u32::from(scale));
+                let sign = if m < 0 { "-" } else { "" };
+                let (i, frac) = ((m / div).abs(), (m % div).abs());
+                if scale == 0 {
+                    write!(f, "{}{}", sign, i)
+                } else {
+                    write!(f, "{}{}.{:0width$}", sign, i, frac, width = scale as usize)
+                }
+            }
         }
     }
 }
@@ -79,6 +367,12 @@ impl fmt::Debug for DataType {
             DataType::Real(..) => write!(f, "Real({})", self),
             DataType::Int(n) => write!(f, "Int({})", n),
             DataType::BigInt(n) => write!(f, "BigInt({})", n),
+            DataType::Float(x) => write!(f, "Float({:?})", x),
+            DataType::Bool(b) => write!(f, "Bool({})", b),
+            DataType::ByteArray(ref bytes) => write!(f, "ByteArray({:?})", bytes),
+            DataType::Date(d) => write!(f, "Date({})", d),
+            DataType::Decimal(..) => write!(f, "Decimal({})", self),
+            DataType::Json(ref s) => write!(f, "Json({})", s.to_string_lossy()),
         }
     }
 }
@@ -91,6 +385,8 @@ impl DataType {
     pub fn deep_clone(&self) -> Self {
         match *self {
             DataType::Text(ref cstr) => DataType::Text(ArcCStr::from(&**cstr)),
+            DataType::Json(ref cstr) => DataType::Json(ArcCStr::from(&**cstr)),
+            DataType::ByteArray(ref bytes) => DataType::ByteArray(Arc::new((**bytes).clone())),
             ref dt => dt.clone(),
         }
     }
@@ -128,7 +424,13 @@ impl PartialEq for DataType {
                 a == b
             }
             (&DataType::Real(ai, af), &DataType::Real(bi, bf)) => ai == bi && af == bf,
+            (&DataType::Float(a), &DataType::Float(b)) => float_bits(a) == float_bits(b),
+            (a, b) if a.is_numeric() && b.is_numeric() => a.cmp(b) == Ordering::Equal,
             (&DataType::Timestamp(tsa), &DataType::Timestamp(tsb)) => tsa == tsb,
+            (&DataType::Bool(a), &DataType::Bool(b)) => a == b,
+            (&DataType::ByteArray(ref a), &DataType::ByteArray(ref b)) => a == b,
+            (&DataType::Date(a), &DataType::Date(b)) => a == b,
+            (&DataType::Json(ref a), &DataType::Json(ref b)) => a == b,
             (&DataType::None, &DataType::None) => true,
 
             _ => false,
@@ -136,6 +438,9 @@ impl PartialEq for DataType {
     }
 }
 
+// Floats are compared such that NaN equals itself, so equality is reflexive.
+impl Eq for DataType {}
+
 use std::cmp::Ordering;
 impl PartialOrd for DataType {
     fn partial_cmp(&self, other: &DataType) -> Option<Ordering> {
@@ -166,14 +471,229 @@ impl Ord for DataType {
                 ai.cmp(bi).then_with(|| af.cmp(bf))
             }
             (&DataType::Timestamp(tsa), &DataType::Timestamp(ref tsb)) => tsa.cmp(tsb),
+            (&DataType::Float(a), &DataType::Float(b)) => float_cmp(a, b),
+            (&DataType::Bool(a), &DataType::Bool(ref b)) => a.cmp(b),
+            (&DataType::ByteArray(ref a), &DataType::ByteArray(ref b)) => a.cmp(b),
+            (&DataType::Date(a), &DataType::Date(ref b)) => a.cmp(b),
+            (&DataType::Decimal(am, ascale), &DataType::Decimal(bm, bscale)) => {
+                decimal_cmp((i128::from(am), ascale), (i128::from(bm), bscale))
+            }
+            (&DataType::Json(ref a), &DataType::Json(ref b)) => a.cmp(b),
             (&DataType::None, &DataType::None) => Ordering::Equal,
 
-            // order Ints, Reals, Text, Timestamps, None
-            (&DataType::Int(..), _) | (&DataType::BigInt(..), _) => Ordering::Greater,
-            (&DataType::Real(..), _) => Ordering::Greater,
-            (&DataType::Text(..), _) | (&DataType::TinyText(..), _) => Ordering::Greater,
-            (&DataType::Timestamp(..), _) => Ordering::Greater,
-            (&DataType::None, _) => Ordering::Greater,
+            // numbers of different types compare by value
+            (a, b) if a.is_numeric() && b.is_numeric() => {
+                match (a.exact_decimal(), b.exact_decimal(), a, b) {
+                    (Some(a), Some(b), _, _) => decimal_cmp(a, b),
+                    (None, Some(b), &DataType::Float(a), _) => float_decimal_cmp(a, b),
+                    (Some(a), None, _, &DataType::Float(b)) => float_decimal_cmp(b, a).reverse(),
+                    (None, None, &DataType::Float(a), &DataType::Float(b)) => float_cmp(a, b),
+                    _ => unreachable!("only floats may not be exact decimals"),
+                }
+            }
+
+            // order numbers, Bools, Text, Bytes, JSON, Dates, Timestamps, None
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+}
+
+impl DataType {
+    /// Returns true if this is a numeric value that can take part in arithmetic.
+    pub fn is_numeric(&self) -> bool {
+        match *self {
+            DataType::Int(..)
+            | DataType::BigInt(..)
+            | DataType::Real(..)
+            | DataType::Float(..)
+            | DataType::Decimal(..) => true,
+            _ => false,
+        }
+    }
+
+    /// This value as a `(mantissa, scale)` pair if it can be represented exactly as a decimal.
+    fn as_decimal(&self) -> Option<(i64, u8)> {
+        match *self {
+            DataType::Int(n) => Some((i64::from(n), 0)),
+            DataType::BigInt(n) => Some((n, 0)),
+            DataType::Decimal(m, scale) => Some((m, scale)),
+            _ => None,
+        }
+    }
+
+    /// This numeric value as a `(mantissa, scale)` pair, with trailing zeroes stripped so that equal
+    /// values have the same representation.
+    ///
+    /// Returns `None` for values that are not numbers, and for floats that are not exactly such a
+    /// decimal with a scale of at most `MAX_DECIMAL_SCALE` and a magnitude below `2^64` (including
+    /// NaN and infinities).
+    pub(crate) fn exact_decimal(&self) -> Option<(i128, u8)> {
+        let (mantissa, scale) = match *self {
+            DataType::Int(n) => (i128::from(n), 0),
+            DataType::BigInt(n) => (i128::from(n), 0),
+            DataType::Real(i, f) => (i128::from(i) * 1_000_000_000 + i128::from(f), 9),
+            DataType::Decimal(m, scale) => (i128::from(m), scale),
+            DataType::Float(f) if f.is_finite() => match float_parts(f) {
+                (m, e) if e >= 0 && e < 64 && m.abs() >> (64 - e) == 0 => (m << e, 0),
+                (m, e) if e < 0 && -e <= i32::from(MAX_DECIMAL_SCALE) => {
+                    // m / 2^-e is m * 5^-e / 10^-e
+                    (m * 5i128.pow(-e as u32), -e as u8)
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(normalize_decimal(mantissa, scale))
+    }
+
+    /// Convert this value to the representation used for columns of the given SQL type.
+    ///
+    /// Values that have no sensible conversion (including `DataType::None`) are returned as-is.
+    pub fn coerce_to(self, ty: &SqlType) -> DataType {
+        match (ty, self) {
+            (_, DataType::None) => DataType::None,
+            (&SqlType::Bool, DataType::Int(n)) => DataType::Bool(n != 0),
+            (&SqlType::Bool, DataType::BigInt(n)) => DataType::Bool(n != 0),
+            (&SqlType::Double, v) | (&SqlType::Float, v) | (&SqlType::Real, v) => {
+                if v.is_numeric() {
+                    DataType::Float((&v).into())
+                } else {
+                    v
+                }
+            }
+            (&SqlType::Decimal(_, scale), v) => {
+                let scale = scale.min(MAX_DECIMAL_SCALE);
+                match v.as_decimal() {
+                    Some((m, s)) => make_decimal(rescale(i128::from(m), s, scale), scale),
+                    None if v.is_numeric() => {
+                        let f: f64 = (&v).into();
+                        make_decimal((f * pow10(scale) as f64).round() as i128, scale)
+                    }
+                    None => v,
+                }
+            }
+            (&SqlType::Blob, v)
+            | (&SqlType::Tinyblob, v)
+            | (&SqlType::Mediumblob, v)
+            | (&SqlType::Longblob, v)
+            | (&SqlType::Binary(_), v)
+            | (&SqlType::Varbinary(_), v) => match v {
+                DataType::Text(..) | DataType::TinyText(..) => {
+                    let s: String = v.into();
+                    DataType::from(s.into_bytes())
+                }
+                v => v,
+            },
+            (&SqlType::Date, DataType::Timestamp(ts)) => DataType::Date(ts.date()),
+            (&SqlType::Date, v @ DataType::Text(..))
+            | (&SqlType::Date, v @ DataType::TinyText(..)) => {
+                let parsed = {
+                    let s: Cow<str> = (&v).into();
+                    NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+                };
+                match parsed {
+                    Ok(d) => DataType::Date(d),
+                    Err(_) => v,
+                }
+            }
+            (_, v) => v,
+        }
+    }
+
+    /// Convert this value to a JSON document, as stored in columns of the `JSON` type.
+    ///
+    /// Text is parsed as JSON, and `None` is returned if it is not a valid document. Other values
+    /// are converted with `to_json`, except for `NULL`, which is kept.
+    pub fn coerce_to_json(self) -> Option<DataType> {
+        match self {
+            DataType::None | DataType::Json(..) => Some(self),
+            DataType::Text(..) | DataType::TinyText(..) => {
+                let s: Cow<str> = (&self).into();
+                DataType::json(&s).ok()
+            }
+            v => Some(v.to_json().into()),
+        }
+    }
+
+    /// The position of this value's type in the order between values of different types.
+    fn type_rank(&self) -> u8 {
+        match *self {
+            DataType::Int(..)
+            | DataType::BigInt(..)
+            | DataType::Real(..)
+            | DataType::Float(..)
+            | DataType::Decimal(..) => 0,
+            DataType::Bool(..) => 1,
+            DataType::Text(..) | DataType::TinyText(..) => 2,
+            DataType::ByteArray(..) => 3,
+            DataType::Json(..) => 4,
+            DataType::Date(..) => 5,
+            DataType::Timestamp(..) => 6,
+            DataType::None => 7,
+        }
+    }
+
+    /// Parse `text` as a JSON document.
+    pub fn json(text: &str) -> Result<DataType, serde_json::Error> {
+        serde_json::from_str::<JsonValue>(text).map(DataType::from)
+    }
+
+    /// This value as a JSON value.
+    ///
+    /// `NULL` becomes JSON's `null`, and values without a JSON counterpart (like dates or byte
+    /// strings) are represented by their textual form. So is a `DataType::Json` that does not hold
+    /// a valid document, which can only be built by hand.
+    pub fn to_json(&self) -> JsonValue {
+        match *self {
+            DataType::None => JsonValue::Null,
+            DataType::Int(n) => n.into(),
+            DataType::BigInt(n) => n.into(),
+            DataType::Bool(b) => b.into(),
+            DataType::Real(..) | DataType::Float(..) | DataType::Decimal(..) => {
+                let f: f64 = self.into();
+                f.into()
+            }
+            DataType::Text(..) | DataType::TinyText(..) => {
+                let s: String = self.into();
+                s.into()
+            }
+            DataType::Json(ref s) => serde_json::from_slice(s.to_bytes())
+                .unwrap_or_else(|_| String::from_utf8_lossy(s.to_bytes()).into_owned().into()),
+            DataType::ByteArray(..) | DataType::Date(..) | DataType::Timestamp(..) => {
+                self.to_string().into()
+            }
+        }
+    }
+
+    /// The part of this JSON document that `path` leads to, like MySQL's `JSON_EXTRACT`.
+    ///
+    /// Returns `NULL` if there is no such part, or if this value is not a JSON document.
+    pub fn json_extract(&self, path: &JsonPath) -> DataType {
+        match *self {
+            DataType::Json(..) => path
+                .extract(&self.to_json())
+                .map(|v| DataType::from(v.clone()))
+                .unwrap_or(DataType::None),
+            _ => DataType::None,
+        }
+    }
+
+    /// The SQL value of this JSON document if it is a scalar, like MySQL's `JSON_UNQUOTE`.
+    ///
+    /// JSON strings, numbers and booleans become text, numbers and booleans, and `null` becomes
+    /// `NULL`. Objects, arrays and values that are not JSON documents are returned as-is.
+    pub fn json_unquote(self) -> DataType {
+        let v = match self {
+            DataType::Json(..) => self.to_json(),
+            _ => return self,
+        };
+        match v {
+            JsonValue::Null => DataType::None,
+            JsonValue::Bool(b) => b.into(),
+            JsonValue::String(s) => s.into(),
+            JsonValue::Number(ref n) if n.is_i64() => n.as_i64().unwrap().into(),
+            JsonValue::Number(ref n) => DataType::Float(n.as_f64().unwrap()),
+            _ => self,
         }
     }
 }
@@ -189,15 +709,30 @@ impl Hash for DataType {
                 let n: i64 = self.into();
                 n.hash(state)
             }
-            DataType::Real(i, f) => {
-                i.hash(state);
-                f.hash(state);
+            // numbers that are equal across types must hash the same, so we hash their exact
+            // value, and the integers among them like `Int` and `BigInt`
+            DataType::Real(..) | DataType::Float(..) | DataType::Decimal(..) => {
+                match self.exact_decimal() {
+                    Some((m, 0)) if m == i128::from(m as i64) => (m as i64).hash(state),
+                    Some((m, scale)) => {
+                        m.hash(state);
+                        scale.hash(state);
+                    }
+                    None => match *self {
+                        DataType::Float(f) => float_bits(f).hash(state),
+                        _ => unreachable!("only floats may not be exact decimals"),
+                    },
+                }
             }
             DataType::Text(..) | DataType::TinyText(..) => {
                 let t: Cow<str> = self.into();
                 t.hash(state)
             }
             DataType::Timestamp(ts) => ts.hash(state),
+            DataType::Bool(b) => b.hash(state),
+            DataType::ByteArray(ref bytes) => bytes.hash(state),
+            DataType::Date(d) => d.hash(state),
+            DataType::Json(ref s) => s.to_bytes().hash(state),
         }
     }
 }
@@ -240,6 +775,32 @@ impl From<f64> for DataType {
     }
 }
 
+impl From<bool> for DataType {
+    fn from(b: bool) -> Self {
+        DataType::Bool(b)
+    }
+}
+
+impl From<Vec<u8>> for DataType {
+    fn from(bytes: Vec<u8>) -> Self {
+        DataType::ByteArray(Arc::new(bytes))
+    }
+}
+
+impl From<NaiveDate> for DataType {
+    fn from(d: NaiveDate) -> Self {
+        DataType::Date(d)
+    }
+}
+
+impl From<JsonValue> for DataType {
+    fn from(v: JsonValue) -> Self {
+        use std::convert::TryFrom;
+        // JSON text escapes control characters, so it never contains a NUL byte
+        DataType::Json(ArcCStr::try_from(v.to_string()).unwrap())
+    }
+}
+
 impl<'a> From<&'a Literal> for DataType {
     fn from(l: &'a Literal) -> Self {
         match *l {
@@ -346,6 +907,8 @@ impl<'a> Into<f64> for &'a DataType {
             DataType::Real(i, f) => i as f64 + f64::from(f) / FLOAT_PRECISION,
             DataType::Int(i) => f64::from(i),
             DataType::BigInt(i) => i as f64,
+            DataType::Float(f) => f,
+            DataType::Decimal(m, scale) => m as f64 / 10f64.powi(i32::from(scale)),
             _ => unreachable!(),
         }
     }
@@ -385,12 +948,25 @@ macro_rules! arithmetic_operation (
             (&DataType::Int(a), &DataType::BigInt(b)) => (i64::from(a) $op b).into(),
             (&DataType::BigInt(a), &DataType::Int(b)) => (a $op i64::from(b)).into(),
 
-            (first @ &DataType::Int(..), second @ &DataType::Real(..)) |
-            (first @ &DataType::Real(..), second @ &DataType::Int(..)) |
-            (first @ &DataType::Real(..), second @ &DataType::Real(..)) => {
+            (first, second) if first.as_decimal().is_some() && second.as_decimal().is_some() => {
+                decimal_arithmetic(
+                    stringify!($op),
+                    first.as_decimal().unwrap(),
+                    second.as_decimal().unwrap(),
+                )
+            }
+            (first, second) if first.is_numeric() && second.is_numeric() => {
                 let a: f64 = first.into();
                 let b: f64 = second.into();
-                (a $op b).into()
+                match (first, second) {
+                    (&DataType::Real(..), &DataType::Real(..))
+                    | (&DataType::Real(..), &DataType::Int(..))
+                    | (&DataType::Real(..), &DataType::BigInt(..))
+                    | (&DataType::Int(..), &DataType::Real(..))
+                    | (&DataType::BigInt(..), &DataType::Real(..)) => (a $op b).into(),
+                    // floats are contagious, and decimals lose exactness when mixed with reals
+                    _ => DataType::Float(a $op b),
+                }
             }
             (first, second) => panic!(
                 format!(
@@ -432,6 +1008,10 @@ impl<'a, 'b> Div<&'b DataType> for &'a DataType {
     type Output = DataType;
 
     fn div(self, other: &'b DataType) -> DataType {
+        // like MySQL, dividing by zero gives NULL
+        if other.is_numeric() && *other == DataType::Int(0) {
+            return DataType::None;
+        }
         arithmetic_operation!(/, self, other)
     }
 }
@@ -456,6 +1036,62 @@ pub enum Modification {
     None,
 }
 
+/// A comparison between a column of a base table row and a constant.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Condition {
+    /// The index of the column to compare.
+    pub column: usize,
+    /// The comparison operator. Only equality and ordering comparisons are supported.
+    pub op: Operator,
+    /// The value to compare the column against.
+    pub value: DataType,
+}
+
+impl Condition {
+    /// Construct a new condition on the given column.
+    pub fn new<V: Into<DataType>>(column: usize, op: Operator, value: V) -> Self {
+        Condition {
+            column,
+            op,
+            value: value.into(),
+        }
+    }
+
+    /// Returns true if this condition can be evaluated at a base table.
+    pub fn is_supported(&self) -> bool {
+        match self.op {
+            Operator::Equal
+            | Operator::NotEqual
+            | Operator::Greater
+            | Operator::GreaterOrEqual
+            | Operator::Less
+            | Operator::LessOrEqual => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the given row satisfies this condition.
+    ///
+    /// A row never satisfies a condition that is not supported (see `is_supported`), or one on a
+    /// column the row does not have.
+    pub fn matches(&self, row: &[DataType]) -> bool {
+        let d = match row.get(self.column) {
+            Some(d) => d,
+            None => return false,
+        };
+        let v = &self.value;
+        match self.op {
+            Operator::Equal => d == v,
+            Operator::NotEqual => d != v,
+            Operator::Greater => d > v,
+            Operator::GreaterOrEqual => d >= v,
+            Operator::Less => d < v,
+            Operator::LessOrEqual => d <= v,
+            _ => false,
+        }
+    }
+}
+
 /// An operation to apply to a base table.
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum TableOperation {
@@ -481,6 +1117,18 @@ pub enum TableOperation {
         /// The key used to identify the row to update.
         key: Vec<DataType>,
     },
+    /// Delete all rows that satisfy every condition in `predicate`.
+    DeleteWhere {
+        /// The conditions a row must satisfy to be deleted.
+        predicate: Vec<Condition>,
+    },
+    /// Update all rows that satisfy every condition in `predicate`.
+    UpdateWhere {
+        /// The conditions a row must satisfy to be updated.
+        predicate: Vec<Condition>,
+        /// The modifications to make to each column of the matching rows.
+        set: Vec<Modification>,
+    },
 }
 
 impl TableOperation {
@@ -492,6 +1140,15 @@ impl TableOperation {
             _ => None,
         }
     }
+
+    #[doc(hidden)]
+    pub fn predicate(&self) -> Option<&[Condition]> {
+        match *self {
+            TableOperation::DeleteWhere { ref predicate }
+            | TableOperation::UpdateWhere { ref predicate, .. } => Some(predicate),
+            _ => None,
+        }
+    }
 }
 
 impl From<Vec<DataType>> for TableOperation {
@@ -612,6 +1269,283 @@ mod tests {
         assert_eq!(format!("{}", big_int), "5");
     }
 
+    #[test]
+    fn richer_data_type_display() {
+        let date = NaiveDate::from_ymd(2018, 10, 3);
+        assert_eq!(format!("{}", DataType::Float(2.5)), "2.5");
+        assert_eq!(format!("{}", DataType::Bool(true)), "true");
+        assert_eq!(format!("{}", DataType::from(vec![0u8, 0xab])), "0x00ab");
+        assert_eq!(format!("{}", DataType::Date(date)), "2018-10-03");
+        assert_eq!(format!("{}", DataType::Decimal(12345, 2)), "123.45");
+        assert_eq!(format!("{}", DataType::Decimal(-5, 2)), "-0.05");
+        assert_eq!(format!("{}", DataType::Decimal(7, 0)), "7");
+        assert_eq!(
+            format!("{:?}", DataType::Decimal(12345, 2)),
+            "Decimal(123.45)"
+        );
+        assert_eq!(format!("{:?}", DataType::Float(1.0)), "Float(1.0)");
+    }
+
+    #[test]
+    fn decimal_arithmetic() {
+        let a = DataType::Decimal(150, 2); // 1.50
+        let b = DataType::Decimal(25, 1); // 2.5
+        assert_eq!(&a + &b, DataType::Decimal(400, 2));
+        assert_eq!(&a - &b, DataType::Decimal(-100, 2));
+        assert_eq!(&a * &b, DataType::Decimal(375, 2));
+        assert_eq!(&b / &DataType::from(2), DataType::Decimal(125_000, 5));
+        assert_eq!(&a + &DataType::from(1), DataType::Decimal(250, 2));
+
+        // exact, unlike floats
+        let tenth = DataType::Decimal(1, 1);
+        assert_eq!(&(&tenth + &tenth) + &tenth, DataType::Decimal(3, 1));
+
+        // floats are contagious
+        assert_eq!(
+            &DataType::Float(0.5) + &DataType::from(1),
+            DataType::Float(1.5)
+        );
+        assert_eq!(&a + &DataType::from(0.5), DataType::Float(2.0));
+
+        // like in MySQL, division by zero and overflow give NULL
+        assert_eq!(&a / &DataType::Decimal(0, 2), DataType::None);
+        assert_eq!(&a / &DataType::from(0), DataType::None);
+        assert_eq!(&DataType::from(1) / &DataType::Float(0.0), DataType::None);
+        let max = DataType::Decimal(i64::max_value(), 0);
+        assert_eq!(&max + &max, DataType::None);
+        assert_eq!(&max / &DataType::Decimal(1, 18), DataType::None);
+    }
+
+    #[test]
+    fn richer_data_type_fungibility() {
+        let hash = |dt: &DataType| {
+            use std::collections::hash_map::DefaultHasher;
+            use std::hash::{Hash, Hasher};
+            let mut s = DefaultHasher::new();
+            dt.hash(&mut s);
+            s.finish()
+        };
+
+        // decimals compare by value regardless of scale
+        assert_eq!(DataType::Decimal(150, 2), DataType::Decimal(15, 1));
+        assert_eq!(
+            hash(&DataType::Decimal(150, 2)),
+            hash(&DataType::Decimal(15, 1))
+        );
+        assert!(DataType::Decimal(151, 2) > DataType::Decimal(15, 1));
+        assert!(DataType::Decimal(-1, 0) < DataType::Decimal(1, 18));
+
+        // signed zeroes are the same, and NaN is equal to itself and greater than everything
+        assert_eq!(DataType::Float(0.0), DataType::Float(-0.0));
+        assert_eq!(hash(&DataType::Float(0.0)), hash(&DataType::Float(-0.0)));
+        let nan = DataType::Float(::std::f64::NAN);
+        assert_eq!(nan, nan.clone());
+        assert!(nan > DataType::Float(::std::f64::INFINITY));
+        assert!(DataType::Float(-1.0) < DataType::Float(1.0));
+
+        assert!(DataType::Bool(false) < DataType::Bool(true));
+        assert!(DataType::from(vec![1u8]) < DataType::from(vec![1u8, 0]));
+        assert!(
+            DataType::Date(NaiveDate::from_ymd(2018, 1, 1))
+                < DataType::Date(NaiveDate::from_ymd(2018, 1, 2))
+        );
+
+        // numbers compare by value across types, and equal numbers hash the same
+        let halves = vec![
+            DataType::Decimal(15, 1),
+            DataType::Real(1, 500_000_000),
+            DataType::Float(1.5),
+        ];
+        for a in &halves {
+            for b in &halves {
+                assert_eq!(a, b);
+                assert_eq!(hash(a), hash(b));
+                assert_eq!(crate::shard_by(a, 7), crate::shard_by(b, 7));
+            }
+        }
+        let ones = vec![
+            DataType::Int(1),
+            DataType::BigInt(1),
+            DataType::Decimal(100, 2),
+            DataType::from(1.0),
+            DataType::Float(1.0),
+        ];
+        for a in &ones {
+            for b in &ones {
+                assert_eq!(a, b);
+                assert_eq!(hash(a), hash(b));
+                assert_eq!(crate::shard_by(a, 7), crate::shard_by(b, 7));
+            }
+        }
+        assert!(DataType::Int(10) > DataType::Float(1.0));
+        assert!(DataType::Float(1.0) < DataType::Int(10));
+        assert!(DataType::Decimal(-15, 1) < DataType::Int(-1));
+        assert!(DataType::Float(1e30) > DataType::BigInt(i64::max_value()));
+        assert!(DataType::Float(-1e-30) < DataType::Int(0));
+        assert!(DataType::Float(-1e-30) > DataType::Decimal(-1, 18));
+        // 0.1 is not exact as a float, so it is not equal to the decimal 0.1
+        assert_ne!(DataType::Float(0.1), DataType::Decimal(1, 1));
+        assert!(DataType::Float(0.1) > DataType::Decimal(1, 1));
+        assert!(DataType::Float(0.3) < DataType::Decimal(3, 1));
+        assert!(nan > DataType::BigInt(i64::max_value()));
+
+        // other types never compare equal, but are totally ordered
+        assert_ne!(DataType::Bool(true), DataType::Int(1));
+        assert!(DataType::Float(1e30) < DataType::Bool(false));
+        assert!(DataType::Date(NaiveDate::from_ymd(2018, 1, 1)) < DataType::None);
+    }
+
+    #[test]
+    fn coerce_to_column_types() {
+        assert_eq!(
+            DataType::Int(1).coerce_to(&SqlType::Bool),
+            DataType::Bool(true)
+        );
+        assert_eq!(
+            DataType::Int(2).coerce_to(&SqlType::Double),
+            DataType::Float(2.0)
+        );
+        assert_eq!(
+            DataType::from(2.5).coerce_to(&SqlType::Decimal(10, 2)),
+            DataType::Decimal(250, 2)
+        );
+        assert_eq!(
+            DataType::Int(3).coerce_to(&SqlType::Decimal(10, 2)),
+            DataType::Decimal(300, 2)
+        );
+        assert_eq!(
+            DataType::Decimal(12345, 3).coerce_to(&SqlType::Decimal(10, 2)),
+            DataType::Decimal(1235, 2)
+        );
+        assert_eq!(
+            DataType::from("ab").coerce_to(&SqlType::Blob),
+            DataType::from(b"ab".to_vec())
+        );
+        assert_eq!(
+            DataType::from("2018-10-03").coerce_to(&SqlType::Date),
+            DataType::Date(NaiveDate::from_ymd(2018, 10, 3))
+        );
+        assert_eq!(
+            DataType::Timestamp(NaiveDateTime::from_timestamp(0, 0)).coerce_to(&SqlType::Date),
+            DataType::Date(NaiveDate::from_ymd(1970, 1, 1))
+        );
+
+        // values that don't fit the type are left alone
+        assert_eq!(DataType::None.coerce_to(&SqlType::Double), DataType::None);
+        assert_eq!(
+            DataType::from("hi").coerce_to(&SqlType::Double),
+            "hi".into()
+        );
+        assert_eq!(DataType::Int(1).coerce_to(&SqlType::Text), DataType::Int(1));
+    }
+
+    #[test]
+    fn collated_text() {
+        let ascii = |s: &str| {
+            Collation::AsciiCaseInsensitive
+                .key(&DataType::from(s))
+                .into_owned()
+        };
+        let unicode = |s: &str| {
+            Collation::UnicodeCaseInsensitive
+                .key(&DataType::from(s))
+                .into_owned()
+        };
+
+        // text itself is always compared byte-wise
+        assert_ne!(DataType::from("Alice"), DataType::from("alice"));
+        assert_eq!(
+            DataType::from("B").cmp(&DataType::from("a")),
+            Ordering::Less
+        );
+
+        // text that only differs in case has the same key under a case-insensitive collation
+        assert_eq!(ascii("Alice"), ascii("aLiCe"));
+        assert_eq!(ascii("Alice"), DataType::from("alice"));
+        assert_ne!(ascii("Alice"), ascii("Alicia"));
+        assert_eq!(
+            Collation::Binary.key(&DataType::from("Alice")).into_owned(),
+            DataType::from("Alice")
+        );
+
+        // only the unicode collation folds non-ASCII letters
+        assert_ne!(ascii("ÅSA"), ascii("åsa"));
+        assert_eq!(unicode("ÅSA"), unicode("åsa"));
+
+        // sharding by the key puts text that is equal under the collation on the same shard
+        for shards in 2..8 {
+            assert_eq!(
+                crate::shard_by(&unicode("ÅSA"), shards),
+                crate::shard_by(&unicode("åsa"), shards)
+            );
+        }
+
+        // keys are only copied if they change, and only text is affected
+        let lower = DataType::from("alice");
+        match Collation::UnicodeCaseInsensitive.key(&lower) {
+            Cow::Borrowed(k) => assert_eq!(k, &lower),
+            Cow::Owned(_) => unreachable!(),
+        }
+        assert_eq!(
+            Collation::AsciiCaseInsensitive
+                .key(&DataType::Int(1))
+                .into_owned(),
+            1.into()
+        );
+
+        assert_eq!(
+            Collation::from_name("utf8mb4_general_ci"),
+            Some(Collation::UnicodeCaseInsensitive)
+        );
+        assert_eq!(
+            Collation::from_name("latin1_swedish_ci"),
+            Some(Collation::AsciiCaseInsensitive)
+        );
+        assert_eq!(Collation::from_name("utf8_bin"), Some(Collation::Binary));
+        assert_eq!(Collation::from_name("utf8mb4_0900_as_cs"), None);
+    }
+
+    #[test]
+    fn json_values() {
+        // documents are stored in a canonical form
+        let doc = DataType::json(r#"{ "b": [1, 2.5, null], "a": {"c": "x"} }"#).unwrap();
+        assert_eq!(doc.to_string(), r#"{"a":{"c":"x"},"b":[1,2.5,null]}"#);
+        assert_eq!(
+            doc,
+            DataType::json(r#"{"a":{"c":"x"},"b":[1,2.5,null]}"#).unwrap()
+        );
+        assert!(DataType::json("{").is_err());
+
+        let extract = |path: &str| doc.json_extract(&path.parse().unwrap());
+        assert_eq!(extract("$.a"), DataType::json(r#"{"c":"x"}"#).unwrap());
+        assert_eq!(extract("$.a.c"), DataType::json("\"x\"").unwrap());
+        assert_eq!(extract("$.a.c").json_unquote(), "x".into());
+        assert_eq!(extract("$.b[0]").json_unquote(), 1.into());
+        assert_eq!(extract("$.b[1]").json_unquote(), DataType::Float(2.5));
+        assert_eq!(extract("$.b[2]").json_unquote(), DataType::None);
+        assert_eq!(extract("$.d"), DataType::None);
+        assert_eq!(
+            DataType::from("x").json_extract(&"$".parse().unwrap()),
+            DataType::None
+        );
+
+        assert_eq!(DataType::from("x").to_json(), JsonValue::from("x"));
+        assert_eq!(DataType::Decimal(25, 1).to_json(), JsonValue::from(2.5));
+        assert_eq!(DataType::None.to_json(), JsonValue::Null);
+
+        use std::convert::TryFrom;
+        let broken = DataType::Json(ArcCStr::try_from("{").unwrap());
+        assert_eq!(broken.to_json(), JsonValue::from("{"));
+
+        assert_eq!(
+            DataType::from("[1, 2]").coerce_to_json(),
+            DataType::json("[1,2]").ok()
+        );
+        assert_eq!(DataType::from("[1,").coerce_to_json(), None);
+        assert_eq!(DataType::from(3).coerce_to_json(), DataType::json("3").ok());
+        assert_eq!(DataType::None.coerce_to_json(), Some(DataType::None));
+    }
+
     #[test]
     fn data_type_fungibility() {
         use std::convert::TryFrom;
@@ -36,6 +36,15 @@ pub struct NodeStats {
     pub materialized: MaterializationStatus,
 }
 
+/// Memory usage of a view, compared to its budget.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ViewStats {
+    /// Total memory size of the view's state, across all shards.
+    pub mem_size: u64,
+    /// The memory budget set for the view, if any.
+    pub budget: Option<u64>,
+}
+
 /// Statistics about the Soup data-flow.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GraphStats {
@@ -43,6 +52,9 @@ pub struct GraphStats {
     #[serde(deserialize_with = "deserialize_domainmap")]
     #[doc(hidden)]
     pub domains: DomainMap,
+    /// Memory usage of each view, by name.
+    #[serde(default)]
+    pub views: HashMap<String, ViewStats>,
 }
 
 use std::ops::Deref;
@@ -0,0 +1,381 @@
+//! Translation of SQL DML statements (`INSERT`, `UPDATE`, `DELETE`) into base table operations.
+//!
+//! Noria has no general-purpose query executor for writes. Instead, each statement is mapped
+//! directly onto the `TableOperation`s that base tables already understand. This means that `WHERE`
+//! clauses must be conjunctions of simple column comparisons (e.g., `DELETE FROM t WHERE id = ?`),
+//! and that updates can only express the modifications in `Modification`.
+use crate::data::*;
+use nom_sql::{
+    ArithmeticBase, ArithmeticExpression, ArithmeticOperator, Column, ConditionBase,
+    ConditionExpression, ConditionTree, FieldValueExpression, Literal, Operator, SqlQuery,
+};
+use std::vec;
+
+/// A DML statement that has been resolved against the schema of its target table.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Dml {
+    /// Insert all the given rows.
+    Insert(Vec<Vec<DataType>>),
+    /// Delete the row with the given key.
+    Delete(Vec<DataType>),
+    /// Apply the given column modifications to the row with the given key.
+    Update(Vec<DataType>, Vec<(usize, Modification)>),
+    /// Delete all rows that satisfy the given conditions.
+    DeleteWhere(Vec<Condition>),
+    /// Apply the given column modifications to all rows that satisfy the given conditions.
+    UpdateWhere(Vec<Condition>, Vec<(usize, Modification)>),
+}
+
+/// Return the name of the table targeted by the given DML statement.
+pub(crate) fn target_table(q: &SqlQuery) -> Result<&str, failure::Error> {
+    match *q {
+        SqlQuery::Insert(ref iq) => Ok(&iq.table.name),
+        SqlQuery::Update(ref uq) => Ok(&uq.table.name),
+        SqlQuery::Delete(ref dq) => Ok(&dq.table.name),
+        ref q => bail!("cannot execute non-DML statement: {:?}", q),
+    }
+}
+
+/// Supplies values for placeholders (`?`) in the order they appear in a statement.
+struct Params(vec::IntoIter<DataType>);
+
+impl Params {
+    fn resolve(&mut self, l: &Literal) -> Result<DataType, failure::Error> {
+        match *l {
+            Literal::Placeholder => self
+                .0
+                .next()
+                .ok_or_else(|| format_err!("not enough parameters given for placeholders")),
+            ref l => Ok(l.into()),
+        }
+    }
+
+    fn finish(mut self) -> Result<(), failure::Error> {
+        if self.0.next().is_some() {
+            bail!("too many parameters given for placeholders");
+        }
+        Ok(())
+    }
+}
+
+fn column_index(columns: &[String], table: &str, c: &Column) -> Result<usize, failure::Error> {
+    if let Some(ref t) = c.table {
+        if t != table {
+            bail!("column {} does not belong to table {}", c.name, table);
+        }
+    }
+    columns
+        .iter()
+        .position(|n| n == &c.name)
+        .ok_or_else(|| format_err!("table {} has no column {}", table, c.name))
+}
+
+/// The rows selected by a `WHERE` clause.
+enum Selection {
+    /// The single row with the given primary key.
+    Key(Vec<DataType>),
+    /// All rows that satisfy the given conditions.
+    Predicate(Vec<Condition>),
+}
+
+/// Determine which rows a `WHERE` clause selects.
+///
+/// The clause must be a conjunction of comparisons between columns and values. If it consists
+/// only of equalities that mention every key column exactly once, it selects a single row by key.
+fn selection(
+    columns: &[String],
+    key: &[usize],
+    table: &str,
+    ce: Option<&ConditionExpression>,
+    params: &mut Params,
+) -> Result<Selection, failure::Error> {
+    fn collect(
+        columns: &[String],
+        table: &str,
+        ce: &ConditionExpression,
+        params: &mut Params,
+        out: &mut Vec<Condition>,
+    ) -> Result<(), failure::Error> {
+        match *ce {
+            ConditionExpression::LogicalOp(ConditionTree {
+                operator: Operator::And,
+                ref left,
+                ref right,
+            }) => {
+                collect(columns, table, left, params, out)?;
+                collect(columns, table, right, params, out)
+            }
+            ConditionExpression::ComparisonOp(ConditionTree {
+                ref operator,
+                ref left,
+                ref right,
+            }) => match (&**left, &**right) {
+                (
+                    &ConditionExpression::Base(ConditionBase::Field(ref c)),
+                    &ConditionExpression::Base(ConditionBase::Literal(ref l)),
+                ) => {
+                    let cond = Condition::new(
+                        column_index(columns, table, c)?,
+                        operator.clone(),
+                        params.resolve(l)?,
+                    );
+                    if !cond.is_supported() {
+                        bail!("unsupported comparison in WHERE clause: {:?}", ce);
+                    }
+                    out.push(cond);
+                    Ok(())
+                }
+                _ => bail!("unsupported comparison in WHERE clause: {:?}", ce),
+            },
+            ConditionExpression::Bracketed(ref inner) => {
+                collect(columns, table, inner, params, out)
+            }
+            _ => bail!(
+                "WHERE clause must be a conjunction of comparisons: {:?}",
+                ce
+            ),
+        }
+    }
+
+    if key.is_empty() {
+        bail!("table {} has no primary key", table);
+    }
+
+    let ce = ce.ok_or_else(|| format_err!("statement must have a WHERE clause"))?;
+    let mut conds = Vec::new();
+    collect(columns, table, ce, params, &mut conds)?;
+
+    let is_key = conds.len() == key.len()
+        && key.iter().all(|&k| {
+            conds
+                .iter()
+                .filter(|c| c.column == k && c.op == Operator::Equal)
+                .count()
+                == 1
+        });
+    if !is_key {
+        return Ok(Selection::Predicate(conds));
+    }
+
+    Ok(Selection::Key(
+        key.iter()
+            .map(|&k| {
+                conds
+                    .iter()
+                    .find(|c| c.column == k)
+                    .map(|c| c.value.clone())
+                    .unwrap()
+            })
+            .collect(),
+    ))
+}
+
+fn modification(
+    coli: usize,
+    columns: &[String],
+    table: &str,
+    e: &FieldValueExpression,
+    params: &mut Params,
+) -> Result<Modification, failure::Error> {
+    let is_self = |b: &ArithmeticBase| match *b {
+        ArithmeticBase::Column(ref c) => column_index(columns, table, c).ok() == Some(coli),
+        _ => false,
+    };
+
+    match *e {
+        FieldValueExpression::Literal(ref l) => Ok(Modification::Set(params.resolve(l)?)),
+        FieldValueExpression::Arithmetic(ArithmeticExpression {
+            ref op,
+            ref left,
+            ref right,
+            ..
+        }) => {
+            // only `x = x + v`, `x = v + x`, and `x = x - v` can be expressed
+            let (op, v) = match (op, left, right) {
+                (&ArithmeticOperator::Add, l, &ArithmeticBase::Scalar(ref v)) if is_self(l) => {
+                    (Operation::Add, v)
+                }
+                (&ArithmeticOperator::Add, &ArithmeticBase::Scalar(ref v), r) if is_self(r) => {
+                    (Operation::Add, v)
+                }
+                (&ArithmeticOperator::Subtract, l, &ArithmeticBase::Scalar(ref v))
+                    if is_self(l) =>
+                {
+                    (Operation::Sub, v)
+                }
+                _ => bail!(
+                    "unsupported modification of column {}: {:?}",
+                    columns[coli],
+                    e
+                ),
+            };
+            Ok(Modification::Apply(op, params.resolve(v)?))
+        }
+    }
+}
+
+/// Resolve a parsed DML statement against the given table schema, substituting `params` for the
+/// placeholders in the statement.
+///
+/// Columns that an `INSERT` does not give values for take their value from `defaults`.
+pub(crate) fn resolve(
+    q: &SqlQuery,
+    columns: &[String],
+    defaults: &[DataType],
+    key: &[usize],
+    params: Vec<DataType>,
+) -> Result<Dml, failure::Error> {
+    let mut params = Params(params.into_iter());
+    let dml = match *q {
+        SqlQuery::Insert(ref iq) => {
+            let table = &iq.table.name;
+            let fields = match iq.fields {
+                Some(ref fields) => fields
+                    .iter()
+                    .map(|c| column_index(columns, table, c))
+                    .collect::<Result<Vec<_>, _>>()?,
+                None => (0..columns.len()).collect(),
+            };
+
+            let rows = iq
+                .data
+                .iter()
+                .map(|values| {
+                    if values.len() != fields.len() {
+                        bail!(
+                            "INSERT has {} columns but {} values",
+                            fields.len(),
+                            values.len()
+                        );
+                    }
+                    let mut row = defaults.to_vec();
+                    for (&coli, v) in fields.iter().zip(values) {
+                        row[coli] = params.resolve(v)?;
+                    }
+                    Ok(row)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Dml::Insert(rows)
+        }
+        SqlQuery::Update(ref uq) => {
+            let table = &uq.table.name;
+            // SET comes before WHERE, so resolve it first to consume parameters in order
+            let set = uq
+                .fields
+                .iter()
+                .map(|&(ref c, ref e)| {
+                    let coli = column_index(columns, table, c)?;
+                    Ok((coli, modification(coli, columns, table, e, &mut params)?))
+                })
+                .collect::<Result<Vec<_>, failure::Error>>()?;
+            match selection(columns, key, table, uq.where_clause.as_ref(), &mut params)? {
+                Selection::Key(key) => Dml::Update(key, set),
+                Selection::Predicate(predicate) => Dml::UpdateWhere(predicate, set),
+            }
+        }
+        SqlQuery::Delete(ref dq) => {
+            let table = &dq.table.name;
+            match selection(columns, key, table, dq.where_clause.as_ref(), &mut params)? {
+                Selection::Key(key) => Dml::Delete(key),
+                Selection::Predicate(predicate) => Dml::DeleteWhere(predicate),
+            }
+        }
+        ref q => bail!("cannot execute non-DML statement: {:?}", q),
+    };
+    params.finish()?;
+    Ok(dml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nom_sql::parser::parse_query;
+
+    fn resolve_str(q: &str, params: Vec<DataType>) -> Result<Dml, failure::Error> {
+        let columns = vec!["id".to_owned(), "title".to_owned(), "votes".to_owned()];
+        let defaults = vec![DataType::None, DataType::None, 0.into()];
+        let q = parse_query(q).unwrap();
+        resolve(&q, &columns, &defaults, &[0], params)
+    }
+
+    #[test]
+    fn insert() {
+        assert_eq!(
+            resolve_str("INSERT INTO a VALUES (1, 'foo', 0);", vec![]).unwrap(),
+            Dml::Insert(vec![vec![1.into(), "foo".into(), 0.into()]])
+        );
+        assert_eq!(
+            resolve_str(
+                "INSERT INTO a (votes, id) VALUES (?, ?);",
+                vec![3.into(), 1.into()]
+            )
+            .unwrap(),
+            Dml::Insert(vec![vec![1.into(), DataType::None, 3.into()]])
+        );
+        assert_eq!(
+            resolve_str("INSERT INTO a (id) VALUES (1);", vec![]).unwrap(),
+            Dml::Insert(vec![vec![1.into(), DataType::None, 0.into()]])
+        );
+        assert!(resolve_str("INSERT INTO a (id) VALUES (?);", vec![]).is_err());
+        assert!(resolve_str("INSERT INTO a (nope) VALUES (1);", vec![]).is_err());
+    }
+
+    #[test]
+    fn delete() {
+        assert_eq!(
+            resolve_str("DELETE FROM a WHERE id = ?;", vec![1.into()]).unwrap(),
+            Dml::Delete(vec![1.into()])
+        );
+        assert_eq!(
+            resolve_str("DELETE FROM a WHERE votes = 1;", vec![]).unwrap(),
+            Dml::DeleteWhere(vec![Condition::new(2, Operator::Equal, 1)])
+        );
+        assert_eq!(
+            resolve_str("DELETE FROM a WHERE id = 1 AND votes < ?;", vec![3.into()]).unwrap(),
+            Dml::DeleteWhere(vec![
+                Condition::new(0, Operator::Equal, 1),
+                Condition::new(2, Operator::Less, 3),
+            ])
+        );
+        assert!(resolve_str("DELETE FROM a WHERE id = 1 OR id = 2;", vec![]).is_err());
+        assert!(resolve_str("DELETE FROM a;", vec![]).is_err());
+        assert!(resolve_str("DELETE FROM a WHERE id = ?;", vec![1.into(), 2.into()]).is_err());
+    }
+
+    #[test]
+    fn update() {
+        assert_eq!(
+            resolve_str(
+                "UPDATE a SET title = ?, votes = votes + 1 WHERE id = ?;",
+                vec!["bar".into(), 1.into()]
+            )
+            .unwrap(),
+            Dml::Update(
+                vec![1.into()],
+                vec![
+                    (1, Modification::Set("bar".into())),
+                    (2, Modification::Apply(Operation::Add, 1.into())),
+                ]
+            )
+        );
+        assert_eq!(
+            resolve_str("UPDATE a SET votes = votes - 2 WHERE id = 1;", vec![]).unwrap(),
+            Dml::Update(
+                vec![1.into()],
+                vec![(2, Modification::Apply(Operation::Sub, 2.into()))]
+            )
+        );
+        assert_eq!(
+            resolve_str(
+                "UPDATE a SET votes = 0 WHERE title = ?;",
+                vec!["foo".into()]
+            )
+            .unwrap(),
+            Dml::UpdateWhere(
+                vec![Condition::new(1, Operator::Equal, "foo")],
+                vec![(2, Modification::Set(0.into()))]
+            )
+        );
+        assert!(resolve_str("UPDATE a SET votes = id + 1 WHERE id = 1;", vec![]).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// One step along a `JsonPath`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum JsonPathLeg {
+    /// The member of an object with the given name.
+    Member(String),
+    /// The element of an array at the given position.
+    Index(usize),
+}
+
+/// A path to a value within a JSON document, written like `$.a.b[0]` as in MySQL's
+/// `JSON_EXTRACT`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct JsonPath(Vec<JsonPathLeg>);
+
+impl JsonPath {
+    /// Construct a path from its legs.
+    pub fn new(legs: Vec<JsonPathLeg>) -> Self {
+        JsonPath(legs)
+    }
+
+    /// The value that this path leads to in `doc`, if there is one.
+    pub fn extract<'a>(&self, doc: &'a Value) -> Option<&'a Value> {
+        self.0.iter().try_fold(doc, |v, leg| match *leg {
+            JsonPathLeg::Member(ref name) => v.get(name.as_str()),
+            JsonPathLeg::Index(i) => v.get(i),
+        })
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = failure::Error;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let mut rest = path.trim();
+        if !rest.starts_with('$') {
+            bail!("JSON path {:?} does not start with $", path);
+        }
+        rest = &rest[1..];
+
+        let mut legs = Vec::new();
+        while !rest.is_empty() {
+            if rest.starts_with(".\"") {
+                let name = &rest[2..];
+                let end = name
+                    .find('"')
+                    .ok_or_else(|| format_err!("unterminated member name in {:?}", path))?;
+                legs.push(JsonPathLeg::Member(name[..end].to_owned()));
+                rest = &name[end + 1..];
+            } else if rest.starts_with('.') {
+                let name = &rest[1..];
+                let end = name
+                    .find(|c: char| c == '.' || c == '[')
+                    .unwrap_or(name.len());
+                if end == 0 {
+                    bail!("empty member name in JSON path {:?}", path);
+                }
+                legs.push(JsonPathLeg::Member(name[..end].to_owned()));
+                rest = &name[end..];
+            } else if rest.starts_with('[') {
+                let end = rest
+                    .find(']')
+                    .ok_or_else(|| format_err!("unterminated array index in {:?}", path))?;
+                let i = rest[1..end]
+                    .trim()
+                    .parse()
+                    .map_err(|_| format_err!("invalid array index in JSON path {:?}", path))?;
+                legs.push(JsonPathLeg::Index(i));
+                rest = &rest[end + 1..];
+            } else {
+                bail!("unexpected {:?} in JSON path {:?}", rest, path);
+            }
+        }
+
+        Ok(JsonPath(legs))
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "$")?;
+        for leg in &self.0 {
+            match *leg {
+                JsonPathLeg::Member(ref name)
+                    if !name.is_empty()
+                        && name.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+                {
+                    write!(f, ".{}", name)?
+                }
+                JsonPathLeg::Member(ref name) => write!(f, ".\"{}\"", name)?,
+                JsonPathLeg::Index(i) => write!(f, "[{}]", i)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_paths() {
+        let path: JsonPath = "$.a.\"b c\"[2].d".parse().unwrap();
+        assert_eq!(
+            path,
+            JsonPath::new(vec![
+                JsonPathLeg::Member("a".to_owned()),
+                JsonPathLeg::Member("b c".to_owned()),
+                JsonPathLeg::Index(2),
+                JsonPathLeg::Member("d".to_owned()),
+            ])
+        );
+        assert_eq!(path.to_string(), "$.a.\"b c\"[2].d");
+        assert_eq!("$".parse::<JsonPath>().unwrap(), JsonPath::new(vec![]));
+
+        assert!("a.b".parse::<JsonPath>().is_err());
+        assert!("$.".parse::<JsonPath>().is_err());
+        assert!("$[x]".parse::<JsonPath>().is_err());
+        assert!("$.\"a".parse::<JsonPath>().is_err());
+    }
+
+    #[test]
+    fn it_extracts_values() {
+        let doc: Value = serde_json::from_str(r#"{"a": {"b": [1, {"c": "x"}]}}"#).unwrap();
+        let extract = |path: &str| path.parse::<JsonPath>().unwrap().extract(&doc).cloned();
+        assert_eq!(extract("$.a.b[0]"), Some(Value::from(1)));
+        assert_eq!(extract("$.a.b[1].c"), Some(Value::from("x")));
+        assert_eq!(extract("$"), Some(doc.clone()));
+        assert_eq!(extract("$.a.b[2]"), None);
+        assert_eq!(extract("$.a.c"), None);
+        assert_eq!(extract("$.a[0]"), None);
+    }
+}
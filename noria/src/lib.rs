@@ -92,6 +92,8 @@
 //! use [`ControllerHandle::table`] to get a handle to the new base table. Base tables support
 //! similar operations as SQL tables, such as [`Table::insert`], [`Table::update`],
 //! [`Table::delete`], and also more esoteric operations like [`Table::insert_or_update`].
+//! Simple SQL `INSERT`, `UPDATE`, and `DELETE` statements can also be executed directly using
+//! [`ControllerHandle::execute`].
 //!
 //! # Alternatives
 //!
@@ -111,11 +113,15 @@ extern crate serde_derive;
 #[macro_use]
 extern crate slog;
 
+use nom_sql::SqlType;
 use petgraph::graph::NodeIndex;
 use std::collections::HashMap;
+use std::time::SystemTime;
 
 mod controller;
 mod data;
+mod dml;
+mod json;
 mod table;
 mod view;
 
@@ -139,7 +145,7 @@ pub mod prelude {
 
 /// Noria errors.
 pub mod error {
-    pub use crate::table::TableError;
+    pub use crate::table::{ConstraintViolation, TableError};
     pub use crate::view::ViewError;
 
     /// An error occured during transport (i.e., while sending or receiving).
@@ -167,15 +173,16 @@ pub mod error {
 }
 
 pub use crate::controller::{ControllerDescriptor, ControllerHandle, ControllerPointer};
-pub use crate::data::{DataType, Modification, Operation, TableOperation};
+pub use crate::data::{Collation, Condition, DataType, Modification, Operation, TableOperation};
+pub use crate::json::{JsonPath, JsonPathLeg};
 pub use crate::table::Table;
-pub use crate::view::View;
+pub use crate::view::{View, WarmProgress};
 
 #[doc(hidden)]
-pub use crate::table::Input;
+pub use crate::table::{Input, InputAck};
 
 #[doc(hidden)]
-pub use crate::view::{ReadQuery, ReadReply};
+pub use crate::view::{sort_by_order, ReadQuery, ReadReply};
 
 #[doc(hidden)]
 pub mod builders {
@@ -210,24 +217,122 @@ pub struct ActivationResult {
     pub expressions_removed: usize,
 }
 
+/// A version of the recipe that was installed on the controller.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct RecipeVersion {
+    /// The recipe's version number.
+    pub version: usize,
+    /// The recipe that was installed, or the queries the previous version was extended with.
+    pub sql: String,
+    /// Whether `sql` extended the previous version rather than replacing it.
+    pub extension: bool,
+    /// When this version was installed.
+    pub installed_at: SystemTime,
+}
+
+/// The changes that installing or extending a recipe would make, as computed without applying it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecipePlan {
+    /// The result that activating the recipe would produce.
+    ///
+    /// Node indices of new nodes are only indicative, as the graph may change before the recipe
+    /// is actually applied.
+    pub activation: ActivationResult,
+    /// Name and description of every node that would be added.
+    pub new_nodes: Vec<(String, String)>,
+    /// Name and description of every existing node that new nodes would be attached to.
+    pub reused_nodes: Vec<(String, String)>,
+    /// Upper bound on the number of rows that would be replayed to populate the new nodes.
+    ///
+    /// This is the number of rows in the base tables that the reused nodes derive from.
+    pub replay_rows: usize,
+}
+
+/// A column of a table or view, as reported by `ControllerHandle::describe`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ColumnDescription {
+    /// The column's name.
+    pub name: String,
+    /// The column's SQL type.
+    ///
+    /// This is `None` for view columns whose type is not known, such as arithmetic expressions.
+    pub sql_type: Option<SqlType>,
+    /// Whether the column may hold NULL values.
+    ///
+    /// View columns are always considered nullable, as outer joins may introduce NULLs.
+    pub nullable: bool,
+}
+
+/// The schema of a base table.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct TableDescription {
+    /// The table's columns, in the order in which writes provide them.
+    pub columns: Vec<ColumnDescription>,
+    /// The names of the columns that make up the table's primary key, if it has one.
+    pub primary_key: Vec<String>,
+    /// The names of the columns of each of the table's unique keys.
+    pub unique_keys: Vec<Vec<String>>,
+}
+
+/// The schema of a view, and how it is materialized.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ViewDescription {
+    /// The columns that lookups into the view return.
+    pub columns: Vec<ColumnDescription>,
+    /// The columns that lookups into the view provide values for, in order.
+    pub key_columns: Vec<ColumnDescription>,
+    /// Whether the view is partially materialized, and so only holds results for keys that have
+    /// been looked up.
+    pub partial: bool,
+    /// Whether the view is split into shards by its key.
+    pub sharded: bool,
+    /// The number of shards the view is split into; 1 if the view is not sharded.
+    pub shards: usize,
+}
+
+/// The schema of a table or view, as reported by `ControllerHandle::describe`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum Description {
+    /// A base table.
+    Table(TableDescription),
+    /// A view.
+    View(ViewDescription),
+}
+
 #[doc(hidden)]
 #[inline]
 pub fn shard_by(dt: &DataType, shards: usize) -> usize {
     match *dt {
-        DataType::Int(n) => n as usize % shards,
-        DataType::BigInt(n) => n as usize % shards,
-        DataType::Text(..) | DataType::TinyText(..) => {
-            use std::borrow::Cow;
-            use std::hash::Hasher;
-            let mut hasher = fnv::FnvHasher::default();
-            let s: Cow<str> = dt.into();
-            hasher.write(s.as_bytes());
-            hasher.finish() as usize % shards
-        }
+        // numbers that are equal across types must end up on the same shard, so integral values
+        // of any type are sharded like integers, and the rest by the hash of their exact value
+        DataType::Int(..)
+        | DataType::BigInt(..)
+        | DataType::Real(..)
+        | DataType::Float(..)
+        | DataType::Decimal(..) => match dt.exact_decimal() {
+            Some((n, 0)) if n == i128::from(n as i64) => n as i64 as usize % shards,
+            _ => {
+                use std::hash::{Hash, Hasher};
+                let mut hasher = fnv::FnvHasher::default();
+                dt.hash(&mut hasher);
+                hasher.finish() as usize % shards
+            }
+        },
         // a bit hacky: send all NULL values to the first shard
         DataType::None => 0,
-        ref x => {
-            unimplemented!("asked to shard on value {:?}", x);
+        // text is sharded byte-wise; callers pass `Collation::key` of a value so that text that is
+        // equal under the column's collation ends up on the same shard
+        DataType::Text(..)
+        | DataType::TinyText(..)
+        | DataType::Timestamp(..)
+        | DataType::Bool(..)
+        | DataType::ByteArray(..)
+        | DataType::Date(..)
+        | DataType::Json(..) => {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = fnv::FnvHasher::default();
+            dt.hash(&mut hasher);
+            hasher.finish() as usize % shards
         }
     }
 }
@@ -4,7 +4,7 @@ use crate::debug::trace::Tracer;
 use crate::error::TransportError;
 use crate::internal::*;
 use crate::{ExclusiveConnection, LocalOrNot, SharedConnection};
-use nom_sql::CreateTableStatement;
+use nom_sql::{ColumnConstraint, ColumnSpecification, CreateTableStatement};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io;
@@ -20,6 +20,35 @@ pub struct Input {
     pub tracer: Tracer,
 }
 
+/// The acknowledgement a base table sends back for each `Input` once it has been applied.
+#[doc(hidden)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct InputAck {
+    /// The values assigned to `AUTO_INCREMENT` columns, in the order the rows were inserted.
+    pub generated: Vec<DataType>,
+    /// The operations that were rejected, by their index in the `Input`, and the constraint that
+    /// each one violated.
+    pub rejected: Vec<(usize, ConstraintViolation)>,
+}
+
+/// A table constraint that an operation violated, which caused the operation to be rejected.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Fail)]
+pub enum ConstraintViolation {
+    /// A `NULL` value was given for a column declared `NOT NULL`.
+    #[fail(display = "column '{}' cannot be NULL", _0)]
+    NotNull(String),
+    /// A row would have duplicated the values of another row in a `UNIQUE` key.
+    #[fail(display = "duplicate entry for unique key ({})", _0)]
+    Unique(String),
+    /// A row referenced a row of another table that does not exist.
+    #[fail(display = "no row matches foreign key ({})", _0)]
+    ForeignKey(String),
+    /// The operation cannot be applied to the table at all, for example because it uses a
+    /// predicate that base tables cannot evaluate.
+    #[fail(display = "unsupported operation: {}", _0)]
+    Unsupported(String),
+}
+
 /// A failed Table operation.
 #[derive(Debug, Fail)]
 pub enum TableError {
@@ -37,6 +66,18 @@ pub enum TableError {
         _1
     )]
     WrongKeyColumnCount(usize, usize),
+    /// A column that does not exist in the table was named.
+    #[fail(display = "unknown column '{}'", _0)]
+    UnknownColumn(String),
+    /// An operation was rejected by the base table because it violated a constraint.
+    #[fail(display = "{}", _0)]
+    ConstraintViolation(#[cause] ConstraintViolation),
+    /// A predicate used a comparison that base tables cannot evaluate.
+    #[fail(display = "unsupported predicate condition: {:?}", _0)]
+    UnsupportedCondition(Condition),
+    /// A value given for a `JSON` column is not a valid JSON document.
+    #[fail(display = "invalid JSON document for column '{}'", _0)]
+    InvalidJson(String),
     /// The underlying connection to Soup produced an error.
     #[fail(display = "{}", _0)]
     TransportError(#[cause] TransportError),
@@ -60,6 +101,8 @@ pub struct TableBuilder {
     pub table_name: String,
     pub columns: Vec<String>,
     pub schema: Option<CreateTableStatement>,
+    pub json_columns: Vec<String>,
+    pub key_collation: Collation,
 
     pub local_port: Option<u16>,
 }
@@ -98,6 +141,8 @@ impl TableBuilder {
             table_name: self.table_name,
             columns: self.columns,
             schema: self.schema,
+            json_columns: self.json_columns,
+            key_collation: self.key_collation,
             exclusivity: SharedConnection,
         })
     }
@@ -120,6 +165,10 @@ pub struct Table<E = SharedConnection> {
     table_name: String,
     columns: Vec<String>,
     schema: Option<CreateTableStatement>,
+    /// The columns declared with the `JSON` type, which only hold valid JSON documents.
+    json_columns: Vec<String>,
+    /// The collation of the first key column, under which writes are sharded.
+    key_collation: Collation,
 
     #[allow(dead_code)]
     exclusivity: E,
@@ -138,6 +187,8 @@ impl Clone for Table<SharedConnection> {
             table_name: self.table_name.clone(),
             columns: self.columns.clone(),
             schema: self.schema.clone(),
+            json_columns: self.json_columns.clone(),
+            key_collation: self.key_collation,
             exclusivity: SharedConnection,
         }
     }
@@ -162,6 +213,8 @@ impl Table<SharedConnection> {
             table_name: self.table_name.clone(),
             columns: self.columns.clone(),
             schema: self.schema.clone(),
+            json_columns: self.json_columns.clone(),
+            key_collation: self.key_collation,
             exclusivity: ExclusiveConnection,
         })
     }
@@ -194,6 +247,168 @@ impl<E> Table<E> {
         self.schema.as_ref()
     }
 
+    /// Get the value that each column takes if an insert does not give one.
+    ///
+    /// This is the `DEFAULT` declared for the column in the table's schema, or `NULL` if the
+    /// column has no declared default.
+    pub(crate) fn defaults(&self) -> Vec<DataType> {
+        let default = |name: &str| {
+            self.schema
+                .as_ref()
+                .and_then(|schema| schema.fields.iter().find(|cs| cs.column.name == name))
+                .and_then(|cs| {
+                    cs.constraints
+                        .iter()
+                        .filter_map(|c| match *c {
+                            ColumnConstraint::DefaultValue(ref dv) => Some(DataType::from(dv)),
+                            _ => None,
+                        })
+                        .next()
+                })
+                .unwrap_or(DataType::None)
+        };
+        self.columns.iter().map(|c| default(c)).collect()
+    }
+
+    /// Returns true if the base assigns the key of rows that are inserted without one.
+    fn assigns_key(&self) -> bool {
+        if self.key.len() != 1 {
+            return false;
+        }
+        let specs = match self.column_specs() {
+            Some(specs) => specs,
+            None => return false,
+        };
+        let spec = self
+            .client_column(self.key[0])
+            .and_then(|col| specs.get(col));
+        match spec {
+            Some(Some(cs)) => cs.constraints.iter().any(|c| match *c {
+                ColumnConstraint::AutoIncrement => true,
+                _ => false,
+            }),
+            _ => false,
+        }
+    }
+
+    /// Get the specification of each column, if the table's schema is known.
+    fn column_specs(&self) -> Option<Vec<Option<&ColumnSpecification>>> {
+        let schema = self.schema.as_ref()?;
+        Some(
+            self.columns
+                .iter()
+                .map(|name| schema.fields.iter().find(|cs| cs.column.name == *name))
+                .collect(),
+        )
+    }
+
+    /// Get the position among the table's columns of the base column with index `col`, or `None`
+    /// if that column has been dropped.
+    ///
+    /// Keys and predicates name columns by their index in the base, which also counts dropped
+    /// columns, whereas rows and column specifications only cover the columns that remain.
+    fn client_column(&self, col: usize) -> Option<usize> {
+        if self.dropped.contains_key(col) {
+            return None;
+        }
+        Some(col - self.dropped.keys().filter(|&d| d < col).count())
+    }
+
+    /// Convert the values in the given operations to the types of the columns they are destined
+    /// for, so that, say, an integer written to a `DOUBLE` column is stored as a float.
+    ///
+    /// Values for `JSON` columns are parsed as JSON documents, and an error is returned if one is
+    /// not valid.
+    fn coerce_values(&self, ops: &mut [TableOperation]) -> Result<(), TableError> {
+        let specs = match self.column_specs() {
+            Some(specs) => specs,
+            None => return Ok(()),
+        };
+        let coerce = |v: &mut DataType, col: usize| -> Result<(), TableError> {
+            if let Some(Some(cs)) = specs.get(col) {
+                let old = ::std::mem::replace(v, DataType::None);
+                *v = old.coerce_to(&cs.sql_type);
+                if self.json_columns.contains(&cs.column.name) {
+                    let old = ::std::mem::replace(v, DataType::None);
+                    *v = old
+                        .coerce_to_json()
+                        .ok_or_else(|| TableError::InvalidJson(cs.column.name.clone()))?;
+                }
+            }
+            Ok(())
+        };
+        let coerce_base = |v: &mut DataType, col: usize| -> Result<(), TableError> {
+            match self.client_column(col) {
+                Some(col) => coerce(v, col),
+                None => Ok(()),
+            }
+        };
+        let coerce_mods = |set: &mut [Modification]| -> Result<(), TableError> {
+            for (col, m) in set.iter_mut().enumerate() {
+                match *m {
+                    Modification::Set(ref mut v) | Modification::Apply(_, ref mut v) => {
+                        coerce(v, col)?
+                    }
+                    Modification::None => {}
+                }
+            }
+            Ok(())
+        };
+        let coerce_predicate = |predicate: &mut [Condition]| -> Result<(), TableError> {
+            for c in predicate {
+                coerce_base(&mut c.value, c.column)?;
+            }
+            Ok(())
+        };
+
+        for op in ops {
+            match *op {
+                TableOperation::Insert(ref mut row) => {
+                    for (col, v) in row.iter_mut().enumerate() {
+                        coerce(v, col)?;
+                    }
+                }
+                TableOperation::InsertOrUpdate {
+                    ref mut row,
+                    ref mut update,
+                } => {
+                    for (col, v) in row.iter_mut().enumerate() {
+                        coerce(v, col)?;
+                    }
+                    coerce_mods(update)?;
+                }
+                TableOperation::Delete { ref mut key } => {
+                    for (&col, v) in self.key.iter().zip(key.iter_mut()) {
+                        coerce_base(v, col)?;
+                    }
+                }
+                TableOperation::Update {
+                    ref mut set,
+                    ref mut key,
+                } => {
+                    coerce_mods(set)?;
+                    for (&col, v) in self.key.iter().zip(key.iter_mut()) {
+                        coerce_base(v, col)?;
+                    }
+                }
+                TableOperation::DeleteWhere { ref mut predicate } => coerce_predicate(predicate)?,
+                TableOperation::UpdateWhere {
+                    ref mut predicate,
+                    ref mut set,
+                } => {
+                    coerce_predicate(predicate)?;
+                    coerce_mods(set)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the indices of the columns that make up this table's key.
+    pub(crate) fn key(&self) -> &[usize] {
+        &self.key
+    }
+
     /// Get the local address this `Table` is bound to.
     pub fn local_addr(&self) -> io::Result<SocketAddr> {
         self.domain_input_handle.borrow().local_addr()
@@ -271,21 +486,29 @@ impl<E> Table<E> {
         }
     }
 
-    fn prep_records(&self, tracer: Tracer, mut ops: Vec<TableOperation>) -> Input {
+    fn prep_records(
+        &self,
+        tracer: Tracer,
+        mut ops: Vec<TableOperation>,
+    ) -> Result<Input, TableError> {
+        self.coerce_values(&mut ops)?;
         self.inject_dropped_cols(&mut ops);
-        Input {
+        Ok(Input {
             dst: self.addr,
             data: ops,
             tracer,
-        }
+        })
     }
 
-    fn send(&mut self, ops: Vec<TableOperation>) -> Result<(), TransportError> {
+    fn send(&mut self, ops: Vec<TableOperation>) -> Result<Vec<InputAck>, TableError> {
         let tracer = self.tracer.take();
-        let m = self.prep_records(tracer, ops);
-        self.domain_input_handle
-            .borrow_mut()
-            .base_send(m, &self.key[..])
+        let m = self.prep_records(tracer, ops)?;
+        let assigned_key = self.assigns_key();
+        let acks =
+            self.domain_input_handle
+                .borrow_mut()
+                .base_send(m, &self.key[..], assigned_key)?;
+        check_acks(acks)
     }
 
     /// Perform multiple operations on this base table in one batch.
@@ -294,6 +517,7 @@ impl<E> Table<E> {
         I: IntoIterator<Item = V>,
         V: Into<TableOperation>,
     {
+        let assigned_key = self.assigns_key();
         let mut dih = self.domain_input_handle.borrow_mut();
         let mut batch_putter = dih.sender();
 
@@ -311,20 +535,24 @@ impl<E> Table<E> {
             .collect::<Result<_, _>>()?;
 
         let tracer = self.tracer.clone();
-        let m = self.prep_records(tracer, data);
-        batch_putter.enqueue(m, &self.key[..])?;
+        let m = self.prep_records(tracer, data)?;
+        batch_putter.enqueue(m, &self.key[..], assigned_key)?;
         self.tracer.take();
-        batch_putter.wait()?;
+        check_acks(batch_putter.wait()?)?;
         Ok(())
     }
 
     /// Perform multiple operations on this base table and only wait for acks once they have all
     /// been enqueued.
+    ///
+    /// If the base table rejects some of the operations because they violate a constraint of the
+    /// table, the remaining operations are still applied, and the first violation is returned.
     pub fn insert_then_wait<I, V>(&mut self, i: I) -> Result<(), TableError>
     where
         I: IntoIterator<Item = V>,
         V: Into<TableOperation>,
     {
+        let assigned_key = self.assigns_key();
         let mut dih = self.domain_input_handle.borrow_mut();
         let mut batch_putter = dih.sender();
 
@@ -338,12 +566,12 @@ impl<E> Table<E> {
             }
 
             let tracer = self.tracer.clone();
-            let m = self.prep_records(tracer, data);
-            batch_putter.enqueue(m, &self.key[..])?;
+            let m = self.prep_records(tracer, data)?;
+            batch_putter.enqueue(m, &self.key[..], assigned_key)?;
         }
 
         self.tracer.take();
-        batch_putter.wait()?;
+        check_acks(batch_putter.wait()?)?;
         Ok(())
     }
 
@@ -353,6 +581,7 @@ impl<E> Table<E> {
     where
         I: IntoIterator<Item = Vec<TableOperation>>,
     {
+        let assigned_key = self.assigns_key();
         let mut dih = self.domain_input_handle.borrow_mut();
         let mut batch_putter = dih.sender();
 
@@ -364,17 +593,23 @@ impl<E> Table<E> {
             }
 
             let tracer = self.tracer.clone();
-            let m = self.prep_records(tracer, batch);
-            batch_putter.enqueue(m, &self.key[..])?;
+            let m = self.prep_records(tracer, batch)?;
+            batch_putter.enqueue(m, &self.key[..], assigned_key)?;
         }
 
         self.tracer.take();
-        batch_putter.wait()?;
+        check_acks(batch_putter.wait()?)?;
         Ok(())
     }
 
-    /// Insert a single row of data into this base table.
-    pub fn insert<V>(&mut self, u: V) -> Result<(), TableError>
+    /// Insert a single row of data into this base table, and return the value that was assigned to
+    /// its `AUTO_INCREMENT` column, if any.
+    ///
+    /// If the table has an `AUTO_INCREMENT` column and the row has `DataType::None` in that
+    /// column, the base table assigns the next available value to it. Values are unique, but
+    /// since each shard of the table assigns its own values, they need not increase in the order
+    /// the rows were inserted.
+    pub fn insert<V>(&mut self, u: V) -> Result<Option<DataType>, TableError>
     where
         V: Into<Vec<DataType>>,
     {
@@ -386,8 +621,27 @@ impl<E> Table<E> {
             ));
         }
 
-        self.send(data)?;
-        Ok(())
+        let acks = self.send(data)?;
+        Ok(acks.into_iter().flat_map(|ack| ack.generated).next())
+    }
+
+    /// Insert a single row of data into this base table, giving values only for some columns.
+    ///
+    /// Columns that are not named take the `DEFAULT` value declared for them when the table was
+    /// created, or `NULL` if they have none. Like `insert`, this returns the value assigned to the
+    /// `AUTO_INCREMENT` column, if any.
+    pub fn insert_named(
+        &mut self,
+        values: &[(&str, DataType)],
+    ) -> Result<Option<DataType>, TableError> {
+        let mut row = self.defaults();
+        for &(column, ref v) in values {
+            match self.columns.iter().position(|c| c == column) {
+                Some(coli) => row[coli] = v.clone(),
+                None => return Err(TableError::UnknownColumn(column.to_owned())),
+            }
+        }
+        self.insert(row)
     }
 
     /// Insert multiple rows of data into this base table.
@@ -449,6 +703,62 @@ impl<E> Table<E> {
         Ok(())
     }
 
+    /// Delete all rows in this base table that satisfy every condition in `predicate`.
+    ///
+    /// The predicate is evaluated at the base table against its current contents, so the keys of
+    /// the affected rows need not be known. Note that the predicate does not observe writes that
+    /// are part of the same batch.
+    pub fn delete_where(&mut self, predicate: Vec<Condition>) -> Result<(), TableError> {
+        assert!(
+            !self.key.is_empty() && self.key_is_primary,
+            "predicate operations can only be applied to base nodes with key columns"
+        );
+
+        self.check_predicate(&predicate)?;
+        self.send(vec![TableOperation::DeleteWhere { predicate }])?;
+        Ok(())
+    }
+
+    /// Update all rows in this base table that satisfy every condition in `predicate`.
+    ///
+    /// `u` is a set of column-modification pairs, as documented in `Table::update`. The caveats of
+    /// `Table::delete_where` also apply here.
+    pub fn update_where<V>(&mut self, predicate: Vec<Condition>, u: V) -> Result<(), TableError>
+    where
+        V: IntoIterator<Item = (usize, Modification)>,
+    {
+        assert!(
+            !self.key.is_empty() && self.key_is_primary,
+            "predicate operations can only be applied to base nodes with key columns"
+        );
+
+        self.check_predicate(&predicate)?;
+        let mut set = vec![Modification::None; self.columns.len()];
+        for (coli, m) in u {
+            if coli >= self.columns.len() {
+                return Err(TableError::WrongColumnCount(self.columns.len(), coli + 1));
+            }
+            set[coli] = m;
+        }
+        self.send(vec![TableOperation::UpdateWhere { predicate, set }])?;
+        Ok(())
+    }
+
+    fn check_predicate(&self, predicate: &[Condition]) -> Result<(), TableError> {
+        for c in predicate {
+            if c.column >= self.columns.len() {
+                return Err(TableError::WrongColumnCount(
+                    self.columns.len(),
+                    c.column + 1,
+                ));
+            }
+            if !c.is_supported() {
+                return Err(TableError::UnsupportedCondition(c.clone()));
+            }
+        }
+        Ok(())
+    }
+
     /// Perform a insert-or-update on this base table.
     ///
     /// If a row already exists for the key in `insert`, the existing row will instead be updated
@@ -501,9 +811,24 @@ impl<E> Table<E> {
     }
 }
 
+/// Turn the first operation that a base table rejected into an error.
+fn check_acks(acks: Vec<InputAck>) -> Result<Vec<InputAck>, TableError> {
+    let violation = acks
+        .iter()
+        .flat_map(|ack| ack.rejected.iter())
+        .map(|&(_, ref violation)| violation.clone())
+        .next();
+    match violation {
+        Some(violation) => Err(TableError::ConstraintViolation(violation)),
+        None => Ok(acks),
+    }
+}
+
 pub(crate) struct DomainInputHandle {
     txs: Vec<TcpSender<LocalOrNot<Input>>>,
     dst_is_local: bool,
+    // the shard to send the next row whose key is assigned by the base to
+    next_shard: usize,
 }
 
 pub(crate) type TableRpc = Rc<RefCell<DomainInputHandle>>;
@@ -526,6 +851,7 @@ impl DomainInputHandle {
         Ok(Self {
             txs: txs?,
             dst_is_local: false,
+            next_shard: 0,
         })
     }
 
@@ -541,9 +867,14 @@ impl DomainInputHandle {
         BatchSendHandle::new(self)
     }
 
-    pub(crate) fn base_send(&mut self, i: Input, key: &[usize]) -> Result<(), TransportError> {
+    pub(crate) fn base_send(
+        &mut self,
+        i: Input,
+        key: &[usize],
+        assigned_key: bool,
+    ) -> Result<Vec<InputAck>, TransportError> {
         let mut s = BatchSendHandle::new(self);
-        s.enqueue(i, key)?;
+        s.enqueue(i, key, assigned_key)?;
         s.wait().map_err(|_| {
             tcp::SendError::IoError(io::Error::new(io::ErrorKind::Other, "write failed")).into()
         })
@@ -561,7 +892,17 @@ impl<'a> BatchSendHandle<'a> {
         Self { dih, sent }
     }
 
-    pub(crate) fn enqueue(&mut self, mut i: Input, key: &[usize]) -> Result<(), TransportError> {
+    /// Send `i` to the shards that own its rows, as determined by the `key` columns.
+    ///
+    /// If `assigned_key` is set, the base assigns the key of inserted rows that have a `NULL` key.
+    /// Since the base does so such that a row belongs to the shard that assigned its key, such
+    /// rows are spread across all shards.
+    pub(crate) fn enqueue(
+        &mut self,
+        mut i: Input,
+        key: &[usize],
+        assigned_key: bool,
+    ) -> Result<(), TransportError> {
         if self.dih.txs.len() == 1 {
             self.dih.txs[0].send(if self.dih.dst_is_local {
                 unsafe { LocalOrNot::for_local_transfer(i) }
@@ -579,18 +920,38 @@ impl<'a> BatchSendHandle<'a> {
             }
             let key_col = key[0];
 
-            let mut shard_writes = vec![Vec::new(); self.dih.txs.len()];
+            let shards = self.dih.txs.len();
+            let mut shard_writes = vec![Vec::new(); shards];
             for r in i.data.drain(..) {
-                let shard = {
-                    let key = match r {
-                        TableOperation::Insert(ref r) => &r[key_col],
-                        TableOperation::Delete { ref key } => &key[0],
-                        TableOperation::Update { ref key, .. } => &key[0],
-                        TableOperation::InsertOrUpdate { ref row, .. } => &row[key_col],
-                    };
-                    crate::shard_by(key, self.dih.txs.len())
+                let key = match r {
+                    TableOperation::Insert(ref r) => Some(&r[key_col]),
+                    TableOperation::Delete { ref key } => Some(&key[0]),
+                    TableOperation::Update { ref key, .. } => Some(&key[0]),
+                    TableOperation::InsertOrUpdate { ref row, .. } => Some(&row[key_col]),
+                    TableOperation::DeleteWhere { ref predicate }
+                    | TableOperation::UpdateWhere { ref predicate, .. } => predicate
+                        .iter()
+                        .find(|c| c.column == key_col && c.op == nom_sql::Operator::Equal)
+                        .map(|c| &c.value),
                 };
-                shard_writes[shard].push(r);
+
+                match key {
+                    Some(&DataType::None) if assigned_key && r.row().is_some() => {
+                        let shard = self.dih.next_shard;
+                        self.dih.next_shard = (shard + 1) % shards;
+                        shard_writes[shard].push(r);
+                    }
+                    Some(key) => {
+                        let shard = crate::shard_by(&self.key_collation.key(key), shards);
+                        shard_writes[shard].push(r);
+                    }
+                    None => {
+                        // predicate may match rows on any shard
+                        for rs in &mut shard_writes {
+                            rs.push(r.clone());
+                        }
+                    }
+                }
             }
 
             for (s, rs) in shard_writes.drain(..).enumerate() {
@@ -620,14 +981,17 @@ impl<'a> BatchSendHandle<'a> {
         Ok(())
     }
 
-    pub(crate) fn wait(self) -> Result<(), TransportError> {
+    pub(crate) fn wait(self) -> Result<Vec<InputAck>, TransportError> {
+        let mut acks = Vec::new();
         for (shard, n) in self.sent.into_iter().enumerate() {
             for _ in 0..n {
                 use bincode;
-                let _: bool = bincode::deserialize_from(&mut (&mut self.dih.txs[shard]).reader())?;
+                acks.push(bincode::deserialize_from(
+                    &mut (&mut self.dih.txs[shard]).reader(),
+                )?);
             }
         }
 
-        Ok(())
+        Ok(acks)
     }
 }
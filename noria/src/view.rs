@@ -2,9 +2,11 @@ use crate::channel::rpc::RpcClient;
 use crate::data::*;
 use crate::error::TransportError;
 use crate::{ExclusiveConnection, SharedConnection};
+use nom_sql::OrderType;
 use petgraph::graph::NodeIndex;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::io;
 use std::net::SocketAddr;
 use std::rc::Rc;
@@ -17,6 +19,18 @@ pub enum ViewError {
     /// The given view is not yet available.
     #[fail(display = "the view is not yet available")]
     NotYetAvailable,
+    /// The given view takes parameters, and so cannot be read in full.
+    #[fail(display = "the view takes parameters, and cannot be read in full")]
+    Parameterized,
+    /// The view is sharded, and so cannot be read a page at a time.
+    #[fail(display = "the view is sharded, and cannot be read a page at a time")]
+    Sharded,
+    /// The view is sharded, and so can only be read with keys of a single column.
+    #[fail(display = "the view is sharded, and can only be read with single-column keys")]
+    CompoundKey,
+    /// The view has no `LIMIT`, and so cannot be looked up with a limit.
+    #[fail(display = "the view has no LIMIT, and cannot be looked up with a limit")]
+    NoLimit,
     /// A lower-level error occurred while communicating with Soup.
     #[fail(display = "{}", _0)]
     TransportError(#[cause] TransportError),
@@ -39,12 +53,30 @@ pub enum ReadQuery {
         keys: Vec<Vec<DataType>>,
         /// Whether to block if a partial replay is triggered
         block: bool,
+        /// Return at most this many rows for each key, sorted by the given columns
+        limit: Option<(Vec<(usize, OrderType)>, usize)>,
     },
     /// Read the size of a leaf view
     Size {
         /// Where to read from
         target: (NodeIndex, usize),
     },
+    /// Trigger replays of the given keys without reading them
+    Warm {
+        /// Where to trigger replays
+        target: (NodeIndex, usize),
+        /// Keys to replay
+        keys: Vec<Vec<DataType>>,
+    },
+    /// Read a page of the rows of a leaf view that has no key
+    Page {
+        /// Where to read from
+        target: (NodeIndex, usize),
+        /// How many rows to skip
+        offset: usize,
+        /// How many rows to read at most
+        limit: usize,
+    },
 }
 
 #[doc(hidden)]
@@ -54,6 +86,29 @@ pub enum ReadReply {
     Normal(Result<Vec<Datas>, ()>),
     /// Read size of view
     Size(usize),
+    /// Errors if view isn't ready yet.
+    Warm(Result<WarmProgress, ()>),
+    /// Errors if view isn't ready yet, and is `None` if the view has a key.
+    Page(Result<Option<Datas>, ()>),
+}
+
+/// How far along the replays triggered by warming a view are.
+///
+/// Warming does not wait for replays to finish. Warming with the same keys again reports how many
+/// of them have been replayed since.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarmProgress {
+    /// The number of keys that are already present in the view.
+    pub present: usize,
+    /// The number of keys that are still being replayed.
+    pub pending: usize,
+}
+
+impl WarmProgress {
+    /// Returns true if all the keys are present in the view.
+    pub fn is_done(&self) -> bool {
+        self.pending == 0
+    }
 }
 
 #[doc(hidden)]
@@ -64,6 +119,11 @@ pub struct ViewBuilder {
     pub shards: Vec<SocketAddr>,
     // one per shard
     pub local_ports: Vec<u16>,
+    // if the view has a LIMIT, its ordering columns and the limit, so that results for several
+    // keys can be merged
+    pub topk: Option<(Vec<(usize, OrderType)>, usize)>,
+    // the collation of the first key column, under which keys are sharded
+    pub key_collation: Collation,
 }
 
 impl ViewBuilder {
@@ -78,6 +138,8 @@ impl ViewBuilder {
         Ok(View {
             node: self.node,
             columns: self.columns,
+            topk: self.topk,
+            key_collation: self.key_collation,
             shard_addrs: self.shards,
             shards: conns,
             exclusivity: ExclusiveConnection,
@@ -126,6 +188,8 @@ impl ViewBuilder {
         Ok(View {
             node: self.node,
             columns: self.columns,
+            topk: self.topk,
+            key_collation: self.key_collation,
             shard_addrs: self.shards,
             shards: conns,
             exclusivity: SharedConnection,
@@ -142,6 +206,8 @@ impl ViewBuilder {
 pub struct View<E = SharedConnection> {
     node: NodeIndex,
     columns: Vec<String>,
+    topk: Option<(Vec<(usize, OrderType)>, usize)>,
+    key_collation: Collation,
     shards: Vec<ViewRpc>,
     shard_addrs: Vec<SocketAddr>,
 
@@ -154,6 +220,8 @@ impl Clone for View<SharedConnection> {
         View {
             node: self.node,
             columns: self.columns.clone(),
+            topk: self.topk.clone(),
+            key_collation: self.key_collation,
             shards: self.shards.clone(),
             shard_addrs: self.shard_addrs.clone(),
             exclusivity: SharedConnection,
@@ -171,12 +239,30 @@ impl View<SharedConnection> {
             node: self.node,
             local_ports: vec![],
             columns: self.columns,
+            topk: self.topk,
+            key_collation: self.key_collation,
             shards: self.shard_addrs,
         }
         .build_exclusive()
     }
 }
 
+/// Sorts rows by the given columns, as a view's `ORDER BY` would.
+#[doc(hidden)]
+pub fn sort_by_order<R: AsRef<[DataType]>>(order: &[(usize, OrderType)], rows: &mut [R]) {
+    rows.sort_by(|a, b| {
+        let (a, b) = (a.as_ref(), b.as_ref());
+        order
+            .iter()
+            .map(|&(c, ref o)| match *o {
+                OrderType::OrderAscending => a[c].cmp(&b[c]),
+                OrderType::OrderDescending => b[c].cmp(&a[c]),
+            })
+            .find(|&o| o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
 #[cfg_attr(
     feature = "cargo-clippy",
     allow(clippy::len_without_is_empty)
@@ -234,6 +320,15 @@ impl<E> View<E> {
         &mut self,
         keys: Vec<Vec<DataType>>,
         block: bool,
+    ) -> Result<Vec<Datas>, ViewError> {
+        self.multi_lookup_limited(keys, None, block)
+    }
+
+    fn multi_lookup_limited(
+        &mut self,
+        keys: Vec<Vec<DataType>>,
+        limit: Option<(Vec<(usize, OrderType)>, usize)>,
+        block: bool,
     ) -> Result<Vec<Datas>, ViewError> {
         if self.shards.len() == 1 {
             let mut shard = self.shards[0].borrow_mut();
@@ -242,6 +337,7 @@ impl<E> View<E> {
                     target: (self.node, 0),
                     keys,
                     block,
+                    limit,
                 })
                 .map_err(TransportError::from)?;
             match reply {
@@ -250,10 +346,12 @@ impl<E> View<E> {
                 _ => unreachable!(),
             }
         } else {
-            assert!(keys.iter().all(|k| k.len() == 1));
+            if keys.iter().any(|k| k.len() != 1) {
+                return Err(ViewError::CompoundKey);
+            }
             let mut shard_queries = vec![Vec::new(); self.shards.len()];
             for key in keys {
-                let shard = crate::shard_by(&key[0], self.shards.len());
+                let shard = crate::shard_by(&self.key_collation.key(&key[0]), self.shards.len());
                 shard_queries[shard].push(key);
             }
 
@@ -271,6 +369,7 @@ impl<E> View<E> {
                             target: (self.node, shardi),
                             keys: mem::replace(shard_queries, Vec::new()),
                             block,
+                            limit: limit.clone(),
                         })
                         .map_err(TransportError::from)?)
                 })
@@ -291,6 +390,51 @@ impl<E> View<E> {
         }
     }
 
+    /// Trigger replays of any of the given keys that are missing from this view, without reading
+    /// them or waiting for the replays to finish.
+    ///
+    /// This is useful for priming a partial view before it starts serving requests. The returned
+    /// `WarmProgress` says how many of the keys were already present; call this again with the
+    /// same keys to check whether the rest have since been replayed.
+    pub fn warm(&mut self, keys: Vec<Vec<DataType>>) -> Result<WarmProgress, ViewError> {
+        let mut shard_keys = vec![Vec::new(); self.shards.len()];
+        if self.shards.len() == 1 {
+            shard_keys[0] = keys;
+        } else {
+            if keys.iter().any(|k| k.len() != 1) {
+                return Err(ViewError::CompoundKey);
+            }
+            for key in keys {
+                let shard = crate::shard_by(&self.key_collation.key(&key[0]), self.shards.len());
+                shard_keys[shard].push(key);
+            }
+        }
+
+        let mut progress = WarmProgress::default();
+        for (shardi, keys) in shard_keys.into_iter().enumerate() {
+            if keys.is_empty() {
+                continue;
+            }
+
+            let mut shard = self.shards[shardi].borrow_mut();
+            let reply = shard
+                .send(&ReadQuery::Warm {
+                    target: (self.node, shardi),
+                    keys,
+                })
+                .map_err(TransportError::from)?;
+            match reply {
+                ReadReply::Warm(Ok(p)) => {
+                    progress.present += p.present;
+                    progress.pending += p.pending;
+                }
+                ReadReply::Warm(Err(())) => return Err(ViewError::NotYetAvailable),
+                _ => unreachable!(),
+            }
+        }
+        Ok(progress)
+    }
+
     /// Retrieve the query results for the given parameter value.
     ///
     /// The method will block if the results are not yet available only when `block` is `true`.
@@ -299,4 +443,108 @@ impl<E> View<E> {
         self.multi_lookup(vec![Vec::from(key)], block)
             .map(|rs| rs.into_iter().next().unwrap())
     }
+
+    /// Retrieve up to `limit` rows of a view that takes no parameters, skipping the first `offset`.
+    ///
+    /// The rows of a view are kept in no particular order, and that order may change whenever
+    /// the view is written to, since removing a row may move another into its place. Each page is
+    /// read from the view as it is when the page is requested, so if the view changes between
+    /// requests for successive pages, rows may be skipped or returned twice, including rows that
+    /// did not change themselves. Use `all` to read every row as of a single point in time.
+    ///
+    /// Views that take parameters return `ViewError::Parameterized`, and sharded views return
+    /// `ViewError::Sharded`.
+    pub fn page(&mut self, offset: usize, limit: usize) -> Result<Datas, ViewError> {
+        // views without parameters have no key to shard by
+        if self.shards.len() != 1 {
+            return Err(ViewError::Sharded);
+        }
+
+        let mut shard = self.shards[0].borrow_mut();
+        let reply = shard
+            .send(&ReadQuery::Page {
+                target: (self.node, 0),
+                offset,
+                limit,
+            })
+            .map_err(TransportError::from)?;
+        match reply {
+            ReadReply::Page(Ok(Some(rows))) => Ok(rows),
+            ReadReply::Page(Ok(None)) => Err(ViewError::Parameterized),
+            ReadReply::Page(Err(())) => Err(ViewError::NotYetAvailable),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Retrieve all the rows of a view that takes no parameters.
+    ///
+    /// The rows are read in a single reply, so they all reflect the same state of the view. Use
+    /// `page` to read a large view in parts instead.
+    pub fn all(&mut self) -> Result<Datas, ViewError> {
+        self.page(0, usize::max_value())
+    }
+
+    /// Retrieve the query results for every combination of the given parameter values.
+    ///
+    /// `params` holds a list of values for each of the view's parameters, so a view defined with
+    /// `WHERE a = ? AND b IN (?)` is looked up with `&[vec![a], vec![b1, b2]]`. The results for
+    /// all the combinations are merged, and if the view has a `LIMIT`, its `ORDER BY` and `LIMIT`
+    /// are applied again to the merged results. Values that are given more than once for a
+    /// parameter are only looked up once.
+    ///
+    /// The method will block if the results are not yet available only when `block` is `true`.
+    pub fn lookup_in(&mut self, params: &[Vec<DataType>], block: bool) -> Result<Datas, ViewError> {
+        let mut keys = vec![vec![]];
+        for values in params {
+            let mut seen = HashSet::new();
+            let values: Vec<_> = values.iter().filter(|&v| seen.insert(v)).collect();
+            keys = keys
+                .into_iter()
+                .flat_map(|key: Vec<DataType>| {
+                    values.iter().map(move |&v| {
+                        let mut key = key.clone();
+                        key.push(v.clone());
+                        key
+                    })
+                })
+                .collect();
+        }
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut rows: Datas = self
+            .multi_lookup(keys, block)?
+            .into_iter()
+            .flatten()
+            .collect();
+        if let Some((ref order, k)) = self.topk {
+            sort_by_order(order, &mut rows);
+            rows.truncate(k);
+        }
+        Ok(rows)
+    }
+
+    /// Retrieve at most `limit` of the query results for the given parameter value.
+    ///
+    /// This is meant for views defined with `LIMIT ?`, which keep up to their maximum limit of
+    /// results for each key, and return them in their `ORDER BY` order. The results are sorted
+    /// and cut down to `limit` by the reader, so only the rows asked for are sent back. A `limit`
+    /// larger than that maximum returns at most the maximum number of results. Views without a
+    /// `LIMIT` return `ViewError::NoLimit`.
+    ///
+    /// The method will block if the results are not yet available only when `block` is `true`.
+    pub fn lookup_with_limit(
+        &mut self,
+        key: &[DataType],
+        limit: usize,
+        block: bool,
+    ) -> Result<Datas, ViewError> {
+        let order = match self.topk {
+            Some((ref order, _)) => order.clone(),
+            None => return Err(ViewError::NoLimit),
+        };
+        self.multi_lookup_limited(vec![Vec::from(key)], Some((order, limit)), block)
+            .map(|rs| rs.into_iter().next().unwrap())
+    }
 }